@@ -27,6 +27,8 @@ mod registry;
 mod urlhint;
 mod service_transaction;
 mod secretstore_acl_storage;
+mod key_server_set;
+mod secretstore_service;
 mod validator_set;
 mod validator_report;
 
@@ -36,5 +38,7 @@ pub use self::registry::Registry;
 pub use self::urlhint::Urlhint;
 pub use self::service_transaction::ServiceTransactionChecker;
 pub use self::secretstore_acl_storage::SecretStoreAclStorage;
+pub use self::key_server_set::KeyServerSet;
+pub use self::secretstore_service::SecretStoreService;
 pub use self::validator_set::ValidatorSet;
 pub use self::validator_report::ValidatorReport;
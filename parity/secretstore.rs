@@ -96,7 +96,11 @@ mod server {
 						port: port,
 					})).collect(),
 					allow_connecting_to_higher_nodes: true,
+					admin_public: None,
+					max_active_key_migrations: 4,
+					wipe_removed_key_shares: false,
 				},
+				service_contract_address: None,
 			};
 
 			let self_key_pair = KeyPair::from_secret(self_secret.clone())
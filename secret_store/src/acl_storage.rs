@@ -14,92 +14,347 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::time;
+use std::sync::{Arc, Weak};
+use std::collections::HashMap;
 use futures::{future, Future};
 use parking_lot::Mutex;
-use ethkey::public_to_address;
-use ethcore::client::{Client, BlockChainClient, BlockId};
+use ethcore::client::{Client, BlockChainClient, BlockId, ChainNotify};
 use native_contracts::SecretStoreAclStorage;
-use types::all::{Error, DocumentAddress, Public};
+use util::{Address, Bytes, H256};
+use types::all::{Error, DocumentAddress};
 
 const ACL_CHECKER_CONTRACT_REGISTRY_NAME: &'static str = "secretstore_acl_checker";
 
+/// Default time to cache ACL check results for.
+pub const DEFAULT_CACHE_TTL: u64 = 60;
+/// Default number of failed contract calls, after which access is denied.
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
 /// ACL storage of Secret Store
 pub trait AclStorage: Send + Sync {
-	/// Check if requestor with `public` key can access document with hash `document`
-	fn check(&self, public: &Public, document: &DocumentAddress) -> Result<bool, Error>;
+	/// Check if requestor with `address` can access document with hash `document`
+	fn check(&self, address: &Address, document: &DocumentAddress) -> Result<bool, Error>;
+}
+
+/// Contracts access, required by on-chain ACL storage.
+pub trait CallContract: Send + Sync {
+	/// Get address of the contract, registered with given name.
+	fn registry_address(&self, name: String) -> Option<Address>;
+	/// Call contract at the latest block.
+	fn call_contract(&self, address: Address, data: Bytes) -> Result<Bytes, String>;
 }
 
 /// On-chain ACL storage implementation.
 pub struct OnChainAclStorage {
-	/// Blockchain client.
-	client: Arc<Client>,
+	/// Contracts caller. Weak, because the client holds this storage in its notify list.
+	client: Weak<CallContract>,
+	/// Time to cache check results for.
+	cache_ttl: time::Duration,
+	/// Number of failed contract calls (per requestor + document), after which access is denied.
+	max_retries: usize,
+	/// Mutable data.
+	data: Mutex<OnChainAclStorageData>,
+}
+
+/// Mutable data of on-chain ACL storage.
+struct OnChainAclStorageData {
 	/// On-chain contract.
-	contract: Mutex<Option<SecretStoreAclStorage>>,
+	contract: Option<SecretStoreAclStorage>,
+	/// Cached check results.
+	cache: HashMap<(Address, DocumentAddress), CachedCheckResult>,
+	/// Number of failed contract calls.
+	failures: HashMap<(Address, DocumentAddress), usize>,
+}
+
+/// Cached result of ACL check.
+struct CachedCheckResult {
+	/// Is access allowed?
+	is_allowed: bool,
+	/// Time when result has been cached.
+	cached_at: time::Instant,
 }
 
 impl OnChainAclStorage {
-	pub fn new(client: Arc<Client>) -> Self {
+	/// Create new on-chain ACL storage with default cache TTL && retries limit.
+	pub fn new(client: Weak<CallContract>) -> Self {
+		OnChainAclStorage::with_options(client, time::Duration::from_secs(DEFAULT_CACHE_TTL), DEFAULT_MAX_RETRIES)
+	}
+
+	/// Create new on-chain ACL storage, which caches check results for `cache_ttl` && denies access
+	/// after `max_retries` failed contract calls for the same requestor && document.
+	pub fn with_options(client: Weak<CallContract>, cache_ttl: time::Duration, max_retries: usize) -> Self {
 		OnChainAclStorage {
 			client: client,
-			contract: Mutex::new(None),
+			cache_ttl: cache_ttl,
+			max_retries: max_retries,
+			data: Mutex::new(OnChainAclStorageData {
+				contract: None,
+				cache: HashMap::new(),
+				failures: HashMap::new(),
+			}),
+		}
+	}
+
+	/// When new block is imported. Cached results may be outdated => forget everything.
+	pub fn on_new_block(&self, block_hash: &H256) {
+		trace!(target: "secretstore", "Invalidating ACL cache on block {}", block_hash);
+
+		let mut data = self.data.lock();
+		data.contract = None;
+		data.cache.clear();
+	}
+
+	/// Check access using the on-chain contract. Data lock is not held while the contract is called.
+	fn check_with_contract(&self, address: Address, document: &DocumentAddress) -> Result<bool, String> {
+		let client = self.client.upgrade().ok_or_else(|| "client is destroyed".to_owned())?;
+		let contract = self.data.lock().contract.clone();
+		let contract = match contract {
+			Some(contract) => Some(contract),
+			None => {
+				let contract = client.registry_address(ACL_CHECKER_CONTRACT_REGISTRY_NAME.to_owned())
+					.and_then(|contract_addr| {
+						trace!(target: "secretstore", "Configuring for ACL checker contract from {}", contract_addr);
+
+						Some(SecretStoreAclStorage::new(contract_addr))
+					});
+				self.data.lock().contract = contract.clone();
+				contract
+			},
+		};
+
+		match contract {
+			Some(contract) => {
+				let do_call = |a, d| future::done(client.call_contract(a, d));
+				contract.check_permissions(do_call, address, document.clone()).wait()
+			},
+			None => Err("ACL checker contract is not configured".to_owned()),
 		}
 	}
 }
 
 impl AclStorage for OnChainAclStorage {
-	fn check(&self, public: &Public, document: &DocumentAddress) -> Result<bool, Error> {
-		let mut contract = self.contract.lock();
-		if !contract.is_some() {
-			*contract = self.client.registry_address(ACL_CHECKER_CONTRACT_REGISTRY_NAME.to_owned())
-				.and_then(|contract_addr| {
-					trace!(target: "secretstore", "Configuring for ACL checker contract from {}", contract_addr);
-
-					Some(SecretStoreAclStorage::new(contract_addr))
-				})
+	fn check(&self, address: &Address, document: &DocumentAddress) -> Result<bool, Error> {
+		let key = (address.clone(), document.clone());
+
+		if let Some(cached) = self.data.lock().cache.get(&key) {
+			if time::Instant::now() - cached.cached_at < self.cache_ttl {
+				return Ok(cached.is_allowed);
+			}
+		}
+
+		// do not hold the lock while waiting for the contract call
+		let check_result = self.check_with_contract(address.clone(), document);
+		let mut data = self.data.lock();
+		match check_result {
+			Ok(is_allowed) => {
+				data.failures.remove(&key);
+				data.cache.insert(key, CachedCheckResult {
+					is_allowed: is_allowed,
+					cached_at: time::Instant::now(),
+				});
+				Ok(is_allowed)
+			},
+			Err(err) => {
+				let failures = {
+					let failures = data.failures.entry(key.clone()).or_insert(0);
+					*failures += 1;
+					*failures
+				};
+
+				if failures > self.max_retries {
+					warn!(target: "secretstore", "Denying access after {} failed ACL checks: {}", failures, err);
+					data.failures.remove(&key);
+					return Err(Error::AccessDenied);
+				}
+
+				Err(Error::TemporarilyUnavailable(err))
+			},
 		}
-		if let Some(ref contract) = *contract {
-			let address = public_to_address(&public);
-			let do_call = |a, d| future::done(self.client.call_contract(BlockId::Latest, a, d));
-			contract.check_permissions(do_call, address, document.clone())
-				.map_err(|err| Error::Internal(err))
-				.wait()
-		} else {
-			Err(Error::Internal("ACL checker contract is not configured".to_owned()))
+	}
+}
+
+impl ChainNotify for OnChainAclStorage {
+	fn new_blocks(&self, imported: Vec<H256>, _invalid: Vec<H256>, enacted: Vec<H256>, retracted: Vec<H256>, _sealed: Vec<H256>, _proposed: Vec<Bytes>, _duration: u64) {
+		if let Some(block_hash) = enacted.last().or(retracted.last()).or(imported.last()) {
+			self.on_new_block(block_hash);
 		}
 	}
 }
 
+impl CallContract for Client {
+	fn registry_address(&self, name: String) -> Option<Address> {
+		BlockChainClient::registry_address(self, name)
+	}
+
+	fn call_contract(&self, address: Address, data: Bytes) -> Result<Bytes, String> {
+		BlockChainClient::call_contract(self, BlockId::Latest, address, data)
+	}
+}
+
 #[cfg(test)]
 pub mod tests {
+	use std::{thread, time};
+	use std::sync::Arc;
 	use std::collections::{HashMap, HashSet};
-	use parking_lot::RwLock;
-	use types::all::{Error, DocumentAddress, Public};
-	use super::AclStorage;
+	use parking_lot::{Mutex, RwLock};
+	use ethkey::{Random, Generator, public_to_address};
+	use util::{Address, Bytes, H256};
+	use types::all::{Error, DocumentAddress};
+	use super::{AclStorage, CallContract, OnChainAclStorage};
 
 	#[derive(Default, Debug)]
 	/// Dummy ACL storage implementation
 	pub struct DummyAclStorage {
-		prohibited: RwLock<HashMap<Public, HashSet<DocumentAddress>>>,
+		prohibited: RwLock<HashMap<Address, HashSet<DocumentAddress>>>,
 	}
 
 	impl DummyAclStorage {
 		#[cfg(test)]
 		/// Prohibit given requestor access to given document
-		pub fn prohibit(&self, public: Public, document: DocumentAddress) {
+		pub fn prohibit(&self, address: Address, document: DocumentAddress) {
 			self.prohibited.write()
-				.entry(public)
+				.entry(address)
 				.or_insert_with(Default::default)
 				.insert(document);
 		}
 	}
 
 	impl AclStorage for DummyAclStorage {
-		fn check(&self, public: &Public, document: &DocumentAddress) -> Result<bool, Error> {
+		fn check(&self, address: &Address, document: &DocumentAddress) -> Result<bool, Error> {
 			Ok(self.prohibited.read()
-				.get(public)
+				.get(address)
 				.map(|docs| !docs.contains(document))
 				.unwrap_or(true))
 		}
 	}
+
+	#[derive(Default)]
+	/// Dummy contract caller, which answers with preconfigured results.
+	struct DummyCallContract {
+		/// Results of the next contract calls. When empty, access is allowed.
+		results: Mutex<Vec<Result<bool, String>>>,
+		/// Number of contract calls made.
+		calls: Mutex<usize>,
+		/// Callback, invoked on every contract call.
+		on_call: Mutex<Option<Box<Fn() + Send>>>,
+	}
+
+	impl DummyCallContract {
+		fn push_result(&self, result: Result<bool, String>) {
+			self.results.lock().insert(0, result);
+		}
+
+		fn calls(&self) -> usize {
+			*self.calls.lock()
+		}
+	}
+
+	impl CallContract for DummyCallContract {
+		fn registry_address(&self, _name: String) -> Option<Address> {
+			Some(Address::default())
+		}
+
+		fn call_contract(&self, _address: Address, _data: Bytes) -> Result<Bytes, String> {
+			*self.calls.lock() += 1;
+			if let Some(ref on_call) = *self.on_call.lock() {
+				on_call();
+			}
+			self.results.lock().pop().unwrap_or(Ok(true)).map(|is_allowed| {
+				let mut result = vec![0u8; 32];
+				result[31] = is_allowed as u8;
+				result
+			})
+		}
+	}
+
+	fn make_acl_storage(cache_ttl: time::Duration, max_retries: usize) -> (Arc<DummyCallContract>, OnChainAclStorage) {
+		let contract = Arc::new(DummyCallContract::default());
+		let acl_storage = OnChainAclStorage::with_options(Arc::downgrade(&contract), cache_ttl, max_retries);
+		(contract, acl_storage)
+	}
+
+	#[test]
+	fn on_chain_acl_storage_uses_cached_results() {
+		let (contract, acl_storage) = make_acl_storage(time::Duration::from_secs(60), 0);
+		let requestor = public_to_address(Random.generate().unwrap().public());
+		let document = DocumentAddress::from(1);
+
+		contract.push_result(Ok(false));
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(false));
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(false));
+		assert_eq!(contract.calls(), 1);
+
+		// other document is not cached
+		assert_eq!(acl_storage.check(&requestor, &DocumentAddress::from(2)), Ok(true));
+		assert_eq!(contract.calls(), 2);
+	}
+
+	#[test]
+	fn on_chain_acl_storage_calls_contract_when_cached_result_expires() {
+		let (contract, acl_storage) = make_acl_storage(time::Duration::from_millis(1), 0);
+		let requestor = public_to_address(Random.generate().unwrap().public());
+		let document = DocumentAddress::from(1);
+
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(true));
+		thread::sleep(time::Duration::from_millis(10));
+		contract.push_result(Ok(false));
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(false));
+		assert_eq!(contract.calls(), 2);
+	}
+
+	#[test]
+	fn on_chain_acl_storage_denies_access_after_retries() {
+		let (contract, acl_storage) = make_acl_storage(time::Duration::from_secs(60), 2);
+		let requestor = public_to_address(Random.generate().unwrap().public());
+		let document = DocumentAddress::from(1);
+
+		for _ in 0..3 {
+			contract.push_result(Err("call failed".into()));
+		}
+		assert_eq!(acl_storage.check(&requestor, &document), Err(Error::TemporarilyUnavailable("call failed".into())));
+		assert_eq!(acl_storage.check(&requestor, &document), Err(Error::TemporarilyUnavailable("call failed".into())));
+		assert_eq!(acl_storage.check(&requestor, &document), Err(Error::AccessDenied));
+		assert_eq!(contract.calls(), 3);
+
+		// failures are not cached
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(true));
+	}
+
+	#[test]
+	fn on_chain_acl_storage_invalidates_cache_on_new_block() {
+		let (contract, acl_storage) = make_acl_storage(time::Duration::from_secs(60), 0);
+		let requestor = public_to_address(Random.generate().unwrap().public());
+		let document = DocumentAddress::from(1);
+
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(true));
+		acl_storage.on_new_block(&H256::from(1));
+		contract.push_result(Ok(false));
+		assert_eq!(acl_storage.check(&requestor, &document), Ok(false));
+		assert_eq!(contract.calls(), 2);
+	}
+
+	#[test]
+	fn on_chain_acl_storage_does_not_hold_lock_while_calling_contract() {
+		let (contract, acl_storage) = make_acl_storage(time::Duration::from_secs(60), 0);
+		let acl_storage = Arc::new(acl_storage);
+		let requestor = public_to_address(Random.generate().unwrap().public());
+
+		// new block is imported while the contract is called
+		let weak_acl_storage = Arc::downgrade(&acl_storage);
+		*contract.on_call.lock() = Some(Box::new(move || if let Some(acl_storage) = weak_acl_storage.upgrade() {
+			acl_storage.on_new_block(&H256::from(1));
+		}));
+		assert_eq!(acl_storage.check(&requestor, &DocumentAddress::from(1)), Ok(true));
+		assert_eq!(contract.calls(), 1);
+	}
+
+	#[test]
+	fn on_chain_acl_storage_does_not_keep_client_alive() {
+		let (contract, acl_storage) = make_acl_storage(time::Duration::from_secs(60), 0);
+		let requestor = public_to_address(Random.generate().unwrap().public());
+
+		drop(contract);
+		assert_eq!(acl_storage.check(&requestor, &DocumentAddress::from(1)), Err(Error::AccessDenied));
+	}
 }
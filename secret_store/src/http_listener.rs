@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::io::Read;
 use std::sync::Arc;
 use hyper::header;
 use hyper::uri::RequestUri;
@@ -25,10 +26,26 @@ use serde_json;
 use url::percent_encoding::percent_decode;
 
 use traits::KeyServer;
-use serialization::{SerializableDocumentEncryptedKeyShadow, SerializableBytes};
-use types::all::{Error, NodeAddress, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow};
+use serialization::{SerializableDocumentEncryptedKeyShadow, SerializableBytes, SerializablePublic};
+use types::all::{Error, Public, NodeAddress, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+	MessageHash, EncryptedMessageSignature};
+
+/// Max size of the request body. Only document key store request has the body.
+const MAX_REQUEST_BODY_SIZE: u64 = 4096;
 
 /// Key server http-requests listener
+/// Endpoints:
+/// POST /{server_key_id}/{signature}/{threshold}: generate server key
+/// POST /document/{server_key_id}/{signature}/{threshold}: generate server key && document key. Document key
+///   is encrypted with requestor public key
+/// POST /shadow/{server_key_id}/{signature}: store document key. Body is the JSON object with hex-encoded
+///   `common_point` && `encrypted_key` fields and numeric `nonce` field. Signature is made over the store request hash
+/// GET /{server_key_id}/{signature}: retrieve document key
+/// GET /shadow/{server_key_id}/{signature}: retrieve document key shadow
+/// DELETE /{server_key_id}/{signature}/{nonce}: remove server key (and document key)
+/// GET /schnorr/{server_key_id}/{signature}/{message_hash}: sign message hash with server key. Result is the
+///   hex-encoded (signature hash, signature) pair, encrypted with requestor public key
+/// GET /metrics: get key server metrics
 pub struct KeyServerHttpListener<T: KeyServer + 'static> {
 	_http_server: HttpListening,
 	handler: Arc<KeyServerSharedHttpHandler<T>>,
@@ -39,12 +56,33 @@ pub struct KeyServerHttpListener<T: KeyServer + 'static> {
 enum Request {
 	/// Invalid request
 	Invalid,
+	/// Generate server key.
+	GenerateServerKey(DocumentAddress, RequestSignature, usize),
+	/// Store document key, encrypted with server key.
+	StoreDocumentKey(DocumentAddress, RequestSignature, Public, Public, u64),
 	/// Generate encryption key.
 	GenerateDocumentKey(DocumentAddress, RequestSignature, usize),
 	/// Request encryption key of given document for given requestor.
 	GetDocumentKey(DocumentAddress, RequestSignature),
 	/// Request shadow of encryption key of given document for given requestor.
 	GetDocumentKeyShadow(DocumentAddress, RequestSignature),
+	/// Remove server key (and document key) of given document from all key servers.
+	RemoveDocumentKey(DocumentAddress, RequestSignature, u64),
+	/// Sign message hash with server key of given document.
+	SignMessage(DocumentAddress, RequestSignature, MessageHash),
+	/// Get key server metrics.
+	Metrics,
+}
+
+#[derive(Serialize, Deserialize)]
+/// Body of document key store request.
+struct StoreDocumentKeyRequest {
+	/// Common point of the encrypted document key.
+	common_point: SerializablePublic,
+	/// Document key, encrypted with server key.
+	encrypted_key: SerializablePublic,
+	/// Nonce of the store request. Every nonce is accepted once.
+	nonce: u64,
 }
 
 /// Cloneable http handler
@@ -79,6 +117,14 @@ impl<T> KeyServerHttpListener<T> where T: KeyServer + 'static {
 }
 
 impl<T> KeyServer for KeyServerHttpListener<T> where T: KeyServer + 'static {
+	fn generate_server_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<Public, Error> {
+		self.handler.key_server.generate_server_key(signature, document, threshold)
+	}
+
+	fn store_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, common_point: &Public, encrypted_document_key: &Public, nonce: u64) -> Result<(), Error> {
+		self.handler.key_server.store_document_key(signature, document, common_point, encrypted_document_key, nonce)
+	}
+
 	fn generate_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<DocumentEncryptedKey, Error> {
 		self.handler.key_server.generate_document_key(signature, document, threshold)
 	}
@@ -90,6 +136,18 @@ impl<T> KeyServer for KeyServerHttpListener<T> where T: KeyServer + 'static {
 	fn document_key_shadow(&self, signature: &RequestSignature, document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error> {
 		self.handler.key_server.document_key_shadow(signature, document)
 	}
+
+	fn remove_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		self.handler.key_server.remove_document_key(signature, document, nonce)
+	}
+
+	fn sign_message(&self, signature: &RequestSignature, document: &DocumentAddress, message: &MessageHash) -> Result<EncryptedMessageSignature, Error> {
+		self.handler.key_server.sign_message(signature, document, message)
+	}
+
+	fn metrics(&self) -> Result<String, Error> {
+		self.handler.key_server.metrics()
+	}
 }
 
 impl<T> Drop for KeyServerHttpListener<T> where T: KeyServer + 'static {
@@ -100,17 +158,39 @@ impl<T> Drop for KeyServerHttpListener<T> where T: KeyServer + 'static {
 }
 
 impl<T> HttpHandler for KeyServerHttpHandler<T> where T: KeyServer + 'static {
-	fn handle(&self, req: HttpRequest, mut res: HttpResponse) {
+	fn handle(&self, mut req: HttpRequest, mut res: HttpResponse) {
 		if req.headers.has::<header::Origin>() {
 			warn!(target: "secretstore", "Ignoring {}-request {} with Origin header", req.method, req.uri);
 			*res.status_mut() = HttpStatusCode::NotFound;
 			return;
 		}
 
+		let mut req_body = Vec::new();
+		let read_result = (&mut req).take(MAX_REQUEST_BODY_SIZE).read_to_end(&mut req_body);
+		if let Err(err) = read_result {
+			warn!(target: "secretstore", "Error {} reading body of {}-request {}", err, req.method, req.uri);
+			*res.status_mut() = HttpStatusCode::BadRequest;
+			return;
+		}
+
 		let req_method = req.method.clone();
 		let req_uri = req.uri.clone();
 		match &req_uri {
-			&RequestUri::AbsolutePath(ref path) => match parse_request(&req_method, &path) {
+			&RequestUri::AbsolutePath(ref path) => match parse_request(&req_method, &path, &req_body) {
+				Request::GenerateServerKey(document, signature, threshold) => {
+					return_server_public_key(req, res, self.handler.key_server.generate_server_key(&signature, &document, threshold)
+						.map_err(|err| {
+							warn!(target: "secretstore", "GenerateServerKey request {} has failed with: {}", req_uri, err);
+							err
+						}));
+				},
+				Request::StoreDocumentKey(document, signature, common_point, encrypted_document_key, nonce) => {
+					return_empty(res, self.handler.key_server.store_document_key(&signature, &document, &common_point, &encrypted_document_key, nonce)
+						.map_err(|err| {
+							warn!(target: "secretstore", "StoreDocumentKey request {} has failed with: {}", req_uri, err);
+							err
+						}));
+				},
 				Request::GenerateDocumentKey(document, signature, threshold) => {
 					return_document_key(req, res, self.handler.key_server.generate_document_key(&signature, &document, threshold)
 						.map_err(|err| {
@@ -153,6 +233,27 @@ impl<T> HttpHandler for KeyServerHttpHandler<T> where T: KeyServer + 'static {
 						Err(err) => return_error(res, err),
 					}
 				},
+				Request::RemoveDocumentKey(document, signature, nonce) => {
+					return_empty(res, self.handler.key_server.remove_document_key(&signature, &document, nonce)
+						.map_err(|err| {
+							warn!(target: "secretstore", "RemoveDocumentKey request {} has failed with: {}", req_uri, err);
+							err
+						}));
+				},
+				Request::SignMessage(document, signature, message_hash) => {
+					return_message_signature(req, res, self.handler.key_server.sign_message(&signature, &document, &message_hash)
+						.map_err(|err| {
+							warn!(target: "secretstore", "SignMessage request {} has failed with: {}", req_uri, err);
+							err
+						}));
+				},
+				Request::Metrics => {
+					return_metrics(req, res, self.handler.key_server.metrics()
+						.map_err(|err| {
+							warn!(target: "secretstore", "Metrics request {} has failed with: {}", req_uri, err);
+							err
+						}));
+				},
 				Request::Invalid => {
 					warn!(target: "secretstore", "Ignoring invalid {}-request {}", req_method, req_uri);
 					*res.status_mut() = HttpStatusCode::BadRequest;
@@ -166,6 +267,27 @@ impl<T> HttpHandler for KeyServerHttpHandler<T> where T: KeyServer + 'static {
 	}
 }
 
+fn return_empty(res: HttpResponse, empty: Result<(), Error>) {
+	if let Err(err) = empty {
+		return_error(res, err);
+	}
+}
+
+fn return_server_public_key(req: HttpRequest, mut res: HttpResponse, server_public: Result<Public, Error>) {
+	let server_public = server_public.
+		and_then(|k| serde_json::to_vec(&SerializablePublic(k)).map_err(|e| Error::Serde(e.to_string())));
+	match server_public {
+		Ok(server_public) => {
+			res.headers_mut().set(header::ContentType::json());
+			if let Err(err) = res.send(&server_public) {
+				// nothing to do, but to log an error
+				warn!(target: "secretstore", "response to request {} has failed with: {}", req.uri, err);
+			}
+		},
+		Err(err) => return_error(res, err),
+	}
+}
+
 fn return_document_key(req: HttpRequest, mut res: HttpResponse, document_key: Result<DocumentEncryptedKey, Error>) {
 	let document_key = document_key.
 		and_then(|k| serde_json::to_vec(&SerializableBytes(k)).map_err(|e| Error::Serde(e.to_string())));
@@ -181,18 +303,49 @@ fn return_document_key(req: HttpRequest, mut res: HttpResponse, document_key: Re
 	}
 }
 
+fn return_message_signature(req: HttpRequest, mut res: HttpResponse, signature: Result<EncryptedMessageSignature, Error>) {
+	let signature = signature.
+		and_then(|s| serde_json::to_vec(&SerializableBytes(s)).map_err(|e| Error::Serde(e.to_string())));
+	match signature {
+		Ok(signature) => {
+			res.headers_mut().set(header::ContentType::json());
+			if let Err(err) = res.send(&signature) {
+				// nothing to do, but to log an error
+				warn!(target: "secretstore", "response to request {} has failed with: {}", req.uri, err);
+			}
+		},
+		Err(err) => return_error(res, err),
+	}
+}
+
+fn return_metrics(req: HttpRequest, mut res: HttpResponse, metrics: Result<String, Error>) {
+	match metrics {
+		Ok(metrics) => {
+			res.headers_mut().set(header::ContentType::plaintext());
+			if let Err(err) = res.send(metrics.as_bytes()) {
+				// nothing to do, but to log an error
+				warn!(target: "secretstore", "response to request {} has failed with: {}", req.uri, err);
+			}
+		},
+		Err(err) => return_error(res, err),
+	}
+}
+
 fn return_error(mut res: HttpResponse, err: Error) {
 	match err {
 		Error::BadSignature => *res.status_mut() = HttpStatusCode::BadRequest,
 		Error::AccessDenied => *res.status_mut() = HttpStatusCode::Forbidden,
 		Error::DocumentNotFound => *res.status_mut() = HttpStatusCode::NotFound,
+		Error::TemporarilyUnavailable(_) => *res.status_mut() = HttpStatusCode::ServiceUnavailable,
 		Error::Serde(_) => *res.status_mut() = HttpStatusCode::BadRequest,
 		Error::Database(_) => *res.status_mut() = HttpStatusCode::InternalServerError,
+		Error::DatabaseConflict => *res.status_mut() = HttpStatusCode::InternalServerError,
+		Error::InvalidThreshold(_) => *res.status_mut() = HttpStatusCode::BadRequest,
 		Error::Internal(_) => *res.status_mut() = HttpStatusCode::InternalServerError,
 	}
 }
 
-fn parse_request(method: &HttpMethod, uri_path: &str) -> Request {
+fn parse_request(method: &HttpMethod, uri_path: &str, body: &[u8]) -> Request {
 	let uri_path = match percent_decode(uri_path.as_bytes()).decode_utf8() {
 		Ok(path) => path,
 		Err(_) => return Request::Invalid,
@@ -202,8 +355,18 @@ fn parse_request(method: &HttpMethod, uri_path: &str) -> Request {
 	if path.len() == 0 {
 		return Request::Invalid;
 	}
+	if path.len() == 1 && &path[0] == "metrics" {
+		return match method {
+			&HttpMethod::Get => Request::Metrics,
+			_ => Request::Invalid,
+		};
+	}
 	let (args_prefix, args_offset) = if &path[0] == "shadow" {
 		("shadow", 1)
+	} else if &path[0] == "document" {
+		("document", 1)
+	} else if &path[0] == "schnorr" {
+		("schnorr", 1)
 	} else {
 		("", 0)
 	};
@@ -215,21 +378,103 @@ fn parse_request(method: &HttpMethod, uri_path: &str) -> Request {
 	let args_len = path.len();
 	let document = path[args_offset].parse();
 	let signature = path[args_offset + 1].parse();
-	let threshold = (if args_len > args_offset + 2 { &path[args_offset + 2] } else { "" }).parse();
-	match (args_prefix, args_len, method, document, signature, threshold) {
-		("",		3, &HttpMethod::Post, Ok(document), Ok(signature), Ok(threshold)) => Request::GenerateDocumentKey(document, signature, threshold),
-		("",		2, &HttpMethod::Get, Ok(document), Ok(signature), _) => Request::GetDocumentKey(document, signature),
-		("shadow",	3, &HttpMethod::Get, Ok(document), Ok(signature), _) => Request::GetDocumentKeyShadow(document, signature),
+	let threshold = (if args_len > args_offset + 2 { &path[args_offset + 2] } else { "" }).parse::<usize>();
+	let nonce = (if args_len > args_offset + 2 { &path[args_offset + 2] } else { "" }).parse::<u64>();
+	let message_hash = (if args_len > args_offset + 2 { &path[args_offset + 2] } else { "" }).parse::<MessageHash>();
+	match (args_prefix, args_len, method, document, signature, threshold, nonce) {
+		("",		3, &HttpMethod::Post, Ok(document), Ok(signature), Ok(threshold), _) => Request::GenerateServerKey(document, signature, threshold),
+		("",		2, &HttpMethod::Get, Ok(document), Ok(signature), _, _) => Request::GetDocumentKey(document, signature),
+		("",		3, &HttpMethod::Delete, Ok(document), Ok(signature), _, Ok(nonce)) => Request::RemoveDocumentKey(document, signature, nonce),
+		("shadow",	3, &HttpMethod::Get, Ok(document), Ok(signature), _, _) => Request::GetDocumentKeyShadow(document, signature),
+		("document",	4, &HttpMethod::Post, Ok(document), Ok(signature), Ok(threshold), _) => Request::GenerateDocumentKey(document, signature, threshold),
+		("schnorr",	4, &HttpMethod::Get, Ok(document), Ok(signature), _, _) => match message_hash {
+			Ok(message_hash) => Request::SignMessage(document, signature, message_hash),
+			Err(_) => Request::Invalid,
+		},
+		("shadow",	3, &HttpMethod::Post, Ok(document), Ok(signature), _, _) => match serde_json::from_slice::<StoreDocumentKeyRequest>(body) {
+			Ok(body) => Request::StoreDocumentKey(document, signature, body.common_point.into(), body.encrypted_key.into(), body.nonce),
+			Err(_) => Request::Invalid,
+		},
 		_ => Request::Invalid,
 	}
 }
 
 #[cfg(test)]
 mod tests {
+	use std::io::Read;
+	use hyper::Client as HttpClient;
 	use hyper::method::Method as HttpMethod;
-	use key_server::tests::DummyKeyServer;
-	use types::all::NodeAddress;
-	use super::{parse_request, Request, KeyServerHttpListener};
+	use hyper::status::StatusCode as HttpStatusCode;
+	use serde_json;
+	use ethcrypto;
+	use ethkey::{self, math, Random, Generator};
+	use key_server::tests::{DummyKeyServer, make_key_servers};
+	use serialization::{SerializableBytes, SerializablePublic};
+	use traits::KeyServer;
+	use types::all::{Error, Public, NodeAddress, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+		MessageHash, EncryptedMessageSignature};
+	use key_server_cluster::{removal_request_hash, store_request_hash};
+	use super::{parse_request, Request, StoreDocumentKeyRequest, KeyServerHttpListener};
+
+	/// Key server, which fails every request with given error.
+	struct FailingKeyServer(Error);
+
+	impl KeyServer for FailingKeyServer {
+		fn generate_server_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _threshold: usize) -> Result<Public, Error> {
+			Err(self.0.clone())
+		}
+
+		fn store_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _common_point: &Public, _encrypted_document_key: &Public, _nonce: u64) -> Result<(), Error> {
+			Err(self.0.clone())
+		}
+
+		fn generate_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _threshold: usize) -> Result<DocumentEncryptedKey, Error> {
+			Err(self.0.clone())
+		}
+
+		fn document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress) -> Result<DocumentEncryptedKey, Error> {
+			Err(self.0.clone())
+		}
+
+		fn document_key_shadow(&self, _signature: &RequestSignature, _document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error> {
+			Err(self.0.clone())
+		}
+
+		fn remove_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _nonce: u64) -> Result<(), Error> {
+			Err(self.0.clone())
+		}
+
+		fn sign_message(&self, _signature: &RequestSignature, _document: &DocumentAddress, _message: &MessageHash) -> Result<EncryptedMessageSignature, Error> {
+			Err(self.0.clone())
+		}
+
+		fn metrics(&self) -> Result<String, Error> {
+			Err(self.0.clone())
+		}
+	}
+
+	fn store_request_body(common_point: &Public, encrypted_document_key: &Public, nonce: u64) -> String {
+		serde_json::to_string(&StoreDocumentKeyRequest {
+			common_point: common_point.clone().into(),
+			encrypted_key: encrypted_document_key.clone().into(),
+			nonce: nonce,
+		}).unwrap()
+	}
+
+	fn request(method: HttpMethod, port: u16, path: &str, body: &str) -> (HttpStatusCode, Vec<u8>) {
+		let url = format!("http://127.0.0.1:{}{}", port, path);
+		let client = HttpClient::new();
+		let mut response = match method {
+			HttpMethod::Get => client.get(&url),
+			HttpMethod::Post => client.post(&url).body(body),
+			HttpMethod::Delete => client.delete(&url),
+			_ => unreachable!("only GET, POST and DELETE requests are used in tests"),
+		}.send().unwrap();
+
+		let mut body = Vec::new();
+		response.read_to_end(&mut body).unwrap();
+		(response.status, body)
+	}
 
 	#[test]
 	fn http_listener_successfully_drops() {
@@ -241,19 +486,244 @@ mod tests {
 
 	#[test]
 	fn parse_request_successful() {
-		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001/a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01"),
+		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001/a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01", &[]),
 			Request::GetDocumentKey("0000000000000000000000000000000000000000000000000000000000000001".into(),
 				"a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01".parse().unwrap()));
-		assert_eq!(parse_request(&HttpMethod::Get, "/%30000000000000000000000000000000000000000000000000000000000000001/a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01"),
+		assert_eq!(parse_request(&HttpMethod::Get, "/%30000000000000000000000000000000000000000000000000000000000000001/a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01", &[]),
 			Request::GetDocumentKey("0000000000000000000000000000000000000000000000000000000000000001".into(),
 				"a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01".parse().unwrap()));
 	}
 
+	#[test]
+	fn parse_server_key_requests_successful() {
+		let document: DocumentAddress = "0000000000000000000000000000000000000000000000000000000000000001".into();
+		let signature: RequestSignature = "a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01".parse().unwrap();
+		let common_point = Random.generate().unwrap().public().clone();
+		let encrypted_document_key = Random.generate().unwrap().public().clone();
+
+		let store_body = store_request_body(&common_point, &encrypted_document_key, 1);
+
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/{:?}/{}/2", document, signature), &[]),
+			Request::GenerateServerKey(document.clone(), signature.clone(), 2));
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/document/{:?}/{}/2", document, signature), &[]),
+			Request::GenerateDocumentKey(document.clone(), signature.clone(), 2));
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/shadow/{:?}/{}", document, signature), store_body.as_bytes()),
+			Request::StoreDocumentKey(document.clone(), signature.clone(), common_point.clone(), encrypted_document_key.clone(), 1));
+		assert_eq!(parse_request(&HttpMethod::Get, &format!("/shadow/{:?}/{}", document, signature), &[]),
+			Request::GetDocumentKeyShadow(document.clone(), signature.clone()));
+
+		// invalid threshold
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/{:?}/{}/threshold", document, signature), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/document/{:?}/{}/threshold", document, signature), &[]), Request::Invalid);
+		// document key is only generated by POST request with threshold
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/document/{:?}/{}", document, signature), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, &format!("/document/{:?}/{}/2", document, signature), &[]), Request::Invalid);
+		// points are passed in the body only
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/shadow/{:?}/{}/{:?}/{:?}", document, signature, common_point, encrypted_document_key), &[]), Request::Invalid);
+		// invalid body
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/shadow/{:?}/{}", document, signature), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/shadow/{:?}/{}", document, signature), b"{\"common_point\":\"0x01\"}"), Request::Invalid);
+	}
+
+	#[test]
+	fn parse_remove_document_key_request_successful() {
+		let document: DocumentAddress = "0000000000000000000000000000000000000000000000000000000000000001".into();
+		let signature: RequestSignature = "a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01".parse().unwrap();
+
+		assert_eq!(parse_request(&HttpMethod::Delete, &format!("/{:?}/{}/5", document, signature), &[]),
+			Request::RemoveDocumentKey(document.clone(), signature.clone(), 5));
+
+		// key removal requires exactly document, signature and nonce
+		assert_eq!(parse_request(&HttpMethod::Delete, &format!("/{:?}/{}", document, signature), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Delete, &format!("/{:?}/{}/5/6", document, signature), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Delete, &format!("/{:?}/{}/nonce", document, signature), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Delete, &format!("/shadow/{:?}/{}", document, signature), &[]), Request::Invalid);
+	}
+
+	#[test]
+	fn parse_sign_message_request_successful() {
+		let document: DocumentAddress = "0000000000000000000000000000000000000000000000000000000000000001".into();
+		let signature: RequestSignature = "a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01".parse().unwrap();
+		let message_hash: MessageHash = "0000000000000000000000000000000000000000000000000000000000000002".into();
+
+		assert_eq!(parse_request(&HttpMethod::Get, &format!("/schnorr/{:?}/{}/{:?}", document, signature, message_hash), &[]),
+			Request::SignMessage(document.clone(), signature.clone(), message_hash.clone()));
+
+		// message hash must be 32 bytes long
+		assert_eq!(parse_request(&HttpMethod::Get, &format!("/schnorr/{:?}/{}/{}", document, signature, "deadbeef"), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, &format!("/schnorr/{:?}/{}/{:?}00", document, signature, message_hash), &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, &format!("/schnorr/{:?}/{}", document, signature), &[]), Request::Invalid);
+		// only GET requests are accepted
+		assert_eq!(parse_request(&HttpMethod::Post, &format!("/schnorr/{:?}/{}/{:?}", document, signature, message_hash), &[]), Request::Invalid);
+	}
+
+	#[test]
+	fn parse_metrics_request_successful() {
+		assert_eq!(parse_request(&HttpMethod::Get, "/metrics", &[]), Request::Metrics);
+		assert_eq!(parse_request(&HttpMethod::Post, "/metrics", &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, "/metrics/1", &[]), Request::Invalid);
+	}
+
+	#[test]
+	fn http_listener_serves_every_endpoint() {
+		let key_server = make_key_servers(6090, 1).pop().unwrap();
+		let address = NodeAddress { address: "127.0.0.1".into(), port: 9010 };
+		let _listener = KeyServerHttpListener::start(&address, key_server).unwrap();
+
+		let requestor = Random.generate().unwrap();
+		let document = DocumentAddress::random();
+		let signature = ethkey::sign(requestor.secret(), &document).unwrap();
+
+		// generate server key
+		let (status, body) = request(HttpMethod::Post, 9010, &format!("/{:?}/{}/0", document, signature), "");
+		assert_eq!(status, HttpStatusCode::Ok);
+		let server_public: SerializablePublic = serde_json::from_slice(&body).unwrap();
+
+		// encrypt document key with server key && store it
+		let document_key = Random.generate().unwrap().public().clone();
+		let encryption_key = Random.generate().unwrap();
+		let common_point = encryption_key.public().clone();
+		let mut encrypted_document_key = server_public.0.clone();
+		math::public_mul_secret(&mut encrypted_document_key, encryption_key.secret()).unwrap();
+		math::public_add(&mut encrypted_document_key, &document_key).unwrap();
+		let store_body = store_request_body(&common_point, &encrypted_document_key, 1);
+		let store_signature = ethkey::sign(requestor.secret(), &store_request_hash(&document, &common_point, &encrypted_document_key, 1)).unwrap();
+		let (status, _) = request(HttpMethod::Post, 9010, &format!("/shadow/{:?}/{}", document, store_signature), &store_body);
+		assert_eq!(status, HttpStatusCode::Ok);
+
+		// retrieve document key
+		let (status, body) = request(HttpMethod::Get, 9010, &format!("/{:?}/{}", document, signature), "");
+		assert_eq!(status, HttpStatusCode::Ok);
+		let retrieved_key: SerializableBytes = serde_json::from_slice(&body).unwrap();
+		let retrieved_key = ethcrypto::ecies::decrypt(requestor.secret(), &ethcrypto::DEFAULT_MAC, &retrieved_key).unwrap();
+		assert_eq!(retrieved_key, document_key.to_vec());
+
+		// retrieve document key shadow
+		let (status, body) = request(HttpMethod::Get, 9010, &format!("/shadow/{:?}/{}", document, signature), "");
+		assert_eq!(status, HttpStatusCode::Ok);
+		let shadow: serde_json::Value = serde_json::from_slice(&body).unwrap();
+		let shadow = shadow.as_object().unwrap();
+		assert!(shadow.contains_key("decrypted_secret"));
+		assert!(shadow.contains_key("common_point"));
+		assert!(shadow.contains_key("decrypt_shadows"));
+
+		// generate server key && document key
+		let other_document = DocumentAddress::random();
+		let other_document_signature = ethkey::sign(requestor.secret(), &other_document).unwrap();
+		let (status, body) = request(HttpMethod::Post, 9010, &format!("/document/{:?}/{}/0", other_document, other_document_signature), "");
+		assert_eq!(status, HttpStatusCode::Ok);
+		let generated_key: SerializableBytes = serde_json::from_slice(&body).unwrap();
+		let generated_key = ethcrypto::ecies::decrypt(requestor.secret(), &ethcrypto::DEFAULT_MAC, &generated_key).unwrap();
+		assert_eq!(generated_key.len(), 64);
+
+		// sign message hash
+		let message_hash = MessageHash::random();
+		let (status, body) = request(HttpMethod::Get, 9010, &format!("/schnorr/{:?}/{}/{:?}", document, signature, message_hash), "");
+		assert_eq!(status, HttpStatusCode::Ok);
+		let message_signature: SerializableBytes = serde_json::from_slice(&body).unwrap();
+		let message_signature = ethcrypto::ecies::decrypt(requestor.secret(), &ethcrypto::DEFAULT_MAC, &message_signature).unwrap();
+		assert_eq!(message_signature.len(), 64);
+
+		// malformed message hash
+		let (status, _) = request(HttpMethod::Get, 9010, &format!("/schnorr/{:?}/{}/{}", document, signature, "deadbeef"), "");
+		assert_eq!(status, HttpStatusCode::BadRequest);
+
+		// message could only be signed with existing server key
+		let unknown_document = DocumentAddress::random();
+		let unknown_signature = ethkey::sign(requestor.secret(), &unknown_document).unwrap();
+		let (status, _) = request(HttpMethod::Get, 9010, &format!("/schnorr/{:?}/{}/{:?}", unknown_document, unknown_signature, message_hash), "");
+		assert_eq!(status, HttpStatusCode::NotFound);
+
+		// only author of the server key could store document key
+		let other_requestor = Random.generate().unwrap();
+		let other_body = store_request_body(&common_point, &encrypted_document_key, 2);
+		let other_signature = ethkey::sign(other_requestor.secret(), &store_request_hash(&document, &common_point, &encrypted_document_key, 2)).unwrap();
+		let (status, _) = request(HttpMethod::Post, 9010, &format!("/shadow/{:?}/{}", document, other_signature), &other_body);
+		assert_eq!(status, HttpStatusCode::Forbidden);
+
+		// signature of the retrieval request could not be used to replace document key
+		let (status, _) = request(HttpMethod::Post, 9010, &format!("/shadow/{:?}/{}", document, signature), &other_body);
+		assert_eq!(status, HttpStatusCode::Forbidden);
+
+		// store request could not be replayed
+		let (status, _) = request(HttpMethod::Post, 9010, &format!("/shadow/{:?}/{}", document, store_signature), &store_body);
+		assert_eq!(status, HttpStatusCode::Forbidden);
+
+		// server key must be generated before document key is stored
+		let unknown_document = DocumentAddress::random();
+		let unknown_signature = ethkey::sign(requestor.secret(), &store_request_hash(&unknown_document, &common_point, &encrypted_document_key, 1)).unwrap();
+		let (status, _) = request(HttpMethod::Post, 9010, &format!("/shadow/{:?}/{}", unknown_document, unknown_signature), &store_body);
+		assert_eq!(status, HttpStatusCode::NotFound);
+
+		// malformed signature
+		let (status, _) = request(HttpMethod::Post, 9010, &format!("/{:?}/{}/0", document, "deadbeef"), "");
+		assert_eq!(status, HttpStatusCode::BadRequest);
+
+		// only author of the server key could remove it
+		let other_removal_signature = ethkey::sign(other_requestor.secret(), &removal_request_hash(&document, 1)).unwrap();
+		let (status, _) = request(HttpMethod::Delete, 9010, &format!("/{:?}/{}/1", document, other_removal_signature), "");
+		assert_eq!(status, HttpStatusCode::Forbidden);
+
+		// remove document key
+		let removal_signature = ethkey::sign(requestor.secret(), &removal_request_hash(&document, 1)).unwrap();
+		let (status, _) = request(HttpMethod::Delete, 9010, &format!("/{:?}/{}/1", document, removal_signature), "");
+		assert_eq!(status, HttpStatusCode::Ok);
+
+		// removed key is not found anymore
+		let removal_signature = ethkey::sign(requestor.secret(), &removal_request_hash(&document, 2)).unwrap();
+		let (status, _) = request(HttpMethod::Delete, 9010, &format!("/{:?}/{}/2", document, removal_signature), "");
+		assert_eq!(status, HttpStatusCode::NotFound);
+
+		// metrics are collected for every completed session
+		let (status, body) = request(HttpMethod::Get, 9010, "/metrics", "");
+		assert_eq!(status, HttpStatusCode::Ok);
+		let metrics = String::from_utf8(body).unwrap();
+		assert!(metrics.contains("secretstore_sessions_started_total{type=\"generation\"} 2\n"));
+		assert!(metrics.contains("secretstore_sessions_completed_total{type=\"generation\"} 2\n"));
+		assert!(metrics.contains("secretstore_sessions_completed_total{type=\"decryption\"} 2\n"));
+		assert!(metrics.contains("secretstore_sessions_failed_total{type=\"decryption\"} 0\n"));
+		assert!(metrics.contains("secretstore_sessions_completed_total{type=\"key_removal\"} 1\n"));
+		assert!(metrics.contains("secretstore_sessions_completed_total{type=\"signing\"} 1\n"));
+		assert!(metrics.contains("secretstore_session_duration_seconds_bucket{type=\"decryption\",le=\"+Inf\"} 2\n"));
+		assert!(metrics.contains("secretstore_session_duration_seconds_count{type=\"decryption\"} 2\n"));
+		assert!(metrics.contains("secretstore_active_sessions 0\n"));
+	}
+
+	#[test]
+	fn http_listener_maps_errors_to_status_codes() {
+		let test_cases = vec![
+			(Error::BadSignature, HttpStatusCode::BadRequest),
+			(Error::AccessDenied, HttpStatusCode::Forbidden),
+			(Error::InvalidThreshold("invalid threshold".into()), HttpStatusCode::BadRequest),
+			(Error::DocumentNotFound, HttpStatusCode::NotFound),
+			(Error::TemporarilyUnavailable("consensus is unreachable".into()), HttpStatusCode::ServiceUnavailable),
+			(Error::Internal("internal".into()), HttpStatusCode::InternalServerError),
+		];
+
+		let document = DocumentAddress::random();
+		let signature = ethkey::sign(Random.generate().unwrap().secret(), &document).unwrap();
+		let point = Random.generate().unwrap().public().clone();
+		for (i, (error, expected_status)) in test_cases.into_iter().enumerate() {
+			let port = 9020 + i as u16;
+			let address = NodeAddress { address: "127.0.0.1".into(), port: port };
+			let _listener = KeyServerHttpListener::start(&address, FailingKeyServer(error)).unwrap();
+
+			assert_eq!(request(HttpMethod::Post, port, &format!("/{:?}/{}/0", document, signature), "").0, expected_status);
+			assert_eq!(request(HttpMethod::Post, port, &format!("/shadow/{:?}/{}", document, signature), &store_request_body(&point, &point, 1)).0, expected_status);
+			assert_eq!(request(HttpMethod::Post, port, &format!("/document/{:?}/{}/0", document, signature), "").0, expected_status);
+			assert_eq!(request(HttpMethod::Get, port, &format!("/{:?}/{}", document, signature), "").0, expected_status);
+			assert_eq!(request(HttpMethod::Get, port, &format!("/shadow/{:?}/{}", document, signature), "").0, expected_status);
+			assert_eq!(request(HttpMethod::Delete, port, &format!("/{:?}/{}/1", document, signature), "").0, expected_status);
+			assert_eq!(request(HttpMethod::Get, port, &format!("/schnorr/{:?}/{}/{:?}", document, signature, document), "").0, expected_status);
+			assert_eq!(request(HttpMethod::Get, port, "/metrics", "").0, expected_status);
+		}
+	}
+
 	#[test]
 	fn parse_request_failed() {
-		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001"), Request::Invalid);
-		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001/"), Request::Invalid);
-		assert_eq!(parse_request(&HttpMethod::Get, "/a/b"), Request::Invalid);
-		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001/a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01/0000000000000000000000000000000000000000000000000000000000000002"), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001", &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001/", &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, "/a/b", &[]), Request::Invalid);
+		assert_eq!(parse_request(&HttpMethod::Get, "/0000000000000000000000000000000000000000000000000000000000000001/a199fb39e11eefb61c78a4074a53c0d4424600a3e74aad4fb9d93a26c30d067e1d4d29936de0c73f19827394a1dd049480a0d581aee7ae7546968da7d3d1c2fd01/0000000000000000000000000000000000000000000000000000000000000002", &[]), Request::Invalid);
 	}
 }
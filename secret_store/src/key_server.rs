@@ -15,6 +15,7 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::thread;
+use std::time;
 use std::sync::Arc;
 use std::sync::mpsc;
 use futures::{self, Future};
@@ -24,10 +25,13 @@ use ethcrypto;
 use ethkey;
 use super::acl_storage::AclStorage;
 use super::key_storage::KeyStorage;
+use super::key_server_set::KeyServerSet;
 use key_server_cluster::ClusterCore;
 use traits::KeyServer;
-use types::all::{Error, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow, ClusterConfiguration};
-use key_server_cluster::{ClusterClient, ClusterConfiguration as NetClusterConfiguration};
+use types::all::{Error, Public, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+	MessageHash, EncryptedMessageSignature, ClusterConfiguration};
+use key_server_cluster::{ClusterClient, ClusterConfiguration as NetClusterConfiguration, SessionsTimeouts, NodeReputationParams, MAINTAIN_INTERVAL,
+	COMPLETED_SESSIONS_RETENTION_INTERVAL, removal_request_hash, store_request_hash};
 
 /// Secret store key server implementation
 pub struct KeyServerImpl {
@@ -43,9 +47,9 @@ pub struct KeyServerCore {
 
 impl KeyServerImpl {
 	/// Create new key server instance
-	pub fn new(config: &ClusterConfiguration, acl_storage: Arc<AclStorage>, key_storage: Arc<KeyStorage>) -> Result<Self, Error> {
+	pub fn new(config: &ClusterConfiguration, key_server_set: Option<Arc<KeyServerSet>>, acl_storage: Arc<AclStorage>, key_storage: Arc<KeyStorage>) -> Result<Self, Error> {
 		Ok(KeyServerImpl {
-			data: Arc::new(Mutex::new(KeyServerCore::new(config, acl_storage, key_storage)?)),
+			data: Arc::new(Mutex::new(KeyServerCore::new(config, key_server_set, acl_storage, key_storage)?)),
 		})
 	}
 
@@ -57,14 +61,34 @@ impl KeyServerImpl {
 }
 
 impl KeyServer for KeyServerImpl {
+	fn generate_server_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<Public, Error> {
+		// recover requestor' public key from signature
+		let public = ethkey::recover(signature, document)
+			.map_err(|_| Error::BadSignature)?;
+
+		// generate server key
+		let generation_result = self.data.lock().cluster.generate_key(document.clone(), public, threshold);
+		generation_result.wait().map_err(Into::into)
+	}
+
+	fn store_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, common_point: &Public, encrypted_document_key: &Public, nonce: u64) -> Result<(), Error> {
+		// check that requestor' signature is valid. Access is checked by the encryption session
+		ethkey::recover(signature, &store_request_hash(document, common_point, encrypted_document_key, nonce))
+			.map_err(|_| Error::BadSignature)?;
+
+		// store encrypted document key
+		let encryption_result = self.data.lock().cluster.store_document_key(document.clone(), signature.clone(), common_point.clone(), encrypted_document_key.clone(), nonce);
+		encryption_result.wait().map_err(Into::into)
+	}
+
 	fn generate_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<DocumentEncryptedKey, Error> {
 		// recover requestor' public key from signature
 		let public = ethkey::recover(signature, document)
 			.map_err(|_| Error::BadSignature)?;
 
 		// generate document key
-		let encryption_session = self.data.lock().cluster.new_encryption_session(document.clone(), threshold)?;
-		let document_key = encryption_session.wait(None)?;
+		let generation_result = self.data.lock().cluster.generate_key(document.clone(), public.clone(), threshold);
+		let document_key = generation_result.wait()?;
 
 		// encrypt document key with requestor public key
 		let document_key = ethcrypto::ecies::encrypt(&public, &ethcrypto::DEFAULT_MAC, &document_key)
@@ -79,8 +103,8 @@ impl KeyServer for KeyServerImpl {
 
 
 		// decrypt document key
-		let decryption_session = self.data.lock().cluster.new_decryption_session(document.clone(), signature.clone(), false)?;
-		let document_key = decryption_session.wait()?.decrypted_secret;
+		let decryption_result = self.data.lock().cluster.retrieve_document_key(document.clone(), signature.clone().into(), false);
+		let document_key = decryption_result.wait()?.decrypted_secret;
 
 		// encrypt document key with requestor public key
 		let document_key = ethcrypto::ecies::encrypt(&public, &ethcrypto::DEFAULT_MAC, &document_key)
@@ -89,13 +113,45 @@ impl KeyServer for KeyServerImpl {
 	}
 
 	fn document_key_shadow(&self, signature: &RequestSignature, document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error> {
-		let decryption_session = self.data.lock().cluster.new_decryption_session(document.clone(), signature.clone(), true)?;
-		decryption_session.wait().map_err(Into::into)
+		let decryption_result = self.data.lock().cluster.retrieve_document_key(document.clone(), signature.clone().into(), true);
+		decryption_result.wait().map_err(Into::into)
+	}
+
+	fn remove_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		// check that requestor' signature is valid. Access is checked by the key removal session
+		ethkey::recover(signature, &removal_request_hash(document, nonce))
+			.map_err(|_| Error::BadSignature)?;
+
+		// remove key from all key holders
+		let removal_result = self.data.lock().cluster.remove_key(document.clone(), signature.clone(), nonce);
+		removal_result.wait().map(|_| ()).map_err(Into::into)
+	}
+
+	fn sign_message(&self, signature: &RequestSignature, document: &DocumentAddress, message: &MessageHash) -> Result<EncryptedMessageSignature, Error> {
+		// recover requestor' public key from signature
+		let public = ethkey::recover(signature, document)
+			.map_err(|_| Error::BadSignature)?;
+
+		// sign message
+		let signing_result = self.data.lock().cluster.sign_message(document.clone(), signature.clone().into(), message.clone());
+		let (signature_hash, signature) = signing_result.wait()?;
+
+		// encrypt combined signature with requestor public key
+		let mut combined_signature = [0; 64];
+		combined_signature[..32].clone_from_slice(&**signature_hash);
+		combined_signature[32..].clone_from_slice(&**signature);
+		let combined_signature = ethcrypto::ecies::encrypt(&public, &ethcrypto::DEFAULT_MAC, &combined_signature)
+			.map_err(|err| Error::Internal(format!("Error encrypting message signature: {}", err)))?;
+		Ok(combined_signature)
+	}
+
+	fn metrics(&self) -> Result<String, Error> {
+		Ok(self.data.lock().cluster.metrics())
 	}
 }
 
 impl KeyServerCore {
-	pub fn new(config: &ClusterConfiguration, acl_storage: Arc<AclStorage>, key_storage: Arc<KeyStorage>) -> Result<Self, Error> {
+	pub fn new(config: &ClusterConfiguration, key_server_set: Option<Arc<KeyServerSet>>, acl_storage: Arc<AclStorage>, key_storage: Arc<KeyStorage>) -> Result<Self, Error> {
 		let config = NetClusterConfiguration {
 			threads: config.threads,
 			self_key_pair: ethkey::KeyPair::from_secret_slice(&config.self_private)?,
@@ -103,7 +159,15 @@ impl KeyServerCore {
 			nodes: config.nodes.iter()
 				.map(|(node_id, node_address)| (node_id.clone(), (node_address.address.clone(), node_address.port)))
 				.collect(),
+			key_server_set: key_server_set,
 			allow_connecting_to_higher_nodes: config.allow_connecting_to_higher_nodes,
+			admin_public: config.admin_public.clone(),
+			max_active_key_migrations: config.max_active_key_migrations,
+			wipe_removed_key_shares: config.wipe_removed_key_shares,
+			maintain_interval: time::Duration::from_secs(MAINTAIN_INTERVAL),
+			completed_sessions_retention: time::Duration::from_secs(COMPLETED_SESSIONS_RETENTION_INTERVAL),
+			sessions_timeouts: SessionsTimeouts::default(),
+			node_reputation: NodeReputationParams::default(),
 			acl_storage: acl_storage,
 			key_storage: key_storage,
 		};
@@ -149,12 +213,23 @@ pub mod tests {
 	use ethkey::{self, Random, Generator};
 	use acl_storage::tests::DummyAclStorage;
 	use key_storage::tests::DummyKeyStorage;
-	use types::all::{Error, ClusterConfiguration, NodeAddress, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow};
+	use util::H256;
+	use key_server_cluster::verify_schnorr_signature;
+	use types::all::{Error, Public, ClusterConfiguration, NodeAddress, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+		MessageHash, EncryptedMessageSignature};
 	use super::{KeyServer, KeyServerImpl};
 
 	pub struct DummyKeyServer;
 
 	impl KeyServer for DummyKeyServer {
+		fn generate_server_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _threshold: usize) -> Result<Public, Error> {
+			unimplemented!()
+		}
+
+		fn store_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _common_point: &Public, _encrypted_document_key: &Public, _nonce: u64) -> Result<(), Error> {
+			unimplemented!()
+		}
+
 		fn generate_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _threshold: usize) -> Result<DocumentEncryptedKey, Error> {
 			unimplemented!()
 		}
@@ -166,9 +241,21 @@ pub mod tests {
 		fn document_key_shadow(&self, _signature: &RequestSignature, _document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error> {
 			unimplemented!()
 		}
+
+		fn remove_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _nonce: u64) -> Result<(), Error> {
+			unimplemented!()
+		}
+
+		fn sign_message(&self, _signature: &RequestSignature, _document: &DocumentAddress, _message: &MessageHash) -> Result<EncryptedMessageSignature, Error> {
+			unimplemented!()
+		}
+
+		fn metrics(&self) -> Result<String, Error> {
+			unimplemented!()
+		}
 	}
 
-	fn make_key_servers(start_port: u16, num_nodes: usize) -> Vec<KeyServerImpl> {
+	pub fn make_key_servers(start_port: u16, num_nodes: usize) -> Vec<KeyServerImpl> {
 		let key_pairs: Vec<_> = (0..num_nodes).map(|_| Random.generate().unwrap()).collect();
 		let configs: Vec<_> = (0..num_nodes).map(|i| ClusterConfiguration {
 				threads: 1,
@@ -183,9 +270,12 @@ pub mod tests {
 						port: start_port + (j as u16),
 					})).collect(),
 				allow_connecting_to_higher_nodes: false,
+				admin_public: None,
+				max_active_key_migrations: 4,
+				wipe_removed_key_shares: false,
 			}).collect();
 		let key_servers: Vec<_> = configs.into_iter().map(|cfg|
-			KeyServerImpl::new(&cfg, Arc::new(DummyAclStorage::default()), Arc::new(DummyKeyStorage::default())).unwrap()
+			KeyServerImpl::new(&cfg, None, Arc::new(DummyAclStorage::default()), Arc::new(DummyKeyStorage::default())).unwrap()
 		).collect();
 
 		// wait until connections are established. It is fast => do not bother with events here
@@ -261,4 +351,28 @@ pub mod tests {
 			}
 		}
 	}
+	#[test]
+	fn schnorr_signing_works_over_network_with_4_nodes() {
+		//::logger::init_log();
+		let key_servers = make_key_servers(6091, 4);
+
+		let test_cases = [0, 1, 2, 3];
+		for threshold in &test_cases {
+			// generate server key
+			let server_key_id = Random.generate().unwrap().secret().clone();
+			let requestor_secret = Random.generate().unwrap().secret().clone();
+			let signature = ethkey::sign(&requestor_secret, &server_key_id).unwrap();
+			let server_public = key_servers[0].generate_server_key(&signature, &server_key_id, *threshold).unwrap();
+
+			// sign message
+			let message_hash = H256::random();
+			let combined_signature = key_servers[0].sign_message(&signature, &server_key_id, &message_hash).unwrap();
+			let combined_signature = ethcrypto::ecies::decrypt(&requestor_secret, &ethcrypto::DEFAULT_MAC, &combined_signature).unwrap();
+			let signature_c = ethkey::Secret::from_slice(&combined_signature[..32]);
+			let signature_s = ethkey::Secret::from_slice(&combined_signature[32..]);
+
+			// check signature
+			assert_eq!(verify_schnorr_signature(&server_public, &(signature_c, signature_s), &message_hash), Ok(true));
+		}
+	}
 }
@@ -0,0 +1,110 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp;
+use std::time::Duration;
+
+/// Decides how long to wait before next retry of failed action.
+pub trait BackoffPolicy {
+	/// Delay before given attempt (attempts are numbered from 1). None if no more retries are allowed.
+	fn next_delay(&mut self, attempt: u32) -> Option<Duration>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Delay is doubled after every failed attempt, until it reaches maximal value.
+pub struct ExponentialBackoff {
+	/// Delay before the first attempt.
+	pub base: Duration,
+	/// Maximal delay between attempts.
+	pub max: Duration,
+	/// Maximal number of attempts.
+	pub max_retries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The same delay is used for every attempt.
+pub struct ConstantBackoff {
+	/// Delay between attempts.
+	pub delay: Duration,
+	/// Maximal number of attempts.
+	pub max_retries: u32,
+}
+
+impl BackoffPolicy for ExponentialBackoff {
+	fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+		if attempt > self.max_retries {
+			return None;
+		}
+
+		Some(1u32.checked_shl(attempt.saturating_sub(1))
+			.and_then(|multiplier| self.base.checked_mul(multiplier))
+			.map(|delay| cmp::min(delay, self.max))
+			.unwrap_or(self.max))
+	}
+}
+
+impl BackoffPolicy for ConstantBackoff {
+	fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+		if attempt > self.max_retries {
+			return None;
+		}
+
+		Some(self.delay)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use super::{BackoffPolicy, ExponentialBackoff, ConstantBackoff};
+
+	#[test]
+	fn exponential_backoff_doubles_delay_until_retries_are_exhausted() {
+		let mut backoff = ExponentialBackoff {
+			base: Duration::from_secs(1),
+			max: Duration::from_secs(60),
+			max_retries: 4,
+		};
+		assert_eq!(backoff.next_delay(1), Some(Duration::from_secs(1)));
+		assert_eq!(backoff.next_delay(2), Some(Duration::from_secs(2)));
+		assert_eq!(backoff.next_delay(3), Some(Duration::from_secs(4)));
+		assert_eq!(backoff.next_delay(4), Some(Duration::from_secs(8)));
+		assert_eq!(backoff.next_delay(5), None);
+	}
+
+	#[test]
+	fn exponential_backoff_is_limited_by_maximal_delay() {
+		let mut backoff = ExponentialBackoff {
+			base: Duration::from_secs(10),
+			max: Duration::from_secs(60),
+			max_retries: ::std::u32::MAX,
+		};
+		assert_eq!(backoff.next_delay(3), Some(Duration::from_secs(40)));
+		assert_eq!(backoff.next_delay(4), Some(Duration::from_secs(60)));
+		assert_eq!(backoff.next_delay(100), Some(Duration::from_secs(60)));
+	}
+
+	#[test]
+	fn constant_backoff_returns_same_delay_until_retries_are_exhausted() {
+		let mut backoff = ConstantBackoff {
+			delay: Duration::from_secs(5),
+			max_retries: 2,
+		};
+		assert_eq!(backoff.next_delay(1), Some(Duration::from_secs(5)));
+		assert_eq!(backoff.next_delay(2), Some(Duration::from_secs(5)));
+		assert_eq!(backoff.next_delay(3), None);
+	}
+}
@@ -15,6 +15,7 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::io;
+use std::fmt;
 use std::time;
 use std::sync::{Arc, Weak};
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -28,20 +29,45 @@ use tokio_io::IoFuture;
 use tokio_core::reactor::{Handle, Remote, Interval};
 use tokio_core::net::{TcpListener, TcpStream};
 use ethkey::{Public, Secret, KeyPair, Signature, Random, Generator};
-use key_server_cluster::{Error, NodeId, SessionId, AclStorage, KeyStorage, DocumentEncryptedKeyShadow};
-use key_server_cluster::message::{self, Message, ClusterMessage, EncryptionMessage, DecryptionMessage};
+use util::H256;
+use types::all::Error as KeyStorageError;
+use key_server_cluster::{Error, NodeId, SessionId, Requester, AclStorage, KeyStorage, KeyServerSet, KeyServerSetMigration, DocumentEncryptedKeyShadow,
+	KeyRemovalRetry};
+use key_server_cluster::message::{self, Message, ClusterMessage, GenerationMessage, EncryptionMessage, DecryptionMessage,
+	ShareAddMessage, ServersSetChangeMessage, KeyRemovalMessage, SigningMessage};
+use key_server_cluster::connection_manager::{ConnectionManager, is_preferred_connection};
+use key_server_cluster::connection_trigger::{ConnectionTrigger, ConnectionsChange};
+use key_server_cluster::completed_sessions::CompletedSessions;
+use key_server_cluster::message_queue::SessionMessageQueue;
+use key_server_cluster::sessions_queue::SessionsQueue;
+use key_server_cluster::session_result::SessionResultFuture;
+use key_server_cluster::metrics::{ClusterMetrics, SessionType};
+use key_server_cluster::session_trace::{SessionSpan, NET_LOG_TARGET};
+use key_server_cluster::node_reputation::{NodeReputation, NodeReputationParams};
 use key_server_cluster::decryption_session::{SessionImpl as DecryptionSessionImpl, SessionState as DecryptionSessionState,
 	SessionParams as DecryptionSessionParams, Session as DecryptionSession, DecryptionSessionId};
+use key_server_cluster::generation_session::{SessionImpl as GenerationSessionImpl, SessionState as GenerationSessionState,
+	SessionParams as GenerationSessionParams, Session as GenerationSession, check_threshold};
 use key_server_cluster::encryption_session::{SessionImpl as EncryptionSessionImpl, SessionState as EncryptionSessionState,
 	SessionParams as EncryptionSessionParams, Session as EncryptionSession};
+use key_server_cluster::share_add_session::{SessionImpl as ShareAddSessionImpl, SessionState as ShareAddSessionState,
+	SessionParams as ShareAddSessionParams, Session as ShareAddSession};
+use key_server_cluster::servers_set_change_session::{self, SessionImpl as ServersSetChangeSessionImpl,
+	SessionState as ServersSetChangeSessionState, SessionParams as ServersSetChangeSessionParams,
+	Session as ServersSetChangeSession, KeyMigrationState, ShareAddSessionsExecutor, ServersSetChange};
+use key_server_cluster::key_removal_session::{SessionImpl as KeyRemovalSessionImpl, SessionState as KeyRemovalSessionState,
+	SessionParams as KeyRemovalSessionParams, Session as KeyRemovalSession};
+use key_server_cluster::signing_session::{SessionImpl as SigningSessionImpl, SessionState as SigningSessionState,
+	SessionParams as SigningSessionParams, SigningSessionId};
 use key_server_cluster::io::{DeadlineStatus, ReadMessage, SharedTcpStream, read_encrypted_message, WriteMessage, write_encrypted_message};
 use key_server_cluster::net::{accept_connection as net_accept_connection, connect as net_connect, Connection as NetConnection};
 
-/// Maintain interval (seconds). Every MAINTAIN_INTERVAL seconds node:
+/// Default maintain interval (seconds). Every maintain interval (see ClusterConfiguration::maintain_interval) node:
 /// 1) checks if connected nodes are responding to KeepAlive messages
 /// 2) tries to connect to disconnected nodes
 /// 3) checks if enc/dec sessions are time-outed
-const MAINTAIN_INTERVAL: u64 = 10;
+/// 4) retries key removal on key holders, which have been unreachable during key removal sessions
+pub const MAINTAIN_INTERVAL: u64 = 10;
 
 /// When no messages have been received from node within KEEP_ALIVE_SEND_INTERVAL seconds,
 /// we must send KeepAlive message to the node to check if it still responds to messages.
@@ -50,6 +76,17 @@ const KEEP_ALIVE_SEND_INTERVAL: u64 = 30;
 /// we must treat this node as non-responding && disconnect from it.
 const KEEP_ALIVE_DISCONNECT_INTERVAL: u64 = 60;
 
+/// When connection attempt to the node fails, next attempt is made after MIN_RECONNECT_BACKOFF seconds.
+/// Every subsequent failure doubles this delay, until it reaches MAX_RECONNECT_BACKOFF seconds.
+const MIN_RECONNECT_BACKOFF: u64 = MAINTAIN_INTERVAL;
+const MAX_RECONNECT_BACKOFF: u64 = 600;
+
+/// When there are no generation session-related messages for GENERATION_SESSION_TIMEOUT_INTERVAL seconds,
+/// we must treat this session as stalled && finish it with an error.
+/// This timeout is for cases when node is responding to KeepAlive messages, but intentionally ignores
+/// session messages.
+const GENERATION_SESSION_TIMEOUT_INTERVAL: u64 = 60;
+
 /// When there are no encryption session-related messages for ENCRYPTION_SESSION_TIMEOUT_INTERVAL seconds,
 /// we must treat this session as stalled && finish it with an error.
 /// This timeout is for cases when node is responding to KeepAlive messages, but intentionally ignores
@@ -62,7 +99,50 @@ const ENCRYPTION_SESSION_TIMEOUT_INTERVAL: u64 = 60;
 /// session messages.
 const DECRYPTION_SESSION_TIMEOUT_INTERVAL: u64 = 60;
 
-/// Encryption sesion timeout interval. It works
+/// When there are no share add session-related messages for SHARE_ADD_SESSION_TIMEOUT_INTERVAL seconds,
+/// we must treat this session as stalled && finish it with an error.
+/// This timeout is for cases when node is responding to KeepAlive messages, but intentionally ignores
+/// session messages.
+const SHARE_ADD_SESSION_TIMEOUT_INTERVAL: u64 = 60;
+
+/// When there are no servers set change session-related messages (or share add sessions completions) for
+/// SERVERS_SET_CHANGE_SESSION_TIMEOUT_INTERVAL seconds, we must treat this session as stalled && finish it with an error.
+const SERVERS_SET_CHANGE_SESSION_TIMEOUT_INTERVAL: u64 = 60;
+
+/// When there are no key removal session-related messages for KEY_REMOVAL_SESSION_TIMEOUT_INTERVAL seconds,
+/// we must treat this session as stalled && finish it with an error.
+/// This timeout is for cases when node is responding to KeepAlive messages, but intentionally ignores
+/// session messages.
+const KEY_REMOVAL_SESSION_TIMEOUT_INTERVAL: u64 = 60;
+
+/// When there are no signing session-related messages for SIGNING_SESSION_TIMEOUT_INTERVAL seconds,
+/// we must treat this session as stalled && finish it with an error.
+/// This timeout is for cases when node is responding to KeepAlive messages, but intentionally ignores
+/// session messages.
+const SIGNING_SESSION_TIMEOUT_INTERVAL: u64 = 60;
+
+/// Messages for sessions, which are not yet created on this node, are buffered (up to EARLY_MESSAGES_LIMIT
+/// messages per session) for EARLY_MESSAGES_TIMEOUT_INTERVAL seconds. They are replayed once session is created.
+/// At most EARLY_MESSAGES_SESSIONS_LIMIT sessions && EARLY_MESSAGES_TOTAL_LIMIT messages are buffered for every
+/// session type - the oldest messages are dropped when limit is reached.
+const EARLY_MESSAGES_LIMIT: usize = 32;
+const EARLY_MESSAGES_SESSIONS_LIMIT: usize = 1024;
+const EARLY_MESSAGES_TOTAL_LIMIT: usize = 8192;
+const EARLY_MESSAGES_TIMEOUT_INTERVAL: u64 = 30;
+
+/// Ids of completed sessions are retained for COMPLETED_SESSIONS_RETENTION_INTERVAL seconds by default, so that late messages
+/// for these sessions are answered with SessionAlreadyCompleted error (instead of being treated as messages for not-yet-created sessions).
+pub const COMPLETED_SESSIONS_RETENTION_INTERVAL: u64 = 60;
+
+/// Every node could run at most MAX_ACTIVE_SESSIONS sessions of every kind (generation, encryption, decryption, share add),
+/// started on its own request. Sessions beyond this limit are queued (up to MAX_QUEUED_SESSIONS) && started
+/// when active sessions complete. Session, which is waiting in the queue for more than SESSIONS_QUEUE_TIMEOUT_INTERVAL
+/// seconds, is failed.
+const MAX_ACTIVE_SESSIONS: usize = 32;
+const MAX_QUEUED_SESSIONS: usize = 256;
+const SESSIONS_QUEUE_TIMEOUT_INTERVAL: u64 = 30;
+
+/// Generation session timeout interval. It works
 /// Empty future.
 type BoxedEmptyFuture = BoxFuture<(), ()>;
 
@@ -70,17 +150,47 @@ type BoxedEmptyFuture = BoxFuture<(), ()>;
 pub trait ClusterClient: Send + Sync {
 	/// Get cluster state.
 	fn cluster_state(&self) -> ClusterState;
-	/// Start new encryption session.
-	fn new_encryption_session(&self, session_id: SessionId, threshold: usize) -> Result<Arc<EncryptionSession>, Error>;
+	/// Start new generation session.
+	fn new_generation_session(&self, session_id: SessionId, author: Public, threshold: usize) -> Result<Arc<GenerationSession>, Error>;
+	/// Start new encryption session. Requestor signs the store request hash (see `store_request_hash`), which includes the nonce.
+	fn new_encryption_session(&self, session_id: SessionId, requestor_signature: Signature, common_point: Public, encrypted_point: Public, nonce: u64) -> Result<Arc<EncryptionSession>, Error>;
 	/// Start new decryption session.
-	fn new_decryption_session(&self, session_id: SessionId, requestor_signature: Signature, is_shadow_decryption: bool) -> Result<Arc<DecryptionSession>, Error>;
+	fn new_decryption_session(&self, session_id: SessionId, requester: Requester, is_shadow_decryption: bool) -> Result<Arc<DecryptionSession>, Error>;
+	/// Start new share add session, extending existing key to new nodes. Admin signature must be computed over
+	/// share_add_session::nodes_sets_hash(old_nodes_set, new_nodes_set).
+	fn new_share_add_session(&self, session_id: SessionId, old_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Arc<ShareAddSession>, Error>;
+	/// Start new share recovery session, re-sharing the key among surviving key holders && new nodes, when the rest of key holders
+	/// are permanently lost. Admin signature must be computed over share_add_session::nodes_sets_hash(surviving_nodes_set, new_nodes_set).
+	fn new_share_recovery_session(&self, session_id: SessionId, surviving_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Arc<ShareAddSession>, Error>;
+	/// Start new servers set change session, migrating every key to the new servers set. Admin signature must be computed over
+	/// servers_set_change_session::servers_set_change_hash(session_id, old_servers_set, new_servers_set), where old servers set
+	/// is the set of all nodes, known to the cluster.
+	fn new_servers_set_change_session(&self, session_id: SessionId, new_servers_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Arc<ServersSetChangeSession>, Error>;
+	/// Get migration, signalled by the key servers set. When there's such migration, servers set could only be changed to the migration set.
+	fn key_server_set_migration(&self) -> Option<KeyServerSetMigration>;
+	/// Generate new server key. Future is resolved with the joint public key when generation session is completed.
+	fn generate_key(&self, session_id: SessionId, author: Public, threshold: usize) -> SessionResultFuture<Public>;
+	/// Store document key. Future is resolved when encryption session is completed. Requestor signs the store request hash
+	/// (see `store_request_hash`), which includes the nonce. Every nonce could be used only once.
+	fn store_document_key(&self, session_id: SessionId, requestor_signature: Signature, common_point: Public, encrypted_point: Public, nonce: u64) -> SessionResultFuture<()>;
+	/// Retrieve document key. Future is resolved with the decryption result when decryption session is completed.
+	fn retrieve_document_key(&self, session_id: SessionId, requester: Requester, is_shadow_decryption: bool) -> SessionResultFuture<DocumentEncryptedKeyShadow>;
+	/// Remove key from every key holder. Future is resolved with key holders, which have been unreachable during key removal session.
+	/// Removal is retried on these key holders when they are connected again. Requestor signs the removal request hash
+	/// (see `removal_request_hash`), which includes the nonce. Every nonce could be used only once.
+	fn remove_key(&self, session_id: SessionId, requestor_signature: Signature, nonce: u64) -> SessionResultFuture<BTreeSet<NodeId>>;
+	/// Sign message hash with the server key. Future is resolved with the (signature hash, signature) pair of Schnorr
+	/// signature when signing session is completed.
+	fn sign_message(&self, session_id: SessionId, requester: Requester, message_hash: H256) -> SessionResultFuture<(Secret, Secret)>;
+	/// Get cluster metrics in Prometheus text format.
+	fn metrics(&self) -> String;
 
 	#[cfg(test)]
-	/// Ask node to make 'faulty' encryption sessions.
-	fn make_faulty_encryption_sessions(&self);
+	/// Ask node to make 'faulty' generation sessions.
+	fn make_faulty_generation_sessions(&self);
 	#[cfg(test)]
-	/// Get active encryption session with given id.
-	fn encryption_session(&self, session_id: &SessionId) -> Option<Arc<EncryptionSessionImpl>>;
+	/// Get active generation session with given id.
+	fn generation_session(&self, session_id: &SessionId) -> Option<Arc<GenerationSessionImpl>>;
 	#[cfg(test)]
 	/// Try connect to disconnected nodes.
 	fn connect(&self);
@@ -94,6 +204,24 @@ pub trait Cluster: Send + Sync {
 	fn send(&self, to: &NodeId, message: Message) -> Result<(), Error>;
 }
 
+/// Sessions container listener.
+pub trait ClusterSessionsListener<S>: Send + Sync {
+	/// When session is inserted into the container. Called while the container is locked => must not access the container.
+	fn on_session_inserted(&self, _session: Arc<S>) {}
+	/// When session starts processing. Session, started by this node, is activated when it leaves the sessions queue.
+	/// Session, started by other node, is activated right after it is inserted (and the container could be locked).
+	fn on_session_activated(&self, _session: Arc<S>) {}
+	/// When session is removed from the container. Session is either completed, or failed, or cancelled.
+	fn on_session_removed(&self, session: Arc<S>);
+	/// When session is replaced with the session with the same id, which serves the same request.
+	/// New session is always started by other node => it is active once inserted.
+	fn on_session_replaced(&self, old_session: Arc<S>, new_session: Arc<S>) {
+		self.on_session_removed(old_session);
+		self.on_session_inserted(new_session.clone());
+		self.on_session_activated(new_session);
+	}
+}
+
 #[derive(Clone)]
 /// Cluster initialization parameters.
 pub struct ClusterConfiguration {
@@ -107,10 +235,46 @@ pub struct ClusterConfiguration {
 	pub listen_address: (String, u16),
 	/// Cluster nodes.
 	pub nodes: BTreeMap<NodeId, (String, u16)>,
+	/// Key servers set. When set, cluster nodes are read from it && `nodes` are ignored.
+	pub key_server_set: Option<Arc<KeyServerSet>>,
 	/// Reference to key storage
 	pub key_storage: Arc<KeyStorage>,
 	/// Reference to ACL storage
 	pub acl_storage: Arc<AclStorage>,
+	/// Administrator public key.
+	pub admin_public: Option<Public>,
+	/// Max number of keys, which are migrated at once by servers set change session.
+	pub max_active_key_migrations: usize,
+	/// Remove key shares from this node, when it is excluded from servers set.
+	pub wipe_removed_key_shares: bool,
+	/// Interval of maintain procedures (see MAINTAIN_INTERVAL).
+	pub maintain_interval: time::Duration,
+	/// How long ids of completed sessions are retained (see COMPLETED_SESSIONS_RETENTION_INTERVAL).
+	pub completed_sessions_retention: time::Duration,
+	/// Timeouts of cluster sessions.
+	pub sessions_timeouts: SessionsTimeouts,
+	/// When nodes, violating protocol, are excluded from new sessions.
+	pub node_reputation: NodeReputationParams,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Cluster sessions timeouts. When there are no session-related messages within the timeout,
+/// session is treated as stalled && finished with an error. Timeouts are checked by maintain procedures.
+pub struct SessionsTimeouts {
+	/// Generation session timeout.
+	pub generation: time::Duration,
+	/// Encryption session timeout.
+	pub encryption: time::Duration,
+	/// Decryption session timeout.
+	pub decryption: time::Duration,
+	/// Share add session timeout.
+	pub share_add: time::Duration,
+	/// Servers set change session timeout.
+	pub servers_set_change: time::Duration,
+	/// Key removal session timeout.
+	pub key_removal: time::Duration,
+	/// Signing session timeout.
+	pub signing: time::Duration,
 }
 
 /// Cluster state.
@@ -154,6 +318,10 @@ pub struct ClusterData {
 	connections: ClusterConnections,
 	/// Active sessions data.
 	sessions: ClusterSessions,
+	/// Cluster metrics.
+	metrics: Arc<ClusterMetrics>,
+	/// Reputation of other nodes.
+	reputation: NodeReputation,
 }
 
 /// Connections that are forming the cluster.
@@ -161,9 +329,13 @@ pub struct ClusterConnections {
 	/// Self node id.
 	pub self_node_id: NodeId,
 	/// All known other key servers.
-	pub nodes: BTreeMap<NodeId, SocketAddr>,
+	pub nodes: RwLock<BTreeMap<NodeId, SocketAddr>>,
 	/// Active connections to key servers.
 	pub connections: RwLock<BTreeMap<NodeId, Arc<Connection>>>,
+	/// Connection state of every other key server.
+	pub manager: ConnectionManager,
+	/// Key servers set watcher. None if key servers set is static.
+	pub trigger: Option<Mutex<ConnectionTrigger>>,
 }
 
 /// Active sessions on this cluster.
@@ -171,17 +343,107 @@ pub struct ClusterSessions {
 	/// Self node id.
 	pub self_node_id: NodeId,
 	/// All nodes ids.
-	pub nodes: BTreeSet<NodeId>,
+	pub nodes: RwLock<BTreeSet<NodeId>>,
 	/// Reference to key storage
 	pub key_storage: Arc<KeyStorage>,
 	/// Reference to ACL storage
 	pub acl_storage: Arc<AclStorage>,
+	/// Administrator public key.
+	pub admin_public: Option<Public>,
+	/// Sessions timeouts.
+	pub timeouts: SessionsTimeouts,
+	/// Active generation sessions.
+	pub generation_sessions: RwLock<BTreeMap<SessionId, QueuedGenerationSession>>,
 	/// Active encryption sessions.
 	pub encryption_sessions: RwLock<BTreeMap<SessionId, QueuedEncryptionSession>>,
 	/// Active decryption sessions.
 	pub decryption_sessions: RwLock<BTreeMap<DecryptionSessionId, QueuedDecryptionSession>>,
-	/// Make faulty encryption sessions.
-	pub make_faulty_encryption_sessions: AtomicBool,
+	/// Active share add sessions.
+	pub share_add_sessions: RwLock<BTreeMap<SessionId, QueuedShareAddSession>>,
+	/// Active servers set change sessions, started by this node.
+	pub servers_set_change_sessions: RwLock<BTreeMap<SessionId, QueuedServersSetChangeSession>>,
+	/// Servers set changes, started by other nodes, along with their master nodes.
+	pub servers_set_changes: RwLock<BTreeMap<SessionId, NodeId>>,
+	/// Active key removal sessions.
+	pub key_removal_sessions: RwLock<BTreeMap<SessionId, QueuedKeyRemovalSession>>,
+	/// Active signing sessions.
+	pub signing_sessions: RwLock<BTreeMap<SigningSessionId, QueuedSigningSession>>,
+	/// Migration, signalled by the key servers set.
+	pub key_server_set_migration: RwLock<Option<KeyServerSetMigration>>,
+	/// Messages for generation sessions, which are not yet created.
+	pub early_generation_messages: SessionMessageQueue<SessionId, GenerationMessage>,
+	/// Messages for encryption sessions, which are not yet created.
+	pub early_encryption_messages: SessionMessageQueue<SessionId, EncryptionMessage>,
+	/// Messages for decryption sessions, which are not yet created.
+	pub early_decryption_messages: SessionMessageQueue<DecryptionSessionId, DecryptionMessage>,
+	/// Messages for share add sessions, which are not yet created.
+	pub early_share_add_messages: SessionMessageQueue<SessionId, ShareAddMessage>,
+	/// Messages for key removal sessions, which are not yet created.
+	pub early_key_removal_messages: SessionMessageQueue<SessionId, KeyRemovalMessage>,
+	/// Messages for signing sessions, which are not yet created.
+	pub early_signing_messages: SessionMessageQueue<SigningSessionId, SigningMessage>,
+	/// Recently completed generation sessions.
+	pub completed_generation_sessions: CompletedSessions<SessionId>,
+	/// Recently completed encryption sessions.
+	pub completed_encryption_sessions: CompletedSessions<SessionId, NodeId>,
+	/// Recently completed decryption sessions.
+	pub completed_decryption_sessions: CompletedSessions<DecryptionSessionId>,
+	/// Recently completed share add sessions.
+	pub completed_share_add_sessions: CompletedSessions<SessionId>,
+	/// Recently completed key removal sessions.
+	pub completed_key_removal_sessions: CompletedSessions<SessionId>,
+	/// Recently completed signing sessions.
+	pub completed_signing_sessions: CompletedSessions<SigningSessionId>,
+	/// Generation sessions, started by this node.
+	pub generation_sessions_queue: SessionsQueue<SessionId, PendingGenerationSession>,
+	/// Encryption sessions, started by this node.
+	pub encryption_sessions_queue: SessionsQueue<SessionId, PendingEncryptionSession>,
+	/// Decryption sessions, started by this node.
+	pub decryption_sessions_queue: SessionsQueue<DecryptionSessionId, PendingDecryptionSession>,
+	/// Share add sessions, started by this node.
+	pub share_add_sessions_queue: SessionsQueue<SessionId, PendingShareAddSession>,
+	/// Key removal sessions, started by this node.
+	pub key_removal_sessions_queue: SessionsQueue<SessionId, PendingKeyRemovalSession>,
+	/// Signing sessions, started by this node.
+	pub signing_sessions_queue: SessionsQueue<SigningSessionId, PendingSigningSession>,
+	/// Generation sessions listeners.
+	pub generation_sessions_listeners: RwLock<Vec<Weak<ClusterSessionsListener<GenerationSessionImpl>>>>,
+	/// Encryption sessions listeners.
+	pub encryption_sessions_listeners: RwLock<Vec<Weak<ClusterSessionsListener<EncryptionSessionImpl>>>>,
+	/// Decryption sessions listeners.
+	pub decryption_sessions_listeners: RwLock<Vec<Weak<ClusterSessionsListener<DecryptionSessionImpl>>>>,
+	/// Key removal sessions listeners.
+	pub key_removal_sessions_listeners: RwLock<Vec<Weak<ClusterSessionsListener<KeyRemovalSessionImpl>>>>,
+	/// Signing sessions listeners.
+	pub signing_sessions_listeners: RwLock<Vec<Weak<ClusterSessionsListener<SigningSessionImpl>>>>,
+	/// Make faulty generation sessions.
+	pub make_faulty_generation_sessions: AtomicBool,
+}
+
+/// Generation session and its message queue.
+pub struct QueuedGenerationSession {
+	/// Session master.
+	pub master: NodeId,
+	/// Cluster view.
+	pub cluster_view: Arc<ClusterView>,
+	/// Last received message time.
+	pub last_message_time: Mutex<time::Instant>,
+	/// Generation session.
+	pub session: Arc<GenerationSessionImpl>,
+	/// Messages queue.
+	pub queue: VecDeque<(NodeId, GenerationMessage)>,
+}
+
+/// Generation session, which is waiting for its turn to start.
+pub struct PendingGenerationSession {
+	/// Generation session.
+	pub session: Arc<GenerationSessionImpl>,
+	/// Author of the key.
+	pub author: Public,
+	/// Session threshold.
+	pub threshold: usize,
+	/// Nodes, participating in the session.
+	pub nodes: BTreeSet<NodeId>,
 }
 
 /// Encryption session and its message queue.
@@ -191,13 +453,37 @@ pub struct QueuedEncryptionSession {
 	/// Cluster view.
 	pub cluster_view: Arc<ClusterView>,
 	/// Last received message time.
-	pub last_message_time: time::Instant,
+	pub last_message_time: Mutex<time::Instant>,
 	/// Encryption session.
 	pub session: Arc<EncryptionSessionImpl>,
 	/// Messages queue.
 	pub queue: VecDeque<(NodeId, EncryptionMessage)>,
 }
 
+/// Encryption session, which is waiting for its turn to start.
+pub struct PendingEncryptionSession {
+	/// Encryption session.
+	pub session: Arc<EncryptionSessionImpl>,
+	/// Requestor signature of the store request.
+	pub requestor_signature: Signature,
+	/// Common (shared) encryption point of the document key.
+	pub common_point: Public,
+	/// Encrypted point of the document key.
+	pub encrypted_point: Public,
+	/// Nonce of the store request.
+	pub nonce: u64,
+}
+
+/// Decryption session, which is waiting for its turn to start.
+pub struct PendingDecryptionSession {
+	/// Decryption session.
+	pub session: Arc<DecryptionSessionImpl>,
+	/// Requester.
+	pub requester: Requester,
+	/// Is shadow decryption requested?
+	pub is_shadow_decryption: bool,
+}
+
 /// Decryption session and its message queue.
 pub struct QueuedDecryptionSession {
 	/// Session master.
@@ -205,19 +491,117 @@ pub struct QueuedDecryptionSession {
 	/// Cluster view.
 	pub cluster_view: Arc<ClusterView>,
 	/// Last received message time.
-	pub last_message_time: time::Instant,
+	pub last_message_time: Mutex<time::Instant>,
 	/// Decryption session.
 	pub session: Arc<DecryptionSessionImpl>,
 	/// Messages queue.
 	pub queue: VecDeque<(NodeId, DecryptionMessage)>,
 }
 
+/// Signing session, which is waiting for its turn to start.
+pub struct PendingSigningSession {
+	/// Signing session.
+	pub session: Arc<SigningSessionImpl>,
+	/// Requester.
+	pub requester: Requester,
+	/// Hash of the message to sign.
+	pub message_hash: H256,
+}
+
+/// Signing session and its message queue.
+pub struct QueuedSigningSession {
+	/// Session master.
+	pub master: NodeId,
+	/// Cluster view.
+	pub cluster_view: Arc<ClusterView>,
+	/// Last received message time.
+	pub last_message_time: Mutex<time::Instant>,
+	/// Signing session.
+	pub session: Arc<SigningSessionImpl>,
+	/// Messages queue.
+	pub queue: VecDeque<(NodeId, SigningMessage)>,
+}
+
+/// Share add session and its message queue.
+pub struct QueuedShareAddSession {
+	/// Session master.
+	pub master: NodeId,
+	/// Cluster view.
+	pub cluster_view: Arc<ClusterView>,
+	/// Last received message time.
+	pub last_message_time: Mutex<time::Instant>,
+	/// Share add session.
+	pub session: Arc<ShareAddSessionImpl>,
+	/// Messages queue.
+	pub queue: VecDeque<(NodeId, ShareAddMessage)>,
+	/// Servers set change session, which has started this session on master node.
+	pub servers_set_change_session: Option<SessionId>,
+}
+
+/// Share add session, which is waiting for its turn to start.
+pub struct PendingShareAddSession {
+	/// Share add session.
+	pub session: Arc<ShareAddSessionImpl>,
+	/// Nodes, which are holding the current version of the key.
+	pub old_nodes_set: BTreeSet<NodeId>,
+	/// Nodes, which will hold the new version of the key (or the new servers set, if session is a part of servers set change).
+	pub new_nodes_set: BTreeSet<NodeId>,
+	/// Servers set change, which session is a part of.
+	pub servers_set_change: Option<ServersSetChange>,
+	/// Is key recovered from surviving key holders (old_nodes_set)?
+	pub is_recovery: bool,
+	/// Administrator signature of (old nodes set, new nodes set) pair (or of the servers set change).
+	pub admin_signature: Signature,
+}
+
+/// Servers set change session.
+pub struct QueuedServersSetChangeSession {
+	/// Cluster view.
+	pub cluster_view: Arc<ClusterView>,
+	/// Last received message (or share add session completion) time.
+	pub last_message_time: Mutex<time::Instant>,
+	/// Servers set change session.
+	pub session: Arc<ServersSetChangeSessionImpl>,
+}
+
+/// Key removal session and its message queue.
+pub struct QueuedKeyRemovalSession {
+	/// Session master.
+	pub master: NodeId,
+	/// Cluster view.
+	pub cluster_view: Arc<ClusterView>,
+	/// Last received message time.
+	pub last_message_time: Mutex<time::Instant>,
+	/// Key removal session.
+	pub session: Arc<KeyRemovalSessionImpl>,
+	/// Messages queue.
+	pub queue: VecDeque<(NodeId, KeyRemovalMessage)>,
+}
+
+/// Key removal session, which is waiting for its turn to start.
+pub struct PendingKeyRemovalSession {
+	/// Key removal session.
+	pub session: Arc<KeyRemovalSessionImpl>,
+	/// Signature of the key author or of the administrator.
+	pub requestor_signature: Signature,
+	/// Removal request nonce.
+	pub nonce: u64,
+	/// Key holders, on which removal is retried. None if key is removed from every key holder.
+	pub key_holders: Option<BTreeSet<NodeId>>,
+	/// Nodes, to which connections are established.
+	pub connected_nodes: BTreeSet<NodeId>,
+}
+
 /// Cluster view core.
 struct ClusterViewCore {
 	/// Cluster reference.
 	cluster: Arc<ClusterData>,
-	/// Subset of nodes, required for this session.
+	/// Subset of session nodes, which are currently connected.
 	nodes: BTreeSet<NodeId>,
+	/// Subset of nodes, required for this session.
+	session_nodes: BTreeSet<NodeId>,
+	/// Tracing span of the session.
+	span: Option<SessionSpan>,
 }
 
 /// Connection to single node.
@@ -236,6 +620,16 @@ pub struct Connection {
 	last_message_time: Mutex<time::Instant>,
 }
 
+/// Generation session implementation, which removes session from cluster on drop.
+struct GenerationSessionWrapper {
+	/// Wrapped session.
+	session: Arc<GenerationSession>,
+	/// Session Id.
+	session_id: SessionId,
+	/// Cluster data reference.
+	cluster: Weak<ClusterData>,
+}
+
 /// Encryption session implementation, which removes session from cluster on drop.
 struct EncryptionSessionWrapper {
 	/// Wrapped session.
@@ -258,11 +652,42 @@ struct DecryptionSessionWrapper {
 	cluster: Weak<ClusterData>,
 }
 
+/// Share add session implementation, which removes session from cluster on drop.
+struct ShareAddSessionWrapper {
+	/// Wrapped session.
+	session: Arc<ShareAddSession>,
+	/// Session Id.
+	session_id: SessionId,
+	/// Cluster data reference.
+	cluster: Weak<ClusterData>,
+}
+
+/// Servers set change session implementation, which removes session from cluster on drop.
+struct ServersSetChangeSessionWrapper {
+	/// Wrapped session.
+	session: Arc<ServersSetChangeSession>,
+	/// Session Id.
+	session_id: SessionId,
+	/// Cluster data reference.
+	cluster: Weak<ClusterData>,
+}
+
+/// Share add sessions executor, which starts share add sessions on behalf of servers set change session.
+struct ClusterShareAddSessionsExecutor {
+	/// Servers set change session Id.
+	session_id: SessionId,
+	/// Cluster data reference.
+	cluster: Weak<ClusterData>,
+}
+
 impl ClusterCore {
 	pub fn new(handle: Handle, config: ClusterConfiguration) -> Result<Arc<Self>, Error> {
 		let listen_address = make_socket_address(&config.listen_address.0, config.listen_address.1)?;
 		let connections = ClusterConnections::new(&config)?;
 		let sessions = ClusterSessions::new(&config);
+		if connections.trigger.is_some() {
+			sessions.update_nodes(connections.nodes.read().keys().cloned().collect());
+		}
 		let data = ClusterData::new(&handle, config, connections, sessions);
 
 		Ok(Arc::new(ClusterCore {
@@ -315,17 +740,24 @@ impl ClusterCore {
 	}
 
 	/// Connect to peer.
-	fn connect(data: Arc<ClusterData>, node_address: SocketAddr) {
+	fn connect(data: Arc<ClusterData>, node_id: NodeId, node_address: SocketAddr) {
 		data.handle.clone().spawn(move |handle| {
-			data.pool.clone().spawn(ClusterCore::connect_future(handle, data, node_address))
+			data.pool.clone().spawn(ClusterCore::connect_future(handle, data, node_id, node_address))
 		})
 	}
 
 	/// Connect to socket using given context and handle.
-	fn connect_future(handle: &Handle, data: Arc<ClusterData>, node_address: SocketAddr) -> BoxedEmptyFuture {
-		let disconnected_nodes = data.connections.disconnected_nodes().keys().cloned().collect();
-		net_connect(&node_address, handle, data.self_key_pair.clone(), disconnected_nodes)
-			.then(move |result| ClusterCore::process_connection_result(data, false, result))
+	fn connect_future(handle: &Handle, data: Arc<ClusterData>, node_id: NodeId, node_address: SocketAddr) -> BoxedEmptyFuture {
+		// only the node which is configured for this address is allowed to answer
+		let expected_nodes = vec![node_id.clone()].into_iter().collect();
+		net_connect(&node_address, handle, data.self_key_pair.clone(), expected_nodes)
+			.then(move |result| {
+				match result {
+					Ok(DeadlineStatus::Meet(Ok(_))) => (),
+					_ => data.connections.manager.on_connect_failed(&node_id, time::Instant::now()),
+				}
+				ClusterCore::process_connection_result(data, false, result)
+			})
 			.then(|_| finished(()))
 			.boxed()
 	}
@@ -362,7 +794,7 @@ impl ClusterCore {
 	/// Schedule mainatain procedures.
 	fn schedule_maintain(handle: &Handle, data: Arc<ClusterData>) {
 		let d = data.clone();
-		let interval: BoxedEmptyFuture = Interval::new(time::Duration::new(MAINTAIN_INTERVAL, 0), handle)
+		let interval: BoxedEmptyFuture = Interval::new(data.config.maintain_interval, handle)
 			.expect("failed to create interval")
 			.and_then(move |_| Ok(ClusterCore::maintain(data.clone())))
 			.for_each(|_| Ok(()))
@@ -377,8 +809,11 @@ impl ClusterCore {
 		trace!(target: "secretstore_net", "{}: executing maintain procedures", data.self_key_pair.public());
 
 		ClusterCore::keep_alive(data.clone());
+		ClusterCore::maintain_connection_trigger(data.clone());
 		ClusterCore::connect_disconnected_nodes(data.clone());
-		data.sessions.stop_stalled_sessions();
+		data.sessions.stop_stalled_sessions(time::Instant::now());
+		data.reputation.maintain(time::Instant::now());
+		ClusterCore::retry_key_removals(data.clone());
 	}
 
 	/// Called for every incomming mesage.
@@ -395,14 +830,17 @@ impl ClusterCore {
 					},
 					Ok((_, Err(err))) => {
 						warn!(target: "secretstore_net", "{}: protocol error {} when reading message from node {}", data.self_key_pair.public(), err, connection.node_id());
+						data.reputation.on_protocol_violation(connection.node_id(), time::Instant::now());
 						// continue serving connection
 						data.spawn(ClusterCore::process_connection_messages(data.clone(), connection));
 						finished(Err(err)).boxed()
 					},
 					Err(err) => {
 						warn!(target: "secretstore_net", "{}: network error {} when reading message from node {}", data.self_key_pair.public(), err, connection.node_id());
-						// close connection
-						data.connections.remove(connection.node_id(), connection.is_inbound());
+						// close connection && let sessions know that the node is gone
+						if data.connections.remove(connection.node_id(), connection.is_inbound()) {
+							data.sessions.on_connection_lost(connection.node_id());
+						}
 						failed(err).boxed()
 					},
 				}
@@ -414,8 +852,9 @@ impl ClusterCore {
 		for connection in data.connections.active_connections() {
 			let last_message_diff = time::Instant::now() - connection.last_message_time();
 			if last_message_diff > time::Duration::from_secs(KEEP_ALIVE_DISCONNECT_INTERVAL) {
-				data.connections.remove(connection.node_id(), connection.is_inbound());
-				data.sessions.on_connection_timeout(connection.node_id());
+				if data.connections.remove(connection.node_id(), connection.is_inbound()) {
+					data.sessions.on_connection_lost(connection.node_id());
+				}
 			}
 			else if last_message_diff > time::Duration::from_secs(KEEP_ALIVE_SEND_INTERVAL) {
 				data.spawn(connection.send_message(Message::Cluster(ClusterMessage::KeepAlive(message::KeepAlive {}))));
@@ -423,12 +862,72 @@ impl ClusterCore {
 		}
 	}
 
+	/// Apply key servers set changes.
+	fn maintain_connection_trigger(data: Arc<ClusterData>) {
+		let change = match data.connections.trigger {
+			Some(ref trigger) => trigger.lock().maintain(|node| data.sessions.has_active_sessions(node)),
+			None => return,
+		};
+
+		if let Some(migration) = change.migration.clone() {
+			data.sessions.on_key_server_set_migration(migration);
+		}
+		data.connections.update_nodes(change);
+		data.sessions.update_nodes(data.connections.nodes.read().keys().cloned().collect());
+	}
+
+	/// Retry key removal on key holders, which have been unreachable during key removal sessions && are connected now.
+	fn retry_key_removals(data: Arc<ClusterData>) {
+		let mut connected_nodes = data.connections.connected_nodes();
+		connected_nodes.insert(data.self_key_pair.public().clone());
+
+		let retries = data.sessions.key_removals_to_retry(&connected_nodes);
+		ClusterCore::start_key_removal_retries(data, connected_nodes, retries);
+	}
+
+	/// Let sessions, which are waiting for the node, know that connection to this node has been established.
+	fn on_connection_established(data: Arc<ClusterData>, node: &NodeId) {
+		let mut connected_nodes = data.connections.connected_nodes();
+		connected_nodes.insert(data.self_key_pair.public().clone());
+
+		// key removals are retried right now, instead of waiting for the next maintain procedures
+		let retries = data.sessions.on_connection_established(node, &connected_nodes);
+		ClusterCore::start_key_removal_retries(data, connected_nodes, retries);
+	}
+
+	/// Start key removal sessions on key holders, which have been unreachable during previous key removal sessions.
+	fn start_key_removal_retries(data: Arc<ClusterData>, connected_nodes: BTreeSet<NodeId>, retries: Vec<(SessionId, KeyRemovalRetry)>) {
+		for (session_id, retry) in retries {
+			// previous key removal session could be still active
+			let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes.clone()));
+			let session = match data.sessions.new_key_removal_session(data.self_key_pair.public().clone(), session_id.clone(), cluster) {
+				Ok(session) => session,
+				Err(_) => continue,
+			};
+
+			trace!(target: "secretstore_net", "{}: retrying removal of key {} on {:?}", data.self_key_pair.public(), session_id, retry.nodes);
+			if data.sessions.start_key_removal_session(session_id.clone(), PendingKeyRemovalSession {
+				session: session.clone(),
+				requestor_signature: retry.requestor_signature,
+				nonce: retry.nonce,
+				key_holders: Some(retry.nodes),
+				connected_nodes: connected_nodes.clone(),
+			}).is_err() {
+				continue;
+			}
+
+			// session could be completed right after initialization (i.e. if none of key holders is connected)
+			let session_state = session.state();
+			if session_state == KeyRemovalSessionState::Finished || session_state == KeyRemovalSessionState::Failed {
+				data.sessions.remove_key_removal_session(&session_id);
+			}
+		}
+	}
+
 	/// Try to connect to every disconnected node.
 	fn connect_disconnected_nodes(data: Arc<ClusterData>) {
-		for (node_id, node_address) in data.connections.disconnected_nodes() {
-			if data.config.allow_connecting_to_higher_nodes || data.self_key_pair.public() < &node_id {
-				ClusterCore::connect(data.clone(), node_address);
-			}
+		for (node_id, node_address) in data.connections.manager.nodes_to_connect(time::Instant::now()) {
+			ClusterCore::connect(data.clone(), node_id, node_address);
 		}
 	}
 
@@ -437,10 +936,15 @@ impl ClusterCore {
 		match result {
 			Ok(DeadlineStatus::Meet(Ok(connection))) => {
 				let connection = Connection::new(is_inbound, connection);
-				if data.connections.insert(connection.clone()) {
-					ClusterCore::process_connection_messages(data.clone(), connection)
-				} else {
-					finished(Ok(())).boxed()
+				match data.connections.insert(connection.clone()) {
+					Some(is_new_connection) => {
+						// sessions are only notified when node was disconnected before, not when connection is replaced
+						if is_new_connection {
+							ClusterCore::on_connection_established(data.clone(), connection.node_id());
+						}
+						ClusterCore::process_connection_messages(data.clone(), connection)
+					},
+					None => finished(Ok(())).boxed(),
 				}
 			},
 			Ok(DeadlineStatus::Meet(Err(_))) => {
@@ -461,52 +965,191 @@ impl ClusterCore {
 		connection.set_last_message_time(time::Instant::now());
 		trace!(target: "secretstore_net", "{}: received message {} from {}", data.self_key_pair.public(), message, connection.node_id());
 		match message {
+			Message::Generation(message) => ClusterCore::process_generation_message(data, connection, message),
 			Message::Encryption(message) => ClusterCore::process_encryption_message(data, connection, message),
 			Message::Decryption(message) => ClusterCore::process_decryption_message(data, connection, message),
+			Message::ShareAdd(message) => ClusterCore::process_share_add_message(data, connection, message),
+			Message::ServersSetChange(message) => ClusterCore::process_servers_set_change_message(data, connection, message),
+			Message::KeyRemoval(message) => ClusterCore::process_key_removal_message(data, connection, message),
+			Message::Signing(message) => ClusterCore::process_signing_message(data, connection, message),
 			Message::Cluster(message) => ClusterCore::process_cluster_message(data, connection, message),
 		}
 	}
 
-	/// Process single encryption message from the connection.
-	fn process_encryption_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: EncryptionMessage) {
+	/// Process single generation message from the connection.
+	fn process_generation_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: GenerationMessage) {
 		let session_id = message.session_id().clone();
 		let mut sender = connection.node_id().clone();
 		let session = match message {
-			EncryptionMessage::InitializeSession(_) => {
+			GenerationMessage::InitializeSession(_) => {
 				let mut connected_nodes = data.connections.connected_nodes();
 				connected_nodes.insert(data.self_key_pair.public().clone());
 
 				let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
-				data.sessions.new_encryption_session(sender.clone(), session_id.clone(), cluster)
+				match data.sessions.new_slave_generation_session(sender.clone(), session_id.clone(), cluster) {
+					Ok(Some(session)) => Ok(session),
+					Ok(None) => return,
+					Err(err) => Err(err),
+				}
 			},
-			_ => {
-				data.sessions.encryption_session(&session_id)
-					.ok_or(Error::InvalidSessionId)
+			_ => match data.sessions.generation_session_or_enqueue(&session_id, &sender, &message) {
+				Ok(Some(session)) => Ok(session),
+				Ok(None) => return,
+				// late message for completed session => let sender know that session is already completed
+				Err(err) => {
+					// do not respond to error reports, so that nodes won't exchange errors endlessly
+					if let GenerationMessage::SessionError(_) = message {
+						return;
+					}
+					data.spawn(connection.send_message(Message::Generation(GenerationMessage::SessionError(message::SessionError {
+						session: session_id.clone().into(),
+						error: format!("{:?}", err),
+					}))));
+					return;
+				},
 			},
 		};
 
+		let span = session.as_ref().ok().and_then(|_| data.sessions.generation_session_span(&session_id));
 		let mut is_queued_message = false;
 		loop {
+			let message_span = span.as_ref().map(|span| span.message_span(&message));
+			if let Some(ref message_span) = message_span {
+				message_span.on_message_received(&sender, &message);
+			}
+			let previous_state = session.as_ref().ok().map(|session| session.state());
 			match session.clone().and_then(|session| match message {
-				EncryptionMessage::InitializeSession(ref message) =>
+				GenerationMessage::InitializeSession(ref message) =>
 					session.on_initialize_session(sender.clone(), message),
-				EncryptionMessage::ConfirmInitialization(ref message) =>
+				GenerationMessage::ConfirmInitialization(ref message) =>
 					session.on_confirm_initialization(sender.clone(), message),
-				EncryptionMessage::CompleteInitialization(ref message) =>
+				GenerationMessage::CompleteInitialization(ref message) =>
 					session.on_complete_initialization(sender.clone(), message),
-				EncryptionMessage::KeysDissemination(ref message) =>
+				GenerationMessage::KeysDissemination(ref message) =>
 					session.on_keys_dissemination(sender.clone(), message),
-				EncryptionMessage::PublicKeyShare(ref message) =>
+				GenerationMessage::PublicKeyShare(ref message) =>
 					session.on_public_key_share(sender.clone(), message),
-				EncryptionMessage::SessionError(ref message) =>
+				GenerationMessage::SessionError(ref message) =>
 					session.on_session_error(sender.clone(), message),
-				EncryptionMessage::SessionCompleted(ref message) => 
+				GenerationMessage::SessionCompleted(ref message) => 
 					session.on_session_completed(sender.clone(), message),
 			}) {
 				Ok(_) => {
 					// if session is completed => stop
 					let session = session.clone().expect("session.method() call finished with success; session exists; qed");
 					let session_state = session.state();
+					if let (Some(span), Some(previous_state)) = (span.as_ref(), previous_state) {
+						if previous_state != session_state {
+							span.on_state_changed(&previous_state, &session_state);
+						}
+					}
+					if session_state == GenerationSessionState::Finished {
+						info!(target: "secretstore_net", "{}: generation session completed", data.self_key_pair.public());
+					}
+					if session_state == GenerationSessionState::Finished || session_state == GenerationSessionState::Failed {
+						data.sessions.remove_generation_session(&session_id);
+						break;
+					}
+
+					// try to dequeue message
+					match data.sessions.dequeue_generation_message(&session_id) {
+						Some((msg_sender, msg)) => {
+							is_queued_message = true;
+							sender = msg_sender;
+							message = msg;
+						},
+						None => break,
+					}
+				},
+				Err(Error::TooEarlyForRequest) => {
+					data.sessions.enqueue_generation_message(&session_id, sender, message, is_queued_message);
+					break;
+				},
+				Err(err) => {
+					warn!(target: "secretstore_net", "{}: generation session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
+					if let Some(ref message_span) = message_span {
+						message_span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+					let error = message::SessionError {
+						session: session_id.clone().into(),
+						error: format!("{:?}", err),
+					};
+					match session {
+						Ok(_) => {
+							data.sessions.respond_with_generation_error(&session_id, error);
+							if err != Error::InvalidSessionId {
+								data.sessions.remove_generation_session(&session_id);
+							}
+						},
+						// session has not been created => active session with the same id (if any) is left untouched
+						Err(_) => data.spawn(connection.send_message(Message::Generation(GenerationMessage::SessionError(error)))),
+					}
+					break;
+				},
+			}
+		}
+	}
+
+	/// Process single encryption message from the connection.
+	fn process_encryption_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: EncryptionMessage) {
+		let session_id = message.session_id().clone();
+		let mut sender = connection.node_id().clone();
+		let session = match message {
+			EncryptionMessage::InitializeEncryptionSession(ref message) => {
+				let mut connected_nodes = data.connections.connected_nodes();
+				connected_nodes.insert(data.self_key_pair.public().clone());
+
+				let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
+				match data.sessions.new_slave_encryption_session(sender.clone(), session_id.clone(), cluster, message) {
+					Ok(Some(session)) => Ok(session),
+					Ok(None) => return,
+					Err(err) => Err(err),
+				}
+			},
+			_ => match data.sessions.encryption_session_or_enqueue(&session_id, &sender, &message) {
+				Ok(Some(session)) => Ok(session),
+				Ok(None) => return,
+				// late message for completed session => let sender know that session is already completed
+				Err(err) => {
+					// do not respond to error reports, so that nodes won't exchange errors endlessly
+					if let EncryptionMessage::EncryptionSessionError(_) = message {
+						return;
+					}
+					data.spawn(connection.send_message(Message::Encryption(EncryptionMessage::EncryptionSessionError(message::EncryptionSessionError {
+						session: session_id.clone().into(),
+						error: format!("{:?}", err),
+					}))));
+					return;
+				},
+			},
+		};
+
+		let span = session.as_ref().ok().and_then(|_| data.sessions.encryption_session_span(&session_id));
+		let mut is_queued_message = false;
+		loop {
+			let message_span = span.as_ref().map(|span| span.message_span(&message));
+			if let Some(ref message_span) = message_span {
+				message_span.on_message_received(&sender, &message);
+			}
+			let previous_state = session.as_ref().ok().map(|session| session.state());
+			match session.clone().and_then(|session| match message {
+				EncryptionMessage::InitializeEncryptionSession(ref message) =>
+					session.on_initialize_session(sender.clone(), message),
+				EncryptionMessage::ConfirmEncryptionInitialization(ref message) =>
+					session.on_confirm_initialization(sender.clone(), message),
+				EncryptionMessage::EncryptionSessionError(ref message) =>
+					session.on_session_error(sender.clone(), message),
+			}) {
+				Ok(_) => {
+					// if session is completed => stop
+					let session = session.clone().expect("session.method() call finished with success; session exists; qed");
+					let session_state = session.state();
+					if let (Some(span), Some(previous_state)) = (span.as_ref(), previous_state) {
+						if previous_state != session_state {
+							span.on_state_changed(&previous_state, &session_state);
+						}
+					}
 					if session_state == EncryptionSessionState::Finished {
 						info!(target: "secretstore_net", "{}: encryption session completed", data.self_key_pair.public());
 					}
@@ -531,11 +1174,20 @@ impl ClusterCore {
 				},
 				Err(err) => {
 					warn!(target: "secretstore_net", "{}: encryption session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
-					data.sessions.respond_with_encryption_error(&session_id, message::SessionError {
+					if let Some(ref message_span) = message_span {
+						message_span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+					let error = message::EncryptionSessionError {
 						session: session_id.clone().into(),
 						error: format!("{:?}", err),
-					});
-					if err != Error::InvalidSessionId {
+					};
+					// master is waiting for every key holder => it must be notified even if session has not been created
+					match session {
+						Ok(_) => data.sessions.respond_with_encryption_error(&session_id, &sender, error),
+						Err(_) => data.spawn(connection.send_message(Message::Encryption(EncryptionMessage::EncryptionSessionError(error)))),
+					}
+					if err != Error::InvalidSessionId && err != Error::DuplicateSessionId {
 						data.sessions.remove_encryption_session(&session_id);
 					}
 					break;
@@ -544,55 +1196,75 @@ impl ClusterCore {
 		}
 	}
 
-	/// Process single decryption message from the connection.
-	fn process_decryption_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: DecryptionMessage) {
+	/// Process single key removal message from the connection.
+	fn process_key_removal_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: KeyRemovalMessage) {
 		let session_id = message.session_id().clone();
-		let sub_session_id = message.sub_session_id().clone();
 		let mut sender = connection.node_id().clone();
 		let session = match message {
-			DecryptionMessage::InitializeDecryptionSession(_) => {
+			KeyRemovalMessage::InitializeKeyRemovalSession(_) => {
 				let mut connected_nodes = data.connections.connected_nodes();
 				connected_nodes.insert(data.self_key_pair.public().clone());
 
 				let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
-				data.sessions.new_decryption_session(sender.clone(), session_id.clone(), sub_session_id.clone(), cluster)
+				data.sessions.new_key_removal_session(sender.clone(), session_id.clone(), cluster)
 			},
-			_ => {
-				data.sessions.decryption_session(&session_id, &sub_session_id)
-					.ok_or(Error::InvalidSessionId)
+			_ => match data.sessions.key_removal_session_or_enqueue(&session_id, &sender, &message) {
+				Ok(Some(session)) => Ok(session),
+				Ok(None) => return,
+				// late message for completed session => let sender know that session is already completed
+				Err(err) => {
+					// do not respond to error reports, so that nodes won't exchange errors endlessly
+					if let KeyRemovalMessage::KeyRemovalSessionError(_) = message {
+						return;
+					}
+					data.spawn(connection.send_message(Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(message::KeyRemovalSessionError {
+						session: session_id.clone().into(),
+						error: format!("{:?}", err),
+					}))));
+					return;
+				},
 			},
 		};
 
+		let span = session.as_ref().ok().and_then(|_| data.sessions.key_removal_session_span(&session_id));
 		let mut is_queued_message = false;
 		loop {
+			let message_span = span.as_ref().map(|span| span.message_span(&message));
+			if let Some(ref message_span) = message_span {
+				message_span.on_message_received(&sender, &message);
+			}
+			let previous_state = session.as_ref().ok().map(|session| session.state());
 			match session.clone().and_then(|session| match message {
-				DecryptionMessage::InitializeDecryptionSession(ref message) =>
+				KeyRemovalMessage::InitializeKeyRemovalSession(ref message) =>
 					session.on_initialize_session(sender.clone(), message),
-				DecryptionMessage::ConfirmDecryptionInitialization(ref message) =>
-					session.on_confirm_initialization(sender.clone(), message),
-				DecryptionMessage::RequestPartialDecryption(ref message) => 
-					session.on_partial_decryption_requested(sender.clone(), message),
-				DecryptionMessage::PartialDecryption(ref message) => 
-					session.on_partial_decryption(sender.clone(), message),
-				DecryptionMessage::DecryptionSessionError(ref message) => 
+				KeyRemovalMessage::KeyShareRemovalStaged(ref message) =>
+					session.on_key_share_removal_staged(sender.clone(), message),
+				KeyRemovalMessage::CommitKeyShareRemoval(ref message) =>
+					session.on_commit_key_share_removal(sender.clone(), message),
+				KeyRemovalMessage::KeyShareRemovalCommitted(ref message) =>
+					session.on_key_share_removal_committed(sender.clone(), message),
+				KeyRemovalMessage::KeyRemovalSessionError(ref message) =>
 					session.on_session_error(sender.clone(), message),
-				DecryptionMessage::DecryptionSessionCompleted(ref message) => 
-					session.on_session_completed(sender.clone(), message),
 			}) {
 				Ok(_) => {
 					// if session is completed => stop
 					let session = session.clone().expect("session.method() call finished with success; session exists; qed");
 					let session_state = session.state();
-					if session_state == DecryptionSessionState::Finished {
-						info!(target: "secretstore_net", "{}: decryption session completed", data.self_key_pair.public());
+					if let (Some(span), Some(previous_state)) = (span.as_ref(), previous_state) {
+						if previous_state != session_state {
+							span.on_state_changed(&previous_state, &session_state);
+						}
 					}
-					if session_state == DecryptionSessionState::Finished || session_state == DecryptionSessionState::Failed {
-						data.sessions.remove_decryption_session(&session_id, &sub_session_id);
+					if session_state == KeyRemovalSessionState::Finished {
+						info!(target: "secretstore_net", "{}: key removal session completed", data.self_key_pair.public());
+					}
+					if session_state == KeyRemovalSessionState::Finished || session_state == KeyRemovalSessionState::Failed {
+						data.sessions.remove_key_removal_session(&session_id);
 						break;
 					}
 
 					// try to dequeue message
-					match data.sessions.dequeue_decryption_message(&session_id, &sub_session_id) {
+					match data.sessions.dequeue_key_removal_message(&session_id) {
 						Some((msg_sender, msg)) => {
 							is_queued_message = true;
 							sender = msg_sender;
@@ -602,18 +1274,26 @@ impl ClusterCore {
 					}
 				},
 				Err(Error::TooEarlyForRequest) => {
-					data.sessions.enqueue_decryption_message(&session_id, &sub_session_id, sender, message, is_queued_message);
+					data.sessions.enqueue_key_removal_message(&session_id, sender, message, is_queued_message);
 					break;
 				},
 				Err(err) => {
-					warn!(target: "secretstore_net", "{}: decryption session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
-					data.sessions.respond_with_decryption_error(&session_id, &sub_session_id, &sender, message::DecryptionSessionError {
+					warn!(target: "secretstore_net", "{}: key removal session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
+					if let Some(ref message_span) = message_span {
+						message_span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+					let error = message::KeyRemovalSessionError {
 						session: session_id.clone().into(),
-						sub_session: sub_session_id.clone().into(),
 						error: format!("{:?}", err),
-					});
-					if err != Error::InvalidSessionId {
-						data.sessions.remove_decryption_session(&session_id, &sub_session_id);
+					};
+					// master is waiting for every reachable key holder => it must be notified even if session has not been created
+					match session {
+						Ok(_) => data.sessions.respond_with_key_removal_error(&session_id, error),
+						Err(_) => data.spawn(connection.send_message(Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(error)))),
+					}
+					if err != Error::InvalidSessionId && err != Error::DuplicateSessionId {
+						data.sessions.remove_key_removal_session(&session_id);
 					}
 					break;
 				},
@@ -621,289 +1301,2066 @@ impl ClusterCore {
 		}
 	}
 
-	/// Process single cluster message from the connection.
-	fn process_cluster_message(data: Arc<ClusterData>, connection: Arc<Connection>, message: ClusterMessage) {
-		match message {
-			ClusterMessage::KeepAlive(_) => data.spawn(connection.send_message(Message::Cluster(ClusterMessage::KeepAliveResponse(message::KeepAliveResponse {})))),
-			ClusterMessage::KeepAliveResponse(_) => (),
-			_ => warn!(target: "secretstore_net", "{}: received unexpected message {} from node {} at {}", data.self_key_pair.public(), message, connection.node_id(), connection.node_address()),
-		}
-	}
-}
-
-impl ClusterConnections {
-	pub fn new(config: &ClusterConfiguration) -> Result<Self, Error> {
-		let mut connections = ClusterConnections {
-			self_node_id: config.self_key_pair.public().clone(),
-			nodes: BTreeMap::new(),
-			connections: RwLock::new(BTreeMap::new()),
-		};
+	/// Process single decryption message from the connection.
+	fn process_decryption_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: DecryptionMessage) {
+		let session_id = message.session_id().clone();
+		let sub_session_id = message.sub_session_id().clone();
+		let mut sender = connection.node_id().clone();
+		let session = match message {
+			DecryptionMessage::InitializeDecryptionSession(_) => {
+				let mut connected_nodes = data.connections.connected_nodes();
+				connected_nodes.insert(data.self_key_pair.public().clone());
+
+				let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
+				data.sessions.new_decryption_session(sender.clone(), session_id.clone(), sub_session_id.clone(), cluster)
+			},
+			_ => match data.sessions.decryption_session_or_enqueue(&session_id, &sub_session_id, &sender, &message) {
+				Ok(Some(session)) => Ok(session),
+				Ok(None) => return,
+				// late message for completed session => let sender know that session is already completed
+				Err(err) => {
+					// do not respond to error reports, so that nodes won't exchange errors endlessly
+					if let DecryptionMessage::DecryptionSessionError(_) = message {
+						return;
+					}
+					data.spawn(connection.send_message(Message::Decryption(DecryptionMessage::DecryptionSessionError(message::DecryptionSessionError {
+						session: session_id.clone().into(),
+						sub_session: sub_session_id.clone().into(),
+						error: format!("{:?}", err),
+					}))));
+					return;
+				},
+			},
+		};
+
+		let span = session.as_ref().ok().and_then(|_| data.sessions.decryption_session_span(&session_id, &sub_session_id));
+		let mut is_queued_message = false;
+		loop {
+			let message_span = span.as_ref().map(|span| span.message_span(&message));
+			if let Some(ref message_span) = message_span {
+				message_span.on_message_received(&sender, &message);
+			}
+			let previous_state = session.as_ref().ok().map(|session| session.state());
+			match session.clone().and_then(|session| match message {
+				DecryptionMessage::InitializeDecryptionSession(ref message) =>
+					session.on_initialize_session(sender.clone(), message),
+				DecryptionMessage::ConfirmDecryptionInitialization(ref message) =>
+					session.on_confirm_initialization(sender.clone(), message),
+				DecryptionMessage::RequestPartialDecryption(ref message) => 
+					session.on_partial_decryption_requested(sender.clone(), message),
+				DecryptionMessage::PartialDecryption(ref message) => 
+					session.on_partial_decryption(sender.clone(), message),
+				DecryptionMessage::DecryptionSessionError(ref message) => 
+					session.on_session_error(sender.clone(), message),
+				DecryptionMessage::DecryptionSessionCompleted(ref message) => 
+					session.on_session_completed(sender.clone(), message),
+			}) {
+				Ok(_) => {
+					// if session is completed => stop
+					let session = session.clone().expect("session.method() call finished with success; session exists; qed");
+					let session_state = session.state();
+					if let (Some(span), Some(previous_state)) = (span.as_ref(), previous_state) {
+						if previous_state != session_state {
+							span.on_state_changed(&previous_state, &session_state);
+						}
+					}
+					if session_state == DecryptionSessionState::Finished {
+						info!(target: "secretstore_net", "{}: decryption session completed", data.self_key_pair.public());
+					}
+					if session_state == DecryptionSessionState::Finished || session_state == DecryptionSessionState::Failed {
+						data.sessions.remove_decryption_session(&session_id, &sub_session_id);
+						break;
+					}
+
+					// try to dequeue message
+					match data.sessions.dequeue_decryption_message(&session_id, &sub_session_id) {
+						Some((msg_sender, msg)) => {
+							is_queued_message = true;
+							sender = msg_sender;
+							message = msg;
+						},
+						None => break,
+					}
+				},
+				Err(Error::TooEarlyForRequest) => {
+					data.sessions.enqueue_decryption_message(&session_id, &sub_session_id, sender, message, is_queued_message);
+					break;
+				},
+				Err(err) => {
+					warn!(target: "secretstore_net", "{}: decryption session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
+					if let Some(ref message_span) = message_span {
+						message_span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+					data.sessions.respond_with_decryption_error(&session_id, &sub_session_id, &sender, message::DecryptionSessionError {
+						session: session_id.clone().into(),
+						sub_session: sub_session_id.clone().into(),
+						error: format!("{:?}", err),
+					});
+					if err != Error::InvalidSessionId {
+						data.sessions.remove_decryption_session(&session_id, &sub_session_id);
+					}
+					break;
+				},
+			}
+		}
+	}
+
+	/// Process single signing message from the connection.
+	fn process_signing_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: SigningMessage) {
+		let session_id = message.session_id().clone();
+		let sub_session_id = message.sub_session_id().clone();
+		let mut sender = connection.node_id().clone();
+		let session = match message {
+			SigningMessage::InitializeSigningSession(_) => {
+				let mut connected_nodes = data.connections.connected_nodes();
+				connected_nodes.insert(data.self_key_pair.public().clone());
+
+				let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
+				data.sessions.new_signing_session(sender.clone(), session_id.clone(), sub_session_id.clone(), cluster)
+			},
+			_ => match data.sessions.signing_session_or_enqueue(&session_id, &sub_session_id, &sender, &message) {
+				Ok(Some(session)) => Ok(session),
+				Ok(None) => return,
+				// late message for completed session => let sender know that session is already completed
+				Err(err) => {
+					// do not respond to error reports, so that nodes won't exchange errors endlessly
+					if let SigningMessage::SigningSessionError(_) = message {
+						return;
+					}
+					data.spawn(connection.send_message(Message::Signing(SigningMessage::SigningSessionError(message::SigningSessionError {
+						session: session_id.clone().into(),
+						sub_session: sub_session_id.clone().into(),
+						error: format!("{:?}", err),
+					}))));
+					return;
+				},
+			},
+		};
+
+		let span = session.as_ref().ok().and_then(|_| data.sessions.signing_session_span(&session_id, &sub_session_id));
+		let mut is_queued_message = false;
+		loop {
+			let message_span = span.as_ref().map(|span| span.message_span(&message));
+			if let Some(ref message_span) = message_span {
+				message_span.on_message_received(&sender, &message);
+			}
+			let previous_state = session.as_ref().ok().map(|session| session.state());
+			match session.clone().and_then(|session| match message {
+				SigningMessage::InitializeSigningSession(ref message) =>
+					session.on_initialize_session(sender.clone(), message),
+				SigningMessage::ConfirmSigningInitialization(ref message) =>
+					session.on_confirm_initialization(sender.clone(), message),
+				SigningMessage::RequestSigningNonce(ref message) =>
+					session.on_nonce_requested(sender.clone(), message),
+				SigningMessage::SigningNonce(ref message) =>
+					session.on_nonce(sender.clone(), message),
+				SigningMessage::RequestPartialSignature(ref message) =>
+					session.on_partial_signature_requested(sender.clone(), message),
+				SigningMessage::PartialSignature(ref message) =>
+					session.on_partial_signature(sender.clone(), message),
+				SigningMessage::SigningSessionError(ref message) =>
+					session.on_session_error(sender.clone(), message),
+				SigningMessage::SigningSessionCompleted(ref message) =>
+					session.on_session_completed(sender.clone(), message),
+			}) {
+				Ok(_) => {
+					// if session is completed => stop
+					let session = session.clone().expect("session.method() call finished with success; session exists; qed");
+					let session_state = session.state();
+					if let (Some(span), Some(previous_state)) = (span.as_ref(), previous_state) {
+						if previous_state != session_state {
+							span.on_state_changed(&previous_state, &session_state);
+						}
+					}
+					if session_state == SigningSessionState::Finished {
+						info!(target: "secretstore_net", "{}: signing session completed", data.self_key_pair.public());
+					}
+					if session_state == SigningSessionState::Finished || session_state == SigningSessionState::Failed {
+						data.sessions.remove_signing_session(&session_id, &sub_session_id);
+						break;
+					}
+
+					// try to dequeue message
+					match data.sessions.dequeue_signing_message(&session_id, &sub_session_id) {
+						Some((msg_sender, msg)) => {
+							is_queued_message = true;
+							sender = msg_sender;
+							message = msg;
+						},
+						None => break,
+					}
+				},
+				Err(Error::TooEarlyForRequest) => {
+					data.sessions.enqueue_signing_message(&session_id, &sub_session_id, sender, message, is_queued_message);
+					break;
+				},
+				Err(err) => {
+					warn!(target: "secretstore_net", "{}: signing session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
+					if let Some(ref message_span) = message_span {
+						message_span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+					data.sessions.respond_with_signing_error(&session_id, &sub_session_id, &sender, message::SigningSessionError {
+						session: session_id.clone().into(),
+						sub_session: sub_session_id.clone().into(),
+						error: format!("{:?}", err),
+					});
+					if err != Error::InvalidSessionId {
+						data.sessions.remove_signing_session(&session_id, &sub_session_id);
+					}
+					break;
+				},
+			}
+		}
+	}
+
+	/// Process single share add message from the connection.
+	fn process_share_add_message(data: Arc<ClusterData>, connection: Arc<Connection>, mut message: ShareAddMessage) {
+		let session_id = message.session_id().clone();
+		let mut sender = connection.node_id().clone();
+		let session = match message {
+			ShareAddMessage::InitializeShareAddSession(ref message) => {
+				// share add session, which is a part of servers set change, must be started by master of the running change
+				if let Some(ref servers_set_change) = message.servers_set_change {
+					if data.sessions.servers_set_changes.read().get(&*servers_set_change.session) != Some(&sender) {
+						warn!(target: "secretstore_net", "{}: share add session {} is not a part of known servers set change", data.self_key_pair.public(), session_id);
+						let error = message::ShareAddSessionError {
+							session: session_id.clone().into(),
+							error: format!("{:?}", Error::AccessDenied),
+						};
+						data.spawn(connection.send_message(Message::ShareAdd(ShareAddMessage::ShareAddSessionError(error))));
+						return;
+					}
+				}
+
+				let mut connected_nodes = data.connections.connected_nodes();
+				connected_nodes.insert(data.self_key_pair.public().clone());
+
+				let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
+				data.sessions.new_share_add_session(sender.clone(), session_id.clone(), cluster, None)
+			},
+			_ => match data.sessions.share_add_session_or_enqueue(&session_id, &sender, &message) {
+				Ok(Some(session)) => Ok(session),
+				Ok(None) => return,
+				// late message for completed session => let sender know that session is already completed
+				Err(err) => {
+					// do not respond to error reports, so that nodes won't exchange errors endlessly
+					if let ShareAddMessage::ShareAddSessionError(_) = message {
+						return;
+					}
+					data.spawn(connection.send_message(Message::ShareAdd(ShareAddMessage::ShareAddSessionError(message::ShareAddSessionError {
+						session: session_id.clone().into(),
+						error: format!("{:?}", err),
+					}))));
+					return;
+				},
+			},
+		};
+
+		let span = session.as_ref().ok().and_then(|_| data.sessions.share_add_session_span(&session_id));
+		let mut is_queued_message = false;
+		loop {
+			let message_span = span.as_ref().map(|span| span.message_span(&message));
+			if let Some(ref message_span) = message_span {
+				message_span.on_message_received(&sender, &message);
+			}
+			let previous_state = session.as_ref().ok().map(|session| session.state());
+			match session.clone().and_then(|session| match message {
+				ShareAddMessage::InitializeShareAddSession(ref message) =>
+					session.on_initialize_session(sender.clone(), message),
+				ShareAddMessage::NewKeysDissemination(ref message) =>
+					session.on_keys_dissemination(sender.clone(), message),
+				ShareAddMessage::NewKeyShareStaged(ref message) =>
+					session.on_new_key_share_staged(sender.clone(), message),
+				ShareAddMessage::CommitNewKeyShare(ref message) =>
+					session.on_commit_new_key_share(sender.clone(), message),
+				ShareAddMessage::ShareAddSessionError(ref message) =>
+					session.on_session_error(sender.clone(), message),
+			}) {
+				Ok(_) => {
+					// if session is completed => stop
+					let session = session.clone().expect("session.method() call finished with success; session exists; qed");
+					let session_state = session.state();
+					if let (Some(span), Some(previous_state)) = (span.as_ref(), previous_state) {
+						if previous_state != session_state {
+							span.on_state_changed(&previous_state, &session_state);
+						}
+					}
+					if session_state == ShareAddSessionState::Finished {
+						info!(target: "secretstore_net", "{}: share add session completed", data.self_key_pair.public());
+					}
+					if session_state == ShareAddSessionState::Finished || session_state == ShareAddSessionState::Failed {
+						data.sessions.remove_share_add_session(&session_id);
+						break;
+					}
+
+					// try to dequeue message
+					match data.sessions.dequeue_share_add_message(&session_id) {
+						Some((msg_sender, msg)) => {
+							is_queued_message = true;
+							sender = msg_sender;
+							message = msg;
+						},
+						None => break,
+					}
+				},
+				Err(Error::TooEarlyForRequest) => {
+					data.sessions.enqueue_share_add_message(&session_id, sender, message, is_queued_message);
+					break;
+				},
+				Err(err) => {
+					warn!(target: "secretstore_net", "{}: share add session error {} when processing message {} from node {}", data.self_key_pair.public(), err, message, sender);
+					if let Some(ref message_span) = message_span {
+						message_span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+					let error = message::ShareAddSessionError {
+						session: session_id.clone().into(),
+						error: format!("{:?}", err),
+					};
+					// master is waiting for every node => it must be notified even if session has not been created
+					match session {
+						Ok(_) => data.sessions.respond_with_share_add_error(&session_id, error),
+						Err(_) => data.spawn(connection.send_message(Message::ShareAdd(ShareAddMessage::ShareAddSessionError(error)))),
+					}
+					if err != Error::InvalidSessionId && err != Error::DuplicateSessionId {
+						data.sessions.remove_share_add_session(&session_id);
+					}
+					break;
+				},
+			}
+		}
+	}
+
+	/// Process single servers set change message from the connection.
+	fn process_servers_set_change_message(data: Arc<ClusterData>, connection: Arc<Connection>, message: ServersSetChangeMessage) {
+		let session_id = message.session_id().clone();
+		let sender = connection.node_id().clone();
+		let self_node_id = data.self_key_pair.public().clone();
+		let admin_public = data.sessions.admin_public.as_ref();
+		let key_storage = &*data.sessions.key_storage;
+		match message {
+			// requests from master are processed without creating session on this node
+			ServersSetChangeMessage::UnknownSessionsRequest(ref message) => {
+				let servers_set = data.sessions.nodes.read().clone();
+				let response = match servers_set_change_session::process_unknown_sessions_request(&servers_set, admin_public, key_storage, message) {
+					Ok(response) => {
+						// remember the change, so that share add sessions, started by master, are accepted
+						data.sessions.servers_set_changes.write().insert(session_id.clone(), sender.clone());
+						ServersSetChangeMessage::UnknownSessions(response)
+					},
+					Err(err) => {
+						warn!(target: "secretstore_net", "{}: servers set change session error {} when processing request from node {}", self_node_id, err, sender);
+						ServersSetChangeMessage::ServersSetChangeError(message::ServersSetChangeError {
+							session: session_id.into(),
+							error: format!("{:?}", err),
+						})
+					},
+				};
+				data.spawn(connection.send_message(Message::ServersSetChange(response)));
+			},
+			ServersSetChangeMessage::ServersSetChangeCompleted(ref message) => {
+				if data.sessions.servers_set_changes.read().get(&session_id) != Some(&sender) {
+					warn!(target: "secretstore_net", "{}: unknown servers set change session completion from node {}", self_node_id, sender);
+					return;
+				}
+				data.sessions.servers_set_changes.write().remove(&session_id);
+
+				let servers_set = data.sessions.nodes.read().clone();
+				match servers_set_change_session::process_servers_set_change_completed(&self_node_id, &servers_set, admin_public, key_storage, data.config.wipe_removed_key_shares, message) {
+					Ok(0) => info!(target: "secretstore_net", "{}: servers set change session completed", self_node_id),
+					Ok(removed_keys) => info!(target: "secretstore_net", "{}: servers set change session completed. {} key shares removed", self_node_id, removed_keys),
+					Err(err) => warn!(target: "secretstore_net", "{}: servers set change session error {} when processing completion from node {}", self_node_id, err, sender),
+				}
+			},
+			// responses are processed by session on master node
+			ServersSetChangeMessage::UnknownSessions(_) | ServersSetChangeMessage::ServersSetChangeError(_) => {
+				let session = match data.sessions.servers_set_change_session(&session_id) {
+					Some(session) => session,
+					None => {
+						trace!(target: "secretstore_net", "{}: ignoring message {} from node {} for unknown servers set change session", self_node_id, message, sender);
+						return;
+					},
+				};
+
+				let span = data.sessions.servers_set_change_session_span(&session_id);
+				if let Some(ref span) = span {
+					span.on_message_received(&sender, &message);
+				}
+				let previous_state = session.state();
+				let result = match message {
+					ServersSetChangeMessage::UnknownSessions(ref message) => session.on_unknown_sessions(sender.clone(), message),
+					ServersSetChangeMessage::ServersSetChangeError(ref message) => session.on_session_error(sender.clone(), message),
+					_ => unreachable!("checked above; qed"),
+				};
+				if let Err(err) = result {
+					warn!(target: "secretstore_net", "{}: servers set change session error {} when processing message {} from node {}", self_node_id, err, message, sender);
+					if let Some(ref span) = span {
+						span.on_message_rejected(&sender, &message, &err);
+					}
+					data.on_session_error(&sender, &err);
+				}
+
+				let session_state = session.state();
+				if let Some(ref span) = span {
+					if previous_state != session_state {
+						span.on_state_changed(&previous_state, &session_state);
+					}
+				}
+				if session_state == ServersSetChangeSessionState::Finished {
+					info!(target: "secretstore_net", "{}: servers set change session completed", self_node_id);
+				}
+				if session_state == ServersSetChangeSessionState::Finished || session_state == ServersSetChangeSessionState::Failed {
+					data.sessions.remove_servers_set_change_session(&session_id);
+				}
+			},
+		}
+	}
+
+	/// Process single cluster message from the connection.
+	fn process_cluster_message(data: Arc<ClusterData>, connection: Arc<Connection>, message: ClusterMessage) {
+		match message {
+			ClusterMessage::KeepAlive(_) => data.spawn(connection.send_message(Message::Cluster(ClusterMessage::KeepAliveResponse(message::KeepAliveResponse {})))),
+			ClusterMessage::KeepAliveResponse(_) => (),
+			_ => warn!(target: "secretstore_net", "{}: received unexpected message {} from node {} at {}", data.self_key_pair.public(), message, connection.node_id(), connection.node_address()),
+		}
+	}
+}
+
+impl ClusterConnections {
+	pub fn new(config: &ClusterConfiguration) -> Result<Self, Error> {
+		let trigger = config.key_server_set.clone()
+			.map(|key_server_set| ConnectionTrigger::new(config.self_key_pair.public().clone(), key_server_set));
+		let nodes = match trigger {
+			Some(ref trigger) => trigger.nodes().clone(),
+			None => {
+				let mut nodes = BTreeMap::new();
+				for (node_id, &(ref node_addr, node_port)) in config.nodes.iter().filter(|&(node_id, _)| node_id != config.self_key_pair.public()) {
+					let socket_address = make_socket_address(&node_addr, node_port)?;
+					nodes.insert(node_id.clone(), socket_address);
+				}
+				nodes
+			},
+		};
+
+		Ok(ClusterConnections {
+			self_node_id: config.self_key_pair.public().clone(),
+			manager: ConnectionManager::new(config.self_key_pair.public().clone(), config.allow_connecting_to_higher_nodes, nodes.clone(),
+				time::Duration::from_secs(MIN_RECONNECT_BACKOFF), time::Duration::from_secs(MAX_RECONNECT_BACKOFF)),
+			nodes: RwLock::new(nodes),
+			connections: RwLock::new(BTreeMap::new()),
+			trigger: trigger.map(Mutex::new),
+		})
+	}
+
+	/// Apply key servers set changes. Connections to removed nodes are closed.
+	pub fn update_nodes(&self, change: ConnectionsChange) {
+		// connections lock is always acquired before nodes lock => do not hold both here
+		for node_id in change.removed.iter() {
+			trace!(target: "secretstore_net", "{}: forgetting node {}", self.self_node_id, node_id);
+			self.manager.remove_node(node_id);
+			self.connections.write().remove(node_id);
+		}
+
+		let mut nodes = self.nodes.write();
+		for node_id in change.removed {
+			nodes.remove(&node_id);
+		}
+		for (node_id, node_address) in change.added.into_iter().chain(change.updated) {
+			trace!(target: "secretstore_net", "{}: using address {} of node {}", self.self_node_id, node_address, node_id);
+			self.manager.add_node(node_id.clone(), node_address.clone());
+			nodes.insert(node_id, node_address);
+		}
+	}
+
+	pub fn cluster_state(&self) -> ClusterState {
+		ClusterState {
+			connected: self.connections.read().keys().cloned().collect(),
+		}
+	}
+
+	pub fn get(&self, node: &NodeId) -> Option<Arc<Connection>> {
+		self.connections.read().get(node).cloned()
+	}
+
+	/// Insert new connection. Returns None if connection is rejected, because we are already connected to the same node.
+	/// Otherwise returns true if node has been disconnected before && false if existing connection has been replaced.
+	pub fn insert(&self, connection: Arc<Connection>) -> Option<bool> {
+		let mut connections = self.connections.write();
+		// we have already connected to the same node => keep only one of connections
+		if connections.contains_key(connection.node_id())
+			&& !is_preferred_connection(&self.self_node_id, connection.node_id(), connection.is_inbound()) {
+			return None;
+		}
+
+		trace!(target: "secretstore_net", "{}: inserting connection to {} at {}", self.self_node_id, connection.node_id(), connection.node_address());
+		let is_new_connection = self.manager.on_connection_established(connection.node_id());
+		if is_new_connection {
+			trace!(target: "secretstore_net", "{}: connection to {} established", self.self_node_id, connection.node_id());
+		}
+		connections.insert(connection.node_id().clone(), connection);
+		Some(is_new_connection)
+	}
+
+	pub fn remove(&self, node: &NodeId, is_inbound: bool) -> bool {
+		let mut connections = self.connections.write();
+		if let Entry::Occupied(entry) = connections.entry(node.clone()) {
+			if entry.get().is_inbound() != is_inbound {
+				return false;
+			}
+
+			trace!(target: "secretstore_net", "{}: removing connection to {} at {}", self.self_node_id, entry.get().node_id(), entry.get().node_address());
+			entry.remove_entry();
+			return self.manager.on_connection_lost(node);
+		}
+
+		false
+	}
+
+	pub fn connected_nodes(&self) -> BTreeSet<NodeId> {
+		self.connections.read().keys().cloned().collect()
+	}
+
+	pub fn active_connections(&self)-> Vec<Arc<Connection>> {
+		self.connections.read().values().cloned().collect()
+	}
+
+	pub fn disconnected_nodes(&self) -> BTreeMap<NodeId, SocketAddr> {
+		let connections = self.connections.read();
+		self.nodes.read().iter()
+			.filter(|&(node_id, _)| !connections.contains_key(node_id))
+			.map(|(node_id, node_address)| (node_id.clone(), node_address.clone()))
+			.collect()
+	}
+}
+
+impl Default for SessionsTimeouts {
+	fn default() -> Self {
+		SessionsTimeouts {
+			generation: time::Duration::from_secs(GENERATION_SESSION_TIMEOUT_INTERVAL),
+			encryption: time::Duration::from_secs(ENCRYPTION_SESSION_TIMEOUT_INTERVAL),
+			decryption: time::Duration::from_secs(DECRYPTION_SESSION_TIMEOUT_INTERVAL),
+			share_add: time::Duration::from_secs(SHARE_ADD_SESSION_TIMEOUT_INTERVAL),
+			servers_set_change: time::Duration::from_secs(SERVERS_SET_CHANGE_SESSION_TIMEOUT_INTERVAL),
+			key_removal: time::Duration::from_secs(KEY_REMOVAL_SESSION_TIMEOUT_INTERVAL),
+			signing: time::Duration::from_secs(SIGNING_SESSION_TIMEOUT_INTERVAL),
+		}
+	}
+}
+
+impl ClusterSessions {
+	pub fn new(config: &ClusterConfiguration) -> Self {
+		ClusterSessions {
+			self_node_id: config.self_key_pair.public().clone(),
+			nodes: RwLock::new(config.nodes.keys().cloned().collect()),
+			acl_storage: config.acl_storage.clone(),
+			key_storage: config.key_storage.clone(),
+			admin_public: config.admin_public.clone(),
+			timeouts: config.sessions_timeouts.clone(),
+			generation_sessions: RwLock::new(BTreeMap::new()),
+			encryption_sessions: RwLock::new(BTreeMap::new()),
+			decryption_sessions: RwLock::new(BTreeMap::new()),
+			share_add_sessions: RwLock::new(BTreeMap::new()),
+			servers_set_change_sessions: RwLock::new(BTreeMap::new()),
+			servers_set_changes: RwLock::new(BTreeMap::new()),
+			key_removal_sessions: RwLock::new(BTreeMap::new()),
+			signing_sessions: RwLock::new(BTreeMap::new()),
+			key_server_set_migration: RwLock::new(None),
+			early_generation_messages: SessionMessageQueue::new(EARLY_MESSAGES_SESSIONS_LIMIT, EARLY_MESSAGES_LIMIT, EARLY_MESSAGES_TOTAL_LIMIT, time::Duration::from_secs(EARLY_MESSAGES_TIMEOUT_INTERVAL)),
+			early_encryption_messages: SessionMessageQueue::new(EARLY_MESSAGES_SESSIONS_LIMIT, EARLY_MESSAGES_LIMIT, EARLY_MESSAGES_TOTAL_LIMIT, time::Duration::from_secs(EARLY_MESSAGES_TIMEOUT_INTERVAL)),
+			early_decryption_messages: SessionMessageQueue::new(EARLY_MESSAGES_SESSIONS_LIMIT, EARLY_MESSAGES_LIMIT, EARLY_MESSAGES_TOTAL_LIMIT, time::Duration::from_secs(EARLY_MESSAGES_TIMEOUT_INTERVAL)),
+			early_share_add_messages: SessionMessageQueue::new(EARLY_MESSAGES_SESSIONS_LIMIT, EARLY_MESSAGES_LIMIT, EARLY_MESSAGES_TOTAL_LIMIT, time::Duration::from_secs(EARLY_MESSAGES_TIMEOUT_INTERVAL)),
+			early_key_removal_messages: SessionMessageQueue::new(EARLY_MESSAGES_SESSIONS_LIMIT, EARLY_MESSAGES_LIMIT, EARLY_MESSAGES_TOTAL_LIMIT, time::Duration::from_secs(EARLY_MESSAGES_TIMEOUT_INTERVAL)),
+			early_signing_messages: SessionMessageQueue::new(EARLY_MESSAGES_SESSIONS_LIMIT, EARLY_MESSAGES_LIMIT, EARLY_MESSAGES_TOTAL_LIMIT, time::Duration::from_secs(EARLY_MESSAGES_TIMEOUT_INTERVAL)),
+			completed_generation_sessions: CompletedSessions::new(config.completed_sessions_retention),
+			completed_encryption_sessions: CompletedSessions::new(config.completed_sessions_retention),
+			completed_decryption_sessions: CompletedSessions::new(config.completed_sessions_retention),
+			completed_share_add_sessions: CompletedSessions::new(config.completed_sessions_retention),
+			completed_key_removal_sessions: CompletedSessions::new(config.completed_sessions_retention),
+			completed_signing_sessions: CompletedSessions::new(config.completed_sessions_retention),
+			generation_sessions_queue: SessionsQueue::new(MAX_ACTIVE_SESSIONS, MAX_QUEUED_SESSIONS, time::Duration::from_secs(SESSIONS_QUEUE_TIMEOUT_INTERVAL)),
+			encryption_sessions_queue: SessionsQueue::new(MAX_ACTIVE_SESSIONS, MAX_QUEUED_SESSIONS, time::Duration::from_secs(SESSIONS_QUEUE_TIMEOUT_INTERVAL)),
+			decryption_sessions_queue: SessionsQueue::new(MAX_ACTIVE_SESSIONS, MAX_QUEUED_SESSIONS, time::Duration::from_secs(SESSIONS_QUEUE_TIMEOUT_INTERVAL)),
+			share_add_sessions_queue: SessionsQueue::new(MAX_ACTIVE_SESSIONS, MAX_QUEUED_SESSIONS, time::Duration::from_secs(SESSIONS_QUEUE_TIMEOUT_INTERVAL)),
+			key_removal_sessions_queue: SessionsQueue::new(MAX_ACTIVE_SESSIONS, MAX_QUEUED_SESSIONS, time::Duration::from_secs(SESSIONS_QUEUE_TIMEOUT_INTERVAL)),
+			signing_sessions_queue: SessionsQueue::new(MAX_ACTIVE_SESSIONS, MAX_QUEUED_SESSIONS, time::Duration::from_secs(SESSIONS_QUEUE_TIMEOUT_INTERVAL)),
+			generation_sessions_listeners: RwLock::new(Vec::new()),
+			encryption_sessions_listeners: RwLock::new(Vec::new()),
+			decryption_sessions_listeners: RwLock::new(Vec::new()),
+			key_removal_sessions_listeners: RwLock::new(Vec::new()),
+			signing_sessions_listeners: RwLock::new(Vec::new()),
+			make_faulty_generation_sessions: AtomicBool::new(false),
+		}
+	}
+
+	pub fn new_generation_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Result<Arc<GenerationSessionImpl>, Error> {
+		let mut generation_sessions = self.generation_sessions.write();
+		// check that there's no active generation session with the same id
+		if generation_sessions.contains_key(&session_id) {
+			return Err(Error::DuplicateSessionId);
+		}
+
+		let is_slave_session = master != self.self_node_id;
+		let session = self.insert_generation_session(&mut *generation_sessions, master, session_id, cluster)?;
+		notify_session_inserted(&self.generation_sessions_listeners, session.clone());
+		if is_slave_session {
+			notify_session_activated(&self.generation_sessions_listeners, session.clone());
+		}
+		Ok(session)
+	}
+
+	/// Create generation session, started by another node. If there's already generation session with the same id, started
+	/// by another master, only session of the preferred master survives. Returns None if the initialization must be ignored.
+	pub fn new_slave_generation_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Result<Option<Arc<GenerationSessionImpl>>, Error> {
+		let (session, superseded_session) = {
+			let mut generation_sessions = self.generation_sessions.write();
+			let is_superseded = match generation_sessions.get(&session_id) {
+				Some(session) if session.master == master => return Err(Error::DuplicateSessionId),
+				Some(session) => {
+					// session, queued at this node, has not been announced to other nodes yet => it always yields
+					let is_queued = session.master == self.self_node_id && self.generation_sessions_queue.is_queued(&session_id);
+					if !is_queued && is_preferred_master(&session.master, &master) {
+						trace!(target: "secretstore_net", "{}: ignoring initialization of generation session {} by {}: session is started by {}",
+							self.self_node_id, session_id, master, session.master);
+						return Ok(None);
+					}
+					if !session.session.is_initializing() {
+						return Err(Error::SessionAlreadyStarted);
+					}
+					true
+				},
+				None => false,
+			};
+
+			let superseded_session = if is_superseded { generation_sessions.remove(&session_id) } else { None };
+			let session = self.insert_generation_session(&mut *generation_sessions, master, session_id.clone(), cluster);
+			if let Ok(ref session) = session {
+				notify_session_inserted(&self.generation_sessions_listeners, session.clone());
+				notify_session_activated(&self.generation_sessions_listeners, session.clone());
+			}
+			(session, superseded_session)
+		};
+
+		if let Some(superseded_session) = superseded_session {
+			superseded_session.session.on_session_superseded();
+			trace_finished_session(&superseded_session.cluster_view, &superseded_session.session.state());
+			notify_session_removed(&self.generation_sessions_listeners, superseded_session.session);
+			for (session_id, session) in self.generation_sessions_queue.remove(&session_id) {
+				self.start_queued_generation_session(session_id, session);
+			}
+		}
+
+		session.map(Some)
+	}
+
+	fn insert_generation_session(&self, generation_sessions: &mut BTreeMap<SessionId, QueuedGenerationSession>, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Result<Arc<GenerationSessionImpl>, Error> {
+		// check that there's no finished generation session with the same id
+		if self.key_storage.contains(&session_id) {
+			return Err(Error::DuplicateSessionId);
+		}
+
+		// communicating to all other nodes is crucial for generation session
+		// => check that we have connections to all cluster nodes
+		if self.nodes.read().iter().any(|n| !cluster.is_connected(n)) {
+			return Err(Error::NodeDisconnected);
+		}
+
+		let session = Arc::new(GenerationSessionImpl::new(GenerationSessionParams {
+			id: session_id.clone(),
+			self_node_id: self.self_node_id.clone(),
+			key_storage: self.key_storage.clone(),
+			cluster: cluster.clone(),
+		}));
+		let span = SessionSpan::new(SessionType::Generation, session_id.clone(), master.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		let generation_session = QueuedGenerationSession {
+			master: master,
+			cluster_view: cluster,
+			last_message_time: Mutex::new(time::Instant::now()),
+			session: session.clone(),
+			queue: self.early_generation_messages.take(&session_id),
+		};
+		if self.make_faulty_generation_sessions.load(Ordering::Relaxed) {
+			generation_session.session.simulate_faulty_behaviour();
+		}
+		generation_sessions.insert(session_id, generation_session);
+		Ok(session)
+	}
+
+	/// Get tracing span of the active generation session.
+	fn generation_session_span(&self, session_id: &SessionId) -> Option<SessionSpan> {
+		self.generation_sessions.read().get(session_id).and_then(|session| session.cluster_view.span())
+	}
+
+	pub fn remove_generation_session(&self, session_id: &SessionId) {
+		let removed_session = {
+			let mut generation_sessions = self.generation_sessions.write();
+			let removed_session = generation_sessions.remove(session_id);
+			if removed_session.is_some() {
+				self.completed_generation_sessions.insert(session_id.clone(), time::Instant::now());
+			}
+			removed_session
+		};
+		if let Some(removed_session) = removed_session {
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
+			notify_session_removed(&self.generation_sessions_listeners, removed_session.session);
+		}
+		for (session_id, session) in self.generation_sessions_queue.remove(session_id) {
+			self.start_queued_generation_session(session_id, session);
+		}
+	}
+
+	/// Cancel generation session, started by this node. Every other node is notified with session error.
+	pub fn cancel_generation_session(&self, session_id: &SessionId) {
+		self.respond_with_generation_error(session_id, message::SessionError {
+			session: session_id.clone().into(),
+			error: "session has been cancelled".into(),
+		});
+		self.remove_generation_session(session_id);
+	}
+
+	/// Add generation sessions listener.
+	pub fn add_generation_sessions_listener(&self, listener: Arc<ClusterSessionsListener<GenerationSessionImpl>>) {
+		self.generation_sessions_listeners.write().push(Arc::downgrade(&listener));
+	}
+
+	/// Start generation session, created by this node, or queue it if there are too many active sessions.
+	pub fn start_generation_session(&self, session_id: SessionId, session: PendingGenerationSession) -> Result<(), Error> {
+		let result = match self.generation_sessions_queue.enqueue(session_id.clone(), session, time::Instant::now()) {
+			Ok(Some(session)) => {
+				notify_session_activated(&self.generation_sessions_listeners, session.session.clone());
+				session.start()
+			},
+			Ok(None) => {
+				trace!(target: "secretstore_net", "{}: generation session {} is queued", self.self_node_id, session_id);
+				Ok(())
+			},
+			Err(err) => Err(err),
+		};
+
+		if result.is_err() {
+			self.remove_generation_session(&session_id);
+		}
+		result
+	}
+
+	fn start_queued_generation_session(&self, session_id: SessionId, session: PendingGenerationSession) {
+		// session timeout starts only when session is activated
+		if let Some(queued_session) = self.generation_sessions.read().get(&session_id) {
+			*queued_session.last_message_time.lock() = time::Instant::now();
+		}
+
+		notify_session_activated(&self.generation_sessions_listeners, session.session.clone());
+		if let Err(err) = session.start() {
+			warn!(target: "secretstore_net", "{}: failed to start queued generation session {}: {}", self.self_node_id, session_id, err);
+			session.session.on_session_timeout();
+			self.remove_generation_session(&session_id);
+		}
+	}
+
+	pub fn generation_session(&self, session_id: &SessionId) -> Option<Arc<GenerationSessionImpl>> {
+		self.generation_sessions.read().get(session_id).map(|s| s.session.clone())
+	}
+
+	/// Get generation session && refresh its last message time. If session is not yet created, message is buffered until it is created.
+	/// Messages for recently completed sessions are rejected with SessionAlreadyCompleted error.
+	pub fn generation_session_or_enqueue(&self, session_id: &SessionId, sender: &NodeId, message: &GenerationMessage) -> Result<Option<Arc<GenerationSessionImpl>>, Error> {
+		// hold the lock, so that session won't be created (or completed) until message is buffered
+		let generation_sessions = self.generation_sessions.read();
+		match generation_sessions.get(session_id) {
+			// only master is waiting for confirmations => these are late confirmations for the session, superseded by this one
+			Some(session) if is_initialization_confirmation(message) && session.master != self.self_node_id => {
+				trace!(target: "secretstore_net", "{}: ignoring message {} from node {} for superseded generation session", self.self_node_id, message, sender);
+				Ok(None)
+			},
+			Some(session) => {
+				*session.last_message_time.lock() = time::Instant::now();
+				Ok(Some(session.session.clone()))
+			},
+			None if self.completed_generation_sessions.contains(session_id) => {
+				trace!(target: "secretstore_net", "{}: rejecting message {} from node {} for completed generation session", self.self_node_id, message, sender);
+				Err(Error::SessionAlreadyCompleted)
+			},
+			None => {
+				trace!(target: "secretstore_net", "{}: postponing message {} from node {} until generation session is created", self.self_node_id, message, sender);
+				self.early_generation_messages.enqueue(session_id.clone(), sender.clone(), message.clone(), time::Instant::now());
+				Ok(None)
+			},
+		}
+	}
+
+	pub fn enqueue_generation_message(&self, session_id: &SessionId, sender: NodeId, message: GenerationMessage, is_queued_message: bool) {
+		self.generation_sessions.write().get_mut(session_id)
+			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
+				else { session.queue.push_back((sender, message)) });
+	}
+
+	pub fn dequeue_generation_message(&self, session_id: &SessionId) -> Option<(NodeId, GenerationMessage)> {
+		self.generation_sessions.write().get_mut(session_id)
+			.and_then(|session| session.queue.pop_front())
+	}
+
+	pub fn respond_with_generation_error(&self, session_id: &SessionId, error: message::SessionError) {
+		self.generation_sessions.read().get(session_id)
+			.map(|s| {
+				// error in generation session is considered fatal
+				// => broadcast error
+
+				// do not bother processing send error, as we already processing error
+				let _ = s.cluster_view.broadcast(Message::Generation(GenerationMessage::SessionError(error)));
+			});
+	}
+
+	#[cfg(test)]
+	pub fn make_faulty_generation_sessions(&self) {
+		self.make_faulty_generation_sessions.store(true, Ordering::Relaxed);
+	}
+
+	pub fn new_encryption_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Result<Arc<EncryptionSessionImpl>, Error> {
+		let mut encryption_sessions = self.encryption_sessions.write();
+		// check that there's no active encryption session with the same id
+		if encryption_sessions.contains_key(&session_id) {
+			return Err(Error::DuplicateSessionId);
+		}
+
+		let is_slave_session = master != self.self_node_id;
+		let session = self.insert_encryption_session(&mut *encryption_sessions, master, session_id, cluster);
+		notify_session_inserted(&self.encryption_sessions_listeners, session.clone());
+		if is_slave_session {
+			notify_session_activated(&self.encryption_sessions_listeners, session.clone());
+		}
+		Ok(session)
+	}
+
+	/// Create encryption session, started by another node. If there's already encryption session with the same id, started
+	/// by another master, only session of the preferred master survives. Returns None if the initialization must be ignored.
+	pub fn new_slave_encryption_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>, message: &message::InitializeEncryptionSession) -> Result<Option<Arc<EncryptionSessionImpl>>, Error> {
+		let (session, superseded_session, is_same_request) = {
+			let mut encryption_sessions = self.encryption_sessions.write();
+			let active_master = match encryption_sessions.get(&session_id) {
+				Some(session) if session.master == master => return Err(Error::DuplicateSessionId),
+				// session, queued at this node, has not been announced to other nodes yet => it always yields
+				Some(session) if session.master == self.self_node_id && self.encryption_sessions_queue.is_queued(&session_id) => None,
+				Some(session) => Some(session.master.clone()),
+				// key holders are completing session right after initialization => check recently completed sessions
+				None => self.completed_encryption_sessions.value(&session_id),
+			};
+			if let Some(active_master) = active_master {
+				if is_preferred_master(&active_master, &master) {
+					trace!(target: "secretstore_net", "{}: ignoring initialization of encryption session {} by {}: session is started by {}",
+						self.self_node_id, session_id, master, active_master);
+					return Ok(None);
+				}
+			}
+
+			let superseded_session = encryption_sessions.remove(&session_id);
+			// if this node has been asked to store the same document key => its request is served by the winning session
+			let is_same_request = superseded_session.as_ref()
+				.map(|s| s.master == self.self_node_id && s.session.is_same_document_key(message))
+				.unwrap_or(false);
+			let session = self.insert_encryption_session(&mut *encryption_sessions, master, session_id.clone(), cluster);
+			if !is_same_request {
+				notify_session_inserted(&self.encryption_sessions_listeners, session.clone());
+				notify_session_activated(&self.encryption_sessions_listeners, session.clone());
+			}
+			(session, superseded_session, is_same_request)
+		};
+
+		if let Some(superseded_session) = superseded_session {
+			if is_same_request {
+				trace_finished_session(&superseded_session.cluster_view, &superseded_session.session.state());
+				notify_session_replaced(&self.encryption_sessions_listeners, superseded_session.session, session.clone());
+			} else {
+				superseded_session.session.on_session_superseded();
+				trace_finished_session(&superseded_session.cluster_view, &superseded_session.session.state());
+				notify_session_removed(&self.encryption_sessions_listeners, superseded_session.session);
+			}
+			for (session_id, session) in self.encryption_sessions_queue.remove(&session_id) {
+				self.start_queued_encryption_session(session_id, session);
+			}
+		}
+
+		Ok(Some(session))
+	}
+
+	fn insert_encryption_session(&self, encryption_sessions: &mut BTreeMap<SessionId, QueuedEncryptionSession>, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Arc<EncryptionSessionImpl> {
+		let session = Arc::new(EncryptionSessionImpl::new(EncryptionSessionParams {
+			id: session_id.clone(),
+			self_node_id: self.self_node_id.clone(),
+			key_storage: self.key_storage.clone(),
+			cluster: cluster.clone(),
+		}));
+		let span = SessionSpan::new(SessionType::Encryption, session_id.clone(), master.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		let encryption_session = QueuedEncryptionSession {
+			master: master,
+			cluster_view: cluster,
+			last_message_time: Mutex::new(time::Instant::now()),
+			session: session.clone(),
+			queue: self.early_encryption_messages.take(&session_id),
+		};
+		encryption_sessions.insert(session_id, encryption_session);
+		session
+	}
+
+	/// Get tracing span of the active encryption session.
+	fn encryption_session_span(&self, session_id: &SessionId) -> Option<SessionSpan> {
+		self.encryption_sessions.read().get(session_id).and_then(|session| session.cluster_view.span())
+	}
+
+	pub fn remove_encryption_session(&self, session_id: &SessionId) {
+		let removed_session = {
+			let mut encryption_sessions = self.encryption_sessions.write();
+			let removed_session = encryption_sessions.remove(session_id);
+			if let Some(ref removed_session) = removed_session {
+				// master is remembered, so that concurrent initialization by another master could be arbitrated
+				self.completed_encryption_sessions.insert_with_value(session_id.clone(), removed_session.master.clone(), time::Instant::now());
+			}
+			removed_session
+		};
+		if let Some(removed_session) = removed_session {
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
+			notify_session_removed(&self.encryption_sessions_listeners, removed_session.session);
+		}
+		for (session_id, session) in self.encryption_sessions_queue.remove(session_id) {
+			self.start_queued_encryption_session(session_id, session);
+		}
+	}
+
+	/// Add encryption sessions listener.
+	pub fn add_encryption_sessions_listener(&self, listener: Arc<ClusterSessionsListener<EncryptionSessionImpl>>) {
+		self.encryption_sessions_listeners.write().push(Arc::downgrade(&listener));
+	}
+
+	/// Start encryption session, created by this node, or queue it if there are too many active sessions.
+	pub fn start_encryption_session(&self, session_id: SessionId, session: PendingEncryptionSession) -> Result<(), Error> {
+		let result = match self.encryption_sessions_queue.enqueue(session_id.clone(), session, time::Instant::now()) {
+			Ok(Some(session)) => {
+				notify_session_activated(&self.encryption_sessions_listeners, session.session.clone());
+				session.start()
+			},
+			Ok(None) => {
+				trace!(target: "secretstore_net", "{}: encryption session {} is queued", self.self_node_id, session_id);
+				Ok(())
+			},
+			Err(err) => Err(err),
+		};
+
+		if result.is_err() {
+			self.remove_encryption_session(&session_id);
+		}
+		result
+	}
+
+	fn start_queued_encryption_session(&self, session_id: SessionId, session: PendingEncryptionSession) {
+		// session timeout starts only when session is activated
+		if let Some(queued_session) = self.encryption_sessions.read().get(&session_id) {
+			*queued_session.last_message_time.lock() = time::Instant::now();
+		}
+
+		notify_session_activated(&self.encryption_sessions_listeners, session.session.clone());
+		if let Err(err) = session.start() {
+			warn!(target: "secretstore_net", "{}: failed to start queued encryption session {}: {}", self.self_node_id, session_id, err);
+			session.session.on_session_timeout();
+			self.remove_encryption_session(&session_id);
+		}
+	}
+
+	/// Get encryption session && refresh its last message time. If session is not yet created, message is buffered until it is created.
+	/// Messages for recently completed sessions are rejected with SessionAlreadyCompleted error.
+	pub fn encryption_session_or_enqueue(&self, session_id: &SessionId, sender: &NodeId, message: &EncryptionMessage) -> Result<Option<Arc<EncryptionSessionImpl>>, Error> {
+		// hold the lock, so that session won't be created (or completed) until message is buffered
+		let encryption_sessions = self.encryption_sessions.read();
+		match encryption_sessions.get(session_id) {
+			Some(session) => {
+				*session.last_message_time.lock() = time::Instant::now();
+				Ok(Some(session.session.clone()))
+			},
+			None if self.completed_encryption_sessions.contains(session_id) => {
+				trace!(target: "secretstore_net", "{}: rejecting message {} from node {} for completed encryption session", self.self_node_id, message, sender);
+				Err(Error::SessionAlreadyCompleted)
+			},
+			None => {
+				trace!(target: "secretstore_net", "{}: postponing message {} from node {} until encryption session is created", self.self_node_id, message, sender);
+				self.early_encryption_messages.enqueue(session_id.clone(), sender.clone(), message.clone(), time::Instant::now());
+				Ok(None)
+			},
+		}
+	}
+
+	pub fn enqueue_encryption_message(&self, session_id: &SessionId, sender: NodeId, message: EncryptionMessage, is_queued_message: bool) {
+		self.encryption_sessions.write().get_mut(session_id)
+			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
+				else { session.queue.push_back((sender, message)) });
+	}
+
+	pub fn dequeue_encryption_message(&self, session_id: &SessionId) -> Option<(NodeId, EncryptionMessage)> {
+		self.encryption_sessions.write().get_mut(session_id)
+			.and_then(|session| session.queue.pop_front())
+	}
+
+	/// Cancel encryption session, started by this node. Every other key holder is notified with session error.
+	pub fn cancel_encryption_session(&self, session_id: &SessionId) {
+		self.encryption_sessions.read().get(session_id)
+			.map(|s| {
+				// do not bother processing send error, as we already processing error
+				let _ = s.cluster_view.broadcast(Message::Encryption(EncryptionMessage::EncryptionSessionError(message::EncryptionSessionError {
+					session: session_id.clone().into(),
+					error: "session has been cancelled".into(),
+				})));
+			});
+		self.remove_encryption_session(session_id);
+	}
+
+	pub fn respond_with_encryption_error(&self, session_id: &SessionId, to: &NodeId, error: message::EncryptionSessionError) {
+		self.encryption_sessions.read().get(session_id)
+			.map(|s| {
+				// error on master node is not reported: key holders are not waiting for anything from master
+				// error on key holder must be reported to master, which is waiting for confirmation
+
+				// do not bother processing send error, as we already processing error
+				if &s.master != s.session.node() {
+					let _ = s.cluster_view.send(to, Message::Encryption(EncryptionMessage::EncryptionSessionError(error)));
+				}
+			});
+	}
+
+	pub fn new_decryption_session(&self, master: NodeId, session_id: SessionId, sub_session_id: Secret, cluster: Arc<ClusterView>) -> Result<Arc<DecryptionSessionImpl>, Error> {
+		let mut decryption_sessions = self.decryption_sessions.write();
+		let session_id = DecryptionSessionId::new(session_id, sub_session_id);
+		if decryption_sessions.contains_key(&session_id) {
+			return Err(Error::DuplicateSessionId);
+		}
+
+		// some of nodes, which were encrypting secret may be down
+		// => do not use these in decryption session
+		let mut encrypted_data = self.key_storage.get(&session_id.id).map_err(|e| Error::KeyStorage(e.into()))?;
+		{
+			let key_version = encrypted_data.last_version_mut().map_err(|e| Error::KeyStorage(e.into()))?;
+			let disconnected_nodes: BTreeSet<_> = key_version.id_numbers.keys().cloned().collect();
+			let disconnected_nodes: BTreeSet<_> = disconnected_nodes.difference(&cluster.nodes()).cloned().collect();
+			for disconnected_node in disconnected_nodes {
+				key_version.id_numbers.remove(&disconnected_node);
+			}
+		}
+
+		let session = Arc::new(DecryptionSessionImpl::new(DecryptionSessionParams {
+			id: session_id.id.clone(),
+			access_key: session_id.access_key.clone(),
+			self_node_id: self.self_node_id.clone(),
+			encrypted_data: encrypted_data,
+			acl_storage: self.acl_storage.clone(),
+			cluster: cluster.clone(),
+		})?);
+		let span = SessionSpan::new(SessionType::Decryption, session_id.id.clone(), master.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		let is_slave_session = master != self.self_node_id;
+		let decryption_session = QueuedDecryptionSession {
+			master: master,
+			cluster_view: cluster,
+			last_message_time: Mutex::new(time::Instant::now()),
+			session: session.clone(),
+			queue: self.early_decryption_messages.take(&session_id),
+		};
+		decryption_sessions.insert(session_id, decryption_session);
+		notify_session_inserted(&self.decryption_sessions_listeners, session.clone());
+		if is_slave_session {
+			notify_session_activated(&self.decryption_sessions_listeners, session.clone());
+		}
+		Ok(session)
+	}
+
+	/// Get tracing span of the active decryption session.
+	fn decryption_session_span(&self, session_id: &SessionId, sub_session_id: &Secret) -> Option<SessionSpan> {
+		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.decryption_sessions.read().get(&session_id).and_then(|session| session.cluster_view.span())
+	}
+
+	pub fn remove_decryption_session(&self, session_id: &SessionId, sub_session_id: &Secret) {
+		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		let removed_session = {
+			let mut decryption_sessions = self.decryption_sessions.write();
+			let removed_session = decryption_sessions.remove(&session_id);
+			if removed_session.is_some() {
+				self.completed_decryption_sessions.insert(session_id.clone(), time::Instant::now());
+			}
+			removed_session
+		};
+		if let Some(removed_session) = removed_session {
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
+			notify_session_removed(&self.decryption_sessions_listeners, removed_session.session);
+		}
+		for (session_id, session) in self.decryption_sessions_queue.remove(&session_id) {
+			self.start_queued_decryption_session(session_id, session);
+		}
+	}
+
+	/// Cancel decryption session, started by this node. Every other node is notified with session error.
+	pub fn cancel_decryption_session(&self, session_id: &SessionId, sub_session_id: &Secret) {
+		self.respond_with_decryption_error(session_id, sub_session_id, &self.self_node_id, message::DecryptionSessionError {
+			session: session_id.clone().into(),
+			sub_session: sub_session_id.clone().into(),
+			error: "session has been cancelled".into(),
+		});
+		self.remove_decryption_session(session_id, sub_session_id);
+	}
+
+	/// Add decryption sessions listener.
+	pub fn add_decryption_sessions_listener(&self, listener: Arc<ClusterSessionsListener<DecryptionSessionImpl>>) {
+		self.decryption_sessions_listeners.write().push(Arc::downgrade(&listener));
+	}
+
+	/// Start decryption session, created by this node, or queue it if there are too many active sessions.
+	pub fn start_decryption_session(&self, session_id: SessionId, sub_session_id: Secret, session: PendingDecryptionSession) -> Result<(), Error> {
+		let decryption_session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		let result = match self.decryption_sessions_queue.enqueue(decryption_session_id, session, time::Instant::now()) {
+			Ok(Some(session)) => {
+				notify_session_activated(&self.decryption_sessions_listeners, session.session.clone());
+				session.start()
+			},
+			Ok(None) => {
+				trace!(target: "secretstore_net", "{}: decryption session {} is queued", self.self_node_id, session_id);
+				Ok(())
+			},
+			Err(err) => Err(err),
+		};
+
+		if result.is_err() {
+			self.remove_decryption_session(&session_id, &sub_session_id);
+		}
+		result
+	}
+
+	fn start_queued_decryption_session(&self, session_id: DecryptionSessionId, session: PendingDecryptionSession) {
+		// session timeout starts only when session is activated
+		if let Some(queued_session) = self.decryption_sessions.read().get(&session_id) {
+			*queued_session.last_message_time.lock() = time::Instant::now();
+		}
+
+		notify_session_activated(&self.decryption_sessions_listeners, session.session.clone());
+		if let Err(err) = session.start() {
+			warn!(target: "secretstore_net", "{}: failed to start queued decryption session {}: {}", self.self_node_id, session_id.id, err);
+			session.session.on_session_timeout();
+			self.remove_decryption_session(&session_id.id, &session_id.access_key);
+		}
+	}
+
+	/// Get decryption session && refresh its last message time. If session is not yet created, message is buffered until it is created.
+	/// Messages for recently completed sessions are rejected with SessionAlreadyCompleted error.
+	pub fn decryption_session_or_enqueue(&self, session_id: &SessionId, sub_session_id: &Secret, sender: &NodeId, message: &DecryptionMessage) -> Result<Option<Arc<DecryptionSessionImpl>>, Error> {
+		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		// hold the lock, so that session won't be created (or completed) until message is buffered
+		let decryption_sessions = self.decryption_sessions.read();
+		match decryption_sessions.get(&session_id) {
+			Some(session) => {
+				*session.last_message_time.lock() = time::Instant::now();
+				Ok(Some(session.session.clone()))
+			},
+			None if self.completed_decryption_sessions.contains(&session_id) => {
+				trace!(target: "secretstore_net", "{}: rejecting message {} from node {} for completed decryption session", self.self_node_id, message, sender);
+				Err(Error::SessionAlreadyCompleted)
+			},
+			None => {
+				trace!(target: "secretstore_net", "{}: postponing message {} from node {} until decryption session is created", self.self_node_id, message, sender);
+				self.early_decryption_messages.enqueue(session_id, sender.clone(), message.clone(), time::Instant::now());
+				Ok(None)
+			},
+		}
+	}
+
+	pub fn enqueue_decryption_message(&self, session_id: &SessionId, sub_session_id: &Secret, sender: NodeId, message: DecryptionMessage, is_queued_message: bool) {
+		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.decryption_sessions.write().get_mut(&session_id)
+			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
+				else { session.queue.push_back((sender, message)) });
+	}
+
+	pub fn dequeue_decryption_message(&self, session_id: &SessionId, sub_session_id: &Secret) -> Option<(NodeId, DecryptionMessage)> {
+		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.decryption_sessions.write().get_mut(&session_id)
+			.and_then(|session| session.queue.pop_front())
+	}
+
+	pub fn respond_with_decryption_error(&self, session_id: &SessionId, sub_session_id: &Secret, to: &NodeId, error: message::DecryptionSessionError) {
+		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.decryption_sessions.read().get(&session_id)
+			.map(|s| {
+				// error in decryption session is non-fatal, if occurs on slave node
+				// => either respond with error
+				// => or broadcast error
+
+				// do not bother processing send error, as we already processing error
+				if &s.master == s.session.node() {
+					let _ = s.cluster_view.broadcast(Message::Decryption(DecryptionMessage::DecryptionSessionError(error)));
+				} else {
+					let _ = s.cluster_view.send(to, Message::Decryption(DecryptionMessage::DecryptionSessionError(error)));
+				}
+			});
+	}
+
+	pub fn new_signing_session(&self, master: NodeId, session_id: SessionId, sub_session_id: Secret, cluster: Arc<ClusterView>) -> Result<Arc<SigningSessionImpl>, Error> {
+		let mut signing_sessions = self.signing_sessions.write();
+		let session_id = SigningSessionId::new(session_id, sub_session_id);
+		if signing_sessions.contains_key(&session_id) {
+			return Err(Error::DuplicateSessionId);
+		}
+
+		// some of key holders may be down
+		// => do not use these in signing session
+		let mut key_share = match self.key_storage.get(&session_id.id) {
+			Ok(key_share) => key_share,
+			Err(KeyStorageError::DocumentNotFound) => return Err(Error::ServerKeyIsNotFound),
+			Err(err) => return Err(Error::KeyStorage(err.into())),
+		};
+		{
+			let key_version = key_share.last_version_mut().map_err(|e| Error::KeyStorage(e.into()))?;
+			let disconnected_nodes: BTreeSet<_> = key_version.id_numbers.keys().cloned().collect();
+			let disconnected_nodes: BTreeSet<_> = disconnected_nodes.difference(&cluster.nodes()).cloned().collect();
+			for disconnected_node in disconnected_nodes {
+				key_version.id_numbers.remove(&disconnected_node);
+			}
+		}
+
+		let session = Arc::new(SigningSessionImpl::new(SigningSessionParams {
+			id: session_id.id.clone(),
+			access_key: session_id.access_key.clone(),
+			self_node_id: self.self_node_id.clone(),
+			key_share: key_share,
+			acl_storage: self.acl_storage.clone(),
+			cluster: cluster.clone(),
+		})?);
+		let span = SessionSpan::new(SessionType::Signing, session_id.id.clone(), master.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		let is_slave_session = master != self.self_node_id;
+		let signing_session = QueuedSigningSession {
+			master: master,
+			cluster_view: cluster,
+			last_message_time: Mutex::new(time::Instant::now()),
+			session: session.clone(),
+			queue: self.early_signing_messages.take(&session_id),
+		};
+		signing_sessions.insert(session_id, signing_session);
+		notify_session_inserted(&self.signing_sessions_listeners, session.clone());
+		if is_slave_session {
+			notify_session_activated(&self.signing_sessions_listeners, session.clone());
+		}
+		Ok(session)
+	}
 
-		for (node_id, &(ref node_addr, node_port)) in config.nodes.iter().filter(|&(node_id, _)| node_id != config.self_key_pair.public()) {
-			let socket_address = make_socket_address(&node_addr, node_port)?;
-			connections.nodes.insert(node_id.clone(), socket_address);
+	/// Get tracing span of the active signing session.
+	fn signing_session_span(&self, session_id: &SessionId, sub_session_id: &Secret) -> Option<SessionSpan> {
+		let session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.signing_sessions.read().get(&session_id).and_then(|session| session.cluster_view.span())
+	}
+
+	pub fn remove_signing_session(&self, session_id: &SessionId, sub_session_id: &Secret) {
+		let session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		let removed_session = {
+			let mut signing_sessions = self.signing_sessions.write();
+			let removed_session = signing_sessions.remove(&session_id);
+			if removed_session.is_some() {
+				self.completed_signing_sessions.insert(session_id.clone(), time::Instant::now());
+			}
+			removed_session
+		};
+		if let Some(removed_session) = removed_session {
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
+			notify_session_removed(&self.signing_sessions_listeners, removed_session.session);
 		}
+		for (session_id, session) in self.signing_sessions_queue.remove(&session_id) {
+			self.start_queued_signing_session(session_id, session);
+		}
+	}
 
-		Ok(connections)
+	/// Cancel signing session, started by this node. Every other node is notified with session error.
+	pub fn cancel_signing_session(&self, session_id: &SessionId, sub_session_id: &Secret) {
+		self.respond_with_signing_error(session_id, sub_session_id, &self.self_node_id, message::SigningSessionError {
+			session: session_id.clone().into(),
+			sub_session: sub_session_id.clone().into(),
+			error: "session has been cancelled".into(),
+		});
+		self.remove_signing_session(session_id, sub_session_id);
 	}
 
-	pub fn cluster_state(&self) -> ClusterState {
-		ClusterState {
-			connected: self.connections.read().keys().cloned().collect(),
+	/// Add signing sessions listener.
+	pub fn add_signing_sessions_listener(&self, listener: Arc<ClusterSessionsListener<SigningSessionImpl>>) {
+		self.signing_sessions_listeners.write().push(Arc::downgrade(&listener));
+	}
+
+	/// Start signing session, created by this node, or queue it if there are too many active sessions.
+	pub fn start_signing_session(&self, session_id: SessionId, sub_session_id: Secret, session: PendingSigningSession) -> Result<(), Error> {
+		let signing_session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		let result = match self.signing_sessions_queue.enqueue(signing_session_id, session, time::Instant::now()) {
+			Ok(Some(session)) => {
+				notify_session_activated(&self.signing_sessions_listeners, session.session.clone());
+				session.start()
+			},
+			Ok(None) => {
+				trace!(target: "secretstore_net", "{}: signing session {} is queued", self.self_node_id, session_id);
+				Ok(())
+			},
+			Err(err) => Err(err),
+		};
+
+		if result.is_err() {
+			self.remove_signing_session(&session_id, &sub_session_id);
 		}
+		result
 	}
 
-	pub fn get(&self, node: &NodeId) -> Option<Arc<Connection>> {
-		self.connections.read().get(node).cloned()
+	fn start_queued_signing_session(&self, session_id: SigningSessionId, session: PendingSigningSession) {
+		// session timeout starts only when session is activated
+		if let Some(queued_session) = self.signing_sessions.read().get(&session_id) {
+			*queued_session.last_message_time.lock() = time::Instant::now();
+		}
+
+		notify_session_activated(&self.signing_sessions_listeners, session.session.clone());
+		if let Err(err) = session.start() {
+			warn!(target: "secretstore_net", "{}: failed to start queued signing session {}: {}", self.self_node_id, session_id.id, err);
+			session.session.on_session_timeout();
+			self.remove_signing_session(&session_id.id, &session_id.access_key);
+		}
 	}
 
-	pub fn insert(&self, connection: Arc<Connection>) -> bool {
-		let mut connections = self.connections.write();
-		if connections.contains_key(connection.node_id()) {
-			// we have already connected to the same node
-			// the agreement is that node with lower id must establish connection to node with higher id
-			if (&self.self_node_id < connection.node_id() && connection.is_inbound())
-				|| (&self.self_node_id > connection.node_id() && !connection.is_inbound()) {
-				return false;
-			}
+	/// Get signing session && refresh its last message time. If session is not yet created, message is buffered until it is created.
+	/// Messages for recently completed sessions are rejected with SessionAlreadyCompleted error.
+	pub fn signing_session_or_enqueue(&self, session_id: &SessionId, sub_session_id: &Secret, sender: &NodeId, message: &SigningMessage) -> Result<Option<Arc<SigningSessionImpl>>, Error> {
+		let session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		// hold the lock, so that session won't be created (or completed) until message is buffered
+		let signing_sessions = self.signing_sessions.read();
+		match signing_sessions.get(&session_id) {
+			Some(session) => {
+				*session.last_message_time.lock() = time::Instant::now();
+				Ok(Some(session.session.clone()))
+			},
+			None if self.completed_signing_sessions.contains(&session_id) => {
+				trace!(target: "secretstore_net", "{}: rejecting message {} from node {} for completed signing session", self.self_node_id, message, sender);
+				Err(Error::SessionAlreadyCompleted)
+			},
+			None => {
+				trace!(target: "secretstore_net", "{}: postponing message {} from node {} until signing session is created", self.self_node_id, message, sender);
+				self.early_signing_messages.enqueue(session_id, sender.clone(), message.clone(), time::Instant::now());
+				Ok(None)
+			},
 		}
+	}
 
-		trace!(target: "secretstore_net", "{}: inserting connection to {} at {}", self.self_node_id, connection.node_id(), connection.node_address());
-		connections.insert(connection.node_id().clone(), connection);
-		true
+	pub fn enqueue_signing_message(&self, session_id: &SessionId, sub_session_id: &Secret, sender: NodeId, message: SigningMessage, is_queued_message: bool) {
+		let session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.signing_sessions.write().get_mut(&session_id)
+			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
+				else { session.queue.push_back((sender, message)) });
 	}
 
-	pub fn remove(&self, node: &NodeId, is_inbound: bool) {
-		let mut connections = self.connections.write();
-		if let Entry::Occupied(entry) = connections.entry(node.clone()) {
-			if entry.get().is_inbound() != is_inbound {
-				return;
-			}
+	pub fn dequeue_signing_message(&self, session_id: &SessionId, sub_session_id: &Secret) -> Option<(NodeId, SigningMessage)> {
+		let session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.signing_sessions.write().get_mut(&session_id)
+			.and_then(|session| session.queue.pop_front())
+	}
 
-			trace!(target: "secretstore_net", "{}: removing connection to {} at {}", self.self_node_id, entry.get().node_id(), entry.get().node_address());
-			entry.remove_entry();
+	pub fn respond_with_signing_error(&self, session_id: &SessionId, sub_session_id: &Secret, to: &NodeId, error: message::SigningSessionError) {
+		let session_id = SigningSessionId::new(session_id.clone(), sub_session_id.clone());
+		self.signing_sessions.read().get(&session_id)
+			.map(|s| {
+				// error in signing session is non-fatal, if occurs on slave node
+				// => either respond with error
+				// => or broadcast error
+
+				// do not bother processing send error, as we already processing error
+				if &s.master == s.session.node() {
+					let _ = s.cluster_view.broadcast(Message::Signing(SigningMessage::SigningSessionError(error)));
+				} else {
+					let _ = s.cluster_view.send(to, Message::Signing(SigningMessage::SigningSessionError(error)));
+				}
+			});
+	}
+
+	pub fn new_share_add_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>, servers_set_change_session: Option<SessionId>) -> Result<Arc<ShareAddSessionImpl>, Error> {
+		let mut share_add_sessions = self.share_add_sessions.write();
+		// check that there's no active share add session with the same id
+		if share_add_sessions.contains_key(&session_id) {
+			return Err(Error::DuplicateSessionId);
 		}
+
+		let session = Arc::new(ShareAddSessionImpl::new(ShareAddSessionParams {
+			id: session_id.clone(),
+			self_node_id: self.self_node_id.clone(),
+			admin_public: self.admin_public.clone(),
+			key_storage: self.key_storage.clone(),
+			cluster: cluster.clone(),
+		}));
+		let span = SessionSpan::new(SessionType::ShareAdd, session_id.clone(), master.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		let share_add_session = QueuedShareAddSession {
+			master: master,
+			cluster_view: cluster,
+			last_message_time: Mutex::new(time::Instant::now()),
+			session: session.clone(),
+			queue: self.early_share_add_messages.take(&session_id),
+			servers_set_change_session: servers_set_change_session,
+		};
+		share_add_sessions.insert(session_id, share_add_session);
+		Ok(session)
 	}
 
-	pub fn connected_nodes(&self) -> BTreeSet<NodeId> {
-		self.connections.read().keys().cloned().collect()
+	/// Get tracing span of the active share add session.
+	fn share_add_session_span(&self, session_id: &SessionId) -> Option<SessionSpan> {
+		self.share_add_sessions.read().get(session_id).and_then(|session| session.cluster_view.span())
 	}
 
-	pub fn active_connections(&self)-> Vec<Arc<Connection>> {
-		self.connections.read().values().cloned().collect()
+	pub fn remove_share_add_session(&self, session_id: &SessionId) {
+		let removed_session = self.share_add_sessions.write().remove(session_id);
+		if let Some(removed_session) = removed_session {
+			self.completed_share_add_sessions.insert(session_id.clone(), time::Instant::now());
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
+
+			// report share add session result to the servers set change session, which has started it
+			if let Some(servers_set_change_session_id) = removed_session.servers_set_change_session {
+				let result = removed_session.session.result().unwrap_or(Err(Error::NodeDisconnected));
+				self.on_share_add_session_completed(&servers_set_change_session_id, session_id, result);
+			}
+		}
+		for (session_id, session) in self.share_add_sessions_queue.remove(session_id) {
+			self.start_queued_share_add_session(session_id, session);
+		}
 	}
 
-	pub fn disconnected_nodes(&self) -> BTreeMap<NodeId, SocketAddr> {
-		let connections = self.connections.read();
-		self.nodes.iter()
-			.filter(|&(node_id, _)| !connections.contains_key(node_id))
-			.map(|(node_id, node_address)| (node_id.clone(), node_address.clone()))
-			.collect()
+	/// Start share add session, created by this node, or queue it if there are too many active sessions.
+	pub fn start_share_add_session(&self, session_id: SessionId, session: PendingShareAddSession) -> Result<(), Error> {
+		let result = match self.share_add_sessions_queue.enqueue(session_id.clone(), session, time::Instant::now()) {
+			Ok(Some(session)) => session.start(),
+			Ok(None) => {
+				trace!(target: "secretstore_net", "{}: share add session {} is queued", self.self_node_id, session_id);
+				Ok(())
+			},
+			Err(err) => Err(err),
+		};
+
+		if result.is_err() {
+			self.remove_share_add_session(&session_id);
+		}
+		result
 	}
-}
 
-impl ClusterSessions {
-	pub fn new(config: &ClusterConfiguration) -> Self {
-		ClusterSessions {
-			self_node_id: config.self_key_pair.public().clone(),
-			nodes: config.nodes.keys().cloned().collect(),
-			acl_storage: config.acl_storage.clone(),
-			key_storage: config.key_storage.clone(),
-			encryption_sessions: RwLock::new(BTreeMap::new()),
-			decryption_sessions: RwLock::new(BTreeMap::new()),
-			make_faulty_encryption_sessions: AtomicBool::new(false),
+	fn start_queued_share_add_session(&self, session_id: SessionId, session: PendingShareAddSession) {
+		// session timeout starts only when session is activated
+		if let Some(queued_session) = self.share_add_sessions.read().get(&session_id) {
+			*queued_session.last_message_time.lock() = time::Instant::now();
+		}
+
+		if let Err(err) = session.start() {
+			warn!(target: "secretstore_net", "{}: failed to start queued share add session {}: {}", self.self_node_id, session_id, err);
+			session.session.on_session_timeout();
+			self.remove_share_add_session(&session_id);
 		}
 	}
 
-	pub fn new_encryption_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Result<Arc<EncryptionSessionImpl>, Error> {
-		let mut encryption_sessions = self.encryption_sessions.write();
-		// check that there's no active encryption session with the same id
-		if encryption_sessions.contains_key(&session_id) {
-			return Err(Error::DuplicateSessionId);
+	/// Get share add session && refresh its last message time. If session is not yet created, message is buffered until it is created.
+	/// Messages for recently completed sessions are rejected with SessionAlreadyCompleted error.
+	pub fn share_add_session_or_enqueue(&self, session_id: &SessionId, sender: &NodeId, message: &ShareAddMessage) -> Result<Option<Arc<ShareAddSessionImpl>>, Error> {
+		// hold the lock, so that session won't be created (or completed) until message is buffered
+		let share_add_sessions = self.share_add_sessions.read();
+		match share_add_sessions.get(session_id) {
+			Some(session) => {
+				*session.last_message_time.lock() = time::Instant::now();
+				// servers set change session is not stalled while its share add sessions are active
+				if let Some(ref servers_set_change_session_id) = session.servers_set_change_session {
+					self.servers_set_change_session(servers_set_change_session_id);
+				}
+				Ok(Some(session.session.clone()))
+			},
+			None if self.completed_share_add_sessions.contains(session_id) => {
+				trace!(target: "secretstore_net", "{}: rejecting message {} from node {} for completed share add session", self.self_node_id, message, sender);
+				Err(Error::SessionAlreadyCompleted)
+			},
+			None => {
+				trace!(target: "secretstore_net", "{}: postponing message {} from node {} until share add session is created", self.self_node_id, message, sender);
+				self.early_share_add_messages.enqueue(session_id.clone(), sender.clone(), message.clone(), time::Instant::now());
+				Ok(None)
+			},
 		}
-		// check that there's no finished encryption session with the same id
-		if self.key_storage.contains(&session_id) {
+	}
+
+	pub fn enqueue_share_add_message(&self, session_id: &SessionId, sender: NodeId, message: ShareAddMessage, is_queued_message: bool) {
+		self.share_add_sessions.write().get_mut(session_id)
+			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
+				else { session.queue.push_back((sender, message)) });
+	}
+
+	pub fn dequeue_share_add_message(&self, session_id: &SessionId) -> Option<(NodeId, ShareAddMessage)> {
+		self.share_add_sessions.write().get_mut(session_id)
+			.and_then(|session| session.queue.pop_front())
+	}
+
+	pub fn respond_with_share_add_error(&self, session_id: &SessionId, error: message::ShareAddSessionError) {
+		self.share_add_sessions.read().get(session_id)
+			.map(|s| {
+				// error on master node is broadcasted by the session itself
+				// error on other node must be reported to master, which asks every other node to discard new version
+
+				// do not bother processing send error, as we already processing error
+				if &s.master != s.session.node() {
+					let _ = s.cluster_view.send(&s.master, Message::ShareAdd(ShareAddMessage::ShareAddSessionError(error)));
+				}
+			});
+	}
+
+	pub fn new_servers_set_change_session(&self, session_id: SessionId, cluster: Arc<ClusterView>, executor: Arc<ShareAddSessionsExecutor>, max_active_migrations: usize) -> Result<Arc<ServersSetChangeSessionImpl>, Error> {
+		let mut servers_set_change_sessions = self.servers_set_change_sessions.write();
+		// check that there's no active servers set change session with the same id
+		if servers_set_change_sessions.contains_key(&session_id) {
 			return Err(Error::DuplicateSessionId);
 		}
 
-		// communicating to all other nodes is crucial for encryption session
-		// => check that we have connections to all cluster nodes
-		if self.nodes.iter().any(|n| !cluster.is_connected(n)) {
-			return Err(Error::NodeDisconnected);
+		let session = Arc::new(ServersSetChangeSessionImpl::new(ServersSetChangeSessionParams {
+			id: session_id.clone(),
+			self_node_id: self.self_node_id.clone(),
+			admin_public: self.admin_public.clone(),
+			key_storage: self.key_storage.clone(),
+			cluster: cluster.clone(),
+			executor: executor,
+			max_active_migrations: max_active_migrations,
+		}));
+		// servers set change session is only created on master node
+		let span = SessionSpan::new(SessionType::ServersSetChange, session_id.clone(), self.self_node_id.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		servers_set_change_sessions.insert(session_id, QueuedServersSetChangeSession {
+			cluster_view: cluster,
+			last_message_time: Mutex::new(time::Instant::now()),
+			session: session.clone(),
+		});
+		Ok(session)
+	}
+
+	pub fn remove_servers_set_change_session(&self, session_id: &SessionId) {
+		let removed_session = self.servers_set_change_sessions.write().remove(session_id);
+		if let Some(removed_session) = removed_session {
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
 		}
+	}
 
-		let session = Arc::new(EncryptionSessionImpl::new(EncryptionSessionParams {
+	/// Get tracing span of the active servers set change session.
+	fn servers_set_change_session_span(&self, session_id: &SessionId) -> Option<SessionSpan> {
+		self.servers_set_change_sessions.read().get(session_id).and_then(|session| session.cluster_view.span())
+	}
+
+	/// Get servers set change session && refresh its last message time.
+	pub fn servers_set_change_session(&self, session_id: &SessionId) -> Option<Arc<ServersSetChangeSessionImpl>> {
+		self.servers_set_change_sessions.read().get(session_id)
+			.map(|session| {
+				*session.last_message_time.lock() = time::Instant::now();
+				session.session.clone()
+			})
+	}
+
+	fn on_share_add_session_completed(&self, servers_set_change_session_id: &SessionId, key_id: &SessionId, result: Result<(), Error>) {
+		// do not hold the lock: completion could start next share add session
+		let session = match self.servers_set_change_session(servers_set_change_session_id) {
+			Some(session) => session,
+			None => return,
+		};
+
+		session.on_share_add_session_completed(key_id, result);
+		let session_state = session.state();
+		if session_state == ServersSetChangeSessionState::Finished || session_state == ServersSetChangeSessionState::Failed {
+			self.remove_servers_set_change_session(servers_set_change_session_id);
+		}
+	}
+
+	pub fn new_key_removal_session(&self, master: NodeId, session_id: SessionId, cluster: Arc<ClusterView>) -> Result<Arc<KeyRemovalSessionImpl>, Error> {
+		let mut key_removal_sessions = self.key_removal_sessions.write();
+		// check that there's no active key removal session with the same id
+		if key_removal_sessions.contains_key(&session_id) {
+			return Err(Error::DuplicateSessionId);
+		}
+
+		let session = Arc::new(KeyRemovalSessionImpl::new(KeyRemovalSessionParams {
 			id: session_id.clone(),
 			self_node_id: self.self_node_id.clone(),
+			admin_public: self.admin_public.clone(),
 			key_storage: self.key_storage.clone(),
 			cluster: cluster.clone(),
 		}));
-		let encryption_session = QueuedEncryptionSession {
+		let span = SessionSpan::new(SessionType::KeyRemoval, session_id.clone(), master.clone(), self.self_node_id.clone());
+		span.on_started();
+		cluster.set_span(span);
+		let is_slave_session = master != self.self_node_id;
+		let key_removal_session = QueuedKeyRemovalSession {
 			master: master,
 			cluster_view: cluster,
-			last_message_time: time::Instant::now(),
+			last_message_time: Mutex::new(time::Instant::now()),
 			session: session.clone(),
-			queue: VecDeque::new()
+			queue: self.early_key_removal_messages.take(&session_id),
 		};
-		if self.make_faulty_encryption_sessions.load(Ordering::Relaxed) {
-			encryption_session.session.simulate_faulty_behaviour();
+		key_removal_sessions.insert(session_id, key_removal_session);
+		notify_session_inserted(&self.key_removal_sessions_listeners, session.clone());
+		if is_slave_session {
+			notify_session_activated(&self.key_removal_sessions_listeners, session.clone());
 		}
-		encryption_sessions.insert(session_id, encryption_session);
 		Ok(session)
 	}
 
-	pub fn remove_encryption_session(&self, session_id: &SessionId) {
-		self.encryption_sessions.write().remove(session_id);
+	/// Get tracing span of the active key removal session.
+	fn key_removal_session_span(&self, session_id: &SessionId) -> Option<SessionSpan> {
+		self.key_removal_sessions.read().get(session_id).and_then(|session| session.cluster_view.span())
 	}
 
-	pub fn encryption_session(&self, session_id: &SessionId) -> Option<Arc<EncryptionSessionImpl>> {
-		self.encryption_sessions.read().get(session_id).map(|s| s.session.clone())
+	pub fn remove_key_removal_session(&self, session_id: &SessionId) {
+		let removed_session = {
+			let mut key_removal_sessions = self.key_removal_sessions.write();
+			let removed_session = key_removal_sessions.remove(session_id);
+			if removed_session.is_some() {
+				self.completed_key_removal_sessions.insert(session_id.clone(), time::Instant::now());
+			}
+			removed_session
+		};
+		if let Some(removed_session) = removed_session {
+			trace_finished_session(&removed_session.cluster_view, &removed_session.session.state());
+			if removed_session.master == self.self_node_id {
+				self.on_key_removal_session_completed(session_id, &removed_session.session);
+			}
+			notify_session_removed(&self.key_removal_sessions_listeners, removed_session.session);
+		}
+		for (session_id, session) in self.key_removal_sessions_queue.remove(session_id) {
+			self.start_queued_key_removal_session(session_id, session);
+		}
 	}
 
-	pub fn enqueue_encryption_message(&self, session_id: &SessionId, sender: NodeId, message: EncryptionMessage, is_queued_message: bool) {
-		self.encryption_sessions.write().get_mut(session_id)
+	/// Add key removal sessions listener.
+	pub fn add_key_removal_sessions_listener(&self, listener: Arc<ClusterSessionsListener<KeyRemovalSessionImpl>>) {
+		self.key_removal_sessions_listeners.write().push(Arc::downgrade(&listener));
+	}
+
+	/// Start key removal session, created by this node, or queue it if there are too many active sessions.
+	pub fn start_key_removal_session(&self, session_id: SessionId, session: PendingKeyRemovalSession) -> Result<(), Error> {
+		let result = match self.key_removal_sessions_queue.enqueue(session_id.clone(), session, time::Instant::now()) {
+			Ok(Some(session)) => {
+				notify_session_activated(&self.key_removal_sessions_listeners, session.session.clone());
+				session.start()
+			},
+			Ok(None) => {
+				trace!(target: "secretstore_net", "{}: key removal session {} is queued", self.self_node_id, session_id);
+				Ok(())
+			},
+			Err(err) => Err(err),
+		};
+
+		if result.is_err() {
+			self.remove_key_removal_session(&session_id);
+		}
+		result
+	}
+
+	fn start_queued_key_removal_session(&self, session_id: SessionId, session: PendingKeyRemovalSession) {
+		// session timeout starts only when session is activated
+		if let Some(queued_session) = self.key_removal_sessions.read().get(&session_id) {
+			*queued_session.last_message_time.lock() = time::Instant::now();
+		}
+
+		notify_session_activated(&self.key_removal_sessions_listeners, session.session.clone());
+		if let Err(err) = session.start() {
+			warn!(target: "secretstore_net", "{}: failed to start queued key removal session {}: {}", self.self_node_id, session_id, err);
+			session.session.on_session_timeout();
+			self.remove_key_removal_session(&session_id);
+		}
+	}
+
+	/// Get key removal session && refresh its last message time. If session is not yet created, message is buffered until it is created.
+	/// Messages for recently completed sessions are rejected with SessionAlreadyCompleted error.
+	pub fn key_removal_session_or_enqueue(&self, session_id: &SessionId, sender: &NodeId, message: &KeyRemovalMessage) -> Result<Option<Arc<KeyRemovalSessionImpl>>, Error> {
+		// hold the lock, so that session won't be created (or completed) until message is buffered
+		let key_removal_sessions = self.key_removal_sessions.read();
+		match key_removal_sessions.get(session_id) {
+			Some(session) => {
+				*session.last_message_time.lock() = time::Instant::now();
+				Ok(Some(session.session.clone()))
+			},
+			None if self.completed_key_removal_sessions.contains(session_id) => {
+				trace!(target: "secretstore_net", "{}: rejecting message {} from node {} for completed key removal session", self.self_node_id, message, sender);
+				Err(Error::SessionAlreadyCompleted)
+			},
+			None => {
+				trace!(target: "secretstore_net", "{}: postponing message {} from node {} until key removal session is created", self.self_node_id, message, sender);
+				self.early_key_removal_messages.enqueue(session_id.clone(), sender.clone(), message.clone(), time::Instant::now());
+				Ok(None)
+			},
+		}
+	}
+
+	pub fn enqueue_key_removal_message(&self, session_id: &SessionId, sender: NodeId, message: KeyRemovalMessage, is_queued_message: bool) {
+		self.key_removal_sessions.write().get_mut(session_id)
 			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
 				else { session.queue.push_back((sender, message)) });
 	}
 
-	pub fn dequeue_encryption_message(&self, session_id: &SessionId) -> Option<(NodeId, EncryptionMessage)> {
-		self.encryption_sessions.write().get_mut(session_id)
+	pub fn dequeue_key_removal_message(&self, session_id: &SessionId) -> Option<(NodeId, KeyRemovalMessage)> {
+		self.key_removal_sessions.write().get_mut(session_id)
 			.and_then(|session| session.queue.pop_front())
 	}
 
-	pub fn respond_with_encryption_error(&self, session_id: &SessionId, error: message::SessionError) {
-		self.encryption_sessions.read().get(session_id)
+	pub fn respond_with_key_removal_error(&self, session_id: &SessionId, error: message::KeyRemovalSessionError) {
+		self.key_removal_sessions.read().get(session_id)
 			.map(|s| {
-				// error in encryption session is considered fatal
-				// => broadcast error
+				// error on master node is broadcasted by the session itself
+				// error on key holder must be reported to master, which asks every other key holder to discard staged removal
 
 				// do not bother processing send error, as we already processing error
-				let _ = s.cluster_view.broadcast(Message::Encryption(EncryptionMessage::SessionError(error)));
+				if &s.master != s.session.node() {
+					let _ = s.cluster_view.send(&s.master, Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(error)));
+				}
 			});
 	}
 
-	#[cfg(test)]
-	pub fn make_faulty_encryption_sessions(&self) {
-		self.make_faulty_encryption_sessions.store(true, Ordering::Relaxed);
+	/// Remember key holders, which have not confirmed removal during key removal session, started by this node.
+	/// Retries are persisted in the key storage, so that they survive restart.
+	fn on_key_removal_session_completed(&self, session_id: &SessionId, session: &KeyRemovalSessionImpl) {
+		// failed session changes nothing: either it is reported to requestor, or it is retried later
+		let pending_nodes = match session.result() {
+			Some(Ok(pending_nodes)) => pending_nodes,
+			_ => return,
+		};
+
+		let retry = if pending_nodes.is_empty() {
+			None
+		} else {
+			warn!(target: "secretstore_net", "{}: key {} removal is not confirmed by key holders {:?}", self.self_node_id, session_id, pending_nodes);
+			let (requestor_signature, nonce) = session.removal_request()
+				.expect("session has completed successfully; removal request is filled in initialization phase; qed");
+			Some(KeyRemovalRetry {
+				requestor_signature: requestor_signature,
+				nonce: nonce,
+				nodes: pending_nodes,
+			})
+		};
+
+		if let Err(err) = self.key_storage.set_key_removal_retry(session_id, retry) {
+			warn!(target: "secretstore_net", "{}: failed to remember key {} removal retry: {}", self.self_node_id, session_id, err);
+		}
 	}
 
-	pub fn new_decryption_session(&self, master: NodeId, session_id: SessionId, sub_session_id: Secret, cluster: Arc<ClusterView>) -> Result<Arc<DecryptionSessionImpl>, Error> {
-		let mut decryption_sessions = self.decryption_sessions.write();
-		let session_id = DecryptionSessionId::new(session_id, sub_session_id);
-		if decryption_sessions.contains_key(&session_id) {
-			return Err(Error::DuplicateSessionId);
+	/// Get key removals, which could be retried on given connected nodes. Key holders, which are no longer
+	/// the part of the cluster, are forgotten.
+	pub fn key_removals_to_retry(&self, connected_nodes: &BTreeSet<NodeId>) -> Vec<(SessionId, KeyRemovalRetry)> {
+		let key_removal_retries = match self.key_storage.key_removal_retries() {
+			Ok(key_removal_retries) => key_removal_retries,
+			Err(err) => {
+				warn!(target: "secretstore_net", "{}: failed to read key removal retries: {}", self.self_node_id, err);
+				return Vec::new();
+			},
+		};
+
+		let nodes = self.nodes.read();
+		let mut retries_to_start = Vec::new();
+		for (session_id, mut retry) in key_removal_retries {
+			let retry_nodes: BTreeSet<_> = retry.nodes.intersection(&*nodes).cloned().collect();
+			if retry_nodes.len() != retry.nodes.len() {
+				retry.nodes = retry_nodes;
+				let updated_retry = if retry.nodes.is_empty() { None } else { Some(retry.clone()) };
+				if let Err(err) = self.key_storage.set_key_removal_retry(&session_id, updated_retry) {
+					warn!(target: "secretstore_net", "{}: failed to update key {} removal retry: {}", self.self_node_id, session_id, err);
+				}
+			}
+
+			if retry.nodes.iter().any(|node| connected_nodes.contains(node)) {
+				retries_to_start.push((session_id, retry));
+			}
 		}
+		retries_to_start
+	}
 
-		// some of nodes, which were encrypting secret may be down
-		// => do not use these in decryption session
-		let mut encrypted_data = self.key_storage.get(&session_id.id).map_err(|e| Error::KeyStorage(e.into()))?;
-		let disconnected_nodes: BTreeSet<_> = encrypted_data.id_numbers.keys().cloned().collect();
-		let disconnected_nodes: BTreeSet<_> = disconnected_nodes.difference(&cluster.nodes()).cloned().collect();
-		for disconnected_node in disconnected_nodes {
-			encrypted_data.id_numbers.remove(&disconnected_node);
+	/// Called when connection to the node is established. Lets every active session, which requires this node, know that
+	/// the node is connected again. Returns key removals, which are waiting for this node.
+	pub fn on_connection_established(&self, node_id: &NodeId, connected_nodes: &BTreeSet<NodeId>) -> Vec<(SessionId, KeyRemovalRetry)> {
+		for cluster_view in self.cluster_views() {
+			cluster_view.on_node_connected(node_id);
+		}
+
+		self.key_removals_to_retry(connected_nodes).into_iter()
+			.filter(|&(_, ref retry)| retry.nodes.contains(node_id))
+			.collect()
+	}
+
+	/// Update set of cluster nodes.
+	pub fn update_nodes(&self, mut nodes: BTreeSet<NodeId>) {
+		nodes.insert(self.self_node_id.clone());
+		*self.nodes.write() = nodes;
+	}
+
+	/// Get cluster views of all active sessions.
+	fn cluster_views(&self) -> Vec<Arc<ClusterView>> {
+		// every container is locked separately
+		let mut cluster_views: Vec<_> = self.generation_sessions.read().values().map(|s| s.cluster_view.clone()).collect();
+		cluster_views.extend(self.encryption_sessions.read().values().map(|s| s.cluster_view.clone()));
+		cluster_views.extend(self.decryption_sessions.read().values().map(|s| s.cluster_view.clone()));
+		cluster_views.extend(self.share_add_sessions.read().values().map(|s| s.cluster_view.clone()));
+		cluster_views.extend(self.servers_set_change_sessions.read().values().map(|s| s.cluster_view.clone()));
+		cluster_views.extend(self.key_removal_sessions.read().values().map(|s| s.cluster_view.clone()));
+		cluster_views.extend(self.signing_sessions.read().values().map(|s| s.cluster_view.clone()));
+		cluster_views
+	}
+
+	/// Is given node participating in any active session?
+	pub fn has_active_sessions(&self, node_id: &NodeId) -> bool {
+		self.generation_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+			|| self.encryption_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+			|| self.decryption_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+			|| self.share_add_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+			|| self.servers_set_change_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+			|| self.key_removal_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+			|| self.signing_sessions.read().values().any(|s| s.cluster_view.is_connected(node_id))
+	}
+
+	/// When key servers set has signalled new migration.
+	pub fn on_key_server_set_migration(&self, migration: KeyServerSetMigration) {
+		trace!(target: "secretstore_net", "{}: key servers set has signalled migration {} to {:?}", self.self_node_id, migration.id, migration.set.keys().collect::<Vec<_>>());
+		*self.key_server_set_migration.write() = Some(migration);
+	}
+
+	fn stop_stalled_sessions(&self, now: time::Instant) {
+		// sessions are removed while iterating => do not hold the lock
+		// queued sessions are not started yet => they could not stall
+		let stalled_generation_sessions: Vec<_> = self.generation_sessions.read().iter()
+			.filter(|&(sid, session)| *session.last_message_time.lock() + self.timeouts.generation < now
+				&& !self.generation_sessions_queue.is_queued(sid))
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_generation_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == GenerationSessionState::Finished
+				|| session.state() == GenerationSessionState::Failed {
+				self.remove_generation_session(&sid);
+			}
+		}
+
+		let stalled_encryption_sessions: Vec<_> = self.encryption_sessions.read().iter()
+			.filter(|&(sid, session)| *session.last_message_time.lock() + self.timeouts.encryption < now
+				&& !self.encryption_sessions_queue.is_queued(sid))
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_encryption_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == EncryptionSessionState::Finished
+				|| session.state() == EncryptionSessionState::Failed {
+				self.remove_encryption_session(&sid);
+			}
+		}
+
+		let stalled_decryption_sessions: Vec<_> = self.decryption_sessions.read().iter()
+			.filter(|&(sid, session)| *session.last_message_time.lock() + self.timeouts.decryption < now
+				&& !self.decryption_sessions_queue.is_queued(sid))
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_decryption_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == DecryptionSessionState::Finished
+				|| session.state() == DecryptionSessionState::Failed {
+				self.remove_decryption_session(&sid.id, &sid.access_key);
+			}
+		}
+
+		let stalled_share_add_sessions: Vec<_> = self.share_add_sessions.read().iter()
+			.filter(|&(sid, session)| *session.last_message_time.lock() + self.timeouts.share_add < now
+				&& !self.share_add_sessions_queue.is_queued(sid))
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_share_add_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == ShareAddSessionState::Finished
+				|| session.state() == ShareAddSessionState::Failed {
+				self.remove_share_add_session(&sid);
+			}
+		}
+
+		let stalled_key_removal_sessions: Vec<_> = self.key_removal_sessions.read().iter()
+			.filter(|&(sid, session)| *session.last_message_time.lock() + self.timeouts.key_removal < now
+				&& !self.key_removal_sessions_queue.is_queued(sid))
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_key_removal_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == KeyRemovalSessionState::Finished
+				|| session.state() == KeyRemovalSessionState::Failed {
+				self.remove_key_removal_session(&sid);
+			}
+		}
+
+		let stalled_signing_sessions: Vec<_> = self.signing_sessions.read().iter()
+			.filter(|&(sid, session)| *session.last_message_time.lock() + self.timeouts.signing < now
+				&& !self.signing_sessions_queue.is_queued(sid))
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_signing_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == SigningSessionState::Finished
+				|| session.state() == SigningSessionState::Failed {
+				self.remove_signing_session(&sid.id, &sid.access_key);
+			}
+		}
+
+		let stalled_servers_set_change_sessions: Vec<_> = self.servers_set_change_sessions.read().iter()
+			.filter(|&(_, session)| *session.last_message_time.lock() + self.timeouts.servers_set_change < now)
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in stalled_servers_set_change_sessions {
+			trace_session_timeout(&cluster_view, None);
+			session.on_session_timeout();
+			if session.state() == ServersSetChangeSessionState::Finished
+				|| session.state() == ServersSetChangeSessionState::Failed {
+				self.remove_servers_set_change_session(&sid);
+			}
 		}
 
-		let session = Arc::new(DecryptionSessionImpl::new(DecryptionSessionParams {
-			id: session_id.id.clone(),
-			access_key: session_id.access_key.clone(),
-			self_node_id: self.self_node_id.clone(),
-			encrypted_data: encrypted_data,
-			acl_storage: self.acl_storage.clone(),
-			cluster: cluster.clone(),
-		})?);
-		let decryption_session = QueuedDecryptionSession {
-			master: master,
-			cluster_view: cluster,
-			last_message_time: time::Instant::now(),
-			session: session.clone(),
-			queue: VecDeque::new()
-		};
-		decryption_sessions.insert(session_id, decryption_session);
-		Ok(session)
-	}
-
-	pub fn remove_decryption_session(&self, session_id: &SessionId, sub_session_id: &Secret) {
-		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
-		self.decryption_sessions.write().remove(&session_id);
-	}
+		for (sid, session) in self.generation_sessions_queue.expire(now) {
+			warn!(target: "secretstore_net", "{}: generation session {} has been waiting in the queue for too long", self.self_node_id, sid);
+			session.session.on_session_timeout();
+			self.remove_generation_session(&sid);
+		}
+		for (sid, session) in self.encryption_sessions_queue.expire(now) {
+			warn!(target: "secretstore_net", "{}: encryption session {} has been waiting in the queue for too long", self.self_node_id, sid);
+			session.session.on_session_timeout();
+			self.remove_encryption_session(&sid);
+		}
+		for (sid, session) in self.decryption_sessions_queue.expire(now) {
+			warn!(target: "secretstore_net", "{}: decryption session {} has been waiting in the queue for too long", self.self_node_id, sid.id);
+			session.session.on_session_timeout();
+			self.remove_decryption_session(&sid.id, &sid.access_key);
+		}
+		for (sid, session) in self.share_add_sessions_queue.expire(now) {
+			warn!(target: "secretstore_net", "{}: share add session {} has been waiting in the queue for too long", self.self_node_id, sid);
+			session.session.on_session_timeout();
+			self.remove_share_add_session(&sid);
+		}
+		for (sid, session) in self.key_removal_sessions_queue.expire(now) {
+			warn!(target: "secretstore_net", "{}: key removal session {} has been waiting in the queue for too long", self.self_node_id, sid);
+			session.session.on_session_timeout();
+			self.remove_key_removal_session(&sid);
+		}
+		for (sid, session) in self.signing_sessions_queue.expire(now) {
+			warn!(target: "secretstore_net", "{}: signing session {} has been waiting in the queue for too long", self.self_node_id, sid.id);
+			session.session.on_session_timeout();
+			self.remove_signing_session(&sid.id, &sid.access_key);
+		}
 
-	pub fn decryption_session(&self, session_id: &SessionId, sub_session_id: &Secret) -> Option<Arc<DecryptionSessionImpl>> {
-		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
-		self.decryption_sessions.read().get(&session_id).map(|s| s.session.clone())
+		self.early_generation_messages.expire(now);
+		self.early_encryption_messages.expire(now);
+		self.early_decryption_messages.expire(now);
+		self.early_share_add_messages.expire(now);
+		self.early_key_removal_messages.expire(now);
+		self.early_signing_messages.expire(now);
+
+		let collected_sessions = self.completed_generation_sessions.collect(now)
+			+ self.completed_encryption_sessions.collect(now)
+			+ self.completed_decryption_sessions.collect(now)
+			+ self.completed_share_add_sessions.collect(now)
+			+ self.completed_key_removal_sessions.collect(now)
+			+ self.completed_signing_sessions.collect(now);
+		if collected_sessions != 0 {
+			trace!(target: "secretstore_net", "{}: forgot {} completed sessions", self.self_node_id, collected_sessions);
+		}
 	}
 
-	pub fn enqueue_decryption_message(&self, session_id: &SessionId, sub_session_id: &Secret, sender: NodeId, message: DecryptionMessage, is_queued_message: bool) {
-		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
-		self.decryption_sessions.write().get_mut(&session_id)
-			.map(|session| if is_queued_message { session.queue.push_front((sender, message)) }
-				else { session.queue.push_back((sender, message)) });
-	}
+	/// Called when connection to the node is lost (either because of network error, or because of keep alive timeout).
+	/// Lets every active session know that the node is disconnected.
+	pub fn on_connection_lost(&self, node_id: &NodeId) {
+		for cluster_view in self.cluster_views() {
+			cluster_view.on_node_disconnected(node_id);
+		}
 
-	pub fn dequeue_decryption_message(&self, session_id: &SessionId, sub_session_id: &Secret) -> Option<(NodeId, DecryptionMessage)> {
-		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
-		self.decryption_sessions.write().get_mut(&session_id)
-			.and_then(|session| session.queue.pop_front())
-	}
+		// sessions are removed while iterating => do not hold the lock
+		let generation_sessions: Vec<_> = self.generation_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in generation_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == GenerationSessionState::Finished
+				|| session.state() == GenerationSessionState::Failed {
+				self.remove_generation_session(&sid);
+			}
+		}
 
-	pub fn respond_with_decryption_error(&self, session_id: &SessionId, sub_session_id: &Secret, to: &NodeId, error: message::DecryptionSessionError) {
-		let session_id = DecryptionSessionId::new(session_id.clone(), sub_session_id.clone());
-		self.decryption_sessions.read().get(&session_id)
-			.map(|s| {
-				// error in decryption session is non-fatal, if occurs on slave node
-				// => either respond with error
-				// => or broadcast error
+		let encryption_sessions: Vec<_> = self.encryption_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in encryption_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == EncryptionSessionState::Finished
+				|| session.state() == EncryptionSessionState::Failed {
+				self.remove_encryption_session(&sid);
+			}
+		}
 
-				// do not bother processing send error, as we already processing error
-				if &s.master == s.session.node() {
-					let _ = s.cluster_view.broadcast(Message::Decryption(DecryptionMessage::DecryptionSessionError(error)));
-				} else {
-					let _ = s.cluster_view.send(to, Message::Decryption(DecryptionMessage::DecryptionSessionError(error)));
-				}
-			});
-	}
+		let decryption_sessions: Vec<_> = self.decryption_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in decryption_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == DecryptionSessionState::Finished
+				|| session.state() == DecryptionSessionState::Failed {
+				self.remove_decryption_session(&sid.id, &sid.access_key);
+			}
+		}
 
-	fn stop_stalled_sessions(&self) {
-		{
-			let sessions = self.encryption_sessions.write();
-			for sid in sessions.keys().collect::<Vec<_>>() {
-				let session = sessions.get(&sid).expect("enumerating only existing sessions; qed");
-				if time::Instant::now() - session.last_message_time > time::Duration::from_secs(ENCRYPTION_SESSION_TIMEOUT_INTERVAL) {
-					session.session.on_session_timeout();
-					if session.session.state() == EncryptionSessionState::Finished
-						|| session.session.state() == EncryptionSessionState::Failed {
-						self.remove_encryption_session(&sid);
-					}
-				}
+		let share_add_sessions: Vec<_> = self.share_add_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in share_add_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == ShareAddSessionState::Finished
+				|| session.state() == ShareAddSessionState::Failed {
+				self.remove_share_add_session(&sid);
 			}
 		}
-		{
-			let sessions = self.decryption_sessions.write();
-			for sid in sessions.keys().collect::<Vec<_>>() {
-				let session = sessions.get(&sid).expect("enumerating only existing sessions; qed");
-				if time::Instant::now() - session.last_message_time > time::Duration::from_secs(DECRYPTION_SESSION_TIMEOUT_INTERVAL) {
-					session.session.on_session_timeout();
-					if session.session.state() == DecryptionSessionState::Finished
-						|| session.session.state() == DecryptionSessionState::Failed {
-						self.remove_decryption_session(&sid.id, &sid.access_key);
-					}
-				}
+
+		let key_removal_sessions: Vec<_> = self.key_removal_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in key_removal_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == KeyRemovalSessionState::Finished
+				|| session.state() == KeyRemovalSessionState::Failed {
+				self.remove_key_removal_session(&sid);
 			}
 		}
-	}
 
-	pub fn on_connection_timeout(&self, node_id: &NodeId) {
-		for (sid, session) in self.encryption_sessions.read().iter() {
-			session.session.on_node_timeout(node_id);
-			if session.session.state() == EncryptionSessionState::Finished
-				|| session.session.state() == EncryptionSessionState::Failed {
-				self.remove_encryption_session(sid);
+		let signing_sessions: Vec<_> = self.signing_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in signing_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == SigningSessionState::Finished
+				|| session.state() == SigningSessionState::Failed {
+				self.remove_signing_session(&sid.id, &sid.access_key);
 			}
 		}
-		for (sid, session) in self.decryption_sessions.read().iter() {
-			session.session.on_node_timeout(node_id);
-			if session.session.state() == DecryptionSessionState::Finished
-				|| session.session.state() == DecryptionSessionState::Failed {
-				self.remove_decryption_session(&sid.id, &sid.access_key);
+
+		let servers_set_change_sessions: Vec<_> = self.servers_set_change_sessions.read().iter()
+			.map(|(sid, session)| (sid.clone(), session.session.clone(), session.cluster_view.clone()))
+			.collect();
+		for (sid, session, cluster_view) in servers_set_change_sessions {
+			trace_session_timeout(&cluster_view, Some(node_id));
+			session.on_node_timeout(node_id);
+			if session.state() == ServersSetChangeSessionState::Finished
+				|| session.state() == ServersSetChangeSessionState::Failed {
+				self.remove_servers_set_change_session(&sid);
 			}
 		}
 	}
@@ -911,16 +3368,50 @@ impl ClusterSessions {
 
 impl ClusterData {
 	pub fn new(handle: &Handle, config: ClusterConfiguration, connections: ClusterConnections, sessions: ClusterSessions) -> Arc<Self> {
+		let metrics = Arc::new(ClusterMetrics::new());
+		sessions.add_generation_sessions_listener(metrics.clone());
+		sessions.add_encryption_sessions_listener(metrics.clone());
+		sessions.add_decryption_sessions_listener(metrics.clone());
+		sessions.add_key_removal_sessions_listener(metrics.clone());
+		sessions.add_signing_sessions_listener(metrics.clone());
+
 		Arc::new(ClusterData {
 			handle: handle.remote().clone(),
 			pool: CpuPool::new(config.threads),
 			self_key_pair: config.self_key_pair.clone(),
 			connections: connections,
 			sessions: sessions,
+			metrics: metrics,
+			reputation: NodeReputation::new(config.self_key_pair.public().clone(), config.node_reputation.clone()),
 			config: config,
 		})
 	}
 
+	/// Called when message from given node has failed to process. Every rejected message is counted in metrics.
+	/// Nodes, which are repeatedly violating protocol, are excluded from new sessions.
+	pub fn on_session_error(&self, sender: &NodeId, err: &Error) {
+		let rejected_node = match *err {
+			Error::InvalidKeyShare(ref node) => node,
+			_ => sender,
+		};
+		self.metrics.on_message_rejected(rejected_node);
+
+		let node = match *err {
+			Error::InvalidMessage | Error::InvalidMessageVersion => sender,
+			Error::InvalidKeyShare(ref node) => node,
+			_ => return,
+		};
+		self.reputation.on_protocol_violation(node, time::Instant::now());
+	}
+
+	/// Get nodes, which could be selected for new session, started by this node: connected nodes (and this node) except for excluded ones.
+	pub fn session_nodes(&self) -> BTreeSet<NodeId> {
+		let excluded_nodes = self.reputation.excluded_nodes(time::Instant::now());
+		let mut nodes: BTreeSet<_> = self.connections.connected_nodes().difference(&excluded_nodes).cloned().collect();
+		nodes.insert(self.self_key_pair.public().clone());
+		nodes
+	}
+
 	/// Get connection to given node.
 	pub fn connection(&self, node: &NodeId) -> Option<Arc<Connection>> {
 		self.connections.get(node)
@@ -981,11 +3472,23 @@ impl ClusterView {
 		ClusterView {
 			core: Arc::new(Mutex::new(ClusterViewCore {
 				cluster: cluster,
+				session_nodes: nodes.clone(),
 				nodes: nodes,
+				span: None,
 			})),
 		}
 	}
 
+	/// Set tracing span of the session, which is using this view.
+	pub fn set_span(&self, span: SessionSpan) {
+		self.core.lock().span = Some(span);
+	}
+
+	/// Get tracing span of the session, which is using this view.
+	pub fn span(&self) -> Option<SessionSpan> {
+		self.core.lock().span.clone()
+	}
+
 	pub fn is_connected(&self, node: &NodeId) -> bool {
 		self.core.lock().nodes.contains(node)
 	}
@@ -993,13 +3496,31 @@ impl ClusterView {
 	pub fn nodes(&self) -> BTreeSet<NodeId> {
 		self.core.lock().nodes.clone()
 	}
+
+	/// When connection to the node is established. Returns true if node is required for the session && it has been disconnected before.
+	pub fn on_node_connected(&self, node: &NodeId) -> bool {
+		let mut core = self.core.lock();
+		if !core.session_nodes.contains(node) || !core.nodes.insert(node.clone()) {
+			return false;
+		}
+
+		if let Some(ref span) = core.span {
+			span.on_node_connected(node);
+		}
+		true
+	}
+
+	/// When connection to the node is lost. Returns true if node is required for the session && it has been connected before.
+	pub fn on_node_disconnected(&self, node: &NodeId) -> bool {
+		self.core.lock().nodes.remove(node)
+	}
 }
 
 impl Cluster for ClusterView {
 	fn broadcast(&self, message: Message) -> Result<(), Error> {
 		let core = self.core.lock();
 		for node in core.nodes.iter().filter(|n| *n != core.cluster.self_key_pair.public()) {
-			trace!(target: "secretstore_net", "{}: sent message {} to {}", core.cluster.self_key_pair.public(), message, node);
+			trace_sent_message(&core, node, &message);
 			let connection = core.cluster.connection(node).ok_or(Error::NodeDisconnected)?;
 			core.cluster.spawn(connection.send_message(message.clone()))
 		}
@@ -1008,7 +3529,7 @@ impl Cluster for ClusterView {
 
 	fn send(&self, to: &NodeId, message: Message) -> Result<(), Error> {
 		let core = self.core.lock();
-		trace!(target: "secretstore_net", "{}: sent message {} to {}", core.cluster.self_key_pair.public(), message, to);
+		trace_sent_message(&core, to, &message);
 		let connection = core.cluster.connection(to).ok_or(Error::NodeDisconnected)?;
 		core.cluster.spawn(connection.send_message(message));
 		Ok(())
@@ -1028,40 +3549,446 @@ impl ClusterClient for ClusterClientImpl {
 		self.data.connections.cluster_state()
 	}
 
-	fn new_encryption_session(&self, session_id: SessionId, threshold: usize) -> Result<Arc<EncryptionSession>, Error> {
+	fn new_generation_session(&self, session_id: SessionId, author: Public, threshold: usize) -> Result<Arc<GenerationSession>, Error> {
 		let mut connected_nodes = self.data.connections.connected_nodes();
 		connected_nodes.insert(self.data.self_key_pair.public().clone());
 
+		// check threshold before the session is (probably) queued
+		check_threshold(threshold, &connected_nodes)?;
+
 		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes.clone()));
+		let session = self.data.sessions.new_generation_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster)?;
+		self.data.sessions.start_generation_session(session_id.clone(), PendingGenerationSession {
+			session: session.clone(),
+			author: author,
+			threshold: threshold,
+			nodes: connected_nodes,
+		})?;
+		Ok(GenerationSessionWrapper::new(Arc::downgrade(&self.data), session_id, session))
+	}
+
+	fn new_encryption_session(&self, session_id: SessionId, requestor_signature: Signature, common_point: Public, encrypted_point: Public, nonce: u64) -> Result<Arc<EncryptionSession>, Error> {
+		let mut connected_nodes = self.data.connections.connected_nodes();
+		connected_nodes.insert(self.data.self_key_pair.public().clone());
+
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes));
 		let session = self.data.sessions.new_encryption_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster)?;
-		session.initialize(threshold, connected_nodes)?;
+		self.data.sessions.start_encryption_session(session_id.clone(), PendingEncryptionSession {
+			session: session.clone(),
+			requestor_signature: requestor_signature,
+			common_point: common_point,
+			encrypted_point: encrypted_point,
+			nonce: nonce,
+		})?;
 		Ok(EncryptionSessionWrapper::new(Arc::downgrade(&self.data), session_id, session))
 	}
 
-	fn new_decryption_session(&self, session_id: SessionId, requestor_signature: Signature, is_shadow_decryption: bool) -> Result<Arc<DecryptionSession>, Error> {
+	fn new_decryption_session(&self, session_id: SessionId, requester: Requester, is_shadow_decryption: bool) -> Result<Arc<DecryptionSession>, Error> {
+		let session_nodes = self.data.session_nodes();
+
+		let access_key = Random.generate()?.secret().clone();
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), session_nodes));
+		let session = self.data.sessions.new_decryption_session(self.data.self_key_pair.public().clone(), session_id, access_key.clone(), cluster)?;
+		self.data.sessions.start_decryption_session(session_id, access_key.clone(), PendingDecryptionSession {
+			session: session.clone(),
+			requester: requester,
+			is_shadow_decryption: is_shadow_decryption,
+		})?;
+		Ok(DecryptionSessionWrapper::new(Arc::downgrade(&self.data), session_id, access_key, session))
+	}
+
+	fn new_share_add_session(&self, session_id: SessionId, old_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Arc<ShareAddSession>, Error> {
+		let mut connected_nodes = self.data.connections.connected_nodes();
+		connected_nodes.insert(self.data.self_key_pair.public().clone());
+
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes));
+		let session = self.data.sessions.new_share_add_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster, None)?;
+		self.data.sessions.start_share_add_session(session_id.clone(), PendingShareAddSession {
+			session: session.clone(),
+			old_nodes_set: old_nodes_set,
+			new_nodes_set: new_nodes_set,
+			servers_set_change: None,
+			is_recovery: false,
+			admin_signature: admin_signature,
+		})?;
+		Ok(ShareAddSessionWrapper::new(Arc::downgrade(&self.data), session_id, session))
+	}
+
+	fn new_share_recovery_session(&self, session_id: SessionId, surviving_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Arc<ShareAddSession>, Error> {
+		let mut connected_nodes = self.data.connections.connected_nodes();
+		connected_nodes.insert(self.data.self_key_pair.public().clone());
+
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes));
+		let session = self.data.sessions.new_share_add_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster, None)?;
+		self.data.sessions.start_share_add_session(session_id.clone(), PendingShareAddSession {
+			session: session.clone(),
+			old_nodes_set: surviving_nodes_set,
+			new_nodes_set: new_nodes_set,
+			servers_set_change: None,
+			is_recovery: true,
+			admin_signature: admin_signature,
+		})?;
+		Ok(ShareAddSessionWrapper::new(Arc::downgrade(&self.data), session_id, session))
+	}
+
+	fn new_servers_set_change_session(&self, session_id: SessionId, new_servers_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Arc<ServersSetChangeSession>, Error> {
+		if let Some(ref migration) = *self.data.sessions.key_server_set_migration.read() {
+			if migration.set.keys().cloned().collect::<BTreeSet<_>>() != new_servers_set {
+				return Err(Error::InvalidNodesConfiguration);
+			}
+		}
+
+		let mut connected_nodes = self.data.connections.connected_nodes();
+		connected_nodes.insert(self.data.self_key_pair.public().clone());
+
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes.clone()));
+		let executor = Arc::new(ClusterShareAddSessionsExecutor {
+			session_id: session_id.clone(),
+			cluster: Arc::downgrade(&self.data),
+		});
+		let session = self.data.sessions.new_servers_set_change_session(session_id.clone(), cluster, executor, self.data.config.max_active_key_migrations)?;
+		let wrapper = ServersSetChangeSessionWrapper::new(Arc::downgrade(&self.data), session_id.clone(), session.clone());
+		let old_servers_set = self.data.sessions.nodes.read().clone();
+		let result = session.initialize(old_servers_set, new_servers_set, admin_signature);
+
+		// session could be completed right after initialization (i.e. if there are no keys to migrate or request is rejected)
+		let session_state = session.state();
+		if session_state == ServersSetChangeSessionState::Finished || session_state == ServersSetChangeSessionState::Failed {
+			self.data.sessions.remove_servers_set_change_session(&session_id);
+		}
+
+		result.map(|_| wrapper)
+	}
+
+	fn key_server_set_migration(&self) -> Option<KeyServerSetMigration> {
+		self.data.sessions.key_server_set_migration.read().clone()
+	}
+
+	fn generate_key(&self, session_id: SessionId, author: Public, threshold: usize) -> SessionResultFuture<Public> {
+		let mut connected_nodes = self.data.connections.connected_nodes();
+		connected_nodes.insert(self.data.self_key_pair.public().clone());
+
+		// check threshold before the session is (probably) queued
+		if let Err(err) = check_threshold(threshold, &connected_nodes) {
+			return SessionResultFuture::failed(err);
+		}
+
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes.clone()));
+		let session = match self.data.sessions.new_generation_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster) {
+			Ok(session) => session,
+			Err(err) => return SessionResultFuture::failed(err),
+		};
+
+		let cancel_data = Arc::downgrade(&self.data);
+		let cancel_session_id = session_id.clone();
+		let (future, listener) = SessionResultFuture::new(session.clone(), GenerationSessionImpl::result, move ||
+			if let Some(data) = cancel_data.upgrade() {
+				data.sessions.cancel_generation_session(&cancel_session_id);
+			});
+		self.data.sessions.add_generation_sessions_listener(listener);
+
+		if let Err(err) = self.data.sessions.start_generation_session(session_id.clone(), PendingGenerationSession {
+			session: session.clone(),
+			author: author,
+			threshold: threshold,
+			nodes: connected_nodes,
+		}) {
+			return SessionResultFuture::failed(err);
+		}
+
+		// session could be completed right after initialization (i.e. if there's single node in the cluster)
+		let session_state = session.state();
+		if session_state == GenerationSessionState::Finished || session_state == GenerationSessionState::Failed {
+			self.data.sessions.remove_generation_session(&session_id);
+		}
+
+		future
+	}
+
+	fn store_document_key(&self, session_id: SessionId, requestor_signature: Signature, common_point: Public, encrypted_point: Public, nonce: u64) -> SessionResultFuture<()> {
+		let mut connected_nodes = self.data.connections.connected_nodes();
+		connected_nodes.insert(self.data.self_key_pair.public().clone());
+
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes));
+		let session = match self.data.sessions.new_encryption_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster) {
+			Ok(session) => session,
+			Err(err) => return SessionResultFuture::failed(err),
+		};
+
+		let cancel_data = Arc::downgrade(&self.data);
+		let cancel_session_id = session_id.clone();
+		let (future, listener) = SessionResultFuture::new(session.clone(), EncryptionSessionImpl::result, move ||
+			if let Some(data) = cancel_data.upgrade() {
+				data.sessions.cancel_encryption_session(&cancel_session_id);
+			});
+		self.data.sessions.add_encryption_sessions_listener(listener);
+
+		if let Err(err) = self.data.sessions.start_encryption_session(session_id.clone(), PendingEncryptionSession {
+			session: session.clone(),
+			requestor_signature: requestor_signature,
+			common_point: common_point,
+			encrypted_point: encrypted_point,
+			nonce: nonce,
+		}) {
+			return SessionResultFuture::failed(err);
+		}
+
+		// session could be completed right after initialization (i.e. if there's single node in the cluster)
+		let session_state = session.state();
+		if session_state == EncryptionSessionState::Finished || session_state == EncryptionSessionState::Failed {
+			self.data.sessions.remove_encryption_session(&session_id);
+		}
+
+		future
+	}
+
+	fn retrieve_document_key(&self, session_id: SessionId, requester: Requester, is_shadow_decryption: bool) -> SessionResultFuture<DocumentEncryptedKeyShadow> {
+		let session_nodes = self.data.session_nodes();
+
+		let access_key = match Random.generate() {
+			Ok(key_pair) => key_pair.secret().clone(),
+			Err(err) => return SessionResultFuture::failed(err.into()),
+		};
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), session_nodes));
+		let session = match self.data.sessions.new_decryption_session(self.data.self_key_pair.public().clone(), session_id.clone(), access_key.clone(), cluster) {
+			Ok(session) => session,
+			Err(err) => return SessionResultFuture::failed(err),
+		};
+
+		let cancel_data = Arc::downgrade(&self.data);
+		let cancel_session_id = session_id.clone();
+		let cancel_access_key = access_key.clone();
+		let (future, listener) = SessionResultFuture::new(session.clone(), DecryptionSessionImpl::result, move ||
+			if let Some(data) = cancel_data.upgrade() {
+				data.sessions.cancel_decryption_session(&cancel_session_id, &cancel_access_key);
+			});
+		self.data.sessions.add_decryption_sessions_listener(listener);
+
+		if let Err(err) = self.data.sessions.start_decryption_session(session_id.clone(), access_key.clone(), PendingDecryptionSession {
+			session: session.clone(),
+			requester: requester,
+			is_shadow_decryption: is_shadow_decryption,
+		}) {
+			return SessionResultFuture::failed(err);
+		}
+
+		// session could be completed right after initialization (i.e. if there's single node in the cluster)
+		let session_state = session.state();
+		if session_state == DecryptionSessionState::Finished || session_state == DecryptionSessionState::Failed {
+			self.data.sessions.remove_decryption_session(&session_id, &access_key);
+		}
+
+		future
+	}
+
+	fn remove_key(&self, session_id: SessionId, requestor_signature: Signature, nonce: u64) -> SessionResultFuture<BTreeSet<NodeId>> {
 		let mut connected_nodes = self.data.connections.connected_nodes();
 		connected_nodes.insert(self.data.self_key_pair.public().clone());
 
-		let access_key = Random.generate()?.secret().clone();
-		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes.clone()));
-		let session = self.data.sessions.new_decryption_session(self.data.self_key_pair.public().clone(), session_id, access_key.clone(), cluster)?;
-		session.initialize(requestor_signature, is_shadow_decryption)?;
-		Ok(DecryptionSessionWrapper::new(Arc::downgrade(&self.data), session_id, access_key, session))
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), connected_nodes.clone()));
+		let session = match self.data.sessions.new_key_removal_session(self.data.self_key_pair.public().clone(), session_id.clone(), cluster) {
+			Ok(session) => session,
+			Err(err) => return SessionResultFuture::failed(err),
+		};
+
+		// key holders discard staged removal when session is timeouted => it is enough to forget the session
+		let cancel_data = Arc::downgrade(&self.data);
+		let cancel_session_id = session_id.clone();
+		let (future, listener) = SessionResultFuture::new(session.clone(), KeyRemovalSessionImpl::result, move ||
+			if let Some(data) = cancel_data.upgrade() {
+				data.sessions.remove_key_removal_session(&cancel_session_id);
+			});
+		self.data.sessions.add_key_removal_sessions_listener(listener);
+
+		if let Err(err) = self.data.sessions.start_key_removal_session(session_id.clone(), PendingKeyRemovalSession {
+			session: session.clone(),
+			requestor_signature: requestor_signature,
+			nonce: nonce,
+			key_holders: None,
+			connected_nodes: connected_nodes,
+		}) {
+			return SessionResultFuture::failed(err);
+		}
+
+		// session could be completed right after initialization (i.e. if there's single node in the cluster)
+		let session_state = session.state();
+		if session_state == KeyRemovalSessionState::Finished || session_state == KeyRemovalSessionState::Failed {
+			self.data.sessions.remove_key_removal_session(&session_id);
+		}
+
+		future
+	}
+
+	fn sign_message(&self, session_id: SessionId, requester: Requester, message_hash: H256) -> SessionResultFuture<(Secret, Secret)> {
+		let session_nodes = self.data.session_nodes();
+
+		let access_key = match Random.generate() {
+			Ok(key_pair) => key_pair.secret().clone(),
+			Err(err) => return SessionResultFuture::failed(err.into()),
+		};
+		let cluster = Arc::new(ClusterView::new(self.data.clone(), session_nodes));
+		let session = match self.data.sessions.new_signing_session(self.data.self_key_pair.public().clone(), session_id.clone(), access_key.clone(), cluster) {
+			Ok(session) => session,
+			Err(err) => return SessionResultFuture::failed(err),
+		};
+
+		let cancel_data = Arc::downgrade(&self.data);
+		let cancel_session_id = session_id.clone();
+		let cancel_access_key = access_key.clone();
+		let (future, listener) = SessionResultFuture::new(session.clone(), SigningSessionImpl::result, move ||
+			if let Some(data) = cancel_data.upgrade() {
+				data.sessions.cancel_signing_session(&cancel_session_id, &cancel_access_key);
+			});
+		self.data.sessions.add_signing_sessions_listener(listener);
+
+		if let Err(err) = self.data.sessions.start_signing_session(session_id.clone(), access_key.clone(), PendingSigningSession {
+			session: session.clone(),
+			requester: requester,
+			message_hash: message_hash,
+		}) {
+			return SessionResultFuture::failed(err);
+		}
+
+		// session could be completed right after initialization (i.e. if there's single node in the cluster)
+		let session_state = session.state();
+		if session_state == SigningSessionState::Finished || session_state == SigningSessionState::Failed {
+			self.data.sessions.remove_signing_session(&session_id, &access_key);
+		}
+
+		future
+	}
+
+	fn metrics(&self) -> String {
+		let connected_nodes = self.data.connections.connected_nodes();
+		let peers: BTreeMap<_, _> = self.data.connections.nodes.read().keys()
+			.map(|node| (node.clone(), connected_nodes.contains(node)))
+			.collect();
+		let mut queued_sessions = BTreeMap::new();
+		queued_sessions.insert(SessionType::Generation, self.data.sessions.generation_sessions_queue.queued_count());
+		queued_sessions.insert(SessionType::Encryption, self.data.sessions.encryption_sessions_queue.queued_count());
+		queued_sessions.insert(SessionType::Decryption, self.data.sessions.decryption_sessions_queue.queued_count());
+		queued_sessions.insert(SessionType::KeyRemoval, self.data.sessions.key_removal_sessions_queue.queued_count());
+		queued_sessions.insert(SessionType::Signing, self.data.sessions.signing_sessions_queue.queued_count());
+		let excluded_peers = self.data.reputation.excluded_nodes(time::Instant::now());
+		self.data.metrics.render(&peers, &excluded_peers, &queued_sessions)
+	}
+
+	#[cfg(test)]
+	fn connect(&self) {
+		ClusterCore::connect_disconnected_nodes(self.data.clone());
+	}
+
+	#[cfg(test)]
+	fn make_faulty_generation_sessions(&self) {
+		self.data.sessions.make_faulty_generation_sessions();
+	}
+
+	#[cfg(test)]
+	fn generation_session(&self, session_id: &SessionId) -> Option<Arc<GenerationSessionImpl>> {
+		self.data.sessions.generation_session(session_id)
+	}
+}
+
+impl PendingGenerationSession {
+	/// Initialize session.
+	pub fn start(&self) -> Result<(), Error> {
+		self.session.initialize(self.author.clone(), self.threshold, self.nodes.clone())
+	}
+}
+
+impl PendingEncryptionSession {
+	/// Initialize session.
+	pub fn start(&self) -> Result<(), Error> {
+		self.session.initialize(self.requestor_signature.clone(), self.common_point.clone(), self.encrypted_point.clone(), self.nonce)
+	}
+}
+
+impl PendingDecryptionSession {
+	/// Initialize session.
+	pub fn start(&self) -> Result<(), Error> {
+		self.session.initialize(self.requester.clone(), self.is_shadow_decryption)
+	}
+}
+
+impl PendingSigningSession {
+	/// Initialize session.
+	pub fn start(&self) -> Result<(), Error> {
+		self.session.initialize(self.requester.clone(), self.message_hash.clone())
+	}
+}
+
+impl PendingShareAddSession {
+	/// Initialize session.
+	pub fn start(&self) -> Result<(), Error> {
+		if let Some(ref servers_set_change) = self.servers_set_change {
+			self.session.initialize_with_servers_set(self.old_nodes_set.clone(), servers_set_change.clone(), self.admin_signature.clone())
+		} else if self.is_recovery {
+			self.session.initialize_recovery(self.old_nodes_set.clone(), self.new_nodes_set.clone(), self.admin_signature.clone())
+		} else {
+			self.session.initialize(self.old_nodes_set.clone(), self.new_nodes_set.clone(), self.admin_signature.clone())
+		}
+	}
+}
+
+impl PendingKeyRemovalSession {
+	/// Initialize session.
+	pub fn start(&self) -> Result<(), Error> {
+		match self.key_holders {
+			Some(ref key_holders) => self.session.initialize_for_nodes(self.requestor_signature.clone(), self.nonce, key_holders.clone(), self.connected_nodes.clone()),
+			None => self.session.initialize(self.requestor_signature.clone(), self.nonce, self.connected_nodes.clone()),
+		}
+	}
+}
+
+impl ShareAddSessionsExecutor for ClusterShareAddSessionsExecutor {
+	fn start_share_add_session(&self, key_id: SessionId, old_nodes_set: BTreeSet<NodeId>, servers_set_change: ServersSetChange, admin_signature: Signature) -> Result<(), Error> {
+		let data = self.cluster.upgrade().ok_or(Error::NodeDisconnected)?;
+		let mut connected_nodes = data.connections.connected_nodes();
+		connected_nodes.insert(data.self_key_pair.public().clone());
+
+		let cluster = Arc::new(ClusterView::new(data.clone(), connected_nodes));
+		let session = data.sessions.new_share_add_session(data.self_key_pair.public().clone(), key_id.clone(), cluster, Some(self.session_id.clone()))?;
+		data.sessions.start_share_add_session(key_id, PendingShareAddSession {
+			session: session,
+			old_nodes_set: old_nodes_set,
+			new_nodes_set: servers_set_change.new_servers_set.clone(),
+			servers_set_change: Some(servers_set_change),
+			is_recovery: false,
+			admin_signature: admin_signature,
+		})
+	}
+}
+
+impl GenerationSessionWrapper {
+	pub fn new(cluster: Weak<ClusterData>, session_id: SessionId, session: Arc<GenerationSession>) -> Arc<Self> {
+		Arc::new(GenerationSessionWrapper {
+			session: session,
+			session_id: session_id,
+			cluster: cluster,
+		})
+	}
+}
+
+impl GenerationSession for GenerationSessionWrapper {
+	fn state(&self) -> GenerationSessionState {
+		self.session.state()
 	}
 
-	#[cfg(test)]
-	fn connect(&self) {
-		ClusterCore::connect_disconnected_nodes(self.data.clone());
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<Public, Error> {
+		self.session.wait(timeout)
 	}
 
 	#[cfg(test)]
-	fn make_faulty_encryption_sessions(&self) {
-		self.data.sessions.make_faulty_encryption_sessions();
+	fn joint_public_key(&self) -> Option<Result<Public, Error>> {
+		self.session.joint_public_key()
 	}
+}
 
-	#[cfg(test)]
-	fn encryption_session(&self, session_id: &SessionId) -> Option<Arc<EncryptionSessionImpl>> {
-		self.data.sessions.encryption_session(session_id)
+impl Drop for GenerationSessionWrapper {
+	fn drop(&mut self) {
+		if let Some(cluster) = self.cluster.upgrade() {
+			cluster.sessions.remove_generation_session(&self.session_id);
+		}
 	}
 }
 
@@ -1080,14 +4007,9 @@ impl EncryptionSession for EncryptionSessionWrapper {
 		self.session.state()
 	}
 
-	fn wait(&self, timeout: Option<time::Duration>) -> Result<Public, Error> {
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error> {
 		self.session.wait(timeout)
 	}
-
-	#[cfg(test)]
-	fn joint_public_key(&self) -> Option<Result<Public, Error>> {
-		self.session.joint_public_key()
-	}
 }
 
 impl Drop for EncryptionSessionWrapper {
@@ -1123,6 +4045,152 @@ impl Drop for DecryptionSessionWrapper {
 	}
 }
 
+impl ShareAddSessionWrapper {
+	pub fn new(cluster: Weak<ClusterData>, session_id: SessionId, session: Arc<ShareAddSession>) -> Arc<Self> {
+		Arc::new(ShareAddSessionWrapper {
+			session: session,
+			session_id: session_id,
+			cluster: cluster,
+		})
+	}
+}
+
+impl ShareAddSession for ShareAddSessionWrapper {
+	fn state(&self) -> ShareAddSessionState {
+		self.session.state()
+	}
+
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error> {
+		self.session.wait(timeout)
+	}
+}
+
+impl Drop for ShareAddSessionWrapper {
+	fn drop(&mut self) {
+		if let Some(cluster) = self.cluster.upgrade() {
+			cluster.sessions.remove_share_add_session(&self.session_id);
+		}
+	}
+}
+
+impl ServersSetChangeSessionWrapper {
+	pub fn new(cluster: Weak<ClusterData>, session_id: SessionId, session: Arc<ServersSetChangeSession>) -> Arc<Self> {
+		Arc::new(ServersSetChangeSessionWrapper {
+			session: session,
+			session_id: session_id,
+			cluster: cluster,
+		})
+	}
+}
+
+impl ServersSetChangeSession for ServersSetChangeSessionWrapper {
+	fn state(&self) -> ServersSetChangeSessionState {
+		self.session.state()
+	}
+
+	fn keys(&self) -> BTreeMap<SessionId, KeyMigrationState> {
+		self.session.keys()
+	}
+
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error> {
+		self.session.wait(timeout)
+	}
+}
+
+impl Drop for ServersSetChangeSessionWrapper {
+	fn drop(&mut self) {
+		if let Some(cluster) = self.cluster.upgrade() {
+			cluster.sessions.remove_servers_set_change_session(&self.session_id);
+		}
+	}
+}
+
+/// Trace session, removed from the container.
+fn trace_finished_session<S: fmt::Debug>(cluster_view: &ClusterView, session_state: &S) {
+	if let Some(span) = cluster_view.span() {
+		span.on_finished(session_state);
+	}
+}
+
+/// Trace timeout of the session (or of connection to the given node).
+fn trace_session_timeout(cluster_view: &ClusterView, node: Option<&NodeId>) {
+	if let Some(span) = cluster_view.span() {
+		span.on_timeout(node);
+	}
+}
+
+/// Trace message, sent by the session. Message is traced within session span, if it is set.
+fn trace_sent_message(core: &ClusterViewCore, to: &NodeId, message: &Message) {
+	match core.span {
+		Some(ref span) => span.message_span(message).on_message_sent(to, message),
+		None => trace!(target: NET_LOG_TARGET, "{}: sent message {} to {}", core.cluster.self_key_pair.public(), message, to),
+	}
+}
+
+/// When several nodes have started session with the same id simultaneously, only one of these sessions survives.
+/// The agreement is that session of the master with lower id wins.
+fn is_preferred_master(master: &NodeId, other_master: &NodeId) -> bool {
+	master < other_master
+}
+
+/// Is this confirmation of generation session initialization?
+fn is_initialization_confirmation(message: &GenerationMessage) -> bool {
+	match *message {
+		GenerationMessage::ConfirmInitialization(_) => true,
+		_ => false,
+	}
+}
+
+/// Notify every alive listener that the session has been inserted. Listeners, which are dropped, are forgotten.
+fn notify_session_inserted<S>(listeners: &RwLock<Vec<Weak<ClusterSessionsListener<S>>>>, session: Arc<S>) {
+	let listeners: Vec<_> = {
+		let mut listeners = listeners.write();
+		listeners.retain(|listener| listener.upgrade().is_some());
+		listeners.iter().filter_map(|listener| listener.upgrade()).collect()
+	};
+	for listener in listeners {
+		listener.on_session_inserted(session.clone());
+	}
+}
+
+/// Notify every alive listener that the session has been activated. Listeners, which are dropped, are forgotten.
+fn notify_session_activated<S>(listeners: &RwLock<Vec<Weak<ClusterSessionsListener<S>>>>, session: Arc<S>) {
+	let listeners: Vec<_> = {
+		let mut listeners = listeners.write();
+		listeners.retain(|listener| listener.upgrade().is_some());
+		listeners.iter().filter_map(|listener| listener.upgrade()).collect()
+	};
+	for listener in listeners {
+		listener.on_session_activated(session.clone());
+	}
+}
+
+/// Notify every alive listener that the session has been removed. Listeners, which are dropped, are forgotten.
+fn notify_session_removed<S>(listeners: &RwLock<Vec<Weak<ClusterSessionsListener<S>>>>, session: Arc<S>) {
+	// listeners could access sessions container => do not hold the lock
+	let listeners: Vec<_> = {
+		let mut listeners = listeners.write();
+		listeners.retain(|listener| listener.upgrade().is_some());
+		listeners.iter().filter_map(|listener| listener.upgrade()).collect()
+	};
+	for listener in listeners {
+		listener.on_session_removed(session.clone());
+	}
+}
+
+/// Notify every alive listener that the session has been replaced. Listeners, which are dropped, are forgotten.
+fn notify_session_replaced<S>(listeners: &RwLock<Vec<Weak<ClusterSessionsListener<S>>>>, old_session: Arc<S>, new_session: Arc<S>) {
+	// listeners could access sessions container => do not hold the lock
+	let listeners: Vec<_> = {
+		let mut listeners = listeners.write();
+		listeners.retain(|listener| listener.upgrade().is_some());
+		listeners.iter().filter_map(|listener| listener.upgrade()).collect()
+	};
+	for listener in listeners {
+		listener.on_session_replaced(old_session.clone(), new_session.clone());
+	}
+}
+
 fn make_socket_address(address: &str, port: u16) -> Result<SocketAddr, Error> {
 	let ip_address: IpAddr = address.parse().map_err(|_| Error::InvalidNodeAddress)?;
 	Ok(SocketAddr::new(ip_address, port))
@@ -1131,15 +4199,29 @@ fn make_socket_address(address: &str, port: u16) -> Result<SocketAddr, Error> {
 #[cfg(test)]
 pub mod tests {
 	use std::sync::Arc;
+	use std::sync::mpsc;
+	use std::thread;
 	use std::time;
-	use std::collections::VecDeque;
+	use std::collections::{BTreeMap, BTreeSet, VecDeque};
+	use futures::Future;
 	use parking_lot::Mutex;
 	use tokio_core::reactor::Core;
-	use ethkey::{Random, Generator};
-	use key_server_cluster::{NodeId, SessionId, Error, DummyAclStorage, DummyKeyStorage};
-	use key_server_cluster::message::Message;
-	use key_server_cluster::cluster::{Cluster, ClusterCore, ClusterConfiguration};
-	use key_server_cluster::encryption_session::{Session as EncryptionSession, SessionState as EncryptionSessionState};
+	use ethkey::{self, Random, Generator, Public};
+	use key_server_cluster::{NodeId, SessionId, Error, DummyAclStorage, DummyKeyStorage, DocumentKeyShare, DocumentKeyShareVersion,
+		KeyRemovalRetry};
+	use key_server_cluster::math;
+	use key_server_cluster::metrics::node_label;
+	use key_server_cluster::node_reputation::NodeReputationParams;
+	use key_server_cluster::message::{self, Message, GenerationMessage, ShareAddMessage, ServersSetChangeMessage};
+	use key_server_cluster::cluster::{Cluster, ClusterCore, ClusterConfiguration, ClusterSessionsListener, ClusterView,
+		SessionsTimeouts, MAINTAIN_INTERVAL, COMPLETED_SESSIONS_RETENTION_INTERVAL};
+	use key_server_cluster::session_result::SessionResultFuture;
+	use key_server_cluster::generation_session::{Session as GenerationSession, SessionImpl as GenerationSessionImpl,
+		SessionState as GenerationSessionState};
+	use key_server_cluster::encryption_session::{Session as EncryptionSession, SessionState as EncryptionSessionState, store_request_hash};
+	use key_server_cluster::key_removal_session::removal_request_hash;
+	use key_server_cluster::servers_set_change_session::{SessionState as ServersSetChangeSessionState, KeyMigrationState,
+		servers_set_change_hash};
 
 	#[derive(Debug)]
 	pub struct DummyCluster {
@@ -1191,6 +4273,17 @@ pub mod tests {
 		}
 	}
 
+	#[derive(Default)]
+	struct GenerationSessionsRecorder {
+		results: Mutex<Vec<Option<Result<Public, Error>>>>,
+	}
+
+	impl ClusterSessionsListener<GenerationSessionImpl> for GenerationSessionsRecorder {
+		fn on_session_removed(&self, session: Arc<GenerationSessionImpl>) {
+			self.results.lock().push(session.result());
+		}
+	}
+
 	pub fn loop_until<F>(core: &mut Core, timeout: time::Duration, predicate: F) where F: Fn() -> bool {
 		let start = time::Instant::now();
 		loop {
@@ -1212,6 +4305,10 @@ pub mod tests {
 	}
 
 	pub fn make_clusters(core: &Core, ports_begin: u16, num_nodes: usize) -> Vec<Arc<ClusterCore>> {
+		make_clusters_with_admin(core, ports_begin, num_nodes, None)
+	}
+
+	pub fn make_clusters_with_admin(core: &Core, ports_begin: u16, num_nodes: usize, admin_public: Option<Public>) -> Vec<Arc<ClusterCore>> {
 		let key_pairs: Vec<_> = (0..num_nodes).map(|_| Random.generate().unwrap()).collect();
 		let cluster_params: Vec<_> = (0..num_nodes).map(|i| ClusterConfiguration {
 			threads: 1,
@@ -1220,8 +4317,16 @@ pub mod tests {
 			nodes: key_pairs.iter().enumerate()
 				.map(|(j, kp)| (kp.public().clone(), ("127.0.0.1".into(), ports_begin + j as u16)))
 				.collect(),
+			key_server_set: None,
 			allow_connecting_to_higher_nodes: false,
 			key_storage: Arc::new(DummyKeyStorage::default()),
+			admin_public: admin_public.clone(),
+			max_active_key_migrations: 4,
+			wipe_removed_key_shares: false,
+			maintain_interval: time::Duration::from_secs(MAINTAIN_INTERVAL),
+			completed_sessions_retention: time::Duration::from_secs(COMPLETED_SESSIONS_RETENTION_INTERVAL),
+			sessions_timeouts: SessionsTimeouts::default(),
+			node_reputation: NodeReputationParams::default(),
 			acl_storage: Arc::new(DummyAclStorage::default()),
 		}).collect();
 		let clusters: Vec<_> = cluster_params.into_iter().enumerate()
@@ -1231,6 +4336,55 @@ pub mod tests {
 		clusters
 	}
 
+	/// Share key among given nodes only, as if it has been generated before other nodes have joined the cluster.
+	fn share_key(clusters: &[Arc<ClusterCore>], key_id: &SessionId, author: &Public) {
+		let polynom = math::generate_random_polynom(1).unwrap();
+		let id_numbers: BTreeMap<_, _> = clusters.iter()
+			.map(|c| (c.config().self_key_pair.public().clone(), math::generate_random_scalar().unwrap()))
+			.collect();
+		for cluster in clusters {
+			let id_number = &id_numbers[cluster.config().self_key_pair.public()];
+			cluster.config().key_storage.insert(key_id.clone(), DocumentKeyShare {
+				author: author.clone(),
+				threshold: 1,
+				common_point: None,
+				encrypted_point: None,
+				versions: vec![DocumentKeyShareVersion::new(id_numbers.clone(), math::compute_polynom(&polynom, id_number).unwrap())],
+			}).unwrap();
+		}
+	}
+
+	/// Is key held by every given node && the last version of the key is shared among these nodes?
+	fn is_key_held_by(clusters: &[Arc<ClusterCore>], key_id: &SessionId, nodes: &BTreeSet<NodeId>) -> bool {
+		clusters.iter()
+			.filter(|c| nodes.contains(c.config().self_key_pair.public()))
+			.all(|c| c.config().key_storage.get(key_id).ok()
+				.and_then(|key_share| key_share.last_version().ok().map(|version| version.id_numbers.keys().cloned().collect::<BTreeSet<_>>()))
+				.map(|version_nodes| &version_nodes == nodes)
+				.unwrap_or(false))
+	}
+
+	/// Wait for results of all given futures, while cluster is running on this thread.
+	pub fn wait_for_results<T>(core: &mut Core, futures: Vec<SessionResultFuture<T>>) -> Vec<Result<T, Error>> where T: Send + 'static {
+		let (tx, rx) = mpsc::channel();
+		let waiters: Vec<_> = futures.into_iter().enumerate().map(|(index, future)| {
+			let tx = tx.clone();
+			thread::spawn(move || tx.send((index, future.wait())).unwrap())
+		}).collect();
+		let results = Mutex::new(BTreeMap::new());
+		loop_until(core, time::Duration::from_millis(1000), || {
+			let mut results = results.lock();
+			while let Ok((index, result)) = rx.try_recv() {
+				results.insert(index, result);
+			}
+			results.len() == waiters.len()
+		});
+		for waiter in waiters {
+			waiter.join().unwrap();
+		}
+		results.into_inner().into_iter().map(|(_, result)| result).collect()
+	}
+
 	pub fn run_clusters(clusters: &[Arc<ClusterCore>]) {
 		for cluster in clusters {
 			cluster.run_listener().unwrap();
@@ -1249,11 +4403,11 @@ pub mod tests {
 	}
 
 	#[test]
-	fn cluster_wont_start_encryption_session_if_not_fully_connected() {
+	fn cluster_wont_start_generation_session_if_not_fully_connected() {
 		let core = Core::new().unwrap();
 		let clusters = make_clusters(&core, 6013, 3);
 		clusters[0].run().unwrap();
-		match clusters[0].client().new_encryption_session(SessionId::default(), 1) {
+		match clusters[0].client().new_generation_session(SessionId::default(), Public::default(), 1) {
 			Err(Error::NodeDisconnected) => (),
 			Err(e) => panic!("unexpected error {:?}", e),
 			_ => panic!("unexpected success"),
@@ -1261,51 +4415,655 @@ pub mod tests {
 	}
 
 	#[test]
-	fn error_in_encryption_session_broadcasted_to_all_other_nodes() {
+	fn cluster_wont_start_generation_session_if_threshold_is_too_large() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6046, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// session is rejected before it is created
+		match clusters[0].client().new_generation_session(SessionId::default(), Public::default(), 3) {
+			Err(Error::InvalidThreshold { requested: 3, nodes: 3 }) => (),
+			Err(e) => panic!("unexpected error {:?}", e),
+			_ => panic!("unexpected success"),
+		}
+		assert!(clusters[0].client().generation_session(&SessionId::default()).is_none());
+
+		// result future is resolved immediately
+		assert_eq!(clusters[0].client().generate_key(SessionId::default(), Public::default(), 3).wait(),
+			Err(Error::InvalidThreshold { requested: 3, nodes: 3 }));
+		assert!(clusters[0].client().generation_session(&SessionId::default()).is_none());
+	}
+
+	#[test]
+	fn misbehaving_node_is_excluded_from_new_decryption_sessions() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6049, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// every node is required to decrypt the key
+		let id_numbers: BTreeMap<_, _> = clusters.iter()
+			.map(|c| (c.data.self_key_pair.public().clone(), math::generate_random_scalar().unwrap()))
+			.collect();
+		clusters[0].data.sessions.key_storage.insert(SessionId::default(), DocumentKeyShare {
+			author: Public::default(),
+			threshold: 2,
+			common_point: Some(Random.generate().unwrap().public().clone()),
+			encrypted_point: Some(Random.generate().unwrap().public().clone()),
+			versions: vec![DocumentKeyShareVersion::new(id_numbers, math::generate_random_scalar().unwrap())],
+		}).unwrap();
+
+		// node is excluded after repeated protocol violations, but it is still connected
+		let misbehaving_node = clusters[2].data.self_key_pair.public().clone();
+		for _ in 0..NodeReputationParams::default().exclusion_threshold {
+			assert!(clusters[0].data.session_nodes().contains(&misbehaving_node));
+			clusters[0].data.on_session_error(&misbehaving_node, &Error::InvalidMessage);
+		}
+		assert!(!clusters[0].data.session_nodes().contains(&misbehaving_node));
+		assert!(clusters[0].data.connections.connected_nodes().contains(&misbehaving_node));
+		assert!(clusters[0].client().metrics().contains(&format!("secretstore_peer_excluded{{node=\"{}\"}} 1", node_label(&misbehaving_node))));
+		assert!(clusters[0].client().metrics().contains(&format!("secretstore_peer_rejects_total{{node=\"{}\"}} {}",
+			node_label(&misbehaving_node), NodeReputationParams::default().exclusion_threshold)));
+
+		// errors, which are not caused by misbehaviour, are ignored
+		let other_node = clusters[1].data.self_key_pair.public().clone();
+		for _ in 0..NodeReputationParams::default().exclusion_threshold {
+			clusters[0].data.on_session_error(&other_node, &Error::TooEarlyForRequest);
+		}
+		assert!(clusters[0].data.session_nodes().contains(&other_node));
+
+		// remaining nodes are not enough to decrypt the key
+		let requester = Random.generate().unwrap().public().clone();
+		match clusters[0].client().new_decryption_session(SessionId::default(), requester.into(), false) {
+			Err(Error::InvalidThreshold { requested: 2, nodes: 2 }) => (),
+			Err(e) => panic!("unexpected error {:?}", e),
+			_ => panic!("unexpected success"),
+		}
+	}
+
+	#[test]
+	fn error_in_generation_session_broadcasted_to_all_other_nodes() {
 		let mut core = Core::new().unwrap();
 		let clusters = make_clusters(&core, 6016, 3);
 		run_clusters(&clusters);
 		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
 
-		// ask one of nodes to produce faulty encryption sessions
-		clusters[1].client().make_faulty_encryption_sessions();
+		// ask one of nodes to produce faulty generation sessions
+		clusters[1].client().make_faulty_generation_sessions();
 
-		// start && wait for encryption session to fail
-		let session = clusters[0].client().new_encryption_session(SessionId::default(), 1).unwrap();
+		// start && wait for generation session to fail
+		let session = clusters[0].client().new_generation_session(SessionId::default(), Public::default(), 1).unwrap();
 		loop_until(&mut core, time::Duration::from_millis(300), || session.joint_public_key().is_some());
 		assert!(session.joint_public_key().unwrap().is_err());
 
 		// check that faulty session is either removed from all nodes, or nonexistent (already removed)
-		assert!(clusters[0].client().encryption_session(&SessionId::default()).is_none());
+		assert!(clusters[0].client().generation_session(&SessionId::default()).is_none());
 		for i in 1..3 {
-			if let Some(session) = clusters[i].client().encryption_session(&SessionId::default()) {
+			if let Some(session) = clusters[i].client().generation_session(&SessionId::default()) {
 				loop_until(&mut core, time::Duration::from_millis(300), || session.joint_public_key().is_some());
 				assert!(session.joint_public_key().unwrap().is_err());
-				assert!(clusters[i].client().encryption_session(&SessionId::default()).is_none());
+				assert!(clusters[i].client().generation_session(&SessionId::default()).is_none());
 			}
 		}
 	}
 
 	#[test]
-	fn encryption_session_is_removed_when_succeeded() {
+	fn generation_session_is_removed_when_succeeded() {
 		let mut core = Core::new().unwrap();
 		let clusters = make_clusters(&core, 6019, 3);
 		run_clusters(&clusters);
 		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
 
-		// start && wait for encryption session to complete
-		let session = clusters[0].client().new_encryption_session(SessionId::default(), 1).unwrap();
-		loop_until(&mut core, time::Duration::from_millis(300), || session.state() == EncryptionSessionState::Finished);
+		// start && wait for generation session to complete
+		let session = clusters[0].client().new_generation_session(SessionId::default(), Public::default(), 1).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(300), || session.state() == GenerationSessionState::Finished);
 		assert!(session.joint_public_key().unwrap().is_ok());
 
 		// check that session is either removed from all nodes, or nonexistent (already removed)
-		assert!(clusters[0].client().encryption_session(&SessionId::default()).is_none());
+		assert!(clusters[0].client().generation_session(&SessionId::default()).is_none());
 		for i in 1..3 {
-			if let Some(session) = clusters[i].client().encryption_session(&SessionId::default()) {
-				loop_until(&mut core, time::Duration::from_millis(300), || session.state() == EncryptionSessionState::Finished);
+			if let Some(session) = clusters[i].client().generation_session(&SessionId::default()) {
+				loop_until(&mut core, time::Duration::from_millis(300), || session.state() == GenerationSessionState::Finished);
 				assert!(session.joint_public_key().unwrap().is_err());
-				assert!(clusters[i].client().encryption_session(&SessionId::default()).is_none());
+				assert!(clusters[i].client().generation_session(&SessionId::default()).is_none());
+			}
+		}
+	}
+
+	#[test]
+	fn late_message_for_completed_session_is_rejected() {
+		let core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6022, 1);
+		let sessions = &clusters[0].data.sessions;
+		let sender = Random.generate().unwrap().public().clone();
+		let unknown_session_id = SessionId::from(1);
+		let completed_session_id = SessionId::from(2);
+		let make_message = |session_id: &SessionId| GenerationMessage::SessionError(message::SessionError {
+			session: session_id.clone().into(),
+			error: "error".into(),
+		});
+		sessions.completed_generation_sessions.insert(completed_session_id.clone(), time::Instant::now());
+
+		// message for unknown session is postponed until session is created
+		assert!(sessions.generation_session_or_enqueue(&unknown_session_id, &sender, &make_message(&unknown_session_id)).unwrap().is_none());
+		assert_eq!(sessions.early_generation_messages.take(&unknown_session_id).len(), 1);
+
+		// message for completed session is rejected
+		assert_eq!(sessions.generation_session_or_enqueue(&completed_session_id, &sender, &make_message(&completed_session_id)).err(),
+			Some(Error::SessionAlreadyCompleted));
+		assert!(sessions.early_generation_messages.take(&completed_session_id).is_empty());
+	}
+
+	#[test]
+	fn late_message_for_completed_session_is_answered_with_error() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6064, 2);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// session is still running on the first node, but it is already completed on the second node
+		let session_id = SessionId::default();
+		let data = clusters[0].data.clone();
+		let nodes = clusters[0].config().nodes.keys().cloned().collect();
+		let master = clusters[1].config().self_key_pair.public().clone();
+		let session = data.sessions.new_generation_session(master, session_id.clone(), Arc::new(ClusterView::new(data.clone(), nodes))).unwrap();
+		clusters[1].data.sessions.completed_generation_sessions.insert(session_id.clone(), time::Instant::now());
+
+		// late message is answered with error => session on the first node fails
+		let connection = clusters[1].connection(clusters[0].config().self_key_pair.public()).unwrap();
+		ClusterCore::process_generation_message(clusters[1].data.clone(), connection, GenerationMessage::PublicKeyShare(message::PublicKeyShare {
+			session: session_id.clone().into(),
+			public_share: Random.generate().unwrap().public().clone().into(),
+		}));
+		loop_until(&mut core, time::Duration::from_millis(300), || session.state() == GenerationSessionState::Failed);
+		assert_eq!(session.joint_public_key(), Some(Err(Error::Io(format!("{:?}", Error::SessionAlreadyCompleted)))));
+		assert!(data.sessions.generation_session(&session_id).is_none());
+	}
+
+	#[test]
+	fn encryption_session_stores_document_key_on_all_nodes() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6025, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// generate server key
+		let author = Random.generate().unwrap();
+		let session_id = SessionId::default();
+		let session = clusters[0].client().new_generation_session(session_id.clone(), author.public().clone(), 1).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(1000), || session.state() == GenerationSessionState::Finished);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(|c| c.config().key_storage.contains(&session_id)));
+
+		// store document key && wait until every node has stored it
+		let common_point = Random.generate().unwrap().public().clone();
+		let encrypted_point = Random.generate().unwrap().public().clone();
+		let requestor_signature = ethkey::sign(author.secret(), &store_request_hash(&session_id, &common_point, &encrypted_point, 1)).unwrap();
+		let session = clusters[0].client().new_encryption_session(session_id.clone(), requestor_signature, common_point.clone(), encrypted_point.clone(), 1).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(300), || session.state() == EncryptionSessionState::Finished);
+		for cluster in &clusters {
+			let key_share = cluster.config().key_storage.get(&session_id).unwrap();
+			assert_eq!(key_share.common_point, Some(common_point.clone()));
+			assert_eq!(key_share.encrypted_point, Some(encrypted_point.clone()));
+		}
+	}
+
+	#[test]
+	fn generation_result_future_is_resolved_on_other_thread() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6030, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// start generation session && wait for its result on other thread, while cluster is running on this thread
+		let future = clusters[0].client().generate_key(SessionId::default(), Public::default(), 1);
+		let (tx, rx) = mpsc::channel();
+		let waiter = thread::spawn(move || tx.send(future.wait()).unwrap());
+		let result = Mutex::new(None);
+		loop_until(&mut core, time::Duration::from_millis(1000), || {
+			let mut result = result.lock();
+			if result.is_none() {
+				*result = rx.try_recv().ok();
 			}
+			result.is_some()
+		});
+		waiter.join().unwrap();
+		assert!(result.lock().take().unwrap().is_ok());
+		assert!(clusters[0].client().generation_session(&SessionId::default()).is_none());
+	}
+
+	#[test]
+	fn generation_session_is_cancelled_when_result_future_is_dropped() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6033, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// listen for sessions, removed on slave node
+		let recorder = Arc::new(GenerationSessionsRecorder::default());
+		clusters[1].data.sessions.add_generation_sessions_listener(recorder.clone());
+
+		// start generation session && drop the future before session is completed
+		drop(clusters[0].client().generate_key(SessionId::default(), Public::default(), 1));
+		assert!(clusters[0].client().generation_session(&SessionId::default()).is_none());
+
+		// check that slave session has been cancelled
+		loop_until(&mut core, time::Duration::from_millis(300), || !recorder.results.lock().is_empty());
+		assert_eq!(recorder.results.lock()[0], Some(Err(Error::Io("session has been cancelled".into()))));
+		assert!(clusters[1].client().generation_session(&SessionId::default()).is_none());
+	}
+
+	#[test]
+	fn encryption_session_is_cancelled_when_result_future_is_dropped() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6068, 2);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		let author = Random.generate().unwrap();
+		let session_id = SessionId::default();
+		share_key(&clusters, &session_id, author.public());
+
+		// slave session is created, but it is not yet initialized (initialization request is rejected as duplicate)
+		let master = clusters[0].config().self_key_pair.public().clone();
+		let data = clusters[1].data.clone();
+		let nodes = clusters[1].config().nodes.keys().cloned().collect();
+		let slave_session = data.sessions.new_encryption_session(master, session_id.clone(), Arc::new(ClusterView::new(data.clone(), nodes))).unwrap();
+
+		// start encryption session && drop the future before session is completed
+		let common_point = Random.generate().unwrap().public().clone();
+		let encrypted_point = Random.generate().unwrap().public().clone();
+		let requestor_signature = ethkey::sign(author.secret(), &store_request_hash(&session_id, &common_point, &encrypted_point, 1)).unwrap();
+		drop(clusters[0].client().store_document_key(session_id.clone(), requestor_signature, common_point, encrypted_point, 1));
+		assert!(!clusters[0].data.sessions.encryption_sessions.read().contains_key(&session_id));
+
+		// check that slave session has been cancelled
+		loop_until(&mut core, time::Duration::from_millis(300), || slave_session.result().is_some());
+		assert_eq!(slave_session.result(), Some(Err(Error::Io("session has been cancelled".into()))));
+		assert!(!data.sessions.encryption_sessions.read().contains_key(&session_id));
+		assert_eq!(clusters[1].config().key_storage.get(&session_id).unwrap().common_point, None);
+	}
+
+	#[test]
+	fn sessions_are_notified_about_connection_events() {
+		let core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6085, 3);
+
+		// encryption session, which is not yet initialized, survives disconnection of other nodes
+		let data = clusters[0].data.clone();
+		let self_node = data.self_key_pair.public().clone();
+		let other_node = clusters[1].config().self_key_pair.public().clone();
+		let nodes: BTreeSet<_> = clusters[0].config().nodes.keys().cloned().collect();
+		let session_id = SessionId::default();
+		data.sessions.new_encryption_session(self_node, session_id.clone(), Arc::new(ClusterView::new(data.clone(), nodes))).unwrap();
+		let cluster_view = data.sessions.encryption_sessions.read()[&session_id].cluster_view.clone();
+		assert!(cluster_view.is_connected(&other_node));
+		assert!(data.sessions.has_active_sessions(&other_node));
+
+		// lost connection is delivered to the session
+		data.sessions.on_connection_lost(&other_node);
+		assert!(!cluster_view.is_connected(&other_node));
+		assert!(!data.sessions.has_active_sessions(&other_node));
+
+		// && so is established connection
+		data.sessions.on_connection_established(&other_node, &BTreeSet::new());
+		assert!(cluster_view.is_connected(&other_node));
+		assert!(data.sessions.has_active_sessions(&other_node));
+
+		// nodes, which are not required for the session, are never added to the session
+		let unknown_node = Random.generate().unwrap().public().clone();
+		data.sessions.on_connection_established(&unknown_node, &BTreeSet::new());
+		assert!(!cluster_view.is_connected(&unknown_node));
+	}
+
+	#[test]
+	fn key_removal_session_removes_key_from_all_nodes() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6036, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// generate server key
+		let author = Random.generate().unwrap();
+		let session_id = SessionId::default();
+		let session = clusters[0].client().new_generation_session(session_id.clone(), author.public().clone(), 1).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(1000), || session.state() == GenerationSessionState::Finished);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(|c| c.config().key_storage.contains(&session_id)));
+
+		// other requestor can not remove the key
+		let other_signature = ethkey::sign(Random.generate().unwrap().secret(), &removal_request_hash(&session_id, 1)).unwrap();
+		assert_eq!(clusters[0].client().remove_key(session_id.clone(), other_signature, 1).wait(), Err(Error::AccessDenied));
+
+		// author signature of other request could not be used to remove the key
+		let retrieval_signature = ethkey::sign(author.secret(), &session_id).unwrap();
+		assert_eq!(clusters[0].client().remove_key(session_id.clone(), retrieval_signature, 1).wait(), Err(Error::AccessDenied));
+
+		// but author can remove the key
+		let requestor_signature = ethkey::sign(author.secret(), &removal_request_hash(&session_id, 1)).unwrap();
+		let future = clusters[0].client().remove_key(session_id.clone(), requestor_signature, 1);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(|c| !c.config().key_storage.contains(&session_id)));
+		assert_eq!(future.wait(), Ok(BTreeSet::new()));
+		assert_eq!(clusters[0].config().key_storage.key_removal_retries(), Ok(BTreeMap::new()));
+	}
+
+	#[test]
+	fn key_removal_is_retried_when_connection_to_key_holder_is_established() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6066, 2);
+		let other_node = clusters[1].config().self_key_pair.public().clone();
+
+		// key has been removed from the first node, but the second node has been unreachable
+		let author = Random.generate().unwrap();
+		let session_id = SessionId::default();
+		share_key(&clusters, &session_id, author.public());
+		clusters[0].config().key_storage.remove(&session_id).unwrap();
+		clusters[0].config().key_storage.set_key_removal_retry(&session_id, Some(KeyRemovalRetry {
+			requestor_signature: ethkey::sign(author.secret(), &removal_request_hash(&session_id, 1)).unwrap(),
+			nonce: 1,
+			nodes: vec![other_node.clone()].into_iter().collect(),
+		})).unwrap();
+		assert_eq!(clusters[0].data.sessions.on_connection_established(&other_node, &BTreeSet::new()).len(), 0);
+		assert_eq!(clusters[0].data.sessions.on_connection_established(&other_node, &vec![other_node.clone()].into_iter().collect()).len(), 1);
+
+		// removal is retried as soon as nodes are connected (maintain procedures are not yet executed)
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+		loop_until(&mut core, time::Duration::from_millis(300), || !clusters[1].config().key_storage.contains(&session_id));
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters[0].config().key_storage.key_removal_retries() == Ok(BTreeMap::new()));
+	}
+
+	#[test]
+	fn key_removal_is_retried_until_key_holder_is_removed_from_cluster() {
+		let core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6039, 2);
+		let sessions = &clusters[0].data.sessions;
+		let other_node = clusters[1].config().self_key_pair.public().clone();
+		let session_id = SessionId::default();
+		clusters[0].config().key_storage.set_key_removal_retry(&session_id, Some(KeyRemovalRetry {
+			requestor_signature: Default::default(),
+			nonce: 1,
+			nodes: vec![other_node.clone()].into_iter().collect(),
+		})).unwrap();
+
+		// removal is retried only when key holder is connected
+		assert!(sessions.key_removals_to_retry(&BTreeSet::new()).is_empty());
+		let connected_nodes = vec![other_node.clone()].into_iter().collect();
+		assert_eq!(sessions.key_removals_to_retry(&connected_nodes).len(), 1);
+
+		// key holder, which is removed from the cluster, is forgotten
+		sessions.update_nodes(BTreeSet::new());
+		assert!(sessions.key_removals_to_retry(&connected_nodes).is_empty());
+		assert_eq!(clusters[0].config().key_storage.key_removal_retries(), Ok(BTreeMap::new()));
+	}
+
+	#[test]
+	fn silent_session_is_stopped_when_timeout_passes() {
+		let core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6042, 2);
+		let data = clusters[0].data.clone();
+		let master = clusters[1].config().self_key_pair.public().clone();
+		let nodes = clusters[0].config().nodes.keys().cloned().collect();
+
+		// slave session, which never receives messages from master
+		let session_id = SessionId::default();
+		let session = data.sessions.new_generation_session(master, session_id.clone(), Arc::new(ClusterView::new(data.clone(), nodes))).unwrap();
+		let (future, listener) = SessionResultFuture::new(session.clone(), GenerationSessionImpl::result, || ());
+		data.sessions.add_generation_sessions_listener(listener);
+		let created_at = *data.sessions.generation_sessions.read()[&session_id].last_message_time.lock();
+		let timeout = data.sessions.timeouts.generation;
+
+		// session is alive until timeout passes
+		data.sessions.stop_stalled_sessions(created_at + timeout);
+		assert!(data.sessions.generation_session(&session_id).is_some());
+
+		// ...and is stopped after that
+		data.sessions.stop_stalled_sessions(created_at + timeout + time::Duration::from_secs(1));
+		assert!(data.sessions.generation_session(&session_id).is_none());
+		assert_eq!(future.wait(), Err(Error::NodeDisconnected));
+	}
+
+	#[test]
+	fn session_receiving_messages_is_not_stopped() {
+		let core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6044, 2);
+		let data = clusters[0].data.clone();
+		let master = clusters[1].config().self_key_pair.public().clone();
+		let nodes = clusters[0].config().nodes.keys().cloned().collect();
+
+		let session_id = SessionId::default();
+		data.sessions.new_generation_session(master.clone(), session_id.clone(), Arc::new(ClusterView::new(data.clone(), nodes))).unwrap();
+		let created_at = *data.sessions.generation_sessions.read()[&session_id].last_message_time.lock();
+		let timeout = data.sessions.timeouts.generation;
+
+		// every message refreshes session activity time
+		thread::sleep(time::Duration::from_millis(10));
+		let message = GenerationMessage::SessionError(message::SessionError {
+			session: session_id.clone().into(),
+			error: "error".into(),
+		});
+		assert!(data.sessions.generation_session_or_enqueue(&session_id, &master, &message).unwrap().is_some());
+		let last_message_at = *data.sessions.generation_sessions.read()[&session_id].last_message_time.lock();
+		assert!(last_message_at > created_at);
+
+		// timeout since session creation has passed, but not since last message
+		data.sessions.stop_stalled_sessions(created_at + timeout + (last_message_at - created_at) / 2);
+		assert!(data.sessions.generation_session(&session_id).is_some());
+
+		data.sessions.stop_stalled_sessions(last_message_at + timeout + time::Duration::from_secs(1));
+		assert!(data.sessions.generation_session(&session_id).is_none());
+	}
+
+	#[test]
+	fn servers_set_change_session_is_refreshed_by_share_add_messages() {
+		let core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6028, 2);
+		let data = clusters[0].data.clone();
+		let other_node = clusters[1].config().self_key_pair.public().clone();
+		let nodes: BTreeSet<_> = clusters[0].config().nodes.keys().cloned().collect();
+
+		// servers set change session has started share add session
+		let servers_set_change_session_id = SessionId::from(1);
+		let executor = Arc::new(ClusterShareAddSessionsExecutor {
+			session_id: servers_set_change_session_id.clone(),
+			cluster: Arc::downgrade(&data),
+		});
+		data.sessions.new_servers_set_change_session(servers_set_change_session_id.clone(),
+			Arc::new(ClusterView::new(data.clone(), nodes.clone())), executor, 4).unwrap();
+		let share_add_session_id = SessionId::from(2);
+		data.sessions.new_share_add_session(data.self_key_pair.public().clone(), share_add_session_id.clone(),
+			Arc::new(ClusterView::new(data.clone(), nodes)), Some(servers_set_change_session_id.clone())).unwrap();
+		let created_at = *data.sessions.servers_set_change_sessions.read()[&servers_set_change_session_id].last_message_time.lock();
+
+		// every message of share add session refreshes servers set change session activity time
+		thread::sleep(time::Duration::from_millis(10));
+		let message = ShareAddMessage::ShareAddSessionError(message::ShareAddSessionError {
+			session: share_add_session_id.clone().into(),
+			error: "error".into(),
+		});
+		assert!(data.sessions.share_add_session_or_enqueue(&share_add_session_id, &other_node, &message).unwrap().is_some());
+		let last_message_at = *data.sessions.servers_set_change_sessions.read()[&servers_set_change_session_id].last_message_time.lock();
+		assert!(last_message_at > created_at);
+	}
+
+	#[test]
+	fn servers_set_change_session_migrates_keys_to_new_node() {
+		let mut core = Core::new().unwrap();
+		let admin = Random.generate().unwrap();
+		let clusters = make_clusters_with_admin(&core, 6052, 4, Some(admin.public().clone()));
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// keys are held by 3 of 4 nodes
+		let author = Random.generate().unwrap();
+		let keys: Vec<_> = (0..2).map(|_| SessionId::random()).collect();
+		for key_id in &keys {
+			share_key(&clusters[0..3], key_id, author.public());
+		}
+
+		// administrator extends servers set to the 4th node
+		let session_id = SessionId::random();
+		let servers_set: BTreeSet<_> = clusters[0].config().nodes.keys().cloned().collect();
+		let admin_signature = ethkey::sign(admin.secret(), &servers_set_change_hash(&session_id, &servers_set, &servers_set)).unwrap();
+		let session = clusters[0].client().new_servers_set_change_session(session_id, servers_set.clone(), admin_signature).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(1000), || session.state() == ServersSetChangeSessionState::Finished);
+		loop_until(&mut core, time::Duration::from_millis(300), || keys.iter().all(|key_id| is_key_held_by(&clusters, key_id, &servers_set)));
+		assert_eq!(session.wait(None), Ok(()));
+		for key_id in &keys {
+			assert_eq!(session.keys()[key_id], KeyMigrationState::Migrated);
 		}
 	}
+
+	#[test]
+	fn servers_set_change_session_is_resumed_after_failure() {
+		let mut core = Core::new().unwrap();
+		let admin = Random.generate().unwrap();
+		let clusters = make_clusters_with_admin(&core, 6056, 4, Some(admin.public().clone()));
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		let author = Random.generate().unwrap();
+		let keys: Vec<_> = (0..2).map(|_| SessionId::random()).collect();
+		for key_id in &keys {
+			share_key(&clusters[0..3], key_id, author.public());
+		}
+
+		// one of holders has lost its share of the second key (i.e. it has crashed && its database is not yet restored)
+		let lost_key_share = clusters[1].config().key_storage.get(&keys[1]).unwrap();
+		clusters[1].config().key_storage.remove(&keys[1]).unwrap();
+
+		// => servers set change fails, but the first key is migrated
+		let servers_set: BTreeSet<_> = clusters[0].config().nodes.keys().cloned().collect();
+		let session_id = SessionId::random();
+		let admin_signature = ethkey::sign(admin.secret(), &servers_set_change_hash(&session_id, &servers_set, &servers_set)).unwrap();
+		let session = clusters[0].client().new_servers_set_change_session(session_id, servers_set.clone(), admin_signature).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(1000), || session.state() == ServersSetChangeSessionState::Failed);
+		loop_until(&mut core, time::Duration::from_millis(300), || is_key_held_by(&clusters, &keys[0], &servers_set)
+			&& clusters.iter().all(|c| c.data.sessions.share_add_sessions.read().is_empty()));
+		assert_eq!(session.keys()[&keys[0]], KeyMigrationState::Migrated);
+		match session.keys()[&keys[1]] {
+			KeyMigrationState::Failed(_) => (),
+			ref state => panic!("unexpected migration state {:?}", state),
+		}
+		assert!(!clusters[3].config().key_storage.contains(&keys[1]));
+
+		// when share is restored, administrator restarts servers set change && only the second key is migrated
+		clusters[1].config().key_storage.insert(keys[1].clone(), lost_key_share).unwrap();
+		let session_id = SessionId::random();
+		let admin_signature = ethkey::sign(admin.secret(), &servers_set_change_hash(&session_id, &servers_set, &servers_set)).unwrap();
+		let session = clusters[0].client().new_servers_set_change_session(session_id, servers_set.clone(), admin_signature).unwrap();
+		loop_until(&mut core, time::Duration::from_millis(1000), || session.state() == ServersSetChangeSessionState::Finished);
+		loop_until(&mut core, time::Duration::from_millis(300), || keys.iter().all(|key_id| is_key_held_by(&clusters, key_id, &servers_set)));
+		assert_eq!(session.keys()[&keys[0]], KeyMigrationState::AlreadyMigrated);
+		assert_eq!(session.keys()[&keys[1]], KeyMigrationState::Migrated);
+	}
+
+	#[test]
+	fn servers_set_change_session_is_rejected_without_valid_admin_signature() {
+		let mut core = Core::new().unwrap();
+		let admin = Random.generate().unwrap();
+		let clusters = make_clusters_with_admin(&core, 6060, 3, Some(admin.public().clone()));
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		let servers_set: BTreeSet<_> = clusters[0].config().nodes.keys().cloned().collect();
+		let session_id = SessionId::random();
+		let admin_signature = ethkey::sign(admin.secret(), &servers_set_change_hash(&session_id, &servers_set, &servers_set)).unwrap();
+
+		// change, which is not signed by administrator, is rejected
+		let other_signature = ethkey::sign(Random.generate().unwrap().secret(), &servers_set_change_hash(&session_id, &servers_set, &servers_set)).unwrap();
+		assert_eq!(clusters[0].client().new_servers_set_change_session(session_id.clone(), servers_set.clone(), other_signature)
+			.map(|_| ()), Err(Error::AccessDenied));
+
+		// administrator signature could not be replayed to start other session
+		assert_eq!(clusters[0].client().new_servers_set_change_session(SessionId::random(), servers_set.clone(), admin_signature.clone())
+			.map(|_| ()), Err(Error::AccessDenied));
+
+		// or to change servers set to other set
+		let mut other_servers_set = servers_set.clone();
+		other_servers_set.remove(clusters[2].config().self_key_pair.public());
+		assert_eq!(clusters[0].client().new_servers_set_change_session(session_id.clone(), other_servers_set, admin_signature.clone())
+			.map(|_| ()), Err(Error::AccessDenied));
+
+		// other nodes are also rejecting replayed requests
+		let master = clusters[0].config().self_key_pair.public().clone();
+		let connection = clusters[1].connection(&master).unwrap();
+		let request = |session_id: &SessionId| ServersSetChangeMessage::UnknownSessionsRequest(message::UnknownSessionsRequest {
+			session: session_id.clone().into(),
+			admin_signature: admin_signature.clone().into(),
+			old_servers_set: servers_set.iter().cloned().map(Into::into).collect(),
+			new_servers_set: servers_set.iter().cloned().map(Into::into).collect(),
+		});
+		let other_session_id = SessionId::random();
+		ClusterCore::process_servers_set_change_message(clusters[1].data.clone(), connection.clone(), request(&other_session_id));
+		assert!(clusters[1].data.sessions.servers_set_changes.read().is_empty());
+		ClusterCore::process_servers_set_change_message(clusters[1].data.clone(), connection, request(&session_id));
+		assert_eq!(clusters[1].data.sessions.servers_set_changes.read().get(&session_id), Some(&master));
+	}
+
+	#[test]
+	fn concurrent_generation_sessions_produce_single_key() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6072, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// two nodes are starting generation of the same key simultaneously
+		let session_id = SessionId::default();
+		let results = wait_for_results(&mut core, (0..2)
+			.map(|i| clusters[i].client().generate_key(session_id.clone(), Public::default(), 1))
+			.collect());
+
+		// session of the node with lower id wins && generated key is only known to its requester
+		let winner = if clusters[0].config().self_key_pair.public() < clusters[1].config().self_key_pair.public() { 0 } else { 1 };
+		assert!(results[winner].is_ok());
+		assert_eq!(results[1 - winner], Err(Error::SessionAlreadyStarted));
+
+		// exactly one key is generated && stored by every node
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(|c| c.client().generation_session(&session_id).is_none()));
+		let key_shares: Vec<_> = clusters.iter().map(|c| c.config().key_storage.get(&session_id).unwrap()).collect();
+		assert!(key_shares.iter().all(|key_share| key_share.common_point.is_some()
+			&& key_share.common_point == key_shares[0].common_point
+			&& key_share.encrypted_point == key_shares[0].encrypted_point));
+	}
+
+	#[test]
+	fn concurrent_encryption_sessions_store_single_document_key() {
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6075, 3);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		let author = Random.generate().unwrap();
+		let winner = if clusters[0].config().self_key_pair.public() < clusters[1].config().self_key_pair.public() { 0 } else { 1 };
+		let document_key = |_| (Random.generate().unwrap().public().clone(), Random.generate().unwrap().public().clone());
+		let stored_document_key = |session_id: &SessionId| clusters.iter()
+			.map(|c| c.config().key_storage.get(session_id).map(|key_share| (key_share.common_point, key_share.encrypted_point)).unwrap())
+			.collect::<BTreeSet<_>>();
+
+		// two nodes are storing the same document key simultaneously => both requesters are served by the winning session
+		let session_id = SessionId::from(1);
+		share_key(&clusters, &session_id, author.public());
+		let (common_point, encrypted_point) = document_key(());
+		let requestor_signature = ethkey::sign(author.secret(), &store_request_hash(&session_id, &common_point, &encrypted_point, 1)).unwrap();
+		let results = wait_for_results(&mut core, (0..2)
+			.map(|i| clusters[i].client().store_document_key(session_id.clone(), requestor_signature.clone(), common_point.clone(), encrypted_point.clone(), 1))
+			.collect());
+		assert_eq!(results, vec![Ok(()), Ok(())]);
+		assert_eq!(stored_document_key(&session_id), vec![(Some(common_point), Some(encrypted_point))].into_iter().collect());
+
+		// two nodes are storing different document keys simultaneously => only document key of the winning session is stored
+		let session_id = SessionId::from(2);
+		share_key(&clusters, &session_id, author.public());
+		let document_keys = vec![document_key(()), document_key(())];
+		let requestor_signatures: Vec<_> = document_keys.iter()
+			.map(|&(ref common_point, ref encrypted_point)| ethkey::sign(author.secret(), &store_request_hash(&session_id, common_point, encrypted_point, 1)).unwrap())
+			.collect();
+		let results = wait_for_results(&mut core, (0..2)
+			.map(|i| clusters[i].client().store_document_key(session_id.clone(), requestor_signatures[i].clone(), document_keys[i].0.clone(), document_keys[i].1.clone(), 1))
+			.collect());
+		assert_eq!(results[winner], Ok(()));
+		assert_eq!(results[1 - winner], Err(Error::SessionAlreadyStarted));
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(|c| c.data.sessions.encryption_sessions.read().is_empty()));
+		assert_eq!(stored_document_key(&session_id), vec![(Some(document_keys[winner].0.clone()), Some(document_keys[winner].1.clone()))].into_iter().collect());
+	}
 }
@@ -0,0 +1,132 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use parking_lot::Mutex;
+
+/// Ids of recently completed (either finished, or failed) sessions.
+/// Session data is dropped as soon as session completes, but its id is retained for some time,
+/// so that late messages are recognized as belonging to completed session rather than to unknown one.
+/// Optional value (i.e. session master) could be retained along with the id.
+pub struct CompletedSessions<K, V = ()> {
+	/// How long session id is retained after session completion.
+	ttl: Duration,
+	/// Completed sessions, their completion time && associated value.
+	sessions: Mutex<BTreeMap<K, (Instant, V)>>,
+	/// Number of session ids, which have been garbage collected.
+	collected: AtomicUsize,
+}
+
+impl<K> CompletedSessions<K> where K: Ord + Clone {
+	/// Remember completed session.
+	pub fn insert(&self, key: K, now: Instant) {
+		self.insert_with_value(key, (), now);
+	}
+}
+
+impl<K, V> CompletedSessions<K, V> where K: Ord + Clone, V: Clone {
+	pub fn new(ttl: Duration) -> Self {
+		CompletedSessions {
+			ttl: ttl,
+			sessions: Mutex::new(BTreeMap::new()),
+			collected: AtomicUsize::new(0),
+		}
+	}
+
+	/// Remember completed session && associated value.
+	pub fn insert_with_value(&self, key: K, value: V, now: Instant) {
+		self.sessions.lock().insert(key, (now, value));
+	}
+
+	/// Check if session has been completed recently.
+	pub fn contains(&self, key: &K) -> bool {
+		self.sessions.lock().contains_key(key)
+	}
+
+	/// Get value, associated with recently completed session.
+	pub fn value(&self, key: &K) -> Option<V> {
+		self.sessions.lock().get(key).map(|&(_, ref value)| value.clone())
+	}
+
+	/// Forget sessions, which have been completed more than ttl ago. Returns number of collected sessions.
+	pub fn collect(&self, now: Instant) -> usize {
+		let mut sessions = self.sessions.lock();
+		let expired: Vec<_> = sessions.iter()
+			.filter(|&(_, &(time, _))| time + self.ttl <= now)
+			.map(|(key, _)| key.clone())
+			.collect();
+		for key in &expired {
+			sessions.remove(key);
+		}
+
+		self.collected.fetch_add(expired.len(), Ordering::Relaxed);
+		expired.len()
+	}
+
+	/// Total number of garbage collected sessions.
+	pub fn collected_count(&self) -> usize {
+		self.collected.load(Ordering::Relaxed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+	use super::CompletedSessions;
+
+	#[test]
+	fn completed_session_is_retained_within_ttl() {
+		let sessions = CompletedSessions::new(Duration::from_secs(60));
+		let now = Instant::now();
+		sessions.insert(1, now);
+		sessions.insert(2, now + Duration::from_secs(30));
+
+		assert_eq!(sessions.collect(now + Duration::from_secs(59)), 0);
+		assert!(sessions.contains(&1));
+		assert!(sessions.contains(&2));
+		assert!(!sessions.contains(&3));
+	}
+
+	#[test]
+	fn completed_session_is_collected_after_ttl() {
+		let sessions = CompletedSessions::new(Duration::from_secs(60));
+		let now = Instant::now();
+		sessions.insert(1, now);
+		sessions.insert(2, now + Duration::from_secs(30));
+
+		assert_eq!(sessions.collect(now + Duration::from_secs(60)), 1);
+		assert!(!sessions.contains(&1));
+		assert!(sessions.contains(&2));
+
+		assert_eq!(sessions.collect(now + Duration::from_secs(90)), 1);
+		assert!(!sessions.contains(&2));
+		assert_eq!(sessions.collected_count(), 2);
+	}
+
+	#[test]
+	fn value_is_retained_along_with_completed_session() {
+		let sessions = CompletedSessions::new(Duration::from_secs(60));
+		let now = Instant::now();
+		sessions.insert_with_value(1, "master", now);
+
+		assert_eq!(sessions.value(&1), Some("master"));
+		assert_eq!(sessions.value(&2), None);
+		assert_eq!(sessions.collect(now + Duration::from_secs(60)), 1);
+		assert_eq!(sessions.value(&1), None);
+	}
+}
@@ -0,0 +1,397 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::u32;
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use parking_lot::Mutex;
+use key_server_cluster::NodeId;
+use key_server_cluster::backoff::{BackoffPolicy, ExponentialBackoff};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Connection state of single peer.
+pub enum PeerState {
+	/// There's no connection to the peer && no connection attempt is in progress.
+	Disconnected,
+	/// Connection attempt is in progress.
+	Connecting,
+	/// Last connection attempt has failed. Next attempt is allowed after given time.
+	Backoff {
+		/// Time of next connection attempt.
+		until: Instant,
+	},
+	/// Connection is established.
+	Connected,
+}
+
+/// Tracks connection state of every other key server && decides when it is time to reconnect.
+pub struct ConnectionManager {
+	/// Self node id.
+	self_node_id: NodeId,
+	/// Allow connecting to 'higher' nodes.
+	allow_connecting_to_higher_nodes: bool,
+	/// Delay between connection attempts. We never stop reconnecting.
+	backoff: Mutex<ExponentialBackoff>,
+	/// Peers data.
+	peers: Mutex<BTreeMap<NodeId, PeerData>>,
+}
+
+/// Single peer data.
+struct PeerData {
+	/// Peer address.
+	address: SocketAddr,
+	/// Connection state.
+	state: PeerState,
+	/// Number of connection attempts failed in a row.
+	failures: u32,
+}
+
+impl ConnectionManager {
+	pub fn new(self_node_id: NodeId, allow_connecting_to_higher_nodes: bool, nodes: BTreeMap<NodeId, SocketAddr>, min_backoff: Duration, max_backoff: Duration) -> Self {
+		ConnectionManager {
+			self_node_id: self_node_id,
+			allow_connecting_to_higher_nodes: allow_connecting_to_higher_nodes,
+			backoff: Mutex::new(ExponentialBackoff {
+				base: min_backoff,
+				max: max_backoff,
+				max_retries: u32::MAX,
+			}),
+			peers: Mutex::new(nodes.into_iter()
+				.map(|(node_id, address)| (node_id, PeerData {
+					address: address,
+					state: PeerState::Disconnected,
+					failures: 0,
+				}))
+				.collect()),
+		}
+	}
+
+	/// Get connection state of given peer.
+	pub fn state(&self, node: &NodeId) -> Option<PeerState> {
+		self.peers.lock().get(node).map(|peer| peer.state)
+	}
+
+	/// Add new peer or update address of existing peer. Established connection (if any) is kept.
+	pub fn add_node(&self, node: NodeId, address: SocketAddr) {
+		let mut peers = self.peers.lock();
+		let peer = peers.entry(node).or_insert_with(|| PeerData {
+			address: address.clone(),
+			state: PeerState::Disconnected,
+			failures: 0,
+		});
+		peer.address = address;
+	}
+
+	/// Forget about peer. Returns true if peer was known before.
+	pub fn remove_node(&self, node: &NodeId) -> bool {
+		self.peers.lock().remove(node).is_some()
+	}
+
+	/// Select peers we should connect to right now && mark them as connecting.
+	pub fn nodes_to_connect(&self, now: Instant) -> BTreeMap<NodeId, SocketAddr> {
+		let mut peers = self.peers.lock();
+		let mut nodes = BTreeMap::new();
+		for (node_id, peer) in peers.iter_mut() {
+			if !self.allow_connecting_to_higher_nodes && &self.self_node_id >= node_id {
+				continue;
+			}
+
+			let is_ready = match peer.state {
+				PeerState::Disconnected => true,
+				PeerState::Backoff { until } => until <= now,
+				PeerState::Connecting | PeerState::Connected => false,
+			};
+			if is_ready {
+				peer.state = PeerState::Connecting;
+				nodes.insert(node_id.clone(), peer.address.clone());
+			}
+		}
+		nodes
+	}
+
+	/// Called when outbound connection attempt has failed.
+	pub fn on_connect_failed(&self, node: &NodeId, now: Instant) {
+		let mut peers = self.peers.lock();
+		if let Some(peer) = peers.get_mut(node) {
+			// inbound connection could be established while we were connecting
+			if peer.state != PeerState::Connecting {
+				return;
+			}
+
+			peer.failures = peer.failures.saturating_add(1);
+			let delay = self.backoff_delay(peer.failures);
+			trace!(target: "secretstore_net", "{}: failed to connect to {} at {}. Retrying in {:?}", self.self_node_id, node, peer.address, delay);
+			peer.state = PeerState::Backoff { until: now + delay };
+		}
+	}
+
+	/// Called when connection to the peer is established. Returns true if peer was not connected before.
+	pub fn on_connection_established(&self, node: &NodeId) -> bool {
+		let mut peers = self.peers.lock();
+		match peers.get_mut(node) {
+			Some(peer) => {
+				let is_new_connection = peer.state != PeerState::Connected;
+				peer.state = PeerState::Connected;
+				peer.failures = 0;
+				is_new_connection
+			},
+			None => false,
+		}
+	}
+
+	/// Called when connection to the peer is lost. Returns true if peer was connected before.
+	pub fn on_connection_lost(&self, node: &NodeId) -> bool {
+		let mut peers = self.peers.lock();
+		match peers.get_mut(node) {
+			Some(peer) => {
+				let was_connected = peer.state == PeerState::Connected;
+				if was_connected {
+					peer.state = PeerState::Disconnected;
+				}
+				was_connected
+			},
+			None => false,
+		}
+	}
+
+	/// Delay before next connection attempt after given number of failed attempts.
+	fn backoff_delay(&self, failures: u32) -> Duration {
+		let mut backoff = self.backoff.lock();
+		let max_delay = backoff.max;
+		backoff.next_delay(failures).unwrap_or(max_delay)
+	}
+}
+
+/// When both nodes have connected to each other simultaneously, only one of these connections is kept.
+/// The agreement is that node with lower id must establish connection to node with higher id.
+pub fn is_preferred_connection(self_node_id: &NodeId, other_node_id: &NodeId, is_inbound: bool) -> bool {
+	if self_node_id < other_node_id {
+		!is_inbound
+	} else {
+		is_inbound
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+	use std::collections::{BTreeMap, VecDeque};
+	use std::net::SocketAddr;
+	use ethkey::{Random, Generator};
+	use key_server_cluster::NodeId;
+	use super::{ConnectionManager, PeerState, is_preferred_connection};
+
+	fn make_nodes(num_nodes: usize) -> Vec<NodeId> {
+		let mut nodes: Vec<_> = (0..num_nodes).map(|_| Random.generate().unwrap().public().clone()).collect();
+		nodes.sort();
+		nodes
+	}
+
+	/// Dialer, which connects to peers without touching network && records connection events in order of delivery.
+	#[derive(Default)]
+	struct MockDialer {
+		/// Results of next connection attempts to every peer.
+		results: BTreeMap<NodeId, VecDeque<bool>>,
+		/// Delivered connection events: true if connection has been established, false if it has been lost.
+		events: Vec<(NodeId, bool)>,
+	}
+
+	impl MockDialer {
+		fn dial(&mut self, manager: &ConnectionManager, now: Instant) {
+			for node in manager.nodes_to_connect(now).keys() {
+				match self.results.get_mut(node).and_then(|results| results.pop_front()) {
+					Some(true) => self.on_connected(manager, node),
+					_ => manager.on_connect_failed(node, now),
+				}
+			}
+		}
+
+		fn on_connected(&mut self, manager: &ConnectionManager, node: &NodeId) {
+			if manager.on_connection_established(node) {
+				self.events.push((node.clone(), true));
+			}
+		}
+
+		fn on_disconnected(&mut self, manager: &ConnectionManager, node: &NodeId) {
+			if manager.on_connection_lost(node) {
+				self.events.push((node.clone(), false));
+			}
+		}
+	}
+
+	fn make_manager(self_node_id: NodeId, nodes: &[NodeId], allow_connecting_to_higher_nodes: bool) -> ConnectionManager {
+		let nodes: BTreeMap<_, _> = nodes.iter().enumerate()
+			.filter(|&(_, n)| n != &self_node_id)
+			.map(|(i, n)| (n.clone(), format!("127.0.0.1:{}", 6000 + i).parse::<SocketAddr>().unwrap()))
+			.collect();
+		ConnectionManager::new(self_node_id, allow_connecting_to_higher_nodes, nodes, Duration::from_secs(10), Duration::from_secs(60))
+	}
+
+	#[test]
+	fn connects_only_to_higher_nodes_by_default() {
+		let nodes = make_nodes(3);
+		let manager = make_manager(nodes[1].clone(), &nodes, false);
+		let to_connect = manager.nodes_to_connect(Instant::now());
+		assert_eq!(to_connect.keys().cloned().collect::<Vec<_>>(), vec![nodes[2].clone()]);
+		assert_eq!(manager.state(&nodes[0]), Some(PeerState::Disconnected));
+		assert_eq!(manager.state(&nodes[2]), Some(PeerState::Connecting));
+
+		let manager = make_manager(nodes[1].clone(), &nodes, true);
+		assert_eq!(manager.nodes_to_connect(Instant::now()).len(), 2);
+	}
+
+	#[test]
+	fn does_not_dial_twice_while_connecting() {
+		let nodes = make_nodes(2);
+		let manager = make_manager(nodes[0].clone(), &nodes, false);
+		let now = Instant::now();
+		assert_eq!(manager.nodes_to_connect(now).len(), 1);
+		assert!(manager.nodes_to_connect(now).is_empty());
+	}
+
+	#[test]
+	fn backoff_grows_exponentially_up_to_maximum() {
+		let nodes = make_nodes(2);
+		let manager = make_manager(nodes[0].clone(), &nodes, false);
+		let start = Instant::now();
+
+		let expected_delays = [10, 20, 40, 60, 60];
+		let mut now = start;
+		for expected_delay in expected_delays.iter() {
+			assert_eq!(manager.nodes_to_connect(now).len(), 1);
+			manager.on_connect_failed(&nodes[1], now);
+			let until = now + Duration::from_secs(*expected_delay);
+			assert_eq!(manager.state(&nodes[1]), Some(PeerState::Backoff { until: until }));
+
+			// not ready before backoff expires
+			assert!(manager.nodes_to_connect(until - Duration::from_secs(1)).is_empty());
+			now = until;
+		}
+
+		// successful connection resets backoff
+		assert_eq!(manager.nodes_to_connect(now).len(), 1);
+		assert!(manager.on_connection_established(&nodes[1]));
+		assert!(manager.on_connection_lost(&nodes[1]));
+		assert_eq!(manager.nodes_to_connect(now).len(), 1);
+		manager.on_connect_failed(&nodes[1], now);
+		assert_eq!(manager.state(&nodes[1]), Some(PeerState::Backoff { until: now + Duration::from_secs(10) }));
+	}
+
+	#[test]
+	fn failed_outbound_attempt_does_not_override_inbound_connection() {
+		let nodes = make_nodes(2);
+		let manager = make_manager(nodes[0].clone(), &nodes, false);
+		let now = Instant::now();
+		assert_eq!(manager.nodes_to_connect(now).len(), 1);
+		assert!(manager.on_connection_established(&nodes[1]));
+		manager.on_connect_failed(&nodes[1], now);
+		assert_eq!(manager.state(&nodes[1]), Some(PeerState::Connected));
+	}
+
+	#[test]
+	fn connection_events_are_reported_once_per_transition() {
+		let nodes = make_nodes(2);
+		let manager = make_manager(nodes[0].clone(), &nodes, false);
+
+		assert!(!manager.on_connection_lost(&nodes[1]));
+		assert!(manager.on_connection_established(&nodes[1]));
+		assert!(!manager.on_connection_established(&nodes[1]));
+		assert!(manager.on_connection_lost(&nodes[1]));
+		assert!(!manager.on_connection_lost(&nodes[1]));
+		assert!(manager.on_connection_established(&nodes[1]));
+
+		// unknown nodes are ignored
+		let unknown_node = Random.generate().unwrap().public().clone();
+		assert!(!manager.on_connection_established(&unknown_node));
+		assert!(!manager.on_connection_lost(&unknown_node));
+	}
+
+	#[test]
+	fn connection_events_are_delivered_in_order_of_transitions() {
+		let nodes = make_nodes(3);
+		let manager = make_manager(nodes[0].clone(), &nodes, false);
+		let mut dialer = MockDialer::default();
+		dialer.results.insert(nodes[1].clone(), vec![false, true].into_iter().collect());
+		dialer.results.insert(nodes[2].clone(), vec![true, true].into_iter().collect());
+		let start = Instant::now();
+
+		// first node is in backoff, second node is connected
+		dialer.dial(&manager, start);
+		assert_eq!(dialer.events, vec![(nodes[2].clone(), true)]);
+
+		// first node connects to us while we are in backoff => outbound connection is never attempted
+		dialer.on_connected(&manager, &nodes[1]);
+		dialer.dial(&manager, start + Duration::from_secs(10));
+		assert_eq!(dialer.events.len(), 2);
+
+		// second node is lost (closing of replaced connection is not reported twice) && then reconnected
+		dialer.on_disconnected(&manager, &nodes[2]);
+		dialer.on_disconnected(&manager, &nodes[2]);
+		dialer.dial(&manager, start + Duration::from_secs(10));
+		dialer.on_connected(&manager, &nodes[2]);
+
+		assert_eq!(dialer.events, vec![
+			(nodes[2].clone(), true),
+			(nodes[1].clone(), true),
+			(nodes[2].clone(), false),
+			(nodes[2].clone(), true),
+		]);
+		assert_eq!(dialer.results[&nodes[1]].len(), 1);
+	}
+
+	#[test]
+	fn added_node_is_connected_and_removed_node_is_forgotten() {
+		let nodes = make_nodes(3);
+		let manager = make_manager(nodes[0].clone(), &nodes[0..2], false);
+		assert_eq!(manager.state(&nodes[2]), None);
+
+		let address: SocketAddr = "127.0.0.1:7000".parse().unwrap();
+		manager.add_node(nodes[2].clone(), address.clone());
+		assert_eq!(manager.state(&nodes[2]), Some(PeerState::Disconnected));
+		assert_eq!(manager.nodes_to_connect(Instant::now()).get(&nodes[2]), Some(&address));
+
+		assert!(manager.remove_node(&nodes[2]));
+		assert!(!manager.remove_node(&nodes[2]));
+		assert_eq!(manager.state(&nodes[2]), None);
+	}
+
+	#[test]
+	fn address_change_does_not_break_established_connection() {
+		let nodes = make_nodes(2);
+		let manager = make_manager(nodes[0].clone(), &nodes, false);
+		assert!(manager.on_connection_established(&nodes[1]));
+
+		let address: SocketAddr = "127.0.0.1:7000".parse().unwrap();
+		manager.add_node(nodes[1].clone(), address.clone());
+		assert_eq!(manager.state(&nodes[1]), Some(PeerState::Connected));
+
+		// new address is used when reconnecting
+		assert!(manager.on_connection_lost(&nodes[1]));
+		assert_eq!(manager.nodes_to_connect(Instant::now()).get(&nodes[1]), Some(&address));
+	}
+
+	#[test]
+	fn simultaneous_connections_are_resolved_deterministically() {
+		let nodes = make_nodes(2);
+
+		// lower node keeps its outbound connection, higher node keeps its inbound connection
+		assert!(is_preferred_connection(&nodes[0], &nodes[1], false));
+		assert!(!is_preferred_connection(&nodes[0], &nodes[1], true));
+		assert!(is_preferred_connection(&nodes[1], &nodes[0], true));
+		assert!(!is_preferred_connection(&nodes[1], &nodes[0], false));
+	}
+}
@@ -0,0 +1,263 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+use std::net::SocketAddr;
+use std::collections::{BTreeMap, BTreeSet};
+use key_server_cluster::{NodeId, KeyServerSet, KeyServerSetMigration};
+
+#[derive(Debug, Default, PartialEq)]
+/// Changes of cluster connections, computed from the key servers set change.
+pub struct ConnectionsChange {
+	/// Nodes, which have been added to the key servers set => we should connect to them.
+	pub added: BTreeMap<NodeId, SocketAddr>,
+	/// Nodes, which have changed their network address => we should use new address when reconnecting.
+	pub updated: BTreeMap<NodeId, SocketAddr>,
+	/// Nodes, which have been removed from the key servers set && have no active sessions => we should disconnect.
+	pub removed: BTreeSet<NodeId>,
+	/// New migration, signalled by the key servers set.
+	pub migration: Option<KeyServerSetMigration>,
+}
+
+/// Watches the key servers set && decides which connections must be established or closed.
+pub struct ConnectionTrigger {
+	/// Self node id.
+	self_node_id: NodeId,
+	/// Key servers set.
+	key_server_set: Arc<KeyServerSet>,
+	/// Other nodes we are keeping connections to.
+	nodes: BTreeMap<NodeId, SocketAddr>,
+	/// Nodes, removed from the key servers set, which are still participating in active sessions.
+	pending_disconnects: BTreeSet<NodeId>,
+	/// Last migration, signalled by the key servers set.
+	migration: Option<KeyServerSetMigration>,
+}
+
+impl ConnectionTrigger {
+	pub fn new(self_node_id: NodeId, key_server_set: Arc<KeyServerSet>) -> Self {
+		let snapshot = key_server_set.snapshot();
+		let mut trigger = ConnectionTrigger {
+			self_node_id: self_node_id,
+			key_server_set: key_server_set,
+			nodes: BTreeMap::new(),
+			pending_disconnects: BTreeSet::new(),
+			migration: snapshot.migration.clone(),
+		};
+		trigger.nodes = trigger.required_nodes(snapshot.current_set, snapshot.new_set, snapshot.migration);
+		trigger
+	}
+
+	/// Other nodes we are keeping connections to.
+	pub fn nodes(&self) -> &BTreeMap<NodeId, SocketAddr> {
+		&self.nodes
+	}
+
+	/// Nodes, removed from the key servers set, which are still participating in active sessions.
+	pub fn pending_disconnects(&self) -> &BTreeSet<NodeId> {
+		&self.pending_disconnects
+	}
+
+	/// Read the key servers set && compute connections changes. Nodes for which `has_active_sessions` returns true
+	/// are not disconnected until their sessions are completed.
+	pub fn maintain<F>(&mut self, has_active_sessions: F) -> ConnectionsChange where F: Fn(&NodeId) -> bool {
+		let snapshot = self.key_server_set.snapshot();
+		let required_nodes = self.required_nodes(snapshot.current_set, snapshot.new_set, snapshot.migration.clone());
+
+		let mut change = ConnectionsChange::default();
+		for (node_id, node_address) in required_nodes.iter() {
+			match self.nodes.get(node_id) {
+				None => { change.added.insert(node_id.clone(), node_address.clone()); },
+				Some(old_address) if old_address != node_address => { change.updated.insert(node_id.clone(), node_address.clone()); },
+				Some(_) => (),
+			}
+
+			// node has been returned to the set before it was disconnected
+			self.pending_disconnects.remove(node_id);
+		}
+		for node_id in self.nodes.keys().filter(|node_id| !required_nodes.contains_key(node_id)) {
+			self.pending_disconnects.insert(node_id.clone());
+		}
+		for node_id in self.pending_disconnects.iter().filter(|node_id| !has_active_sessions(node_id)) {
+			change.removed.insert(node_id.clone());
+		}
+
+		for node_id in change.removed.iter() {
+			self.pending_disconnects.remove(node_id);
+		}
+
+		// keep addresses of nodes, which are waiting for disconnect
+		let pending_nodes: Vec<_> = self.pending_disconnects.iter()
+			.filter_map(|node_id| self.nodes.get(node_id).map(|address| (node_id.clone(), address.clone())))
+			.collect();
+		self.nodes = required_nodes.into_iter().chain(pending_nodes).collect();
+
+		if snapshot.migration.as_ref().map(|migration| &migration.id) != self.migration.as_ref().map(|migration| &migration.id) {
+			change.migration = snapshot.migration.clone();
+			self.migration = snapshot.migration;
+		}
+
+		change
+	}
+
+	/// We are keeping connections to current, new and migration servers (if any).
+	fn required_nodes(&self, current_set: BTreeMap<NodeId, SocketAddr>, new_set: BTreeMap<NodeId, SocketAddr>, migration: Option<KeyServerSetMigration>) -> BTreeMap<NodeId, SocketAddr> {
+		let migration_set = migration.map(|migration| migration.set).unwrap_or_default();
+		current_set.into_iter()
+			.chain(new_set)
+			.chain(migration_set)
+			.filter(|&(ref node_id, _)| node_id != &self.self_node_id)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::net::SocketAddr;
+	use ethkey::{Random, Generator};
+	use util::H256;
+	use key_server_cluster::{NodeId, KeyServerSetSnapshot, KeyServerSetMigration};
+	use key_server_set::tests::MapKeyServerSet;
+	use super::ConnectionTrigger;
+
+	fn make_nodes(num_nodes: usize) -> Vec<(NodeId, SocketAddr)> {
+		(0..num_nodes).map(|i| (Random.generate().unwrap().public().clone(), format!("127.0.0.1:{}", 6000 + i).parse().unwrap())).collect()
+	}
+
+	fn make_trigger(nodes: &[(NodeId, SocketAddr)]) -> (Arc<MapKeyServerSet>, ConnectionTrigger) {
+		let key_server_set = Arc::new(MapKeyServerSet::new(nodes.iter().cloned().collect()));
+		let trigger = ConnectionTrigger::new(nodes[0].0.clone(), key_server_set.clone());
+		(key_server_set, trigger)
+	}
+
+	fn set_current_nodes(key_server_set: &MapKeyServerSet, nodes: &[(NodeId, SocketAddr)]) {
+		key_server_set.set_snapshot(KeyServerSetSnapshot {
+			current_set: nodes.iter().cloned().collect(),
+			..Default::default()
+		});
+	}
+
+	#[test]
+	fn self_node_is_excluded() {
+		let nodes = make_nodes(3);
+		let (_, trigger) = make_trigger(&nodes);
+		assert_eq!(trigger.nodes(), &nodes[1..].iter().cloned().collect());
+	}
+
+	#[test]
+	fn nothing_changes_when_set_is_not_changed() {
+		let nodes = make_nodes(3);
+		let (_, mut trigger) = make_trigger(&nodes);
+		assert_eq!(trigger.maintain(|_| false), Default::default());
+	}
+
+	#[test]
+	fn added_node_is_connected() {
+		let nodes = make_nodes(3);
+		let (key_server_set, mut trigger) = make_trigger(&nodes[0..2]);
+		set_current_nodes(&key_server_set, &nodes);
+
+		let change = trigger.maintain(|_| false);
+		assert_eq!(change.added, vec![nodes[2].clone()].into_iter().collect());
+		assert!(change.updated.is_empty());
+		assert!(change.removed.is_empty());
+		assert_eq!(trigger.nodes(), &nodes[1..].iter().cloned().collect());
+	}
+
+	#[test]
+	fn removed_node_is_disconnected() {
+		let nodes = make_nodes(3);
+		let (key_server_set, mut trigger) = make_trigger(&nodes);
+		set_current_nodes(&key_server_set, &nodes[0..2]);
+
+		let change = trigger.maintain(|_| false);
+		assert!(change.added.is_empty());
+		assert_eq!(change.removed, vec![nodes[2].0.clone()].into_iter().collect());
+		assert_eq!(trigger.nodes(), &nodes[1..2].iter().cloned().collect());
+	}
+
+	#[test]
+	fn node_address_change_is_reported() {
+		let nodes = make_nodes(3);
+		let (key_server_set, mut trigger) = make_trigger(&nodes);
+		let mut new_nodes = nodes.clone();
+		new_nodes[1].1 = "127.0.0.1:7000".parse().unwrap();
+		set_current_nodes(&key_server_set, &new_nodes);
+
+		let change = trigger.maintain(|_| false);
+		assert!(change.added.is_empty());
+		assert_eq!(change.updated, vec![new_nodes[1].clone()].into_iter().collect());
+		assert!(change.removed.is_empty());
+		assert_eq!(trigger.nodes()[&nodes[1].0], new_nodes[1].1);
+	}
+
+	#[test]
+	fn removed_node_is_disconnected_when_its_sessions_are_completed() {
+		let nodes = make_nodes(3);
+		let (key_server_set, mut trigger) = make_trigger(&nodes);
+		set_current_nodes(&key_server_set, &nodes[0..2]);
+
+		// node still participates in active sessions => disconnect is deferred
+		let change = trigger.maintain(|_| true);
+		assert!(change.removed.is_empty());
+		assert_eq!(trigger.pending_disconnects(), &vec![nodes[2].0.clone()].into_iter().collect());
+		assert!(trigger.nodes().contains_key(&nodes[2].0));
+
+		// sessions are completed => disconnect
+		let change = trigger.maintain(|_| false);
+		assert_eq!(change.removed, vec![nodes[2].0.clone()].into_iter().collect());
+		assert!(trigger.pending_disconnects().is_empty());
+		assert!(!trigger.nodes().contains_key(&nodes[2].0));
+	}
+
+	#[test]
+	fn pending_disconnect_is_cancelled_when_node_returns_to_set() {
+		let nodes = make_nodes(3);
+		let (key_server_set, mut trigger) = make_trigger(&nodes);
+		set_current_nodes(&key_server_set, &nodes[0..2]);
+		trigger.maintain(|_| true);
+
+		set_current_nodes(&key_server_set, &nodes);
+		let change = trigger.maintain(|_| false);
+		assert_eq!(change, Default::default());
+		assert!(trigger.pending_disconnects().is_empty());
+	}
+
+	#[test]
+	fn migration_is_reported_once() {
+		let nodes = make_nodes(4);
+		let (key_server_set, mut trigger) = make_trigger(&nodes[0..3]);
+		let migration = KeyServerSetMigration {
+			id: H256::from(1),
+			set: nodes[1..].iter().cloned().collect(),
+		};
+		key_server_set.set_snapshot(KeyServerSetSnapshot {
+			current_set: nodes[0..3].iter().cloned().collect(),
+			new_set: nodes[1..].iter().cloned().collect(),
+			migration: Some(migration.clone()),
+		});
+
+		// connections to the migration servers are established && nobody is disconnected while migrating
+		let change = trigger.maintain(|_| false);
+		assert_eq!(change.added, vec![nodes[3].clone()].into_iter().collect());
+		assert!(change.removed.is_empty());
+		assert_eq!(change.migration, Some(migration));
+
+		let change = trigger.maintain(|_| false);
+		assert_eq!(change.migration, None);
+		assert_eq!(trigger.nodes(), &nodes[1..].iter().cloned().collect());
+	}
+}
@@ -20,8 +20,8 @@ use std::sync::Arc;
 use parking_lot::{Mutex, Condvar};
 use ethcrypto::ecies::encrypt;
 use ethcrypto::DEFAULT_MAC;
-use ethkey::{self, Secret, Public, Signature};
-use key_server_cluster::{Error, AclStorage, DocumentKeyShare, NodeId, SessionId, DocumentEncryptedKeyShadow};
+use ethkey::{Secret, Public};
+use key_server_cluster::{Error, AclStorage, Requester, DocumentKeyShare, DocumentKeyShareVersion, NodeId, SessionId, DocumentEncryptedKeyShadow};
 use key_server_cluster::cluster::Cluster;
 use key_server_cluster::math;
 use key_server_cluster::message::{Message, DecryptionMessage, InitializeDecryptionSession, ConfirmDecryptionInitialization,
@@ -77,7 +77,7 @@ pub struct SessionParams {
 	pub access_key: Secret,
 	/// Id of node, on which this session is running.
 	pub self_node_id: Public,
-	/// Encrypted data (result of running encryption_session::SessionImpl).
+	/// Encrypted data (result of running generation_session::SessionImpl).
 	pub encrypted_data: DocumentKeyShare,
 	/// ACL storage.
 	pub acl_storage: Arc<AclStorage>,
@@ -103,7 +103,7 @@ struct SessionData {
 	// === Values, filled when session initialization just starts ===
 	/// Reference to the node, which has started this session.
 	master: Option<NodeId>,
-	/// Public key of requestor.
+	/// Public key of requestor. Only required (and filled) when shadow decryption is requested.
 	requestor: Option<Public>,
 	/// Is shadow decryption requested?
 	is_shadow_decryption: Option<bool>,
@@ -182,6 +182,11 @@ impl SessionImpl {
 		self.data.lock().state.clone()
 	}
 
+	/// Get session result. Returns None if session is not completed yet.
+	pub fn result(&self) -> Option<Result<DocumentEncryptedKeyShadow, Error>> {
+		self.data.lock().decrypted_secret.clone()
+	}
+
 	#[cfg(test)]
 	/// Get this session access key.
 	pub fn access_key(&self) -> &Secret {
@@ -195,7 +200,7 @@ impl SessionImpl {
 	}
 
 	/// Initialize decryption session.
-	pub fn initialize(&self, requestor_signature: Signature, is_shadow_decryption: bool) -> Result<(), Error> {
+	pub fn initialize(&self, requester: Requester, is_shadow_decryption: bool) -> Result<(), Error> {
 		let mut data = self.data.lock();
 
 		// check state
@@ -203,29 +208,35 @@ impl SessionImpl {
 			return Err(Error::InvalidStateForRequest);
 		}
 
-		// recover requestor signature
-		let requestor_public = ethkey::recover(&requestor_signature, &self.id)?;
+		// other key servers do not trust unverified requester => this node must decrypt data on its own
+		if !requester.is_verifiable() && self.encrypted_data.threshold != 0 {
+			return Err(Error::InsufficientRequesterData("requester signature is required to decrypt with other key servers".into()));
+		}
+
+		// ACL is checked by requestor address, decrypt shadows are encrypted with requestor public
+		let requestor_address = requester.address(&self.id)?;
+		let requestor_public = if is_shadow_decryption { Some(requester.public(&self.id)?) } else { None };
 
 		// update state
 		data.master = Some(self.node().clone());
 		data.state = SessionState::WaitingForInitializationConfirm;
-		data.requestor = Some(requestor_public.clone());
+		data.requestor = requestor_public;
 		data.is_shadow_decryption = Some(is_shadow_decryption);
-		data.requested_nodes.extend(self.encrypted_data.id_numbers.keys().cloned());
+		data.requested_nodes.extend(key_version(&self.encrypted_data).id_numbers.keys().cloned());
 
 		// ..and finally check access on our's own
-		let is_requestor_allowed_to_read = self.acl_storage.check(&requestor_public, &self.id).unwrap_or(false);
+		let is_requestor_allowed_to_read = self.acl_storage.check(&requestor_address, &self.id).unwrap_or(false);
 		process_initialization_response(&self.encrypted_data, &mut *data, self.node(), is_requestor_allowed_to_read)?;
 
 		// check if we have enough nodes to decrypt data
 		match data.state {
 			// not enough nodes => pass initialization message to all other nodes
 			SessionState::WaitingForInitializationConfirm => {
-				for node in self.encrypted_data.id_numbers.keys().filter(|n| *n != self.node()) {
+				for node in key_version(&self.encrypted_data).id_numbers.keys().filter(|n| *n != self.node()) {
 					self.cluster.send(node, Message::Decryption(DecryptionMessage::InitializeDecryptionSession(InitializeDecryptionSession {
 							session: self.id.clone().into(),
 							sub_session: self.access_key.clone().into(),
-							requestor_signature: requestor_signature.clone().into(),
+							requester: requester.clone().into(),
 							is_shadow_decryption: is_shadow_decryption,
 						})))?;
 				}
@@ -259,14 +270,21 @@ impl SessionImpl {
 			return Err(Error::InvalidStateForRequest);
 		}
 
-		// recover requestor signature
-		let requestor_public = ethkey::recover(&message.requestor_signature, &self.id)?;
+		// public key or address of the requester could be forged by master => only signature is accepted
+		let requester: Requester = message.requester.clone().into();
+		if !requester.is_verifiable() {
+			return Err(Error::InsufficientRequesterData("requester signature is required".into()));
+		}
+
+		// ACL is checked by requestor address, decrypt shadows are encrypted with requestor public
+		let requestor_address = requester.address(&self.id)?;
+		let requestor_public = if message.is_shadow_decryption { Some(requester.public(&self.id)?) } else { None };
 
 		// check access
-		let is_requestor_allowed_to_read = self.acl_storage.check(&requestor_public, &self.id).unwrap_or(false);
+		let is_requestor_allowed_to_read = self.acl_storage.check(&requestor_address, &self.id).unwrap_or(false);
 		data.state = if is_requestor_allowed_to_read { SessionState::WaitingForPartialDecryptionRequest }
 			else { SessionState::Failed };
-		data.requestor = Some(requestor_public);
+		data.requestor = requestor_public;
 		data.is_shadow_decryption = Some(message.is_shadow_decryption);
 
 		// respond to master node
@@ -344,10 +362,9 @@ impl SessionImpl {
 
 		// calculate shadow point
 		let decryption_result = {
-			let requestor = data.requestor.as_ref().expect("requestor public is filled during initialization; WaitingForPartialDecryptionRequest follows initialization; qed");
 			let is_shadow_decryption = data.is_shadow_decryption.expect("is_shadow_decryption is filled during initialization; WaitingForPartialDecryptionRequest follows initialization; qed");
 			let nodes = message.nodes.iter().cloned().map(Into::into).collect();
-			do_partial_decryption(self.node(), &requestor, is_shadow_decryption, &nodes, &self.access_key, &self.encrypted_data)?
+			do_partial_decryption(self.node(), data.requestor.as_ref(), is_shadow_decryption, &nodes, &self.access_key, &self.encrypted_data)?
 		};
 		self.cluster.send(&sender, Message::Decryption(DecryptionMessage::PartialDecryption(PartialDecryption {
 			session: self.id.clone().into(),
@@ -451,7 +468,7 @@ impl SessionImpl {
 						data.rejected_nodes.insert(node.clone());
 					}
 					// check if we still have enough nodes for decryption
-					if self.encrypted_data.id_numbers.len() - data.rejected_nodes.len() >= self.encrypted_data.threshold + 1 {
+					if key_version(&self.encrypted_data).id_numbers.len() - data.rejected_nodes.len() >= self.encrypted_data.threshold + 1 {
 						return;
 					}
 				}
@@ -478,7 +495,7 @@ impl SessionImpl {
 						data.confirmed_nodes.remove(node);
 						data.rejected_nodes.insert(node.clone());
 						// check if we still have enough nodes for decryption
-						if self.encrypted_data.id_numbers.len() - data.rejected_nodes.len() >= self.encrypted_data.threshold + 1 {
+						if key_version(&self.encrypted_data).id_numbers.len() - data.rejected_nodes.len() >= self.encrypted_data.threshold + 1 {
 							// we are going to stop session anyway => ignore error
 							let _ = SessionImpl::start_waiting_for_partial_decryption(self.node().clone(), self.id.clone(), self.access_key.clone(), &self.cluster, &self.encrypted_data, &mut *data);
 							return;
@@ -524,7 +541,7 @@ impl SessionImpl {
 					}
 
 					// check if we still have enough nodes for decryption
-					if self.encrypted_data.id_numbers.len() - data.rejected_nodes.len() >= self.encrypted_data.threshold + 1 {
+					if key_version(&self.encrypted_data).id_numbers.len() - data.rejected_nodes.len() >= self.encrypted_data.threshold + 1 {
 						// we are going to stop session anyway => ignore error
 						let _ = SessionImpl::start_waiting_for_partial_decryption(self.node().clone(), self.id.clone(), self.access_key.clone(), &self.cluster, &self.encrypted_data, &mut *data);
 						return;
@@ -560,9 +577,8 @@ impl SessionImpl {
 
 		if data.confirmed_nodes.remove(&self_node_id) {
 			let decryption_result = {
-				let requestor = data.requestor.as_ref().expect("requestor public is filled during initialization; WaitingForPartialDecryption follows initialization; qed");
 				let is_shadow_decryption = data.is_shadow_decryption.expect("is_shadow_decryption is filled during initialization; WaitingForPartialDecryption follows initialization; qed");
-				do_partial_decryption(&self_node_id, &requestor, is_shadow_decryption, &data.confirmed_nodes, &access_key, &encrypted_data)?
+				do_partial_decryption(&self_node_id, data.requestor.as_ref(), is_shadow_decryption, &data.confirmed_nodes, &access_key, &encrypted_data)?
 			};
 			data.shadow_points.insert(self_node_id.clone(), decryption_result);
 		}
@@ -573,11 +589,11 @@ impl SessionImpl {
 	fn do_decryption(access_key: Secret, encrypted_data: &DocumentKeyShare, data: &mut SessionData) -> Result<(), Error> {
 		// decrypt the secret using shadow points
 		let joint_shadow_point = math::compute_joint_shadow_point(data.shadow_points.values().map(|s| &s.shadow_point))?;
-		let decrypted_secret = math::decrypt_with_joint_shadow(encrypted_data.threshold, &access_key, &encrypted_data.encrypted_point, &joint_shadow_point)?;
+		let decrypted_secret = math::decrypt_with_joint_shadow(encrypted_data.threshold, &access_key, encrypted_point(encrypted_data), &joint_shadow_point)?;
 		let is_shadow_decryption = data.is_shadow_decryption.expect("is_shadow_decryption is filled during initialization; decryption follows initialization; qed");
 		let (common_point, decrypt_shadows) = if is_shadow_decryption {
 			(
-				Some(math::make_common_shadow_point(encrypted_data.threshold, encrypted_data.common_point.clone())?),
+				Some(math::make_common_shadow_point(encrypted_data.threshold, common_point(encrypted_data).clone())?),
 				Some(data.shadow_points.values()
 					.map(|s| s.decrypt_shadow.as_ref().expect("decrypt_shadow is filled during partial decryption; decryption follows partial decryption; qed").clone())
 					.collect())
@@ -639,15 +655,33 @@ impl Ord for DecryptionSessionId {
 
 
 fn check_encrypted_data(self_node_id: &Public, encrypted_data: &DocumentKeyShare) -> Result<(), Error> {
-	use key_server_cluster::encryption_session::{check_cluster_nodes, check_threshold};
+	use key_server_cluster::generation_session::{check_cluster_nodes, check_threshold};
 
-	let nodes = encrypted_data.id_numbers.keys().cloned().collect();
+	let key_version = encrypted_data.last_version().map_err(|e| Error::KeyStorage(e.into()))?;
+	let nodes = key_version.id_numbers.keys().cloned().collect();
 	check_cluster_nodes(self_node_id, &nodes)?;
 	check_threshold(encrypted_data.threshold, &nodes)?;
 
+	// server key could be generated without document key => nothing to decrypt
+	if encrypted_data.common_point.is_none() || encrypted_data.encrypted_point.is_none() {
+		return Err(Error::DocumentKeyIsNotFound);
+	}
+
 	Ok(())
 }
 
+fn key_version(encrypted_data: &DocumentKeyShare) -> &DocumentKeyShareVersion {
+	encrypted_data.last_version().expect("key version is checked in check_encrypted_data; session is only created after check; qed")
+}
+
+fn common_point(encrypted_data: &DocumentKeyShare) -> &Public {
+	encrypted_data.common_point.as_ref().expect("common point is checked in check_encrypted_data; session is only created after check; qed")
+}
+
+fn encrypted_point(encrypted_data: &DocumentKeyShare) -> &Public {
+	encrypted_data.encrypted_point.as_ref().expect("encrypted point is checked in check_encrypted_data; session is only created after check; qed")
+}
+
 fn process_initialization_response(encrypted_data: &DocumentKeyShare, data: &mut SessionData, node: &NodeId, check_result: bool) -> Result<(), Error> {
 	if !data.requested_nodes.remove(node) {
 		return Err(Error::InvalidMessage);
@@ -666,7 +700,7 @@ fn process_initialization_response(encrypted_data: &DocumentKeyShare, data: &mut
 			data.rejected_nodes.insert(node.clone());
 
 			// check if we still can receive enough confirmations to do a decryption?
-			if encrypted_data.id_numbers.len() - data.rejected_nodes.len() < encrypted_data.threshold + 1 {
+			if key_version(encrypted_data).id_numbers.len() - data.rejected_nodes.len() < encrypted_data.threshold + 1 {
 				data.decrypted_secret = Some(Err(Error::AccessDenied));
 				data.state = SessionState::Failed;
 			}
@@ -676,20 +710,24 @@ fn process_initialization_response(encrypted_data: &DocumentKeyShare, data: &mut
 	Ok(())
 }
 
-fn do_partial_decryption(node: &NodeId, requestor_public: &Public, is_shadow_decryption: bool, participants: &BTreeSet<NodeId>, access_key: &Secret, encrypted_data: &DocumentKeyShare) -> Result<PartialDecryptionResult, Error> {
-	let node_id_number = &encrypted_data.id_numbers[node];
-	let node_secret_share = &encrypted_data.secret_share;
+fn do_partial_decryption(node: &NodeId, requestor_public: Option<&Public>, is_shadow_decryption: bool, participants: &BTreeSet<NodeId>, access_key: &Secret, encrypted_data: &DocumentKeyShare) -> Result<PartialDecryptionResult, Error> {
+	let key_version = key_version(encrypted_data);
+	let node_id_number = &key_version.id_numbers[node];
+	let node_secret_share = &key_version.secret_share;
 	let other_id_numbers = participants.iter()
 		.filter(|id| *id != node)
-		.map(|id| &encrypted_data.id_numbers[id]);
+		.map(|id| &key_version.id_numbers[id]);
 	let node_shadow = math::compute_node_shadow(node_id_number, node_secret_share, other_id_numbers)?;
 	let decrypt_shadow = if is_shadow_decryption { Some(math::generate_random_scalar()?) } else { None };
-	let (shadow_point, decrypt_shadow) = math::compute_node_shadow_point(access_key, &encrypted_data.common_point, &node_shadow, decrypt_shadow)?;
+	let (shadow_point, decrypt_shadow) = math::compute_node_shadow_point(access_key, common_point(encrypted_data), &node_shadow, decrypt_shadow)?;
 	Ok(PartialDecryptionResult {
 		shadow_point: shadow_point,
 		decrypt_shadow: match decrypt_shadow {
 			None => None,
-			Some(decrypt_shadow) => Some(encrypt(requestor_public, &DEFAULT_MAC, &**decrypt_shadow)?),
+			Some(decrypt_shadow) => {
+				let requestor_public = requestor_public.expect("requestor public is filled during initialization when shadow decryption is requested; qed");
+				Some(encrypt(requestor_public, &DEFAULT_MAC, &**decrypt_shadow)?)
+			},
 		},
 	})
 }
@@ -700,7 +738,7 @@ mod tests {
 	use std::collections::BTreeMap;
 	use super::super::super::acl_storage::tests::DummyAclStorage;
 	use ethkey::{self, Random, Generator, Public, Secret};
-	use key_server_cluster::{NodeId, DocumentKeyShare, SessionId, Error, DocumentEncryptedKeyShadow};
+	use key_server_cluster::{NodeId, DocumentKeyShare, DocumentKeyShareVersion, SessionId, Error, DocumentEncryptedKeyShadow, Requester};
 	use key_server_cluster::cluster::tests::DummyCluster;
 	use key_server_cluster::decryption_session::{SessionImpl, SessionParams, SessionState};
 	use key_server_cluster::message::{self, Message, DecryptionMessage};
@@ -734,11 +772,11 @@ mod tests {
 		let common_point: Public = "6962be696e1bcbba8e64cc7fddf140f854835354b5804f3bb95ae5a2799130371b589a131bd39699ac7174ccb35fc4342dab05331202209582fc8f3a40916ab0".into();
 		let encrypted_point: Public = "b07031982bde9890e12eff154765f03c56c3ab646ad47431db5dd2d742a9297679c4c65b998557f8008469afd0c43d40b6c5f6c6a1c7354875da4115237ed87a".into();
 		let encrypted_datas: Vec<_> = (0..5).map(|i| DocumentKeyShare {
+			author: Public::default(),
 			threshold: 3,
-			id_numbers: id_numbers.clone().into_iter().collect(),
-			secret_share: secret_shares[i].clone(),
-			common_point: common_point.clone(),
-			encrypted_point: encrypted_point.clone(),
+			common_point: Some(common_point.clone()),
+			encrypted_point: Some(encrypted_point.clone()),
+			versions: vec![DocumentKeyShareVersion::new(id_numbers.clone().into_iter().collect(), secret_shares[i].clone())],
 		}).collect();
 		let acl_storages: Vec<_> = (0..5).map(|_| Arc::new(DummyAclStorage::default())).collect();
 		let clusters: Vec<_> = (0..5).map(|i| {
@@ -760,6 +798,31 @@ mod tests {
 		(clusters, acl_storages, sessions)
 	}
 
+	fn prepare_single_node_decryption_session() -> (Arc<DummyAclStorage>, SessionImpl) {
+		// prepare data, encrypted with the key of single node (scheme 1-of-1)
+		let self_node_id = Random.generate().unwrap().public().clone();
+		let joint_key_pair = Random.generate().unwrap();
+		let encrypted_secret = math::encrypt_secret(&SECRET_PLAIN.into(), joint_key_pair.public()).unwrap();
+		let acl_storage = Arc::new(DummyAclStorage::default());
+		let session = SessionImpl::new(SessionParams {
+			id: SessionId::default(),
+			access_key: Random.generate().unwrap().secret().clone(),
+			self_node_id: self_node_id.clone(),
+			encrypted_data: DocumentKeyShare {
+				author: Public::default(),
+				threshold: 0,
+				common_point: Some(encrypted_secret.common_point),
+				encrypted_point: Some(encrypted_secret.encrypted_point),
+				versions: vec![DocumentKeyShareVersion::new(vec![(self_node_id.clone(), Random.generate().unwrap().secret().clone())].into_iter().collect(),
+					joint_key_pair.secret().clone())],
+			},
+			acl_storage: acl_storage.clone(),
+			cluster: Arc::new(DummyCluster::new(self_node_id)),
+		}).unwrap();
+
+		(acl_storage, session)
+	}
+
 	fn do_messages_exchange(clusters: &[Arc<DummyCluster>], sessions: &[SessionImpl]) {
 		do_messages_exchange_until(clusters, sessions, |_, _, _| false);
 	}
@@ -792,11 +855,11 @@ mod tests {
 			access_key: Random.generate().unwrap().secret().clone(),
 			self_node_id: self_node_id.clone(),
 			encrypted_data: DocumentKeyShare {
+				author: Public::default(),
 				threshold: 0,
-				id_numbers: nodes,
-				secret_share: Random.generate().unwrap().secret().clone(),
-				common_point: Random.generate().unwrap().public().clone(),
-				encrypted_point: Random.generate().unwrap().public().clone(),
+				common_point: Some(Random.generate().unwrap().public().clone()),
+				encrypted_point: Some(Random.generate().unwrap().public().clone()),
+				versions: vec![DocumentKeyShareVersion::new(nodes, Random.generate().unwrap().secret().clone())],
 			},
 			acl_storage: Arc::new(DummyAclStorage::default()),
 			cluster: Arc::new(DummyCluster::new(self_node_id.clone())),
@@ -817,11 +880,11 @@ mod tests {
 			access_key: Random.generate().unwrap().secret().clone(),
 			self_node_id: self_node_id.clone(),
 			encrypted_data: DocumentKeyShare {
+				author: Public::default(),
 				threshold: 0,
-				id_numbers: nodes,
-				secret_share: Random.generate().unwrap().secret().clone(),
-				common_point: Random.generate().unwrap().public().clone(),
-				encrypted_point: Random.generate().unwrap().public().clone(),
+				common_point: Some(Random.generate().unwrap().public().clone()),
+				encrypted_point: Some(Random.generate().unwrap().public().clone()),
+				versions: vec![DocumentKeyShareVersion::new(nodes, Random.generate().unwrap().secret().clone())],
 			},
 			acl_storage: Arc::new(DummyAclStorage::default()),
 			cluster: Arc::new(DummyCluster::new(self_node_id.clone())),
@@ -842,16 +905,40 @@ mod tests {
 			access_key: Random.generate().unwrap().secret().clone(),
 			self_node_id: self_node_id.clone(),
 			encrypted_data: DocumentKeyShare {
+				author: Public::default(),
 				threshold: 2,
-				id_numbers: nodes,
-				secret_share: Random.generate().unwrap().secret().clone(),
-				common_point: Random.generate().unwrap().public().clone(),
-				encrypted_point: Random.generate().unwrap().public().clone(),
+				common_point: Some(Random.generate().unwrap().public().clone()),
+				encrypted_point: Some(Random.generate().unwrap().public().clone()),
+				versions: vec![DocumentKeyShareVersion::new(nodes, Random.generate().unwrap().secret().clone())],
+			},
+			acl_storage: Arc::new(DummyAclStorage::default()),
+			cluster: Arc::new(DummyCluster::new(self_node_id.clone())),
+		}) {
+			Err(Error::InvalidThreshold { requested: 2, nodes: 2 }) => (),
+			_ => panic!("unexpected"),
+		}
+	}
+
+	#[test]
+	fn fails_to_construct_if_document_key_is_not_stored() {
+		let mut nodes = BTreeMap::new();
+		let self_node_id = Random.generate().unwrap().public().clone();
+		nodes.insert(self_node_id.clone(), Random.generate().unwrap().secret().clone());
+		match SessionImpl::new(SessionParams {
+			id: SessionId::default(),
+			access_key: Random.generate().unwrap().secret().clone(),
+			self_node_id: self_node_id.clone(),
+			encrypted_data: DocumentKeyShare {
+				author: Public::default(),
+				threshold: 0,
+				common_point: None,
+				encrypted_point: None,
+				versions: vec![DocumentKeyShareVersion::new(nodes, Random.generate().unwrap().secret().clone())],
 			},
 			acl_storage: Arc::new(DummyAclStorage::default()),
 			cluster: Arc::new(DummyCluster::new(self_node_id.clone())),
 		}) {
-			Err(Error::InvalidThreshold) => (),
+			Err(Error::DocumentKeyIsNotFound) => (),
 			_ => panic!("unexpected"),
 		}
 	}
@@ -859,18 +946,18 @@ mod tests {
 	#[test]
 	fn fails_to_initialize_when_already_initialized() {
 		let (_, _, sessions) = prepare_decryption_sessions();
-		assert_eq!(sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap(), ());
-		assert_eq!(sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap_err(), Error::InvalidStateForRequest);
+		assert_eq!(sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap(), ());
+		assert_eq!(sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap_err(), Error::InvalidStateForRequest);
 	}
 
 	#[test]
 	fn fails_to_accept_initialization_when_already_initialized() {
 		let (_, _, sessions) = prepare_decryption_sessions();
-		assert_eq!(sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap(), ());
+		assert_eq!(sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap(), ());
 		assert_eq!(sessions[0].on_initialize_session(sessions[1].node().clone(), &message::InitializeDecryptionSession {
 			session: SessionId::default().into(),
 			sub_session: sessions[0].access_key().clone().into(),
-			requestor_signature: ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap().into(),
+			requester: Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()).into(),
 			is_shadow_decryption: false,
 		}).unwrap_err(), Error::InvalidStateForRequest);
 	}
@@ -881,7 +968,7 @@ mod tests {
 		assert_eq!(sessions[1].on_initialize_session(sessions[0].node().clone(), &message::InitializeDecryptionSession {
 			session: SessionId::default().into(),
 			sub_session: sessions[0].access_key().clone().into(),
-			requestor_signature: ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap().into(),
+			requester: Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()).into(),
 			is_shadow_decryption: false,
 		}).unwrap(), ());
 		assert_eq!(sessions[1].on_partial_decryption_requested(sessions[2].node().clone(), &message::RequestPartialDecryption {
@@ -897,7 +984,7 @@ mod tests {
 		assert_eq!(sessions[1].on_initialize_session(sessions[0].node().clone(), &message::InitializeDecryptionSession {
 			session: SessionId::default().into(),
 			sub_session: sessions[0].access_key().clone().into(),
-			requestor_signature: ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap().into(),
+			requester: Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()).into(),
 			is_shadow_decryption: false,
 		}).unwrap(), ());
 		assert_eq!(sessions[1].on_partial_decryption_requested(sessions[0].node().clone(), &message::RequestPartialDecryption {
@@ -921,7 +1008,7 @@ mod tests {
 	#[test]
 	fn fails_to_accept_partial_decrypt_twice() {
 		let (clusters, _, sessions) = prepare_decryption_sessions();
-		sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap();
+		sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		let mut pd_from = None;
 		let mut pd_msg = None;
@@ -949,7 +1036,7 @@ mod tests {
 	#[test]
 	fn node_is_marked_rejected_when_timed_out_during_initialization_confirmation() {
 		let (_, _, sessions) = prepare_decryption_sessions();
-		sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap();
+		sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		// 1 node disconnects => we still can recover secret
 		sessions[0].on_node_timeout(sessions[1].node());
@@ -967,8 +1054,8 @@ mod tests {
 		let (clusters, acl_storages, sessions) = prepare_decryption_sessions();
 		let key_pair = Random.generate().unwrap();
 
-		acl_storages[1].prohibit(key_pair.public().clone(), SessionId::default());
-		sessions[0].initialize(ethkey::sign(key_pair.secret(), &SessionId::default()).unwrap(), false).unwrap();
+		acl_storages[1].prohibit(ethkey::public_to_address(key_pair.public()), SessionId::default());
+		sessions[0].initialize(Requester::Signature(ethkey::sign(key_pair.secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		do_messages_exchange_until(&clusters, &sessions, |_, _, _| sessions[0].state() == SessionState::WaitingForPartialDecryption);
 
@@ -980,7 +1067,7 @@ mod tests {
 	#[test]
 	fn session_does_not_fail_if_requested_node_disconnects() {
 		let (clusters, _, sessions) = prepare_decryption_sessions();
-		sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap();
+		sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		do_messages_exchange_until(&clusters, &sessions, |_, _, _| sessions[0].state() == SessionState::WaitingForPartialDecryption);
 
@@ -996,7 +1083,7 @@ mod tests {
 	#[test]
 	fn session_does_not_fail_if_node_with_shadow_point_disconnects() {
 		let (clusters, _, sessions) = prepare_decryption_sessions();
-		sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap();
+		sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		do_messages_exchange_until(&clusters, &sessions, |_, _, _| sessions[0].state() == SessionState::WaitingForPartialDecryption
 			&& sessions[0].data.lock().shadow_points.len() == 2);
@@ -1013,7 +1100,7 @@ mod tests {
 	#[test]
 	fn session_restarts_if_confirmed_node_disconnects() {
 		let (clusters, _, sessions) = prepare_decryption_sessions();
-		sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap();
+		sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		do_messages_exchange_until(&clusters, &sessions, |_, _, _| sessions[0].state() == SessionState::WaitingForPartialDecryption);
 
@@ -1028,7 +1115,7 @@ mod tests {
 	#[test]
 	fn session_does_not_fail_if_non_master_node_disconnects_from_non_master_node() {
 		let (clusters, _, sessions) = prepare_decryption_sessions();
-		sessions[0].initialize(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap(), false).unwrap();
+		sessions[0].initialize(Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap()), false).unwrap();
 
 		do_messages_exchange_until(&clusters, &sessions, |_, _, _| sessions[0].state() == SessionState::WaitingForPartialDecryption);
 
@@ -1045,7 +1132,7 @@ mod tests {
 		// now let's try to do a decryption
 		let key_pair = Random.generate().unwrap();
 		let signature = ethkey::sign(key_pair.secret(), &SessionId::default()).unwrap();
-		sessions[0].initialize(signature, false).unwrap();
+		sessions[0].initialize(Requester::Signature(signature), false).unwrap();
 
 		do_messages_exchange(&clusters, &sessions);
 
@@ -1069,7 +1156,7 @@ mod tests {
 		// now let's try to do a decryption
 		let key_pair = Random.generate().unwrap();
 		let signature = ethkey::sign(key_pair.secret(), &SessionId::default()).unwrap();
-		sessions[0].initialize(signature, true).unwrap();
+		sessions[0].initialize(Requester::Signature(signature), true).unwrap();
 
 		do_messages_exchange(&clusters, &sessions);
 
@@ -1102,12 +1189,12 @@ mod tests {
 		// now let's try to do a decryption
 		let key_pair = Random.generate().unwrap();
 		let signature = ethkey::sign(key_pair.secret(), &SessionId::default()).unwrap();
-		sessions[0].initialize(signature, false).unwrap();
+		sessions[0].initialize(Requester::Signature(signature), false).unwrap();
 
 		// we need 4 out of 5 nodes to agree to do a decryption
 		// let's say that 2 of these nodes are disagree
-		acl_storages[1].prohibit(key_pair.public().clone(), SessionId::default());
-		acl_storages[2].prohibit(key_pair.public().clone(), SessionId::default());
+		acl_storages[1].prohibit(ethkey::public_to_address(key_pair.public()), SessionId::default());
+		acl_storages[2].prohibit(ethkey::public_to_address(key_pair.public()), SessionId::default());
 
 		let node3 = sessions[3].node().clone();
 		do_messages_exchange_until(&clusters, &sessions, |from, _, _msg| from == &node3);
@@ -1128,11 +1215,11 @@ mod tests {
 		// we need 4 out of 5 nodes to agree to do a decryption
 		// let's say that 1 of these nodes (master) is disagree
 		let key_pair = Random.generate().unwrap();
-		acl_storages[0].prohibit(key_pair.public().clone(), SessionId::default());
+		acl_storages[0].prohibit(ethkey::public_to_address(key_pair.public()), SessionId::default());
 
 		// now let's try to do a decryption
 		let signature = ethkey::sign(key_pair.secret(), &SessionId::default()).unwrap();
-		sessions[0].initialize(signature, false).unwrap();
+		sessions[0].initialize(Requester::Signature(signature), false).unwrap();
 
 		do_messages_exchange(&clusters, &sessions);
 
@@ -1148,6 +1235,99 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn complete_dec_session_with_address_requester() {
+		let key_pair = Random.generate().unwrap();
+		let address = ethkey::public_to_address(key_pair.public());
+
+		// ACL is checked by requester address
+		let (acl_storage, session) = prepare_single_node_decryption_session();
+		acl_storage.prohibit(address.clone(), SessionId::default());
+		session.initialize(Requester::Address(address.clone()), false).unwrap();
+		assert_eq!(session.decrypted_secret(), Some(Err(Error::AccessDenied)));
+
+		// address is enough for regular decryption, performed by this node only
+		let (_, session) = prepare_single_node_decryption_session();
+		session.initialize(Requester::Address(address), false).unwrap();
+
+		assert_eq!(session.decrypted_secret().unwrap().unwrap(), DocumentEncryptedKeyShadow {
+			decrypted_secret: SECRET_PLAIN.into(),
+			common_point: None,
+			decrypt_shadows: None,
+		});
+	}
+
+	#[test]
+	fn complete_shadow_dec_session_with_public_requester() {
+		let (_, session) = prepare_single_node_decryption_session();
+
+		let key_pair = Random.generate().unwrap();
+		session.initialize(Requester::Public(key_pair.public().clone()), true).unwrap();
+
+		// decrypt shadows are encrypted with requester public
+		let decrypted_secret = session.decrypted_secret().unwrap().unwrap();
+		use ethcrypto::DEFAULT_MAC;
+		use ethcrypto::ecies::decrypt;
+		let decrypt_shadows: Vec<_> = decrypted_secret.decrypt_shadows.unwrap().into_iter()
+			.map(|c| Secret::from_slice(&decrypt(key_pair.secret(), &DEFAULT_MAC, &c).unwrap()))
+			.collect();
+		let decrypted_secret = math::decrypt_with_shadow_coefficients(decrypted_secret.decrypted_secret, decrypted_secret.common_point.unwrap(), decrypt_shadows).unwrap();
+		assert_eq!(decrypted_secret, SECRET_PLAIN.into());
+	}
+
+	#[test]
+	fn fails_to_initialize_with_unverified_requester_if_other_nodes_are_required() {
+		let (_, _, sessions) = prepare_decryption_sessions();
+		let key_pair = Random.generate().unwrap();
+		match sessions[0].initialize(Requester::Public(key_pair.public().clone()), false) {
+			Err(Error::InsufficientRequesterData(_)) => (),
+			_ => panic!("unexpected"),
+		}
+		match sessions[0].initialize(Requester::Address(ethkey::public_to_address(key_pair.public())), false) {
+			Err(Error::InsufficientRequesterData(_)) => (),
+			_ => panic!("unexpected"),
+		}
+		assert_eq!(sessions[0].state(), SessionState::WaitingForInitialization);
+	}
+
+	#[test]
+	fn slave_rejects_unverified_requester() {
+		let (clusters, _, sessions) = prepare_decryption_sessions();
+		let key_pair = Random.generate().unwrap();
+		for requester in vec![Requester::Public(key_pair.public().clone()), Requester::Address(ethkey::public_to_address(key_pair.public()))] {
+			match sessions[1].on_initialize_session(sessions[0].node().clone(), &message::InitializeDecryptionSession {
+				session: SessionId::default().into(),
+				sub_session: sessions[0].access_key().clone().into(),
+				requester: requester.into(),
+				is_shadow_decryption: false,
+			}) {
+				Err(Error::InsufficientRequesterData(_)) => (),
+				_ => panic!("unexpected"),
+			}
+		}
+		assert_eq!(sessions[1].state(), SessionState::WaitingForInitialization);
+		assert!(clusters[1].take_message().is_none());
+	}
+
+	#[test]
+	fn fails_to_initialize_shadow_decryption_with_address_requester() {
+		let (_, _, sessions) = prepare_decryption_sessions();
+		let address = ethkey::public_to_address(Random.generate().unwrap().public());
+		match sessions[0].initialize(Requester::Address(address.clone()), true) {
+			Err(Error::InsufficientRequesterData(_)) => (),
+			_ => panic!("unexpected"),
+		}
+		match sessions[1].on_initialize_session(sessions[0].node().clone(), &message::InitializeDecryptionSession {
+			session: SessionId::default().into(),
+			sub_session: sessions[0].access_key().clone().into(),
+			requester: Requester::Address(address).into(),
+			is_shadow_decryption: true,
+		}) {
+			Err(Error::InsufficientRequesterData(_)) => (),
+			_ => panic!("unexpected"),
+		}
+	}
+
 	#[test]
 	fn decryption_session_works_over_network() {
 		// TODO
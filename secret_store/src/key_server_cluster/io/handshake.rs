@@ -26,6 +26,8 @@ use key_server_cluster::io::{write_message, write_encrypted_message, WriteMessag
 	read_message, read_encrypted_message, compute_shared_key};
 
 /// Start handshake procedure with another node from the cluster.
+/// Both nodes derive ECDH shared key and sign each other's confirmation. Signatures and all messages which follow
+/// the handshake are sent over authenticated encrypted channel (see `encrypt_message`) using this key.
 pub fn handshake<A>(a: A, self_key_pair: KeyPair, trusted_nodes: BTreeSet<NodeId>) -> Handshake<A> where A: AsyncWrite + AsyncRead {
 	let self_confirmation_plain = Random.generate().map(|kp| *kp.secret().clone()).map_err(Into::into);
 	handshake_with_plain_confirmation(a, self_confirmation_plain, self_key_pair, trusted_nodes)
@@ -252,6 +254,7 @@ mod tests {
 	use futures::Future;
 	use ethkey::{Random, Generator, sign};
 	use util::H256;
+	use key_server_cluster::Error;
 	use key_server_cluster::io::message::compute_shared_key;
 	use key_server_cluster::io::message::tests::TestIo;
 	use key_server_cluster::message::{Message, ClusterMessage, NodePublicKey, NodePrivateKeySignature};
@@ -309,4 +312,40 @@ mod tests {
 			shared_key: shared_key,
 		}));
 	}
+
+	#[test]
+	fn active_handshake_fails_if_peer_is_not_expected() {
+		let (self_confirmation_plain, io) = prepare_test_io();
+		let self_key_pair = io.self_key_pair().clone();
+		let trusted_nodes: BTreeSet<_> = vec![Random.generate().unwrap().public().clone()].into_iter().collect();
+
+		let handshake = handshake_with_plain_confirmation(io, Ok(self_confirmation_plain), self_key_pair, trusted_nodes);
+		let handshake_result = handshake.wait().unwrap();
+		assert_eq!(handshake_result.1, Err(Error::InvalidNodeId));
+	}
+
+	#[test]
+	fn passive_handshake_fails_if_peer_is_not_trusted() {
+		let (self_confirmation_plain, io) = prepare_test_io();
+		let self_key_pair = io.self_key_pair().clone();
+		let trusted_nodes: BTreeSet<_> = vec![Random.generate().unwrap().public().clone()].into_iter().collect();
+
+		let mut handshake = accept_handshake(io, self_key_pair, trusted_nodes);
+		handshake.set_self_confirmation_plain(self_confirmation_plain);
+
+		let handshake_result = handshake.wait().unwrap();
+		assert_eq!(handshake_result.1, Err(Error::InvalidNodeId));
+	}
+
+	#[test]
+	fn active_handshake_fails_if_signature_ciphertext_is_tampered() {
+		let (self_confirmation_plain, mut io) = prepare_test_io();
+		io.corrupt_last_input_byte();
+		let self_key_pair = io.self_key_pair().clone();
+		let trusted_nodes: BTreeSet<_> = vec![io.peer_public().clone()].into_iter().collect();
+
+		let handshake = handshake_with_plain_confirmation(io, Ok(self_confirmation_plain), self_key_pair, trusted_nodes);
+		let handshake_result = handshake.wait().unwrap();
+		assert_eq!(handshake_result.1, Err(Error::InvalidMessage));
+	}
 }
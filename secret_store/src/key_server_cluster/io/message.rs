@@ -23,18 +23,23 @@ use ethcrypto::ecdh::agree;
 use ethcrypto::ecies::{encrypt_single_message, decrypt_single_message};
 use ethkey::{Public, Secret, KeyPair};
 use ethkey::math::curve_order;
-use util::{H256, U256};
+use util::{H256, U256, Hashable};
 use key_server_cluster::Error;
-use key_server_cluster::message::{Message, ClusterMessage, EncryptionMessage, DecryptionMessage};
+use key_server_cluster::message::{Message, ClusterMessage, GenerationMessage, EncryptionMessage, DecryptionMessage, ShareAddMessage,
+	ServersSetChangeMessage, KeyRemovalMessage, SigningMessage};
 
-/// Size of serialized header.
-pub const MESSAGE_HEADER_SIZE: usize = 4;
+/// Size of serialized header: 2-byte version, 1-byte kind && 2-byte payload size.
+pub const MESSAGE_HEADER_SIZE: usize = 5;
+/// Current header version. Version 2 has introduced MAC trailer of encrypted messages && 2-byte version field.
+pub const CURRENT_HEADER_VERSION: u16 = 2;
+/// Size of MAC trailer of encrypted message.
+pub const MESSAGE_MAC_SIZE: usize = 32;
 
 #[derive(Debug, PartialEq)]
 /// Message header.
 pub struct MessageHeader {
 	/// Message/Header version.
-	pub version: u8,
+	pub version: u16,
 	/// Message kind.
 	pub kind: u8,
 	/// Message payload size (without header).
@@ -67,13 +72,17 @@ pub fn serialize_message(message: Message) -> Result<SerializedMessage, Error> {
 		Message::Cluster(ClusterMessage::KeepAlive(payload))								=> (3, serde_json::to_vec(&payload)),
 		Message::Cluster(ClusterMessage::KeepAliveResponse(payload))						=> (4, serde_json::to_vec(&payload)),
 
-		Message::Encryption(EncryptionMessage::InitializeSession(payload))					=> (50, serde_json::to_vec(&payload)),
-		Message::Encryption(EncryptionMessage::ConfirmInitialization(payload))				=> (51, serde_json::to_vec(&payload)),
-		Message::Encryption(EncryptionMessage::CompleteInitialization(payload))				=> (52, serde_json::to_vec(&payload)),
-		Message::Encryption(EncryptionMessage::KeysDissemination(payload))					=> (53, serde_json::to_vec(&payload)),
-		Message::Encryption(EncryptionMessage::PublicKeyShare(payload))						=> (54, serde_json::to_vec(&payload)),
-		Message::Encryption(EncryptionMessage::SessionError(payload))						=> (55, serde_json::to_vec(&payload)),
-		Message::Encryption(EncryptionMessage::SessionCompleted(payload))					=> (56, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::InitializeSession(payload))					=> (50, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::ConfirmInitialization(payload))				=> (51, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::CompleteInitialization(payload))				=> (52, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::KeysDissemination(payload))					=> (53, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::PublicKeyShare(payload))						=> (54, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::SessionError(payload))						=> (55, serde_json::to_vec(&payload)),
+		Message::Generation(GenerationMessage::SessionCompleted(payload))					=> (56, serde_json::to_vec(&payload)),
+
+		Message::Encryption(EncryptionMessage::InitializeEncryptionSession(payload))		=> (80, serde_json::to_vec(&payload)),
+		Message::Encryption(EncryptionMessage::ConfirmEncryptionInitialization(payload))	=> (81, serde_json::to_vec(&payload)),
+		Message::Encryption(EncryptionMessage::EncryptionSessionError(payload))				=> (82, serde_json::to_vec(&payload)),
 
 		Message::Decryption(DecryptionMessage::InitializeDecryptionSession(payload))		=> (100, serde_json::to_vec(&payload)),
 		Message::Decryption(DecryptionMessage::ConfirmDecryptionInitialization(payload))	=> (101, serde_json::to_vec(&payload)),
@@ -81,12 +90,38 @@ pub fn serialize_message(message: Message) -> Result<SerializedMessage, Error> {
 		Message::Decryption(DecryptionMessage::PartialDecryption(payload))					=> (103, serde_json::to_vec(&payload)),
 		Message::Decryption(DecryptionMessage::DecryptionSessionError(payload))				=> (104, serde_json::to_vec(&payload)),
 		Message::Decryption(DecryptionMessage::DecryptionSessionCompleted(payload))			=> (105, serde_json::to_vec(&payload)),
+
+		Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(payload))				=> (120, serde_json::to_vec(&payload)),
+		Message::ShareAdd(ShareAddMessage::NewKeysDissemination(payload))					=> (121, serde_json::to_vec(&payload)),
+		Message::ShareAdd(ShareAddMessage::NewKeyShareStaged(payload))						=> (122, serde_json::to_vec(&payload)),
+		Message::ShareAdd(ShareAddMessage::CommitNewKeyShare(payload))						=> (123, serde_json::to_vec(&payload)),
+		Message::ShareAdd(ShareAddMessage::ShareAddSessionError(payload))					=> (124, serde_json::to_vec(&payload)),
+
+		Message::ServersSetChange(ServersSetChangeMessage::UnknownSessionsRequest(payload))		=> (130, serde_json::to_vec(&payload)),
+		Message::ServersSetChange(ServersSetChangeMessage::UnknownSessions(payload))			=> (131, serde_json::to_vec(&payload)),
+		Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeCompleted(payload))	=> (132, serde_json::to_vec(&payload)),
+		Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeError(payload))		=> (133, serde_json::to_vec(&payload)),
+
+		Message::KeyRemoval(KeyRemovalMessage::InitializeKeyRemovalSession(payload))			=> (140, serde_json::to_vec(&payload)),
+		Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalStaged(payload))				=> (141, serde_json::to_vec(&payload)),
+		Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(payload))				=> (142, serde_json::to_vec(&payload)),
+		Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(payload))				=> (143, serde_json::to_vec(&payload)),
+		Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalCommitted(payload))			=> (144, serde_json::to_vec(&payload)),
+
+		Message::Signing(SigningMessage::InitializeSigningSession(payload))				=> (150, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::ConfirmSigningInitialization(payload))			=> (151, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::RequestSigningNonce(payload))					=> (152, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::SigningNonce(payload))							=> (153, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::RequestPartialSignature(payload))				=> (154, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::PartialSignature(payload))						=> (155, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::SigningSessionError(payload))					=> (156, serde_json::to_vec(&payload)),
+		Message::Signing(SigningMessage::SigningSessionCompleted(payload))				=> (157, serde_json::to_vec(&payload)),
 	};
 
 	let payload = payload.map_err(|err| Error::Serde(err.to_string()))?;
 	build_serialized_message(MessageHeader {
 		kind: message_kind,
-		version: 1,
+		version: CURRENT_HEADER_VERSION,
 		size: 0,
 	}, payload)
 }
@@ -99,13 +134,17 @@ pub fn deserialize_message(header: &MessageHeader, payload: Vec<u8>) -> Result<M
 		3	=> Message::Cluster(ClusterMessage::KeepAlive(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
 		4	=> Message::Cluster(ClusterMessage::KeepAliveResponse(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
 
-		50	=> Message::Encryption(EncryptionMessage::InitializeSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
-		51	=> Message::Encryption(EncryptionMessage::ConfirmInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
-		52	=> Message::Encryption(EncryptionMessage::CompleteInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
-		53	=> Message::Encryption(EncryptionMessage::KeysDissemination(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
-		54	=> Message::Encryption(EncryptionMessage::PublicKeyShare(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
-		55	=> Message::Encryption(EncryptionMessage::SessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
-		56	=> Message::Encryption(EncryptionMessage::SessionCompleted(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		50	=> Message::Generation(GenerationMessage::InitializeSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		51	=> Message::Generation(GenerationMessage::ConfirmInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		52	=> Message::Generation(GenerationMessage::CompleteInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		53	=> Message::Generation(GenerationMessage::KeysDissemination(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		54	=> Message::Generation(GenerationMessage::PublicKeyShare(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		55	=> Message::Generation(GenerationMessage::SessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		56	=> Message::Generation(GenerationMessage::SessionCompleted(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+
+		80	=> Message::Encryption(EncryptionMessage::InitializeEncryptionSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		81	=> Message::Encryption(EncryptionMessage::ConfirmEncryptionInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		82	=> Message::Encryption(EncryptionMessage::EncryptionSessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
 
 		100	=> Message::Decryption(DecryptionMessage::InitializeDecryptionSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
 		101	=> Message::Decryption(DecryptionMessage::ConfirmDecryptionInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
@@ -114,22 +153,70 @@ pub fn deserialize_message(header: &MessageHeader, payload: Vec<u8>) -> Result<M
 		104	=> Message::Decryption(DecryptionMessage::DecryptionSessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
 		105	=> Message::Decryption(DecryptionMessage::DecryptionSessionCompleted(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
 
-		_ => return Err(Error::Serde(format!("unknown message type {}", header.kind))),
+		120	=> Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		121	=> Message::ShareAdd(ShareAddMessage::NewKeysDissemination(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		122	=> Message::ShareAdd(ShareAddMessage::NewKeyShareStaged(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		123	=> Message::ShareAdd(ShareAddMessage::CommitNewKeyShare(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		124	=> Message::ShareAdd(ShareAddMessage::ShareAddSessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+
+		130	=> Message::ServersSetChange(ServersSetChangeMessage::UnknownSessionsRequest(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		131	=> Message::ServersSetChange(ServersSetChangeMessage::UnknownSessions(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		132	=> Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeCompleted(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		133	=> Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+
+		140	=> Message::KeyRemoval(KeyRemovalMessage::InitializeKeyRemovalSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		141	=> Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalStaged(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		142	=> Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		143	=> Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		144	=> Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalCommitted(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+
+		150	=> Message::Signing(SigningMessage::InitializeSigningSession(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		151	=> Message::Signing(SigningMessage::ConfirmSigningInitialization(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		152	=> Message::Signing(SigningMessage::RequestSigningNonce(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		153	=> Message::Signing(SigningMessage::SigningNonce(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		154	=> Message::Signing(SigningMessage::RequestPartialSignature(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		155	=> Message::Signing(SigningMessage::PartialSignature(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		156	=> Message::Signing(SigningMessage::SigningSessionError(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+		157	=> Message::Signing(SigningMessage::SigningSessionCompleted(serde_json::from_slice(&payload).map_err(|err| Error::Serde(err.to_string()))?)),
+
+		_ => return Err(Error::InvalidMessage),
 	})
 }
 
 /// Encrypt serialized message.
+/// Payload is ECIES-encrypted (AES-128-CTR + HMAC-SHA256; ethcrypto provides no AES-GCM) and followed by keccak
+/// MAC trailer, which also authenticates the message header.
 pub fn encrypt_message(key: &KeyPair, message: SerializedMessage) -> Result<SerializedMessage, Error> {
 	let mut header: Vec<_> = message.into();
 	let payload = header.split_off(MESSAGE_HEADER_SIZE);
-	let encrypted_payload = encrypt_single_message(key.public(), &payload)?;
+	let mut encrypted_payload = encrypt_single_message(key.public(), &payload)?;
+
+	let mut header = deserialize_header(&header)?;
+	let encrypted_payload_len = encrypted_payload.len() + MESSAGE_MAC_SIZE;
+	if encrypted_payload_len > u16::MAX as usize {
+		return Err(Error::InvalidMessage);
+	}
+	header.size = encrypted_payload_len as u16;
 
-	let header = deserialize_header(&header)?;
+	let mac = compute_message_mac(key, &serialize_header(&header)?, &encrypted_payload);
+	encrypted_payload.extend_from_slice(&*mac);
 	build_serialized_message(header, encrypted_payload)
 }
 
-/// Decrypt serialized message.
-pub fn decrypt_message(key: &KeyPair, payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+/// Check MAC trailer && decrypt serialized message.
+pub fn decrypt_message(key: &KeyPair, header: &MessageHeader, mut payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+	if payload.len() < MESSAGE_MAC_SIZE {
+		return Err(Error::InvalidMessage);
+	}
+
+	let payload_len = payload.len() - MESSAGE_MAC_SIZE;
+	let mac = payload.split_off(payload_len);
+	let expected_mac = compute_message_mac(key, &serialize_header(header)?, &payload);
+	// compare all bytes to make comparison time independent of the MAC contents
+	if mac.iter().zip(expected_mac.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) != 0 {
+		return Err(Error::InvalidMessage);
+	}
+
 	Ok(decrypt_single_message(key.secret(), &payload)?)
 }
 
@@ -145,10 +232,19 @@ pub fn compute_shared_key(self_secret: &Secret, other_public: &Public) -> Result
 	Ok(shared_key_pair)
 }
 
+/// Compute MAC of encrypted message: keccak(shared_secret || header || encrypted_payload).
+fn compute_message_mac(key: &KeyPair, header: &[u8], encrypted_payload: &[u8]) -> H256 {
+	let mut data = Vec::with_capacity(32 + header.len() + encrypted_payload.len());
+	data.extend_from_slice(&***key.secret());
+	data.extend_from_slice(header);
+	data.extend_from_slice(encrypted_payload);
+	data.sha3()
+}
+
 /// Serialize message header.
 fn serialize_header(header: &MessageHeader) -> Result<Vec<u8>, Error> {
 	let mut buffer = Vec::with_capacity(MESSAGE_HEADER_SIZE);
-	buffer.write_u8(header.version)?;
+	buffer.write_u16::<LittleEndian>(header.version)?;
 	buffer.write_u8(header.kind)?;
 	buffer.write_u16::<LittleEndian>(header.size)?;
 	Ok(buffer)
@@ -156,12 +252,21 @@ fn serialize_header(header: &MessageHeader) -> Result<Vec<u8>, Error> {
 
 /// Deserialize message header.
 pub fn deserialize_header(data: &[u8]) -> Result<MessageHeader, Error> {
+	if data.len() != MESSAGE_HEADER_SIZE {
+		return Err(Error::InvalidMessage);
+	}
+
 	let mut reader = Cursor::new(data);
-	Ok(MessageHeader {
-		version: reader.read_u8()?,
+	let header = MessageHeader {
+		version: reader.read_u16::<LittleEndian>()?,
 		kind: reader.read_u8()?,
 		size: reader.read_u16::<LittleEndian>()?,
-	})
+	};
+	if header.version != CURRENT_HEADER_VERSION {
+		return Err(Error::InvalidMessageVersion);
+	}
+
+	Ok(header)
 }
 
 /// Build serialized message from header && payload
@@ -182,10 +287,13 @@ pub mod tests {
 	use std::io;
 	use futures::Poll;
 	use tokio_io::{AsyncRead, AsyncWrite};
-	use ethkey::{KeyPair, Public};
-	use key_server_cluster::message::Message;
-	use super::{MESSAGE_HEADER_SIZE, MessageHeader, compute_shared_key, encrypt_message, serialize_message,
-		serialize_header, deserialize_header};
+	use ethkey::{Random, Generator, KeyPair, Public, Signature};
+	use util::H256;
+	use key_server_cluster::{Error, Requester};
+	use key_server_cluster::message::{self, Message, ClusterMessage, GenerationMessage, EncryptionMessage, DecryptionMessage, ShareAddMessage,
+		ServersSetChangeMessage, KeyRemovalMessage, SigningMessage};
+	use super::{MESSAGE_HEADER_SIZE, MESSAGE_MAC_SIZE, CURRENT_HEADER_VERSION, MessageHeader, compute_shared_key, encrypt_message,
+		decrypt_message, serialize_message, deserialize_message, build_serialized_message, serialize_header, deserialize_header};
 
 	pub struct TestIo {
 		self_key_pair: KeyPair,
@@ -230,6 +338,12 @@ pub mod tests {
 				input_buffer.push(b);
 			}
 		}
+
+		pub fn corrupt_last_input_byte(&mut self) {
+			let input_buffer = self.input_buffer.get_mut();
+			let last = input_buffer.len() - 1;
+			input_buffer[last] ^= 1;
+		}
 	}
 
 	impl AsyncRead for TestIo {}
@@ -260,7 +374,7 @@ pub mod tests {
 	fn header_serialization_works() {
 		let header = MessageHeader {
 			kind: 1,
-			version: 2,
+			version: CURRENT_HEADER_VERSION,
 			size: 3,
 		};
 
@@ -270,4 +384,348 @@ pub mod tests {
 		let deserialized_header = deserialize_header(&serialized_header).unwrap();
 		assert_eq!(deserialized_header, header);
 	}
+
+	fn all_messages() -> Vec<Message> {
+		let session: message::MessageSessionId = H256::default().into();
+		let node: message::MessageNodeId = Random.generate().unwrap().public().clone().into();
+		let point: message::MessageNodeId = Random.generate().unwrap().public().clone().into();
+		let secret = Random.generate().unwrap().secret().clone();
+		vec![
+			Message::Cluster(ClusterMessage::NodePublicKey(message::NodePublicKey {
+				node_id: node.clone(),
+				confirmation_plain: H256::default().into(),
+			})),
+			Message::Cluster(ClusterMessage::NodePrivateKeySignature(message::NodePrivateKeySignature {
+				confirmation_signed: Signature::default().into(),
+			})),
+			Message::Cluster(ClusterMessage::KeepAlive(message::KeepAlive {})),
+			Message::Cluster(ClusterMessage::KeepAliveResponse(message::KeepAliveResponse {})),
+			Message::Generation(GenerationMessage::InitializeSession(message::InitializeSession {
+				session: session.clone(),
+				derived_point: point.clone(),
+			})),
+			Message::Generation(GenerationMessage::ConfirmInitialization(message::ConfirmInitialization {
+				session: session.clone(),
+				derived_point: point.clone(),
+			})),
+			Message::Generation(GenerationMessage::CompleteInitialization(message::CompleteInitialization {
+				session: session.clone(),
+				author: node.clone(),
+				nodes: vec![(node.clone(), secret.clone().into())].into_iter().collect(),
+				threshold: 1,
+				derived_point: point.clone(),
+			})),
+			Message::Generation(GenerationMessage::KeysDissemination(message::KeysDissemination {
+				session: session.clone(),
+				secret1: secret.clone().into(),
+				secret2: secret.clone().into(),
+				publics: vec![point.clone()],
+			})),
+			Message::Generation(GenerationMessage::PublicKeyShare(message::PublicKeyShare {
+				session: session.clone(),
+				public_share: point.clone(),
+			})),
+			Message::Generation(GenerationMessage::SessionError(message::SessionError {
+				session: session.clone(),
+				error: "error".into(),
+			})),
+			Message::Generation(GenerationMessage::SessionCompleted(message::SessionCompleted {
+				session: session.clone(),
+				common_point: point.clone(),
+				encrypted_point: point.clone(),
+			})),
+			Message::Encryption(EncryptionMessage::InitializeEncryptionSession(message::InitializeEncryptionSession {
+				session: session.clone(),
+				requestor_signature: Signature::default().into(),
+				common_point: point.clone(),
+				encrypted_point: point.clone(),
+				nonce: 1,
+			})),
+			Message::Encryption(EncryptionMessage::ConfirmEncryptionInitialization(message::ConfirmEncryptionInitialization {
+				session: session.clone(),
+			})),
+			Message::Encryption(EncryptionMessage::EncryptionSessionError(message::EncryptionSessionError {
+				session: session.clone(),
+				error: "error".into(),
+			})),
+			Message::Decryption(DecryptionMessage::InitializeDecryptionSession(message::InitializeDecryptionSession {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				requester: Requester::Signature(Signature::default()).into(),
+				is_shadow_decryption: true,
+			})),
+			Message::Decryption(DecryptionMessage::ConfirmDecryptionInitialization(message::ConfirmDecryptionInitialization {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				is_confirmed: true,
+			})),
+			Message::Decryption(DecryptionMessage::RequestPartialDecryption(message::RequestPartialDecryption {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				nodes: vec![node.clone()].into_iter().collect(),
+			})),
+			Message::Decryption(DecryptionMessage::PartialDecryption(message::PartialDecryption {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				shadow_point: point.clone(),
+				decrypt_shadow: Some(vec![1, 2, 3]),
+			})),
+			Message::Decryption(DecryptionMessage::DecryptionSessionError(message::DecryptionSessionError {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				error: "error".into(),
+			})),
+			Message::Decryption(DecryptionMessage::DecryptionSessionCompleted(message::DecryptionSessionCompleted {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+			})),
+			Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(message::InitializeShareAddSession {
+				session: session.clone(),
+				admin_signature: Signature::default().into(),
+				servers_set_change: Some(message::ShareAddServersSetChange {
+					session: session.clone(),
+					old_servers_set: vec![node.clone()].into_iter().collect(),
+					new_servers_set: vec![node.clone()].into_iter().collect(),
+				}),
+				old_nodes: vec![node.clone()].into_iter().collect(),
+				nodes: vec![(node.clone(), secret.clone().into())].into_iter().collect(),
+				version: session.clone(),
+				is_recovery: false,
+				author: node.clone(),
+				threshold: 1,
+				common_point: Some(point.clone()),
+				encrypted_point: None,
+				derived_point: point.clone(),
+			})),
+			Message::ShareAdd(ShareAddMessage::NewKeysDissemination(message::NewKeysDissemination {
+				session: session.clone(),
+				secret1: secret.clone().into(),
+				secret2: secret.clone().into(),
+				publics: vec![point.clone()],
+			})),
+			Message::ShareAdd(ShareAddMessage::NewKeyShareStaged(message::NewKeyShareStaged {
+				session: session.clone(),
+			})),
+			Message::ShareAdd(ShareAddMessage::CommitNewKeyShare(message::CommitNewKeyShare {
+				session: session.clone(),
+			})),
+			Message::ShareAdd(ShareAddMessage::ShareAddSessionError(message::ShareAddSessionError {
+				session: session.clone(),
+				error: "error".into(),
+			})),
+			Message::ServersSetChange(ServersSetChangeMessage::UnknownSessionsRequest(message::UnknownSessionsRequest {
+				session: session.clone(),
+				admin_signature: Signature::default().into(),
+				old_servers_set: vec![node.clone()].into_iter().collect(),
+				new_servers_set: vec![node.clone()].into_iter().collect(),
+			})),
+			Message::ServersSetChange(ServersSetChangeMessage::UnknownSessions(message::UnknownSessions {
+				session: session.clone(),
+				keys: vec![session.clone()],
+			})),
+			Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeCompleted(message::ServersSetChangeCompleted {
+				session: session.clone(),
+				admin_signature: Signature::default().into(),
+				old_servers_set: vec![node.clone()].into_iter().collect(),
+				new_servers_set: vec![node.clone()].into_iter().collect(),
+			})),
+			Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeError(message::ServersSetChangeError {
+				session: session.clone(),
+				error: "error".into(),
+			})),
+			Message::KeyRemoval(KeyRemovalMessage::InitializeKeyRemovalSession(message::InitializeKeyRemovalSession {
+				session: session.clone(),
+				requestor_signature: Signature::default().into(),
+				nonce: 1,
+			})),
+			Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalStaged(message::KeyShareRemovalStaged {
+				session: session.clone(),
+			})),
+			Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(message::CommitKeyShareRemoval {
+				session: session.clone(),
+			})),
+			Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalCommitted(message::KeyShareRemovalCommitted {
+				session: session.clone(),
+			})),
+			Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(message::KeyRemovalSessionError {
+				session: session.clone(),
+				error: "error".into(),
+			})),
+			Message::Signing(SigningMessage::InitializeSigningSession(message::InitializeSigningSession {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				requester: Requester::Signature(Signature::default()).into(),
+				message_hash: H256::default().into(),
+			})),
+			Message::Signing(SigningMessage::ConfirmSigningInitialization(message::ConfirmSigningInitialization {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				is_confirmed: true,
+			})),
+			Message::Signing(SigningMessage::RequestSigningNonce(message::RequestSigningNonce {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				nodes: vec![node.clone()].into_iter().collect(),
+			})),
+			Message::Signing(SigningMessage::SigningNonce(message::SigningNonce {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				nonce_public: point.clone(),
+			})),
+			Message::Signing(SigningMessage::RequestPartialSignature(message::RequestPartialSignature {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				nonces: vec![(node.clone(), point.clone())].into_iter().collect(),
+			})),
+			Message::Signing(SigningMessage::PartialSignature(message::PartialSignature {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				partial_signature: secret.clone().into(),
+			})),
+			Message::Signing(SigningMessage::SigningSessionError(message::SigningSessionError {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+				error: "error".into(),
+			})),
+			Message::Signing(SigningMessage::SigningSessionCompleted(message::SigningSessionCompleted {
+				session: session.clone(),
+				sub_session: secret.clone().into(),
+			})),
+		]
+	}
+
+	fn split_message(message: Message) -> (MessageHeader, Vec<u8>) {
+		let mut header: Vec<_> = serialize_message(message).unwrap().into();
+		let payload = header.split_off(MESSAGE_HEADER_SIZE);
+		(deserialize_header(&header).unwrap(), payload)
+	}
+
+	#[test]
+	fn every_message_kind_roundtrips() {
+		let mut kinds = Vec::new();
+		for message in all_messages() {
+			let serialized_message = serialize_message(message).unwrap();
+			let (header, payload) = split_message(deserialize_message(&deserialize_header(&serialized_message[..MESSAGE_HEADER_SIZE]).unwrap(),
+				serialized_message[MESSAGE_HEADER_SIZE..].to_vec()).unwrap());
+			assert_eq!(header.version, CURRENT_HEADER_VERSION);
+			assert_eq!(header.size as usize, payload.len());
+			assert_eq!(&serialized_message[MESSAGE_HEADER_SIZE..], &payload[..]);
+			assert!(!kinds.contains(&header.kind));
+			kinds.push(header.kind);
+		}
+	}
+
+	#[test]
+	fn encrypted_message_roundtrips() {
+		let key_pair = Random.generate().unwrap();
+		let message = all_messages().remove(0);
+		let plain_payload = split_message(message.clone()).1;
+
+		let encrypted_message = encrypt_message(&key_pair, serialize_message(message).unwrap()).unwrap();
+		let header = deserialize_header(&encrypted_message[..MESSAGE_HEADER_SIZE]).unwrap();
+		assert_eq!(header.size as usize, encrypted_message.len() - MESSAGE_HEADER_SIZE);
+
+		let payload = decrypt_message(&key_pair, &header, encrypted_message[MESSAGE_HEADER_SIZE..].to_vec()).unwrap();
+		assert_eq!(payload, plain_payload);
+		assert!(deserialize_message(&header, payload).is_ok());
+	}
+
+	#[test]
+	fn truncated_header_is_rejected() {
+		let serialized_header = serialize_header(&MessageHeader {
+			kind: 1,
+			version: CURRENT_HEADER_VERSION,
+			size: 3,
+		}).unwrap();
+
+		for len in 0..MESSAGE_HEADER_SIZE {
+			assert_eq!(deserialize_header(&serialized_header[..len]), Err(Error::InvalidMessage));
+		}
+	}
+
+	#[test]
+	fn unsupported_header_version_is_rejected() {
+		let serialized_header = serialize_header(&MessageHeader {
+			kind: 1,
+			version: CURRENT_HEADER_VERSION + 1,
+			size: 3,
+		}).unwrap();
+
+		assert_eq!(deserialize_header(&serialized_header), Err(Error::InvalidMessageVersion));
+	}
+
+	#[test]
+	fn previous_header_version_is_rejected() {
+		let serialized_header = serialize_header(&MessageHeader {
+			kind: 1,
+			version: 1,
+			size: 3,
+		}).unwrap();
+
+		assert_eq!(deserialize_header(&serialized_header), Err(Error::InvalidMessageVersion));
+	}
+
+	#[test]
+	fn unknown_message_kind_is_rejected() {
+		let (mut header, payload) = split_message(all_messages().remove(0));
+		header.kind = 0;
+		assert_eq!(deserialize_message(&header, payload.clone()).unwrap_err(), Error::InvalidMessage);
+		header.kind = 255;
+		assert_eq!(deserialize_message(&header, payload).unwrap_err(), Error::InvalidMessage);
+	}
+
+	#[test]
+	fn truncated_or_garbage_payload_is_rejected() {
+		for message in all_messages() {
+			let (header, payload) = split_message(message);
+			assert!(deserialize_message(&header, payload[..payload.len() / 2].to_vec()).is_err());
+			assert!(deserialize_message(&header, vec![0xff; payload.len()]).is_err());
+		}
+	}
+
+	#[test]
+	fn oversized_payload_is_rejected() {
+		let header = MessageHeader {
+			kind: 1,
+			version: CURRENT_HEADER_VERSION,
+			size: 0,
+		};
+
+		assert!(build_serialized_message(header, vec![0; ::std::u16::MAX as usize]).is_ok());
+		let header = MessageHeader {
+			kind: 1,
+			version: CURRENT_HEADER_VERSION,
+			size: 0,
+		};
+		assert_eq!(build_serialized_message(header, vec![0; ::std::u16::MAX as usize + 1]), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	fn tampered_encrypted_payload_is_rejected() {
+		let key_pair = Random.generate().unwrap();
+		let encrypted_message = encrypt_message(&key_pair, serialize_message(all_messages().remove(0)).unwrap()).unwrap();
+		let header = deserialize_header(&encrypted_message[..MESSAGE_HEADER_SIZE]).unwrap();
+		let payload = encrypted_message[MESSAGE_HEADER_SIZE..].to_vec();
+		for i in 0..payload.len() {
+			let mut tampered_payload = payload.clone();
+			tampered_payload[i] ^= 1;
+			assert_eq!(decrypt_message(&key_pair, &header, tampered_payload), Err(Error::InvalidMessage));
+		}
+	}
+
+	#[test]
+	fn bad_mac_is_rejected() {
+		let key_pair = Random.generate().unwrap();
+		let encrypted_message = encrypt_message(&key_pair, serialize_message(all_messages().remove(0)).unwrap()).unwrap();
+		let mut header = deserialize_header(&encrypted_message[..MESSAGE_HEADER_SIZE]).unwrap();
+		let payload = encrypted_message[MESSAGE_HEADER_SIZE..].to_vec();
+
+		// truncated MAC
+		assert_eq!(decrypt_message(&key_pair, &header, payload[..MESSAGE_MAC_SIZE - 1].to_vec()), Err(Error::InvalidMessage));
+		// MAC computed with other key
+		assert_eq!(decrypt_message(&Random.generate().unwrap(), &header, payload.clone()), Err(Error::InvalidMessage));
+		// MAC computed over other header
+		header.kind = 2;
+		assert_eq!(decrypt_message(&key_pair, &header, payload), Err(Error::InvalidMessage));
+	}
 }
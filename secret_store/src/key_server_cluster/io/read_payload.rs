@@ -55,7 +55,7 @@ impl<A> Future for ReadPayload<A> where A: AsyncRead {
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
 		let (read, data) = try_ready!(self.reader.poll());
 		let payload = if let Some(key) = self.key.take() {
-			decrypt_message(&key, data)
+			decrypt_message(&key, &self.header, data)
 				.and_then(|data| deserialize_message(&self.header, data))
 		} else {
 			deserialize_message(&self.header, data)
@@ -0,0 +1,988 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::mem;
+use std::collections::BTreeSet;
+use std::fmt::{Debug, Formatter, Error as FmtError};
+use std::time;
+use std::sync::Arc;
+use parking_lot::{Condvar, Mutex};
+use ethkey::{self, Public, Signature};
+use util::{H256, Hashable};
+use types::all::Error as KeyStorageError;
+use key_server_cluster::{Error, NodeId, SessionId, KeyStorage, TransactionalKeyStorage, DocumentKeyShare};
+use key_server_cluster::cluster::Cluster;
+use key_server_cluster::message::{Message, KeyRemovalMessage, InitializeKeyRemovalSession, KeyShareRemovalStaged,
+	CommitKeyShareRemoval, KeyShareRemovalCommitted, KeyRemovalSessionError};
+
+/// Key removal session API.
+pub trait Session: Send + Sync + 'static {
+	/// Get key removal session state.
+	fn state(&self) -> SessionState;
+	/// Wait until session is completed. Returns key holders, which have not confirmed removal during the session.
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<BTreeSet<NodeId>, Error>;
+}
+
+/// Key removal session.
+/// Removes share of the key from every node holding it. Removal could be requested either by the author of the key,
+/// or by the administrator. Requestor signs the removal request (see `removal_request_hash`) && every nonce is
+/// accepted only once, so that neither other requests signatures, nor previous removal requests could be replayed.
+/// Brief overview:
+/// 1) initialization: master node checks requestor signature, stages removal of its own key share && asks every other
+///    reachable key holder to do the same
+/// 2) every other key holder performs the same check, stages removal of its key share && confirms it
+/// 3) when every reachable key holder has confirmed, master commits removal && asks every other key holder to commit
+/// 4) every other key holder commits removal && confirms it
+/// If any key holder fails before master commit, staged removal is discarded on every node. Key holders, which have
+/// been unreachable during the session, or have not confirmed commit (i.e. they have failed or timeouted after master
+/// commit), are reported in the session result, so that removal could be retried later.
+pub struct SessionImpl {
+	/// Unique session id.
+	id: SessionId,
+	/// Public identifier of this node.
+	self_node_id: NodeId,
+	/// Administrator public key.
+	admin_public: Option<Public>,
+	/// Key storage, where removal is staged until commit.
+	key_storage: TransactionalKeyStorage,
+	/// Cluster which allows this node to send messages to other nodes in the cluster.
+	cluster: Arc<Cluster>,
+	/// SessionImpl completion condvar.
+	completed: Condvar,
+	/// Mutable session data.
+	data: Mutex<SessionData>,
+}
+
+/// SessionImpl creation parameters
+pub struct SessionParams {
+	/// SessionImpl identifier.
+	pub id: SessionId,
+	/// Id of node, on which this session is running.
+	pub self_node_id: Public,
+	/// Administrator public key.
+	pub admin_public: Option<Public>,
+	/// Key storage.
+	pub key_storage: Arc<KeyStorage>,
+	/// Cluster
+	pub cluster: Arc<Cluster>,
+}
+
+#[derive(Debug)]
+/// Mutable data of key removal session.
+struct SessionData {
+	/// Current state of the session.
+	state: SessionState,
+	/// Reference to the node, which has started this session.
+	master: Option<NodeId>,
+	/// Signature of the key author or of the administrator.
+	requestor_signature: Option<Signature>,
+	/// Removal request nonce.
+	nonce: Option<u64>,
+	/// Other key holders, which are participating in the session (on master node).
+	nodes: BTreeSet<NodeId>,
+	/// Key holders, which have not yet staged removal (on master node).
+	awaiting_staging: BTreeSet<NodeId>,
+	/// Key holders, which have not yet confirmed commit (on master node).
+	awaiting_commit: BTreeSet<NodeId>,
+	/// Key holders, which are unreachable or have not confirmed commit (on master node).
+	pending_nodes: BTreeSet<NodeId>,
+	/// Session result.
+	result: Option<Result<BTreeSet<NodeId>, Error>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Key removal session state.
+pub enum SessionState {
+	/// Every node starts in this state.
+	WaitingForInitialization,
+	/// Master node has staged removal && is waiting for every other reachable key holder to stage it.
+	WaitingForStaging,
+	/// Key holder has staged removal && is waiting for master to commit it.
+	WaitingForCommit,
+	/// Master node has committed removal && is waiting for every other key holder to confirm commit.
+	WaitingForCommitConfirmation,
+	/// Key share is removed.
+	Finished,
+	/// Session has failed. Staged removal is discarded.
+	Failed,
+}
+
+impl SessionImpl {
+	/// Create new key removal session.
+	pub fn new(params: SessionParams) -> Self {
+		SessionImpl {
+			id: params.id,
+			self_node_id: params.self_node_id,
+			admin_public: params.admin_public,
+			key_storage: TransactionalKeyStorage::new(params.key_storage),
+			cluster: params.cluster,
+			completed: Condvar::new(),
+			data: Mutex::new(SessionData {
+				state: SessionState::WaitingForInitialization,
+				master: None,
+				requestor_signature: None,
+				nonce: None,
+				nodes: BTreeSet::new(),
+				awaiting_staging: BTreeSet::new(),
+				awaiting_commit: BTreeSet::new(),
+				pending_nodes: BTreeSet::new(),
+				result: None,
+			}),
+		}
+	}
+
+	/// Get this node Id.
+	pub fn node(&self) -> &NodeId {
+		&self.self_node_id
+	}
+
+	/// Get session result. Returns None if session is not completed yet.
+	pub fn result(&self) -> Option<Result<BTreeSet<NodeId>, Error>> {
+		self.data.lock().result.clone()
+	}
+
+	/// Get removal request (requestor signature && nonce). Returns None if session is not initialized yet.
+	pub fn removal_request(&self) -> Option<(Signature, u64)> {
+		let data = self.data.lock();
+		match (data.requestor_signature.as_ref(), data.nonce) {
+			(Some(requestor_signature), Some(nonce)) => Some((requestor_signature.clone(), nonce)),
+			_ => None,
+		}
+	}
+
+	/// Start new session initialization. This must be called on master node, which is one of key holders.
+	pub fn initialize(&self, requestor_signature: Signature, nonce: u64, connected_nodes: BTreeSet<NodeId>) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// check that requestor is allowed to remove the key
+		let key_share = self.read_key_share()?;
+		self.check_requestor(&key_share, &requestor_signature, nonce)?;
+
+		// stage removal of own key share && ask every other key holder to do the same
+		self.key_storage.remove(&self.id).map_err(|e| Error::KeyStorage(e.into()))?;
+		let result = self.start(&mut *data, requestor_signature, nonce, key_holders(&key_share), &connected_nodes);
+		self.process_result(&mut *data, result)
+	}
+
+	/// Start session initialization on given key holders only. This is called on master node to retry removal on
+	/// key holders, which have been unreachable during the previous session. Master could have already removed its
+	/// own key share, so requestor is checked by key holders only.
+	pub fn initialize_for_nodes(&self, requestor_signature: Signature, nonce: u64, key_holders: BTreeSet<NodeId>, connected_nodes: BTreeSet<NodeId>) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		let result = self.start(&mut *data, requestor_signature, nonce, key_holders, &connected_nodes);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When session initialization message is received.
+	pub fn on_initialize_session(&self, sender: NodeId, message: &InitializeKeyRemovalSession) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_initialize_session(&mut *data, sender, message);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When key holder has staged removal of its key share.
+	pub fn on_key_share_removal_staged(&self, sender: NodeId, message: &KeyShareRemovalStaged) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_key_share_removal_staged(&mut *data, sender);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When master asks to commit staged removal.
+	pub fn on_commit_key_share_removal(&self, sender: NodeId, message: &CommitKeyShareRemoval) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_commit_key_share_removal(&mut *data, sender);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When key holder has committed removal of its key share.
+	pub fn on_key_share_removal_committed(&self, sender: NodeId, message: &KeyShareRemovalCommitted) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_key_share_removal_committed(&mut *data, sender);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When error has occured on another node.
+	pub fn on_session_error(&self, sender: NodeId, message: &KeyRemovalSessionError) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		// committed removal could not be rolled back && failed session is already reported
+		if data.state == SessionState::Finished || data.state == SessionState::Failed {
+			return Ok(());
+		}
+
+		// master has already committed removal => key holder, which has failed to commit, must be retried later
+		if data.state == SessionState::WaitingForCommitConfirmation {
+			warn!("{}: key holder {} has failed to commit key removal with error: {}", self.node(), sender, message.error);
+
+			self.on_commit_not_confirmed(&mut *data, &sender);
+			return Ok(());
+		}
+
+		warn!("{}: key removal session failed with error: {} from {}", self.node(), message.error, sender);
+
+		self.fail(&mut *data, Error::Io(message.error.clone()), Some(&sender));
+
+		Ok(())
+	}
+
+	/// When connection to one of cluster nodes has timeouted.
+	pub fn on_node_timeout(&self, node: &NodeId) {
+		let mut data = self.data.lock();
+
+		if data.state == SessionState::Finished || data.state == SessionState::Failed {
+			return;
+		}
+
+		// master treats disconnected key holder as unreachable: removal is retried when it is connected again
+		if data.master.as_ref() == Some(self.node()) {
+			if data.state == SessionState::WaitingForCommitConfirmation {
+				warn!("{}: key holder {} has been disconnected before confirming key removal", self.node(), node);
+
+				self.on_commit_not_confirmed(&mut *data, node);
+				return;
+			}
+
+			if !data.nodes.remove(node) {
+				return;
+			}
+
+			warn!("{}: key holder {} has been disconnected during key removal session", self.node(), node);
+
+			data.awaiting_staging.remove(node);
+			data.pending_nodes.insert(node.clone());
+			let result = self.try_commit_removal(&mut *data);
+			let _ = self.process_result(&mut *data, result);
+			return;
+		}
+
+		// key holder could not commit removal without master
+		if data.master.as_ref() != Some(node) {
+			return;
+		}
+
+		warn!("{}: key removal session failed because {} connection has timeouted", self.node(), node);
+
+		self.fail(&mut *data, Error::NodeDisconnected, Some(node));
+	}
+
+	/// When session timeout has occured.
+	pub fn on_session_timeout(&self) {
+		let mut data = self.data.lock();
+
+		if data.state == SessionState::Finished || data.state == SessionState::Failed {
+			return;
+		}
+
+		// master has already committed removal => every key holder, which has not confirmed it, must be retried later
+		if data.state == SessionState::WaitingForCommitConfirmation {
+			warn!("{}: key removal is not confirmed by {:?} before timeout", self.node(), data.awaiting_commit);
+
+			let awaiting_commit = mem::replace(&mut data.awaiting_commit, BTreeSet::new());
+			data.pending_nodes.extend(awaiting_commit);
+			self.try_complete(&mut *data);
+			return;
+		}
+
+		warn!("{}: key removal session failed with timeout", self.node());
+
+		self.fail(&mut *data, Error::NodeDisconnected, None);
+	}
+
+	/// Ask every reachable key holder to stage removal of its key share.
+	fn start(&self, data: &mut SessionData, requestor_signature: Signature, nonce: u64, key_holders: BTreeSet<NodeId>, connected_nodes: &BTreeSet<NodeId>) -> Result<(), Error> {
+		data.master = Some(self.node().clone());
+		data.requestor_signature = Some(requestor_signature.clone());
+		data.nonce = Some(nonce);
+		data.nodes = key_holders.iter().filter(|n| *n != self.node() && connected_nodes.contains(*n)).cloned().collect();
+		data.pending_nodes = key_holders.iter().filter(|n| *n != self.node() && !connected_nodes.contains(*n)).cloned().collect();
+		data.awaiting_staging = data.nodes.clone();
+		data.state = SessionState::WaitingForStaging;
+
+		for node in &data.nodes {
+			self.cluster.send(node, Message::KeyRemoval(KeyRemovalMessage::InitializeKeyRemovalSession(InitializeKeyRemovalSession {
+				session: self.id.clone().into(),
+				requestor_signature: requestor_signature.clone().into(),
+				nonce: nonce,
+			})))?;
+		}
+
+		// if there are no other reachable key holders => commit removal right now
+		self.try_commit_removal(data)
+	}
+
+	/// Process initialization request on key holder.
+	fn process_initialize_session(&self, data: &mut SessionData, sender: NodeId, message: &InitializeKeyRemovalSession) -> Result<(), Error> {
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// node, which has no key share (i.e. it has already been removed), has nothing to stage
+		match self.key_storage.get(&self.id) {
+			Ok(key_share) => {
+				self.check_requestor(&key_share, &message.requestor_signature, message.nonce)?;
+				if !key_holders(&key_share).contains(&sender) {
+					return Err(Error::InvalidMessage);
+				}
+
+				self.key_storage.remove(&self.id).map_err(|e| Error::KeyStorage(e.into()))?;
+			},
+			Err(KeyStorageError::DocumentNotFound) => (),
+			Err(err) => return Err(Error::KeyStorage(err.into())),
+		}
+
+		data.master = Some(sender.clone());
+		data.requestor_signature = Some(message.requestor_signature.clone().into());
+		data.nonce = Some(message.nonce);
+		data.state = SessionState::WaitingForCommit;
+		self.cluster.send(&sender, Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalStaged(KeyShareRemovalStaged {
+			session: self.id.clone().into(),
+		})))
+	}
+
+	/// Process staging confirmation on master node.
+	fn process_key_share_removal_staged(&self, data: &mut SessionData, sender: NodeId) -> Result<(), Error> {
+		// only master is waiting for these notifications
+		if data.master.as_ref() != Some(self.node()) {
+			return Err(Error::InvalidMessage);
+		}
+		if data.state != SessionState::WaitingForStaging {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if !data.awaiting_staging.remove(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		self.try_commit_removal(data)
+	}
+
+	/// Process request to commit staged removal.
+	fn process_commit_key_share_removal(&self, data: &mut SessionData, sender: NodeId) -> Result<(), Error> {
+		// check state
+		if data.state != SessionState::WaitingForCommit {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if data.master.as_ref() != Some(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		self.key_storage.commit().map_err(|e| Error::KeyStorage(e.into()))?;
+		self.remember_nonce(data);
+		self.complete(data, Ok(BTreeSet::new()));
+
+		// if master won't receive confirmation, it retries removal later
+		self.cluster.send(&sender, Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalCommitted(KeyShareRemovalCommitted {
+			session: self.id.clone().into(),
+		})))
+	}
+
+	/// Process commit confirmation on master node.
+	fn process_key_share_removal_committed(&self, data: &mut SessionData, sender: NodeId) -> Result<(), Error> {
+		// only master is waiting for these notifications
+		if data.master.as_ref() != Some(self.node()) {
+			return Err(Error::InvalidMessage);
+		}
+		if data.state != SessionState::WaitingForCommitConfirmation {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if !data.awaiting_commit.remove(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		self.try_complete(data);
+		Ok(())
+	}
+
+	/// Commit removal if every reachable key holder has staged it.
+	fn try_commit_removal(&self, data: &mut SessionData) -> Result<(), Error> {
+		if data.state != SessionState::WaitingForStaging || !data.awaiting_staging.is_empty() {
+			return Ok(());
+		}
+
+		// commit on master first, so that other nodes won't commit if master fails
+		self.key_storage.commit().map_err(|e| Error::KeyStorage(e.into()))?;
+		self.remember_nonce(data);
+
+		// key holder, which has not received commit request, discards staged removal => removal is pending there
+		data.state = SessionState::WaitingForCommitConfirmation;
+		for node in &data.nodes {
+			if self.cluster.send(node, Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(CommitKeyShareRemoval {
+				session: self.id.clone().into(),
+			}))).is_err() {
+				data.pending_nodes.insert(node.clone());
+			} else {
+				data.awaiting_commit.insert(node.clone());
+			}
+		}
+
+		self.try_complete(data);
+		Ok(())
+	}
+
+	/// Key holder has not confirmed commit => removal must be retried on it later.
+	fn on_commit_not_confirmed(&self, data: &mut SessionData, node: &NodeId) {
+		if data.awaiting_commit.remove(node) {
+			data.pending_nodes.insert(node.clone());
+			self.try_complete(data);
+		}
+	}
+
+	/// Complete session on master node if every key holder has either confirmed commit, or is pending.
+	fn try_complete(&self, data: &mut SessionData) {
+		if data.state == SessionState::WaitingForCommitConfirmation && data.awaiting_commit.is_empty() {
+			let pending_nodes = data.pending_nodes.clone();
+			self.complete(data, Ok(pending_nodes));
+		}
+	}
+
+	/// Fail session if error has occured while processing request.
+	fn process_result(&self, data: &mut SessionData, result: Result<(), Error>) -> Result<(), Error> {
+		match result {
+			Err(Error::TooEarlyForRequest) => Err(Error::TooEarlyForRequest),
+			Err(err) => {
+				// committed removal could not be rolled back
+				if data.state != SessionState::Finished && data.state != SessionState::Failed
+					&& data.state != SessionState::WaitingForCommitConfirmation {
+					self.fail(data, err.clone(), None);
+				}
+				Err(err)
+			},
+			Ok(()) => Ok(()),
+		}
+	}
+
+	/// Discard staged removal && complete session with error.
+	fn fail(&self, data: &mut SessionData, error: Error, failed_node: Option<&NodeId>) {
+		self.key_storage.rollback();
+
+		// master must ask other key holders to discard staged removal
+		if data.master.as_ref() == Some(self.node()) {
+			for node in data.nodes.iter().filter(|n| Some(*n) != failed_node) {
+				// do not bother processing send error, as we already processing error
+				let _ = self.cluster.send(node, Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(KeyRemovalSessionError {
+					session: self.id.clone().into(),
+					error: error.clone().into(),
+				})));
+			}
+		}
+
+		self.complete(data, Err(error));
+	}
+
+	/// Complete session with given result.
+	fn complete(&self, data: &mut SessionData, result: Result<BTreeSet<NodeId>, Error>) {
+		data.state = if result.is_ok() { SessionState::Finished } else { SessionState::Failed };
+		data.result = Some(result);
+		self.completed.notify_all();
+	}
+
+	/// Check that requestor is allowed to remove the key && that removal request has not been processed yet.
+	/// Key could be removed either by its author, or by the administrator.
+	fn check_requestor(&self, key_share: &DocumentKeyShare, requestor_signature: &Signature, nonce: u64) -> Result<(), Error> {
+		let requestor = ethkey::recover(requestor_signature, &removal_request_hash(&self.id, nonce))?;
+		if requestor != key_share.author && Some(&requestor) != self.admin_public.as_ref() {
+			return Err(Error::AccessDenied);
+		}
+
+		match self.key_storage.is_key_removal_nonce_used(&self.id, nonce) {
+			Ok(false) => Ok(()),
+			Ok(true) => Err(Error::AccessDenied),
+			Err(err) => Err(Error::KeyStorage(err.into())),
+		}
+	}
+
+	/// Remember nonce of committed removal request, so that it could not be replayed.
+	fn remember_nonce(&self, data: &SessionData) {
+		let nonce = data.nonce.expect("nonce is filled in initialization phase; removal is committed after initialization; qed");
+		if let Err(err) = self.key_storage.insert_key_removal_nonce(&self.id, nonce) {
+			warn!("{}: failed to remember nonce of key {} removal request: {}", self.node(), self.id, err);
+		}
+	}
+
+	/// Read key share from the key storage.
+	fn read_key_share(&self) -> Result<DocumentKeyShare, Error> {
+		match self.key_storage.get(&self.id) {
+			Ok(key_share) => Ok(key_share),
+			Err(KeyStorageError::DocumentNotFound) => Err(Error::ServerKeyIsNotFound),
+			Err(err) => Err(Error::KeyStorage(err.into())),
+		}
+	}
+}
+
+impl Session for SessionImpl {
+	fn state(&self) -> SessionState {
+		self.data.lock().state.clone()
+	}
+
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<BTreeSet<NodeId>, Error> {
+		let mut data = self.data.lock();
+		if !data.result.is_some() {
+			match timeout {
+				None => self.completed.wait(&mut data),
+				Some(timeout) => { self.completed.wait_for(&mut data, timeout); },
+			}
+		}
+
+		match data.result.as_ref() {
+			Some(result) => result.clone(),
+			None => Err(Error::Io("timeout".into())),
+		}
+	}
+}
+
+impl Debug for SessionImpl {
+	fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+		write!(f, "Key removal session {} on {}", self.id, self.self_node_id)
+	}
+}
+
+/// Compute hash of key removal request, which must be signed by the requestor: keccak("remove" || key_id || nonce).
+/// It differs from messages, signed for other requests, so their signatures could not be used to remove the key.
+pub fn removal_request_hash(key_id: &SessionId, nonce: u64) -> H256 {
+	let mut data = b"remove".to_vec();
+	data.extend_from_slice(&**key_id);
+	for i in (0..8).rev() {
+		data.push((nonce >> (i * 8)) as u8);
+	}
+	data.sha3()
+}
+
+/// Get all nodes, which are holding share of any version of the key.
+fn key_holders(key_share: &DocumentKeyShare) -> BTreeSet<NodeId> {
+	key_share.versions.iter()
+		.flat_map(|version| version.id_numbers.keys().cloned())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time;
+	use std::sync::Arc;
+	use std::collections::{BTreeSet, BTreeMap, VecDeque};
+	use ethkey::{self, Random, Generator, KeyPair};
+	use key_server_cluster::{NodeId, SessionId, Error, KeyStorage, DummyKeyStorage, DocumentKeyShare, DocumentKeyShareVersion};
+	use key_server_cluster::message::{self, Message, KeyRemovalMessage};
+	use key_server_cluster::cluster::tests::DummyCluster;
+	use key_server_cluster::key_removal_session::{Session, SessionImpl, SessionState, SessionParams, removal_request_hash};
+
+	#[derive(Debug)]
+	struct Node {
+		pub cluster: Arc<DummyCluster>,
+		pub key_storage: Arc<DummyKeyStorage>,
+		pub session: SessionImpl,
+	}
+
+	#[derive(Debug)]
+	struct MessageLoop {
+		pub session_id: SessionId,
+		pub author: KeyPair,
+		pub admin: KeyPair,
+		pub nodes: BTreeMap<NodeId, Node>,
+		pub queue: VecDeque<(NodeId, NodeId, Message)>,
+	}
+
+	impl MessageLoop {
+		pub fn new(nodes_num: usize) -> Self {
+			let session_id = SessionId::default();
+			let author = Random.generate().unwrap();
+			let admin = Random.generate().unwrap();
+			let id_numbers: BTreeMap<_, _> = (0..nodes_num)
+				.map(|_| (Random.generate().unwrap().public().clone(), Random.generate().unwrap().secret().clone()))
+				.collect();
+
+			let mut nodes = BTreeMap::new();
+			for node_id in id_numbers.keys() {
+				let cluster = Arc::new(DummyCluster::new(node_id.clone()));
+				for other_node_id in id_numbers.keys() {
+					cluster.add_node(other_node_id.clone());
+				}
+
+				let key_storage = Arc::new(DummyKeyStorage::default());
+				key_storage.insert(session_id.clone(), DocumentKeyShare {
+					author: author.public().clone(),
+					threshold: nodes_num - 1,
+					common_point: None,
+					encrypted_point: None,
+					versions: vec![DocumentKeyShareVersion::new(id_numbers.clone(), Random.generate().unwrap().secret().clone())],
+				}).unwrap();
+
+				let session = SessionImpl::new(SessionParams {
+					id: session_id.clone(),
+					self_node_id: node_id.clone(),
+					admin_public: Some(admin.public().clone()),
+					key_storage: key_storage.clone(),
+					cluster: cluster.clone(),
+				});
+				nodes.insert(node_id.clone(), Node { cluster: cluster, key_storage: key_storage, session: session });
+			}
+
+			MessageLoop {
+				session_id: session_id,
+				author: author,
+				admin: admin,
+				nodes: nodes,
+				queue: VecDeque::new(),
+			}
+		}
+
+		pub fn master(&self) -> &Node {
+			self.nodes.values().nth(0).unwrap()
+		}
+
+		pub fn first_slave(&self) -> &Node {
+			self.nodes.values().nth(1).unwrap()
+		}
+
+		pub fn all_nodes(&self) -> BTreeSet<NodeId> {
+			self.nodes.keys().cloned().collect()
+		}
+
+		pub fn requestor_signature(&self, requestor: &KeyPair) -> ethkey::Signature {
+			ethkey::sign(requestor.secret(), &removal_request_hash(&self.session_id, 1)).unwrap()
+		}
+
+		pub fn restore_key_shares(&self) {
+			let version = DocumentKeyShareVersion::new(self.nodes.keys().map(|n| (n.clone(), Random.generate().unwrap().secret().clone())).collect(),
+				Random.generate().unwrap().secret().clone());
+			for node in self.nodes.values() {
+				node.key_storage.insert(self.session_id.clone(), DocumentKeyShare {
+					author: self.author.public().clone(),
+					threshold: self.nodes.len() - 1,
+					common_point: None,
+					encrypted_point: None,
+					versions: vec![version.clone()],
+				}).unwrap();
+			}
+		}
+
+		pub fn restart_sessions(&mut self) {
+			let session_id = self.session_id.clone();
+			let admin_public = self.admin.public().clone();
+			for (node_id, node) in self.nodes.iter_mut() {
+				node.session = SessionImpl::new(SessionParams {
+					id: session_id.clone(),
+					self_node_id: node_id.clone(),
+					admin_public: Some(admin_public.clone()),
+					key_storage: node.key_storage.clone(),
+					cluster: node.cluster.clone(),
+				});
+			}
+		}
+
+		pub fn take_message(&mut self) -> Option<(NodeId, NodeId, Message)> {
+			self.nodes.values()
+				.filter_map(|n| n.cluster.take_message().map(|m| (n.session.node().clone(), m.0, m.1)))
+				.nth(0)
+				.or_else(|| self.queue.pop_front())
+		}
+
+		pub fn process_message(&mut self, msg: (NodeId, NodeId, Message)) -> Result<(), Error> {
+			match msg.2 {
+				Message::KeyRemoval(KeyRemovalMessage::InitializeKeyRemovalSession(ref message)) => self.nodes[&msg.1].session.on_initialize_session(msg.0.clone(), &message),
+				Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalStaged(ref message)) => self.nodes[&msg.1].session.on_key_share_removal_staged(msg.0.clone(), &message),
+				Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(ref message)) => self.nodes[&msg.1].session.on_commit_key_share_removal(msg.0.clone(), &message),
+				Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalCommitted(ref message)) => self.nodes[&msg.1].session.on_key_share_removal_committed(msg.0.clone(), &message),
+				Message::KeyRemoval(KeyRemovalMessage::KeyRemovalSessionError(ref message)) => self.nodes[&msg.1].session.on_session_error(msg.0.clone(), &message),
+				_ => panic!("unexpected"),
+			}
+		}
+
+		pub fn run(&mut self) {
+			while let Some((from, to, message)) = self.take_message() {
+				self.process_message((from, to, message)).unwrap();
+			}
+		}
+	}
+
+	#[test]
+	fn key_is_removed_from_all_key_holders() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+		assert_eq!(l.master().session.state(), SessionState::WaitingForStaging);
+		l.run();
+
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+		for node in l.nodes.values() {
+			assert_eq!(node.session.state(), SessionState::Finished);
+			assert!(!node.key_storage.contains(&l.session_id));
+		}
+	}
+
+	#[test]
+	fn key_is_removed_by_administrator() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.admin), 1, l.all_nodes()).unwrap();
+		l.run();
+
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+		assert!(l.nodes.values().all(|n| !n.key_storage.contains(&l.session_id)));
+	}
+
+	#[test]
+	fn fails_to_initialize_if_key_is_not_found() {
+		let l = MessageLoop::new(3);
+		l.master().key_storage.remove(&l.session_id).unwrap();
+		assert_eq!(l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap_err(),
+			Error::ServerKeyIsNotFound);
+	}
+
+	#[test]
+	fn fails_to_initialize_if_requestor_is_neither_author_nor_administrator() {
+		let l = MessageLoop::new(3);
+		let requestor = Random.generate().unwrap();
+		assert_eq!(l.master().session.initialize(l.requestor_signature(&requestor), 1, l.all_nodes()).unwrap_err(),
+			Error::AccessDenied);
+		assert!(l.master().key_storage.contains(&l.session_id));
+		assert!(l.master().cluster.take_message().is_none());
+	}
+
+	#[test]
+	fn key_holder_rejects_request_if_requestor_is_neither_author_nor_administrator() {
+		let l = MessageLoop::new(3);
+		let requestor = Random.generate().unwrap();
+		assert_eq!(l.first_slave().session.on_initialize_session(l.master().session.node().clone(), &message::InitializeKeyRemovalSession {
+			session: l.session_id.clone().into(),
+			requestor_signature: l.requestor_signature(&requestor).into(),
+			nonce: 1,
+		}).unwrap_err(), Error::AccessDenied);
+		assert_eq!(l.first_slave().session.state(), SessionState::Failed);
+		assert!(l.first_slave().key_storage.contains(&l.session_id));
+	}
+
+	#[test]
+	fn staged_removal_is_discarded_if_key_holder_fails() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+
+		// let every key holder stage removal
+		let master_id = l.master().session.node().clone();
+		let slave_id = l.first_slave().session.node().clone();
+		while let Some((from, to, message)) = l.take_message() {
+			if to != master_id {
+				l.process_message((from, to, message)).unwrap();
+			}
+		}
+
+		// one of key holders reports error => master asks other key holders to discard staged removal
+		l.master().session.on_session_error(slave_id, &message::KeyRemovalSessionError {
+			session: l.session_id.clone().into(),
+			error: "error".into(),
+		}).unwrap();
+		l.run();
+
+		for node in l.nodes.values().filter(|n| n.session.node() != &slave_id) {
+			assert_eq!(node.session.state(), SessionState::Failed);
+			assert!(node.key_storage.contains(&l.session_id));
+		}
+	}
+
+	#[test]
+	fn disconnected_key_holder_is_reported_as_unreachable() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+		let slave_id = l.first_slave().session.node().clone();
+		l.master().session.on_node_timeout(&slave_id);
+		while let Some((from, to, message)) = l.take_message() {
+			if to != slave_id {
+				l.process_message((from, to, message)).unwrap();
+			}
+		}
+
+		assert_eq!(l.master().session.wait(None), Ok(vec![slave_id.clone()].into_iter().collect()));
+		for node in l.nodes.values() {
+			assert_eq!(node.key_storage.contains(&l.session_id), node.session.node() == &slave_id);
+		}
+	}
+
+	#[test]
+	fn master_waits_for_commit_confirmations() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+
+		// process every message, except commit confirmations
+		let master_id = l.master().session.node().clone();
+		let mut confirmations = Vec::new();
+		while let Some((from, to, message)) = l.take_message() {
+			match message {
+				Message::KeyRemoval(KeyRemovalMessage::KeyShareRemovalCommitted(_)) => confirmations.push((from, to, message)),
+				_ => l.process_message((from, to, message)).unwrap(),
+			}
+		}
+
+		assert_eq!(l.master().session.state(), SessionState::WaitingForCommitConfirmation);
+		assert!(!l.master().key_storage.contains(&l.session_id));
+		assert_eq!(confirmations.len(), 2);
+		assert!(confirmations.iter().all(|&(_, ref to, _)| to == &master_id));
+
+		for confirmation in confirmations {
+			l.process_message(confirmation).unwrap();
+		}
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+	}
+
+	#[test]
+	fn key_holder_which_has_not_confirmed_commit_is_reported_as_pending() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+
+		// first key holder stages removal, but does not receive commit request before timeout
+		let slave_id = l.first_slave().session.node().clone();
+		while let Some((from, to, message)) = l.take_message() {
+			match message {
+				Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(_)) if to == slave_id => (),
+				_ => l.process_message((from, to, message)).unwrap(),
+			}
+		}
+		l.first_slave().session.on_session_timeout();
+		assert_eq!(l.first_slave().session.state(), SessionState::Failed);
+		assert!(l.first_slave().key_storage.contains(&l.session_id));
+
+		// master has already committed removal => it completes with pending key holder, which must be retried
+		l.master().session.on_session_timeout();
+		assert_eq!(l.master().session.state(), SessionState::Finished);
+		assert_eq!(l.master().session.wait(None), Ok(vec![slave_id.clone()].into_iter().collect()));
+		assert!(!l.master().key_storage.contains(&l.session_id));
+	}
+
+	#[test]
+	fn key_holder_which_has_failed_to_commit_is_reported_as_pending() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+
+		// first key holder fails to commit removal
+		let slave_id = l.first_slave().session.node().clone();
+		while let Some((from, to, message)) = l.take_message() {
+			match message {
+				Message::KeyRemoval(KeyRemovalMessage::CommitKeyShareRemoval(_)) if to == slave_id => (),
+				_ => l.process_message((from, to, message)).unwrap(),
+			}
+		}
+		l.master().session.on_session_error(slave_id.clone(), &message::KeyRemovalSessionError {
+			session: l.session_id.clone().into(),
+			error: "error".into(),
+		}).unwrap();
+
+		assert_eq!(l.master().session.wait(None), Ok(vec![slave_id.clone()].into_iter().collect()));
+		assert_eq!(l.master().session.state(), SessionState::Finished);
+		for node in l.nodes.values() {
+			assert_eq!(node.key_storage.contains(&l.session_id), node.session.node() == &slave_id);
+		}
+	}
+
+	#[test]
+	fn key_is_removed_from_offline_key_holder_when_it_is_connected_again() {
+		let mut l = MessageLoop::new(3);
+
+		// first key holder is offline during removal
+		let offline_node = l.first_slave().session.node().clone();
+		let mut connected_nodes = l.all_nodes();
+		connected_nodes.remove(&offline_node);
+		let requestor_signature = l.requestor_signature(&l.author);
+		l.master().session.initialize(requestor_signature.clone(), 1, connected_nodes).unwrap();
+		l.run();
+
+		let unreachable_nodes = l.master().session.wait(None).unwrap();
+		assert_eq!(unreachable_nodes, vec![offline_node.clone()].into_iter().collect());
+		for node in l.nodes.values() {
+			assert_eq!(node.key_storage.contains(&l.session_id), node.session.node() == &offline_node);
+		}
+
+		// when offline key holder is connected again, master (which has no key share now) retries removal
+		l.restart_sessions();
+		l.master().session.initialize_for_nodes(requestor_signature, 1, unreachable_nodes, l.all_nodes()).unwrap();
+		l.run();
+
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+		assert_eq!(l.nodes[&offline_node].session.state(), SessionState::Finished);
+		assert!(l.nodes.values().all(|n| !n.key_storage.contains(&l.session_id)));
+	}
+
+	#[test]
+	fn key_holder_without_key_share_confirms_removal() {
+		let mut l = MessageLoop::new(2);
+		let slave_id = l.first_slave().session.node().clone();
+		l.first_slave().key_storage.remove(&l.session_id).unwrap();
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+		l.run();
+
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+		assert_eq!(l.nodes[&slave_id].session.state(), SessionState::Finished);
+	}
+
+	#[test]
+	fn wait_fails_when_timeout_passes() {
+		let l = MessageLoop::new(3);
+		assert_eq!(l.master().session.wait(Some(time::Duration::from_millis(10))), Err(Error::Io("timeout".into())));
+	}
+
+	#[test]
+	fn fails_to_initialize_with_signature_of_other_request() {
+		let l = MessageLoop::new(3);
+		let retrieval_signature = ethkey::sign(l.author.secret(), &l.session_id).unwrap();
+		assert_eq!(l.master().session.initialize(retrieval_signature, 1, l.all_nodes()).unwrap_err(), Error::AccessDenied);
+		assert!(l.master().key_storage.contains(&l.session_id));
+	}
+
+	#[test]
+	fn removal_request_could_not_be_replayed() {
+		let mut l = MessageLoop::new(3);
+		l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap();
+		l.run();
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+
+		// key is generated again with the same id => previous removal request is rejected by every key holder
+		l.restore_key_shares();
+		l.restart_sessions();
+		assert_eq!(l.master().session.initialize(l.requestor_signature(&l.author), 1, l.all_nodes()).unwrap_err(), Error::AccessDenied);
+		assert_eq!(l.first_slave().session.on_initialize_session(l.master().session.node().clone(), &message::InitializeKeyRemovalSession {
+			session: l.session_id.clone().into(),
+			requestor_signature: l.requestor_signature(&l.author).into(),
+			nonce: 1,
+		}).unwrap_err(), Error::AccessDenied);
+		assert!(l.nodes.values().all(|n| n.key_storage.contains(&l.session_id)));
+
+		// but request with new nonce is accepted
+		l.restart_sessions();
+		let signature = ethkey::sign(l.author.secret(), &removal_request_hash(&l.session_id, 2)).unwrap();
+		l.master().session.initialize(signature, 2, l.all_nodes()).unwrap();
+		l.run();
+		assert_eq!(l.master().session.wait(None), Ok(BTreeSet::new()));
+		assert!(l.nodes.values().all(|n| !n.key_storage.contains(&l.session_id)));
+	}
+}
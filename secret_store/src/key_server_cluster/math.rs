@@ -15,6 +15,7 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use ethkey::{Public, Secret, Random, Generator, math};
+use util::{H256, Hashable};
 use key_server_cluster::Error;
 
 #[derive(Debug)]
@@ -202,6 +203,20 @@ pub fn compute_node_shadow<'a, I>(node_number: &Secret, node_secret_share: &Secr
 	Ok(shadow)
 }
 
+/// Compute secret subshare of the node: its secret share, multiplied by Lagrange coefficient (at zero point).
+/// Sum of subshares of any threshold + 1 nodes is equal to the joint secret.
+pub fn compute_secret_subshare<'a, I>(node_number: &Secret, node_secret_share: &Secret, other_nodes_numbers: I) -> Result<Secret, Error> where I: Iterator<Item=&'a Secret> {
+	let mut subshare = node_secret_share.clone();
+	for other_node_number in other_nodes_numbers {
+		let mut coeff = other_node_number.clone();
+		coeff.sub(node_number)?;
+		coeff.inv()?;
+		coeff.mul(other_node_number)?;
+		subshare.mul(&coeff)?;
+	}
+	Ok(subshare)
+}
+
 /// Compute shadow point for the node.
 pub fn compute_node_shadow_point(access_key: &Secret, common_point: &Public, node_shadow: &Secret, decrypt_shadow: Option<Secret>) -> Result<(Public, Option<Secret>), Error> {
 	let mut shadow_key = node_shadow.clone();
@@ -274,6 +289,44 @@ pub fn make_common_shadow_point(threshold: usize, mut common_point: Public) -> R
 	}
 }
 
+/// Compute Schnorr signature hash: keccak(nonce_public || message_hash), interpreted as scalar.
+pub fn compute_schnorr_signature_hash(nonce_public: &Public, message_hash: &H256) -> Result<Secret, Error> {
+	let mut data = nonce_public.to_vec();
+	data.extend_from_slice(&**message_hash);
+	Ok(Secret::from_unsafe_slice(&*data.sha3())?)
+}
+
+/// Compute partial Schnorr signature of the node: its one-time nonce + signature hash * its secret subshare.
+/// Sum of partial signatures of nodes, which have computed joint nonce, is the signature, made with the joint secret.
+pub fn compute_partial_schnorr_signature<'a, I>(nonce: &Secret, signature_hash: &Secret, node_number: &Secret, node_secret_share: &Secret, other_nodes_numbers: I) -> Result<Secret, Error> where I: Iterator<Item=&'a Secret> {
+	let mut partial_signature = compute_secret_subshare(node_number, node_secret_share, other_nodes_numbers)?;
+	partial_signature.mul(signature_hash)?;
+	partial_signature.add(nonce)?;
+	Ok(partial_signature)
+}
+
+/// Compute Schnorr signature from partial signatures.
+pub fn compute_schnorr_signature<'a, I>(mut partial_signatures: I) -> Result<Secret, Error> where I: Iterator<Item=&'a Secret> {
+	let mut signature = partial_signatures.next().expect("compute_schnorr_signature is called when at least one node has signed; qed").clone();
+	while let Some(partial_signature) = partial_signatures.next() {
+		signature.add(partial_signature)?;
+	}
+	Ok(signature)
+}
+
+#[cfg(test)]
+/// Verify Schnorr signature (signature hash, signature) of the message hash against the public key.
+pub fn verify_schnorr_signature(public: &Public, signature: &(Secret, Secret), message_hash: &H256) -> Result<bool, Error> {
+	// nonce_public = signature * G - signature_hash * public
+	let mut nonce_public = math::generation_point();
+	math::public_mul_secret(&mut nonce_public, &signature.1)?;
+	let mut public_mul_hash = public.clone();
+	math::public_mul_secret(&mut public_mul_hash, &signature.0)?;
+	math::public_sub(&mut nonce_public, &public_mul_hash)?;
+
+	Ok(compute_schnorr_signature_hash(&nonce_public, message_hash)? == signature.0)
+}
+
 #[cfg(test)]
 /// Decrypt shadow-encrypted secret.
 pub fn decrypt_with_shadow_coefficients(mut decrypted_shadow: Public, mut common_shadow_point: Public, shadow_coefficients: Vec<Secret>) -> Result<Public, Error> {
@@ -384,4 +437,43 @@ pub mod tests {
 			assert_eq!(document_secret_plain, document_secret_decrypted);
 		}
 	}
+
+	#[test]
+	fn full_schnorr_signature_math_session() {
+		let test_cases = [(0, 1), (0, 2), (1, 2), (1, 3), (2, 3), (1, 4), (2, 4), (3, 4), (1, 5), (2, 5), (3, 5), (4, 5),
+			(1, 10), (2, 10), (3, 10), (4, 10), (5, 10), (6, 10), (7, 10), (8, 10), (9, 10)];
+		for &(t, n) in &test_cases {
+			// === PART1: DKG ===
+			let id_numbers: Vec<_> = (0..n).map(|_| generate_random_scalar().unwrap()).collect();
+			let polynoms1: Vec<_> = (0..n).map(|_| generate_random_polynom(t).unwrap()).collect();
+			let secrets1: Vec<_> = (0..n).map(|i| (0..n).map(|j| compute_polynom(&polynoms1[i], &id_numbers[j]).unwrap()).collect::<Vec<_>>()).collect();
+			let public_shares: Vec<_> = (0..n).map(|i| compute_public_share(&polynoms1[i][0]).unwrap()).collect();
+			let secret_shares: Vec<_> = (0..n).map(|i| compute_secret_share(secrets1.iter().map(|s| &s[i])).unwrap()).collect();
+			let joint_public = compute_joint_public(public_shares.iter()).unwrap();
+
+			// === PART2: signing with last t + 1 nodes ===
+			let message_hash = H256::random();
+			let signing_nodes: Vec<_> = (n - t - 1..n).collect();
+
+			// every signing node generates one-time nonce && the joint nonce public is computed
+			let nonces: Vec<_> = signing_nodes.iter().map(|_| generate_random_scalar().unwrap()).collect();
+			let nonces_publics: Vec<_> = nonces.iter().map(|nonce| compute_public_share(nonce).unwrap()).collect();
+			let nonce_public = compute_joint_public(nonces_publics.iter()).unwrap();
+			let signature_hash = compute_schnorr_signature_hash(&nonce_public, &message_hash).unwrap();
+
+			// every signing node computes its partial signature
+			let partial_signatures: Vec<_> = signing_nodes.iter().zip(nonces.iter()).map(|(&i, nonce)|
+				compute_partial_schnorr_signature(nonce, &signature_hash, &id_numbers[i], &secret_shares[i], signing_nodes.iter()
+					.filter(|&&j| j != i)
+					.map(|&j| &id_numbers[j])).unwrap()).collect();
+			let signature = (signature_hash, compute_schnorr_signature(partial_signatures.iter()).unwrap());
+
+			// signature is verified against the joint public
+			assert!(verify_schnorr_signature(&joint_public, &signature, &message_hash).unwrap());
+
+			// ...and is not valid for other message
+			let other_message_hash = H256::random();
+			assert!(!verify_schnorr_signature(&joint_public, &signature, &other_message_hash).unwrap());
+		}
+	}
 }
@@ -18,7 +18,7 @@ use std::fmt;
 use std::collections::{BTreeSet, BTreeMap};
 use ethkey::Secret;
 use key_server_cluster::SessionId;
-use super::{SerializableH256, SerializablePublic, SerializableSecret, SerializableSignature};
+use super::{SerializableH256, SerializablePublic, SerializableSecret, SerializableSignature, SerializableRequester};
 
 pub type MessageSessionId = SerializableH256;
 pub type MessageNodeId = SerializablePublic;
@@ -28,10 +28,20 @@ pub type MessageNodeId = SerializablePublic;
 pub enum Message {
 	/// Cluster message.
 	Cluster(ClusterMessage),
+	/// Generation message.
+	Generation(GenerationMessage),
 	/// Encryption message.
 	Encryption(EncryptionMessage),
 	/// Decryption message.
 	Decryption(DecryptionMessage),
+	/// Share add message.
+	ShareAdd(ShareAddMessage),
+	/// Servers set change message.
+	ServersSetChange(ServersSetChangeMessage),
+	/// Key removal message.
+	KeyRemoval(KeyRemovalMessage),
+	/// Signing message.
+	Signing(SigningMessage),
 }
 
 #[derive(Clone, Debug)]
@@ -48,8 +58,8 @@ pub enum ClusterMessage {
 }
 
 #[derive(Clone, Debug)]
-/// All possible messages that can be sent during encryption session.
-pub enum EncryptionMessage {
+/// All possible messages that can be sent during generation session.
+pub enum GenerationMessage {
 	/// Initialize new DKG session.
 	InitializeSession(InitializeSession),
 	/// Confirm DKG session initialization.
@@ -66,6 +76,17 @@ pub enum EncryptionMessage {
 	SessionCompleted(SessionCompleted),
 }
 
+#[derive(Clone, Debug)]
+/// All possible messages that can be sent during encryption session.
+pub enum EncryptionMessage {
+	/// Initialize encryption session.
+	InitializeEncryptionSession(InitializeEncryptionSession),
+	/// Confirm encryption session initialization.
+	ConfirmEncryptionInitialization(ConfirmEncryptionInitialization),
+	/// When encryption session error has occured.
+	EncryptionSessionError(EncryptionSessionError),
+}
+
 #[derive(Clone, Debug)]
 /// All possible messages that can be sent during decryption session.
 pub enum DecryptionMessage {
@@ -83,6 +104,70 @@ pub enum DecryptionMessage {
 	DecryptionSessionCompleted(DecryptionSessionCompleted),
 }
 
+#[derive(Clone, Debug)]
+/// All possible messages that can be sent during share add session.
+pub enum ShareAddMessage {
+	/// Initialize share add session.
+	InitializeShareAddSession(InitializeShareAddSession),
+	/// Shares of the new key version are sent to every node.
+	NewKeysDissemination(NewKeysDissemination),
+	/// Node has staged new key version.
+	NewKeyShareStaged(NewKeyShareStaged),
+	/// Every node has staged new key version => it must be committed.
+	CommitNewKeyShare(CommitNewKeyShare),
+	/// When share add session error has occured.
+	ShareAddSessionError(ShareAddSessionError),
+}
+
+#[derive(Clone, Debug)]
+/// All possible messages that can be sent during servers set change session.
+pub enum ServersSetChangeMessage {
+	/// Every node is asked to report all keys it is holding.
+	UnknownSessionsRequest(UnknownSessionsRequest),
+	/// Keys, held by the node.
+	UnknownSessions(UnknownSessions),
+	/// Every key has been migrated to the new servers set.
+	ServersSetChangeCompleted(ServersSetChangeCompleted),
+	/// When servers set change session error has occured.
+	ServersSetChangeError(ServersSetChangeError),
+}
+
+#[derive(Clone, Debug)]
+/// All possible messages that can be sent during key removal session.
+pub enum KeyRemovalMessage {
+	/// Initialize key removal session.
+	InitializeKeyRemovalSession(InitializeKeyRemovalSession),
+	/// Node has staged key share removal.
+	KeyShareRemovalStaged(KeyShareRemovalStaged),
+	/// Every reachable key holder has staged key share removal => it must be committed.
+	CommitKeyShareRemoval(CommitKeyShareRemoval),
+	/// Key holder has committed key share removal.
+	KeyShareRemovalCommitted(KeyShareRemovalCommitted),
+	/// When key removal session error has occured.
+	KeyRemovalSessionError(KeyRemovalSessionError),
+}
+
+#[derive(Clone, Debug)]
+/// All possible messages that can be sent during signing session.
+pub enum SigningMessage {
+	/// Initialize signing session.
+	InitializeSigningSession(InitializeSigningSession),
+	/// Confirm/reject signing session initialization.
+	ConfirmSigningInitialization(ConfirmSigningInitialization),
+	/// Request one-time nonce commitment from the node.
+	RequestSigningNonce(RequestSigningNonce),
+	/// One-time nonce commitment of the node.
+	SigningNonce(SigningNonce),
+	/// Request partial signature from the node.
+	RequestPartialSignature(RequestPartialSignature),
+	/// Partial signature of the node.
+	PartialSignature(PartialSignature),
+	/// When signing session error has occured.
+	SigningSessionError(SigningSessionError),
+	/// When signing session is completed.
+	SigningSessionCompleted(SigningSessionCompleted),
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// Introduce node public key.
 pub struct NodePublicKey {
@@ -136,6 +221,8 @@ pub struct ConfirmInitialization {
 pub struct CompleteInitialization {
 	/// Session Id.
 	pub session: MessageSessionId,
+	/// Public key of the session author.
+	pub author: SerializablePublic,
 	/// All session participants along with their identification numbers.
 	pub nodes: BTreeMap<MessageNodeId, SerializableSecret>,
 	/// Decryption threshold. During decryption threshold-of-route.len() nodes must came to
@@ -187,15 +274,208 @@ pub struct SessionCompleted {
 	pub encrypted_point: SerializablePublic,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is requested to store encrypted document key.
+pub struct InitializeEncryptionSession {
+	/// Encryption session Id.
+	pub session: MessageSessionId,
+	/// Requestor signature of the store request.
+	pub requestor_signature: SerializableSignature,
+	/// Common (shared) encryption point.
+	pub common_point: SerializablePublic,
+	/// Encrypted point.
+	pub encrypted_point: SerializablePublic,
+	/// Nonce of the store request.
+	pub nonce: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node has stored encrypted document key.
+pub struct ConfirmEncryptionInitialization {
+	/// Encryption session Id.
+	pub session: MessageSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// When encryption session error has occured.
+pub struct EncryptionSessionError {
+	/// Encryption session Id.
+	pub session: MessageSessionId,
+	/// Error message.
+	pub error: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is requested to participate in share add session.
+pub struct InitializeShareAddSession {
+	/// Share add session Id (it is equal to the id of the key, which is extended).
+	pub session: MessageSessionId,
+	/// Administrator signature of (old nodes set, new nodes set) pair. When session is a part of servers
+	/// set change session, it is the signature of the servers set change.
+	pub admin_signature: SerializableSignature,
+	/// Servers set change. Some if session is a part of servers set change session.
+	pub servers_set_change: Option<ShareAddServersSetChange>,
+	/// Nodes, which are dealing shares of the new key version. On recovery, these are surviving holders of the key.
+	pub old_nodes: BTreeSet<MessageNodeId>,
+	/// All nodes of the new key version along with their identification numbers.
+	pub nodes: BTreeMap<MessageNodeId, SerializableSecret>,
+	/// Hash of the key version, which is re-shared.
+	pub version: SerializableH256,
+	/// Is the key recovered? If so, holders of the version, which are not in old_nodes, are considered lost.
+	pub is_recovery: bool,
+	/// Key author.
+	pub author: SerializablePublic,
+	/// Key threshold.
+	pub threshold: usize,
+	/// Common (shared) encryption point. None if document key is not yet stored.
+	pub common_point: Option<SerializablePublic>,
+	/// Encrypted point. None if document key is not yet stored.
+	pub encrypted_point: Option<SerializablePublic>,
+	/// Point, used to verify shares of the new key version.
+	pub derived_point: SerializablePublic,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Servers set change, which share add session is a part of.
+pub struct ShareAddServersSetChange {
+	/// Servers set change session Id.
+	pub session: MessageSessionId,
+	/// Every node of the cluster, which has been asked to report its keys.
+	pub old_servers_set: BTreeSet<MessageNodeId>,
+	/// New servers set.
+	pub new_servers_set: BTreeSet<MessageNodeId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Shares of the new key version are sent to every node by every old node.
+pub struct NewKeysDissemination {
+	/// Share add session Id.
+	pub session: MessageSessionId,
+	/// Secret 1.
+	pub secret1: SerializableSecret,
+	/// Secret 2.
+	pub secret2: SerializableSecret,
+	/// Public values.
+	pub publics: Vec<SerializablePublic>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node has computed && staged its share of the new key version.
+pub struct NewKeyShareStaged {
+	/// Share add session Id.
+	pub session: MessageSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Staged key version must be committed.
+pub struct CommitNewKeyShare {
+	/// Share add session Id.
+	pub session: MessageSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// When share add session error has occured.
+pub struct ShareAddSessionError {
+	/// Share add session Id.
+	pub session: MessageSessionId,
+	/// Error message.
+	pub error: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is requested to report all keys it is holding.
+pub struct UnknownSessionsRequest {
+	/// Servers set change session Id.
+	pub session: MessageSessionId,
+	/// Administrator signature of the servers set change.
+	pub admin_signature: SerializableSignature,
+	/// Every node of the cluster, which is asked to report its keys.
+	pub old_servers_set: BTreeSet<MessageNodeId>,
+	/// New servers set.
+	pub new_servers_set: BTreeSet<MessageNodeId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Keys, held by the node.
+pub struct UnknownSessions {
+	/// Servers set change session Id.
+	pub session: MessageSessionId,
+	/// Ids of all keys, held by the node.
+	pub keys: Vec<MessageSessionId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Every key has been migrated to the new servers set.
+pub struct ServersSetChangeCompleted {
+	/// Servers set change session Id.
+	pub session: MessageSessionId,
+	/// Administrator signature of the servers set change.
+	pub admin_signature: SerializableSignature,
+	/// Every node of the cluster, which is asked to report its keys.
+	pub old_servers_set: BTreeSet<MessageNodeId>,
+	/// New servers set.
+	pub new_servers_set: BTreeSet<MessageNodeId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// When servers set change session error has occured.
+pub struct ServersSetChangeError {
+	/// Servers set change session Id.
+	pub session: MessageSessionId,
+	/// Error message.
+	pub error: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Key holder is requested to remove its share of the key.
+pub struct InitializeKeyRemovalSession {
+	/// Key removal session Id (it is equal to the id of the key, which is removed).
+	pub session: MessageSessionId,
+	/// Signature of the key author or of the administrator over the removal request.
+	pub requestor_signature: SerializableSignature,
+	/// Removal request nonce.
+	pub nonce: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Key holder has staged removal of its key share.
+pub struct KeyShareRemovalStaged {
+	/// Key removal session Id.
+	pub session: MessageSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Key holder is requested to commit staged key share removal.
+pub struct CommitKeyShareRemoval {
+	/// Key removal session Id.
+	pub session: MessageSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Key holder has committed removal of its key share.
+pub struct KeyShareRemovalCommitted {
+	/// Key removal session Id.
+	pub session: MessageSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// When key removal session error has occured.
+pub struct KeyRemovalSessionError {
+	/// Key removal session Id.
+	pub session: MessageSessionId,
+	/// Error message.
+	pub error: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// Node is requested to decrypt data, encrypted in given session.
 pub struct InitializeDecryptionSession {
-	/// Encryption session Id.
+	/// Generation session Id.
 	pub session: MessageSessionId,
 	/// Decryption session Id.
 	pub sub_session: SerializableSecret,
-	/// Requestor signature.
-	pub requestor_signature: SerializableSignature,
+	/// Requester.
+	pub requester: SerializableRequester,
 	/// Is shadow decryption requested? When true, decryption result
 	/// will be visible to the owner of requestor public key only.
 	pub is_shadow_decryption: bool,
@@ -204,7 +484,7 @@ pub struct InitializeDecryptionSession {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// Node is responding to decryption request.
 pub struct ConfirmDecryptionInitialization {
-	/// Encryption session Id.
+	/// Generation session Id.
 	pub session: MessageSessionId,
 	/// Decryption session Id.
 	pub sub_session: SerializableSecret,
@@ -215,7 +495,7 @@ pub struct ConfirmDecryptionInitialization {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// Node is requested to do a partial decryption.
 pub struct RequestPartialDecryption {
-	/// Encryption session Id.
+	/// Generation session Id.
 	pub session: MessageSessionId,
 	/// Decryption session Id.
 	pub sub_session: SerializableSecret,
@@ -226,7 +506,7 @@ pub struct RequestPartialDecryption {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// Node has partially decrypted the secret.
 pub struct PartialDecryption {
-	/// Encryption session Id.
+	/// Generation session Id.
 	pub session: MessageSessionId,
 	/// Decryption session Id.
 	pub sub_session: SerializableSecret,
@@ -239,7 +519,7 @@ pub struct PartialDecryption {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// When decryption session error has occured.
 pub struct DecryptionSessionError {
-	/// Encryption session Id.
+	/// Generation session Id.
 	pub session: MessageSessionId,
 	/// Decryption session Id.
 	pub sub_session: SerializableSecret,
@@ -250,22 +530,155 @@ pub struct DecryptionSessionError {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// When decryption session is completed.
 pub struct DecryptionSessionCompleted {
-	/// Encryption session Id.
+	/// Generation session Id.
 	pub session: MessageSessionId,
 	/// Decryption session Id.
 	pub sub_session: SerializableSecret,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is requested to sign message hash with the server key, generated in given session.
+pub struct InitializeSigningSession {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Requester.
+	pub requester: SerializableRequester,
+	/// Hash of the message to sign.
+	pub message_hash: SerializableH256,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is responding to signing request.
+pub struct ConfirmSigningInitialization {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Is node confirmed to make a partial signature?
+	pub is_confirmed: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is requested to generate one-time nonce for signing.
+pub struct RequestSigningNonce {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Nodes that are agreed to do a signing.
+	pub nodes: BTreeSet<MessageNodeId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node has generated one-time nonce for signing.
+pub struct SigningNonce {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Public of the one-time nonce.
+	pub nonce_public: SerializablePublic,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node is requested to do a partial signing.
+pub struct RequestPartialSignature {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Nonce publics of all nodes that are agreed to do a signing.
+	pub nonces: BTreeMap<MessageNodeId, SerializablePublic>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Node has partially signed the message.
+pub struct PartialSignature {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Partial signature.
+	pub partial_signature: SerializableSecret,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// When signing session error has occured.
+pub struct SigningSessionError {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+	/// Error message.
+	pub error: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// When signing session is completed.
+pub struct SigningSessionCompleted {
+	/// Generation session Id.
+	pub session: MessageSessionId,
+	/// Signing session Id.
+	pub sub_session: SerializableSecret,
+}
+
+impl GenerationMessage {
+	pub fn session_id(&self) -> &SessionId {
+		match *self {
+			GenerationMessage::InitializeSession(ref msg) => &msg.session,
+			GenerationMessage::ConfirmInitialization(ref msg) => &msg.session,
+			GenerationMessage::CompleteInitialization(ref msg) => &msg.session,
+			GenerationMessage::KeysDissemination(ref msg) => &msg.session,
+			GenerationMessage::PublicKeyShare(ref msg) => &msg.session,
+			GenerationMessage::SessionError(ref msg) => &msg.session,
+			GenerationMessage::SessionCompleted(ref msg) => &msg.session,
+		}
+	}
+}
+
 impl EncryptionMessage {
 	pub fn session_id(&self) -> &SessionId {
 		match *self {
-			EncryptionMessage::InitializeSession(ref msg) => &msg.session,
-			EncryptionMessage::ConfirmInitialization(ref msg) => &msg.session,
-			EncryptionMessage::CompleteInitialization(ref msg) => &msg.session,
-			EncryptionMessage::KeysDissemination(ref msg) => &msg.session,
-			EncryptionMessage::PublicKeyShare(ref msg) => &msg.session,
-			EncryptionMessage::SessionError(ref msg) => &msg.session,
-			EncryptionMessage::SessionCompleted(ref msg) => &msg.session,
+			EncryptionMessage::InitializeEncryptionSession(ref msg) => &msg.session,
+			EncryptionMessage::ConfirmEncryptionInitialization(ref msg) => &msg.session,
+			EncryptionMessage::EncryptionSessionError(ref msg) => &msg.session,
+		}
+	}
+}
+
+impl ShareAddMessage {
+	pub fn session_id(&self) -> &SessionId {
+		match *self {
+			ShareAddMessage::InitializeShareAddSession(ref msg) => &msg.session,
+			ShareAddMessage::NewKeysDissemination(ref msg) => &msg.session,
+			ShareAddMessage::NewKeyShareStaged(ref msg) => &msg.session,
+			ShareAddMessage::CommitNewKeyShare(ref msg) => &msg.session,
+			ShareAddMessage::ShareAddSessionError(ref msg) => &msg.session,
+		}
+	}
+}
+
+impl ServersSetChangeMessage {
+	pub fn session_id(&self) -> &SessionId {
+		match *self {
+			ServersSetChangeMessage::UnknownSessionsRequest(ref msg) => &msg.session,
+			ServersSetChangeMessage::UnknownSessions(ref msg) => &msg.session,
+			ServersSetChangeMessage::ServersSetChangeCompleted(ref msg) => &msg.session,
+			ServersSetChangeMessage::ServersSetChangeError(ref msg) => &msg.session,
+		}
+	}
+}
+
+impl KeyRemovalMessage {
+	pub fn session_id(&self) -> &SessionId {
+		match *self {
+			KeyRemovalMessage::InitializeKeyRemovalSession(ref msg) => &msg.session,
+			KeyRemovalMessage::KeyShareRemovalStaged(ref msg) => &msg.session,
+			KeyRemovalMessage::CommitKeyShareRemoval(ref msg) => &msg.session,
+			KeyRemovalMessage::KeyShareRemovalCommitted(ref msg) => &msg.session,
+			KeyRemovalMessage::KeyRemovalSessionError(ref msg) => &msg.session,
 		}
 	}
 }
@@ -294,12 +707,45 @@ impl DecryptionMessage {
 	}
 }
 
+impl SigningMessage {
+	pub fn session_id(&self) -> &SessionId {
+		match *self {
+			SigningMessage::InitializeSigningSession(ref msg) => &msg.session,
+			SigningMessage::ConfirmSigningInitialization(ref msg) => &msg.session,
+			SigningMessage::RequestSigningNonce(ref msg) => &msg.session,
+			SigningMessage::SigningNonce(ref msg) => &msg.session,
+			SigningMessage::RequestPartialSignature(ref msg) => &msg.session,
+			SigningMessage::PartialSignature(ref msg) => &msg.session,
+			SigningMessage::SigningSessionError(ref msg) => &msg.session,
+			SigningMessage::SigningSessionCompleted(ref msg) => &msg.session,
+		}
+	}
+
+	pub fn sub_session_id(&self) -> &Secret {
+		match *self {
+			SigningMessage::InitializeSigningSession(ref msg) => &msg.sub_session,
+			SigningMessage::ConfirmSigningInitialization(ref msg) => &msg.sub_session,
+			SigningMessage::RequestSigningNonce(ref msg) => &msg.sub_session,
+			SigningMessage::SigningNonce(ref msg) => &msg.sub_session,
+			SigningMessage::RequestPartialSignature(ref msg) => &msg.sub_session,
+			SigningMessage::PartialSignature(ref msg) => &msg.sub_session,
+			SigningMessage::SigningSessionError(ref msg) => &msg.sub_session,
+			SigningMessage::SigningSessionCompleted(ref msg) => &msg.sub_session,
+		}
+	}
+}
+
 impl fmt::Display for Message {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
 			Message::Cluster(ref message) => write!(f, "Cluster.{}", message),
+			Message::Generation(ref message) => write!(f, "Generation.{}", message),
 			Message::Encryption(ref message) => write!(f, "Encryption.{}", message),
 			Message::Decryption(ref message) => write!(f, "Decryption.{}", message),
+			Message::ShareAdd(ref message) => write!(f, "ShareAdd.{}", message),
+			Message::ServersSetChange(ref message) => write!(f, "ServersSetChange.{}", message),
+			Message::KeyRemoval(ref message) => write!(f, "KeyRemoval.{}", message),
+			Message::Signing(ref message) => write!(f, "Signing.{}", message),
 		}
 	}
 }
@@ -315,16 +761,26 @@ impl fmt::Display for ClusterMessage {
 	}
 }
 
+impl fmt::Display for GenerationMessage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			GenerationMessage::InitializeSession(_) => write!(f, "InitializeSession"),
+			GenerationMessage::ConfirmInitialization(_) => write!(f, "ConfirmInitialization"),
+			GenerationMessage::CompleteInitialization(_) => write!(f, "CompleteInitialization"),
+			GenerationMessage::KeysDissemination(_) => write!(f, "KeysDissemination"),
+			GenerationMessage::PublicKeyShare(_) => write!(f, "PublicKeyShare"),
+			GenerationMessage::SessionError(ref msg) => write!(f, "SessionError({})", msg.error),
+			GenerationMessage::SessionCompleted(_) => write!(f, "SessionCompleted"),
+		}
+	}
+}
+
 impl fmt::Display for EncryptionMessage {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
-			EncryptionMessage::InitializeSession(_) => write!(f, "InitializeSession"),
-			EncryptionMessage::ConfirmInitialization(_) => write!(f, "ConfirmInitialization"),
-			EncryptionMessage::CompleteInitialization(_) => write!(f, "CompleteInitialization"),
-			EncryptionMessage::KeysDissemination(_) => write!(f, "KeysDissemination"),
-			EncryptionMessage::PublicKeyShare(_) => write!(f, "PublicKeyShare"),
-			EncryptionMessage::SessionError(ref msg) => write!(f, "SessionError({})", msg.error),
-			EncryptionMessage::SessionCompleted(_) => write!(f, "SessionCompleted"),
+			EncryptionMessage::InitializeEncryptionSession(_) => write!(f, "InitializeEncryptionSession"),
+			EncryptionMessage::ConfirmEncryptionInitialization(_) => write!(f, "ConfirmEncryptionInitialization"),
+			EncryptionMessage::EncryptionSessionError(ref msg) => write!(f, "EncryptionSessionError({})", msg.error),
 		}
 	}
 }
@@ -341,3 +797,53 @@ impl fmt::Display for DecryptionMessage {
 		}
 	}
 }
+
+impl fmt::Display for ShareAddMessage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ShareAddMessage::InitializeShareAddSession(_) => write!(f, "InitializeShareAddSession"),
+			ShareAddMessage::NewKeysDissemination(_) => write!(f, "NewKeysDissemination"),
+			ShareAddMessage::NewKeyShareStaged(_) => write!(f, "NewKeyShareStaged"),
+			ShareAddMessage::CommitNewKeyShare(_) => write!(f, "CommitNewKeyShare"),
+			ShareAddMessage::ShareAddSessionError(ref msg) => write!(f, "ShareAddSessionError({})", msg.error),
+		}
+	}
+}
+
+impl fmt::Display for ServersSetChangeMessage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ServersSetChangeMessage::UnknownSessionsRequest(_) => write!(f, "UnknownSessionsRequest"),
+			ServersSetChangeMessage::UnknownSessions(_) => write!(f, "UnknownSessions"),
+			ServersSetChangeMessage::ServersSetChangeCompleted(_) => write!(f, "ServersSetChangeCompleted"),
+			ServersSetChangeMessage::ServersSetChangeError(ref msg) => write!(f, "ServersSetChangeError({})", msg.error),
+		}
+	}
+}
+
+impl fmt::Display for KeyRemovalMessage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			KeyRemovalMessage::InitializeKeyRemovalSession(_) => write!(f, "InitializeKeyRemovalSession"),
+			KeyRemovalMessage::KeyShareRemovalStaged(_) => write!(f, "KeyShareRemovalStaged"),
+			KeyRemovalMessage::CommitKeyShareRemoval(_) => write!(f, "CommitKeyShareRemoval"),
+			KeyRemovalMessage::KeyShareRemovalCommitted(_) => write!(f, "KeyShareRemovalCommitted"),
+			KeyRemovalMessage::KeyRemovalSessionError(ref msg) => write!(f, "KeyRemovalSessionError({})", msg.error),
+		}
+	}
+}
+
+impl fmt::Display for SigningMessage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SigningMessage::InitializeSigningSession(_) => write!(f, "InitializeSigningSession"),
+			SigningMessage::ConfirmSigningInitialization(_) => write!(f, "ConfirmSigningInitialization"),
+			SigningMessage::RequestSigningNonce(_) => write!(f, "RequestSigningNonce"),
+			SigningMessage::SigningNonce(_) => write!(f, "SigningNonce"),
+			SigningMessage::RequestPartialSignature(_) => write!(f, "RequestPartialSignature"),
+			SigningMessage::PartialSignature(_) => write!(f, "PartialSignature"),
+			SigningMessage::SigningSessionError(ref msg) => write!(f, "SigningSessionError({})", msg.error),
+			SigningMessage::SigningSessionCompleted(_) => write!(f, "SigningSessionCompleted"),
+		}
+	}
+}
@@ -0,0 +1,271 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use parking_lot::Mutex;
+use key_server_cluster::NodeId;
+
+/// Messages, which have been received before the session they belong to has been created on this node.
+/// Sessions span multiple connections, so messages could be delivered out of order.
+/// Both number of sessions && total number of messages are limited, so that other nodes could not
+/// exhaust memory by sending messages for sessions, which are never created.
+pub struct SessionMessageQueue<K, M> {
+	/// Max number of sessions, which messages are buffered.
+	max_sessions: usize,
+	/// Max number of messages, buffered for single session.
+	max_messages: usize,
+	/// Max number of messages, buffered for all sessions.
+	max_total_messages: usize,
+	/// Max time message could stay in the queue.
+	timeout: Duration,
+	/// Queued messages.
+	queues: Mutex<Queues<K, M>>,
+	/// Number of messages, which have been dropped before session has been created.
+	dropped_messages: AtomicUsize,
+}
+
+/// Queued messages of all sessions.
+struct Queues<K, M> {
+	/// Queued messages of every session.
+	sessions: BTreeMap<K, VecDeque<QueuedMessage<M>>>,
+	/// Total number of queued messages.
+	messages_count: usize,
+}
+
+/// Single queued message.
+struct QueuedMessage<M> {
+	/// Message arrival time.
+	time: Instant,
+	/// Message sender.
+	sender: NodeId,
+	/// Message itself.
+	message: M,
+}
+
+impl<K, M> SessionMessageQueue<K, M> where K: Ord + Clone + Debug {
+	pub fn new(max_sessions: usize, max_messages: usize, max_total_messages: usize, timeout: Duration) -> Self {
+		SessionMessageQueue {
+			max_sessions: max_sessions,
+			max_messages: max_messages,
+			max_total_messages: max_total_messages,
+			timeout: timeout,
+			queues: Mutex::new(Queues {
+				sessions: BTreeMap::new(),
+				messages_count: 0,
+			}),
+			dropped_messages: AtomicUsize::new(0),
+		}
+	}
+
+	/// Buffer message for the session. When session queue is full, the oldest message of the session is dropped.
+	/// When there are too many sessions, all messages of the session with the oldest message are dropped.
+	/// When there are too many messages, the oldest message of all sessions is dropped.
+	pub fn enqueue(&self, key: K, sender: NodeId, message: M, now: Instant) {
+		let mut queues = self.queues.lock();
+		if !queues.sessions.contains_key(&key) && queues.sessions.len() >= self.max_sessions {
+			if let Some(oldest_key) = queues.oldest_session() {
+				let dropped_messages = queues.remove(&oldest_key).map(|queue| queue.len()).unwrap_or(0);
+				debug!(target: "secretstore_net", "dropping {} messages for session {:?}: too many sessions", dropped_messages, oldest_key);
+				self.dropped_messages.fetch_add(dropped_messages, Ordering::Relaxed);
+			}
+		}
+		if queues.sessions.get(&key).map(|queue| queue.len() >= self.max_messages).unwrap_or(false) {
+			queues.pop_front(&key);
+			self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+		} else if queues.messages_count >= self.max_total_messages {
+			if let Some(oldest_key) = queues.oldest_session() {
+				debug!(target: "secretstore_net", "dropping message for session {:?}: too many messages", oldest_key);
+				queues.pop_front(&oldest_key);
+				self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+
+		queues.messages_count += 1;
+		queues.sessions.entry(key).or_insert_with(VecDeque::new).push_back(QueuedMessage {
+			time: now,
+			sender: sender,
+			message: message,
+		});
+	}
+
+	/// Take all messages, buffered for the session, in arrival order.
+	pub fn take(&self, key: &K) -> VecDeque<(NodeId, M)> {
+		self.queues.lock().remove(key)
+			.map(|queue| queue.into_iter().map(|m| (m.sender, m.message)).collect())
+			.unwrap_or_default()
+	}
+
+	/// Drop messages, which are waiting for too long. Returns number of dropped messages.
+	pub fn expire(&self, now: Instant) -> usize {
+		let mut queues = self.queues.lock();
+		let mut expired_messages = 0;
+		let mut empty_queues = Vec::new();
+		for (key, queue) in queues.sessions.iter_mut() {
+			let queue_len = queue.len();
+			queue.retain(|m| m.time + self.timeout > now);
+			if queue.len() != queue_len {
+				debug!(target: "secretstore_net", "dropping {} expired messages for session {:?}", queue_len - queue.len(), key);
+				expired_messages += queue_len - queue.len();
+			}
+			if queue.is_empty() {
+				empty_queues.push(key.clone());
+			}
+		}
+		for key in empty_queues {
+			queues.sessions.remove(&key);
+		}
+
+		queues.messages_count -= expired_messages;
+		self.dropped_messages.fetch_add(expired_messages, Ordering::Relaxed);
+		expired_messages
+	}
+
+	/// Number of messages, which have been dropped either because of queue overflow or expiration.
+	pub fn dropped_messages(&self) -> usize {
+		self.dropped_messages.load(Ordering::Relaxed)
+	}
+}
+
+impl<K, M> Queues<K, M> where K: Ord + Clone {
+	/// Get session, which has the oldest queued message.
+	fn oldest_session(&self) -> Option<K> {
+		self.sessions.iter()
+			.filter_map(|(key, queue)| queue.front().map(|m| (key, m.time)))
+			.min_by_key(|&(_, time)| time)
+			.map(|(key, _)| key.clone())
+	}
+
+	/// Remove all messages of the session.
+	fn remove(&mut self, key: &K) -> Option<VecDeque<QueuedMessage<M>>> {
+		let queue = self.sessions.remove(key);
+		if let Some(ref queue) = queue {
+			self.messages_count -= queue.len();
+		}
+		queue
+	}
+
+	/// Remove the oldest message of the session.
+	fn pop_front(&mut self, key: &K) {
+		let is_empty = match self.sessions.get_mut(key) {
+			Some(queue) => {
+				if queue.pop_front().is_some() {
+					self.messages_count -= 1;
+				}
+				queue.is_empty()
+			},
+			None => false,
+		};
+		if is_empty {
+			self.sessions.remove(key);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+	use ethkey::{Random, Generator};
+	use super::SessionMessageQueue;
+
+	#[test]
+	fn buffered_messages_are_replayed_in_arrival_order() {
+		let node = Random.generate().unwrap().public().clone();
+		let queue = SessionMessageQueue::new(10, 10, 100, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, node.clone(), "first", now);
+		queue.enqueue(2, node.clone(), "other", now);
+		queue.enqueue(1, node.clone(), "second", now);
+
+		assert_eq!(queue.take(&1).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec!["first", "second"]);
+		assert!(queue.take(&1).is_empty());
+		assert_eq!(queue.take(&2).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec!["other"]);
+		assert_eq!(queue.dropped_messages(), 0);
+	}
+
+	#[test]
+	fn queue_overflow_drops_oldest_message() {
+		let node = Random.generate().unwrap().public().clone();
+		let queue = SessionMessageQueue::new(10, 2, 100, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, node.clone(), 1, now);
+		queue.enqueue(1, node.clone(), 2, now);
+		queue.enqueue(1, node.clone(), 3, now);
+
+		assert_eq!(queue.take(&1).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec![2, 3]);
+		assert_eq!(queue.dropped_messages(), 1);
+	}
+
+	#[test]
+	fn messages_expire_after_timeout() {
+		let node = Random.generate().unwrap().public().clone();
+		let queue = SessionMessageQueue::new(10, 10, 100, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, node.clone(), 1, now);
+		queue.enqueue(1, node.clone(), 2, now + Duration::from_secs(5));
+
+		assert_eq!(queue.expire(now + Duration::from_secs(9)), 0);
+		assert_eq!(queue.expire(now + Duration::from_secs(10)), 1);
+		assert_eq!(queue.dropped_messages(), 1);
+		assert_eq!(queue.take(&1).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec![2]);
+
+		queue.enqueue(1, node.clone(), 3, now);
+		assert_eq!(queue.expire(now + Duration::from_secs(60)), 1);
+		assert!(queue.take(&1).is_empty());
+		assert_eq!(queue.dropped_messages(), 2);
+	}
+
+	#[test]
+	fn too_many_sessions_drop_session_with_oldest_message() {
+		let node = Random.generate().unwrap().public().clone();
+		let queue = SessionMessageQueue::new(2, 10, 100, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, node.clone(), 1, now + Duration::from_secs(1));
+		queue.enqueue(2, node.clone(), 2, now);
+		queue.enqueue(1, node.clone(), 3, now + Duration::from_secs(2));
+		queue.enqueue(3, node.clone(), 4, now + Duration::from_secs(3));
+
+		assert!(queue.take(&2).is_empty());
+		assert_eq!(queue.dropped_messages(), 1);
+		assert_eq!(queue.take(&1).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec![1, 3]);
+		assert_eq!(queue.take(&3).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec![4]);
+	}
+
+	#[test]
+	fn too_many_messages_drop_oldest_message() {
+		let node = Random.generate().unwrap().public().clone();
+		let queue = SessionMessageQueue::new(10, 10, 3, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, node.clone(), 1, now + Duration::from_secs(1));
+		queue.enqueue(2, node.clone(), 2, now);
+		queue.enqueue(1, node.clone(), 3, now + Duration::from_secs(2));
+		queue.enqueue(3, node.clone(), 4, now + Duration::from_secs(3));
+		queue.enqueue(3, node.clone(), 5, now + Duration::from_secs(4));
+
+		assert_eq!(queue.dropped_messages(), 2);
+		assert!(queue.take(&2).is_empty());
+		assert_eq!(queue.take(&1).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec![3]);
+		assert_eq!(queue.take(&3).into_iter().map(|(_, m)| m).collect::<Vec<_>>(), vec![4, 5]);
+
+		// taken messages are not counted anymore
+		for i in 0..3 {
+			queue.enqueue(4, node.clone(), i, now);
+		}
+		assert_eq!(queue.dropped_messages(), 2);
+	}
+}
@@ -0,0 +1,405 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::Write;
+use std::time;
+use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use parking_lot::Mutex;
+use rustc_serialize::hex::ToHex;
+use key_server_cluster::NodeId;
+use key_server_cluster::cluster::ClusterSessionsListener;
+use key_server_cluster::generation_session::SessionImpl as GenerationSessionImpl;
+use key_server_cluster::encryption_session::SessionImpl as EncryptionSessionImpl;
+use key_server_cluster::decryption_session::SessionImpl as DecryptionSessionImpl;
+use key_server_cluster::key_removal_session::SessionImpl as KeyRemovalSessionImpl;
+use key_server_cluster::signing_session::SessionImpl as SigningSessionImpl;
+
+/// Upper bounds (in seconds) of session duration histogram buckets.
+const SESSION_DURATION_BUCKETS: [f64; 9] = [0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0];
+/// Number of NodeId hex characters, used in per-node labels.
+const NODE_LABEL_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Type of session, which is tracked by metrics.
+pub enum SessionType {
+	/// Generation session.
+	Generation,
+	/// Encryption session.
+	Encryption,
+	/// Decryption session.
+	Decryption,
+	/// Key removal session.
+	KeyRemoval,
+	/// Share add session.
+	ShareAdd,
+	/// Servers set change session.
+	ServersSetChange,
+	/// Signing session.
+	Signing,
+}
+
+/// Cluster metrics registry. Sessions are tracked using sessions containers listeners.
+pub struct ClusterMetrics {
+	/// Metrics data.
+	data: Mutex<ClusterMetricsData>,
+}
+
+/// Cluster metrics data.
+struct ClusterMetricsData {
+	/// Metrics of every session type.
+	sessions: BTreeMap<SessionType, SessionsMetrics>,
+	/// Activation time of every active session. Session is identified by its address, which is unique while it is in the container.
+	active_sessions: HashMap<usize, time::Instant>,
+	/// Number of session messages, rejected because of every node.
+	rejects: BTreeMap<NodeId, u64>,
+}
+
+#[derive(Default)]
+/// Metrics of single session type.
+struct SessionsMetrics {
+	/// Number of started sessions.
+	started: u64,
+	/// Number of successfully completed sessions.
+	completed: u64,
+	/// Number of failed (or cancelled) sessions.
+	failed: u64,
+	/// Durations of removed sessions. Time, spent in the sessions queue, is not included.
+	duration: Histogram,
+}
+
+/// Histogram of observed values.
+struct Histogram {
+	/// Number of observations in every bucket (not cumulative). The last bucket is +Inf.
+	counts: Vec<u64>,
+	/// Sum of observed values.
+	sum: f64,
+}
+
+impl ClusterMetrics {
+	pub fn new() -> Self {
+		ClusterMetrics {
+			data: Mutex::new(ClusterMetricsData {
+				sessions: [SessionType::Generation, SessionType::Encryption, SessionType::Decryption, SessionType::KeyRemoval,
+					SessionType::Signing].iter()
+					.map(|session_type| (*session_type, SessionsMetrics::default()))
+					.collect(),
+				active_sessions: HashMap::new(),
+				rejects: BTreeMap::new(),
+			}),
+		}
+	}
+
+	/// Render metrics in Prometheus text format. Cluster state is passed by the caller.
+	pub fn render(&self, peers: &BTreeMap<NodeId, bool>, excluded_peers: &BTreeSet<NodeId>, queued_sessions: &BTreeMap<SessionType, usize>) -> String {
+		let data = self.data.lock();
+		let mut out = String::new();
+
+		write_counter(&mut out, "secretstore_sessions_started_total", "Number of sessions, started on this node.",
+			data.sessions.iter().map(|(session_type, metrics)| (*session_type, metrics.started)));
+		write_counter(&mut out, "secretstore_sessions_completed_total", "Number of sessions, successfully completed on this node.",
+			data.sessions.iter().map(|(session_type, metrics)| (*session_type, metrics.completed)));
+		write_counter(&mut out, "secretstore_sessions_failed_total", "Number of sessions, failed or cancelled on this node.",
+			data.sessions.iter().map(|(session_type, metrics)| (*session_type, metrics.failed)));
+
+		let _ = writeln!(out, "# HELP secretstore_session_duration_seconds Duration of sessions, removed from this node.");
+		let _ = writeln!(out, "# TYPE secretstore_session_duration_seconds histogram");
+		for (session_type, metrics) in data.sessions.iter() {
+			metrics.duration.write(&mut out, "secretstore_session_duration_seconds", session_type.label());
+		}
+
+		let _ = writeln!(out, "# HELP secretstore_active_sessions Number of sessions, active on this node.");
+		let _ = writeln!(out, "# TYPE secretstore_active_sessions gauge");
+		let _ = writeln!(out, "secretstore_active_sessions {}", data.active_sessions.len());
+
+		let _ = writeln!(out, "# HELP secretstore_queued_sessions Number of sessions, waiting for their turn to start.");
+		let _ = writeln!(out, "# TYPE secretstore_queued_sessions gauge");
+		for (session_type, queued) in queued_sessions {
+			let _ = writeln!(out, "secretstore_queued_sessions{{type=\"{}\"}} {}", session_type.label(), queued);
+		}
+
+		let _ = writeln!(out, "# HELP secretstore_connected_peers Number of key servers, connected to this node.");
+		let _ = writeln!(out, "# TYPE secretstore_connected_peers gauge");
+		let _ = writeln!(out, "secretstore_connected_peers {}", peers.values().filter(|is_connected| **is_connected).count());
+
+		let _ = writeln!(out, "# HELP secretstore_peer_connected Is connection to the key server established.");
+		let _ = writeln!(out, "# TYPE secretstore_peer_connected gauge");
+		for (node, is_connected) in peers {
+			let _ = writeln!(out, "secretstore_peer_connected{{node=\"{}\"}} {}", node_label(node), if *is_connected { 1 } else { 0 });
+		}
+
+		let _ = writeln!(out, "# HELP secretstore_peer_excluded Is the key server excluded from new sessions after repeated protocol violations.");
+		let _ = writeln!(out, "# TYPE secretstore_peer_excluded gauge");
+		for node in peers.keys() {
+			let _ = writeln!(out, "secretstore_peer_excluded{{node=\"{}\"}} {}", node_label(node), if excluded_peers.contains(node) { 1 } else { 0 });
+		}
+
+		let _ = writeln!(out, "# HELP secretstore_peer_rejects_total Number of session messages, rejected because of the key server.");
+		let _ = writeln!(out, "# TYPE secretstore_peer_rejects_total counter");
+		for (node, rejects) in data.rejects.iter() {
+			let _ = writeln!(out, "secretstore_peer_rejects_total{{node=\"{}\"}} {}", node_label(node), rejects);
+		}
+
+		out
+	}
+
+	/// When session message is rejected because of given node.
+	pub fn on_message_rejected(&self, node: &NodeId) {
+		*self.data.lock().rejects.entry(node.clone()).or_insert(0) += 1;
+	}
+
+	/// When session is inserted into the container.
+	fn on_session_started(&self, session_type: SessionType) {
+		self.data.lock().sessions.get_mut(&session_type).expect("metrics are created for every session type; qed").started += 1;
+	}
+
+	/// When session leaves the sessions queue (or is started by other node). Queued sessions are not active.
+	fn start_session_timer<S>(&self, session: &Arc<S>) {
+		self.data.lock().active_sessions.insert(session_key(session), time::Instant::now());
+	}
+
+	/// When session is removed from the container.
+	fn on_session_finished<S>(&self, session_type: SessionType, session: &Arc<S>, is_completed: bool) {
+		let mut data = self.data.lock();
+		let start_time = data.active_sessions.remove(&session_key(session));
+		let metrics = data.sessions.get_mut(&session_type).expect("metrics are created for every session type; qed");
+		if is_completed {
+			metrics.completed += 1;
+		} else {
+			metrics.failed += 1;
+		}
+		if let Some(start_time) = start_time {
+			metrics.duration.observe(duration_to_seconds(start_time.elapsed()));
+		}
+	}
+}
+
+impl ClusterSessionsListener<GenerationSessionImpl> for ClusterMetrics {
+	fn on_session_inserted(&self, _session: Arc<GenerationSessionImpl>) {
+		self.on_session_started(SessionType::Generation);
+	}
+
+	fn on_session_activated(&self, session: Arc<GenerationSessionImpl>) {
+		self.start_session_timer(&session);
+	}
+
+	fn on_session_removed(&self, session: Arc<GenerationSessionImpl>) {
+		let is_completed = session.result().map(|result| result.is_ok()).unwrap_or(false);
+		self.on_session_finished(SessionType::Generation, &session, is_completed);
+	}
+}
+
+impl ClusterSessionsListener<EncryptionSessionImpl> for ClusterMetrics {
+	fn on_session_inserted(&self, _session: Arc<EncryptionSessionImpl>) {
+		self.on_session_started(SessionType::Encryption);
+	}
+
+	fn on_session_activated(&self, session: Arc<EncryptionSessionImpl>) {
+		self.start_session_timer(&session);
+	}
+
+	fn on_session_removed(&self, session: Arc<EncryptionSessionImpl>) {
+		let is_completed = session.result().map(|result| result.is_ok()).unwrap_or(false);
+		self.on_session_finished(SessionType::Encryption, &session, is_completed);
+	}
+}
+
+impl ClusterSessionsListener<DecryptionSessionImpl> for ClusterMetrics {
+	fn on_session_inserted(&self, _session: Arc<DecryptionSessionImpl>) {
+		self.on_session_started(SessionType::Decryption);
+	}
+
+	fn on_session_activated(&self, session: Arc<DecryptionSessionImpl>) {
+		self.start_session_timer(&session);
+	}
+
+	fn on_session_removed(&self, session: Arc<DecryptionSessionImpl>) {
+		let is_completed = session.result().map(|result| result.is_ok()).unwrap_or(false);
+		self.on_session_finished(SessionType::Decryption, &session, is_completed);
+	}
+}
+
+impl ClusterSessionsListener<KeyRemovalSessionImpl> for ClusterMetrics {
+	fn on_session_inserted(&self, _session: Arc<KeyRemovalSessionImpl>) {
+		self.on_session_started(SessionType::KeyRemoval);
+	}
+
+	fn on_session_activated(&self, session: Arc<KeyRemovalSessionImpl>) {
+		self.start_session_timer(&session);
+	}
+
+	fn on_session_removed(&self, session: Arc<KeyRemovalSessionImpl>) {
+		let is_completed = session.result().map(|result| result.is_ok()).unwrap_or(false);
+		self.on_session_finished(SessionType::KeyRemoval, &session, is_completed);
+	}
+}
+
+impl ClusterSessionsListener<SigningSessionImpl> for ClusterMetrics {
+	fn on_session_inserted(&self, _session: Arc<SigningSessionImpl>) {
+		self.on_session_started(SessionType::Signing);
+	}
+
+	fn on_session_activated(&self, session: Arc<SigningSessionImpl>) {
+		self.start_session_timer(&session);
+	}
+
+	fn on_session_removed(&self, session: Arc<SigningSessionImpl>) {
+		let is_completed = session.result().map(|result| result.is_ok()).unwrap_or(false);
+		self.on_session_finished(SessionType::Signing, &session, is_completed);
+	}
+}
+
+impl SessionType {
+	/// Value of metric label.
+	pub fn label(&self) -> &'static str {
+		match *self {
+			SessionType::Generation => "generation",
+			SessionType::Encryption => "encryption",
+			SessionType::Decryption => "decryption",
+			SessionType::KeyRemoval => "key_removal",
+			SessionType::ShareAdd => "share_add",
+			SessionType::ServersSetChange => "servers_set_change",
+			SessionType::Signing => "signing",
+		}
+	}
+}
+
+impl Default for Histogram {
+	fn default() -> Self {
+		Histogram {
+			counts: vec![0; SESSION_DURATION_BUCKETS.len() + 1],
+			sum: 0f64,
+		}
+	}
+}
+
+impl Histogram {
+	/// Record single observation.
+	fn observe(&mut self, value: f64) {
+		let bucket = SESSION_DURATION_BUCKETS.iter()
+			.position(|bound| value <= *bound)
+			.unwrap_or(SESSION_DURATION_BUCKETS.len());
+		self.counts[bucket] += 1;
+		self.sum += value;
+	}
+
+	/// Write histogram samples. Buckets are cumulative, as required by the format.
+	fn write(&self, out: &mut String, name: &str, type_label: &str) {
+		let mut cumulative_count = 0;
+		for (index, count) in self.counts.iter().enumerate() {
+			cumulative_count += *count;
+			let bound = SESSION_DURATION_BUCKETS.get(index).map(|bound| bound.to_string()).unwrap_or_else(|| "+Inf".into());
+			let _ = writeln!(out, "{}_bucket{{type=\"{}\",le=\"{}\"}} {}", name, type_label, bound, cumulative_count);
+		}
+		let _ = writeln!(out, "{}_sum{{type=\"{}\"}} {}", name, type_label, self.sum);
+		let _ = writeln!(out, "{}_count{{type=\"{}\"}} {}", name, type_label, cumulative_count);
+	}
+}
+
+/// Write counter with value for every session type.
+fn write_counter<I>(out: &mut String, name: &str, help: &str, values: I) where I: Iterator<Item=(SessionType, u64)> {
+	let _ = writeln!(out, "# HELP {} {}", name, help);
+	let _ = writeln!(out, "# TYPE {} counter", name);
+	for (session_type, value) in values {
+		let _ = writeln!(out, "{}{{type=\"{}\"}} {}", name, session_type.label(), value);
+	}
+}
+
+/// Node label is a hex prefix of NodeId => cardinality is bounded.
+pub fn node_label(node: &NodeId) -> String {
+	node.to_hex()[..NODE_LABEL_LEN].into()
+}
+
+/// Key of the session in active sessions map.
+fn session_key<S>(session: &Arc<S>) -> usize {
+	&**session as *const S as usize
+}
+
+fn duration_to_seconds(duration: time::Duration) -> f64 {
+	duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000f64
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::collections::{BTreeMap, BTreeSet};
+	use ethkey::{Random, Generator};
+	use super::{Histogram, ClusterMetrics, SessionType, node_label};
+
+	#[test]
+	fn histogram_buckets_are_cumulative() {
+		let mut histogram = Histogram::default();
+		histogram.observe(0.0078125);
+		histogram.observe(0.25);
+		histogram.observe(0.5);
+		histogram.observe(1024.0);
+
+		let mut out = String::new();
+		histogram.write(&mut out, "duration", "generation");
+		let lines: Vec<_> = out.lines().collect();
+		assert_eq!(lines[0], "duration_bucket{type=\"generation\",le=\"0.01\"} 1");
+		assert_eq!(lines[3], "duration_bucket{type=\"generation\",le=\"0.5\"} 3");
+		assert_eq!(lines[8], "duration_bucket{type=\"generation\",le=\"60\"} 3");
+		assert_eq!(lines[9], "duration_bucket{type=\"generation\",le=\"+Inf\"} 4");
+		assert_eq!(lines[10], "duration_sum{type=\"generation\"} 1024.7578125");
+		assert_eq!(lines[11], "duration_count{type=\"generation\"} 4");
+	}
+
+	#[test]
+	fn peers_are_labeled_with_node_id_prefix() {
+		let node = Random.generate().unwrap().public().clone();
+		let peers: BTreeMap<_, _> = vec![(node.clone(), true)].into_iter().collect();
+		let queued_sessions: BTreeMap<_, _> = vec![(SessionType::Decryption, 3)].into_iter().collect();
+		let excluded_peers: BTreeSet<_> = vec![node.clone()].into_iter().collect();
+		let out = ClusterMetrics::new().render(&peers, &excluded_peers, &queued_sessions);
+
+		assert_eq!(node_label(&node).len(), 8);
+		assert!(out.contains(&format!("secretstore_peer_connected{{node=\"{}\"}} 1", node_label(&node))));
+		assert!(out.contains(&format!("secretstore_peer_excluded{{node=\"{}\"}} 1", node_label(&node))));
+		assert!(out.contains("secretstore_connected_peers 1"));
+		assert!(out.contains("secretstore_queued_sessions{type=\"decryption\"} 3"));
+		assert!(out.contains("secretstore_sessions_started_total{type=\"key_removal\"} 0"));
+	}
+
+	#[test]
+	fn rejects_are_counted_per_node() {
+		let node = Random.generate().unwrap().public().clone();
+		let metrics = ClusterMetrics::new();
+		metrics.on_message_rejected(&node);
+		metrics.on_message_rejected(&node);
+		let out = metrics.render(&BTreeMap::new(), &BTreeSet::new(), &BTreeMap::new());
+
+		assert!(out.contains(&format!("secretstore_peer_rejects_total{{node=\"{}\"}} 2\n", node_label(&node))));
+	}
+
+	#[test]
+	fn queued_sessions_are_not_active() {
+		let session = Arc::new(0u8);
+		let metrics = ClusterMetrics::new();
+		metrics.on_session_started(SessionType::Decryption);
+		let out = metrics.render(&BTreeMap::new(), &BTreeSet::new(), &BTreeMap::new());
+		assert!(out.contains("secretstore_sessions_started_total{type=\"decryption\"} 1\n"));
+		assert!(out.contains("secretstore_active_sessions 0\n"));
+
+		metrics.start_session_timer(&session);
+		assert!(metrics.render(&BTreeMap::new(), &BTreeSet::new(), &BTreeMap::new()).contains("secretstore_active_sessions 1\n"));
+
+		metrics.on_session_finished(SessionType::Decryption, &session, true);
+		let out = metrics.render(&BTreeMap::new(), &BTreeSet::new(), &BTreeMap::new());
+		assert!(out.contains("secretstore_active_sessions 0\n"));
+		assert!(out.contains("secretstore_session_duration_seconds_count{type=\"decryption\"} 1\n"));
+	}
+}
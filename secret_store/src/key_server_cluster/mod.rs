@@ -20,13 +20,24 @@ use ethkey;
 use ethcrypto;
 use super::types::all::DocumentAddress;
 
-pub use super::types::all::{NodeId, DocumentEncryptedKeyShadow};
+pub use super::types::all::{NodeId, DocumentEncryptedKeyShadow, Requester};
 pub use super::acl_storage::AclStorage;
-pub use super::key_storage::{KeyStorage, DocumentKeyShare};
-pub use super::serialization::{SerializableSignature, SerializableH256, SerializableSecret, SerializablePublic};
-pub use self::cluster::{ClusterCore, ClusterConfiguration, ClusterClient};
-pub use self::encryption_session::Session as EncryptionSession;
+pub use super::key_server_set::{KeyServerSet, KeyServerSetSnapshot, KeyServerSetMigration};
+pub use super::key_storage::{KeyStorage, TransactionalKeyStorage, DocumentKeyShare, DocumentKeyShareVersion, KeyRemovalRetry};
+pub use super::serialization::{SerializableSignature, SerializableH256, SerializableSecret, SerializablePublic, SerializableRequester};
+pub use self::cluster::{ClusterCore, ClusterConfiguration, ClusterClient, ClusterSessionsListener, SessionsTimeouts, MAINTAIN_INTERVAL,
+	COMPLETED_SESSIONS_RETENTION_INTERVAL};
+pub use self::session_result::SessionResultFuture;
+pub use self::node_reputation::NodeReputationParams;
+pub use self::generation_session::Session as GenerationSession;
 pub use self::decryption_session::Session as DecryptionSession;
+pub use self::encryption_session::{Session as EncryptionSession, store_request_hash};
+pub use self::share_add_session::Session as ShareAddSession;
+pub use self::servers_set_change_session::Session as ServersSetChangeSession;
+pub use self::key_removal_session::{Session as KeyRemovalSession, removal_request_hash};
+pub use self::signing_session::Session as SigningSession;
+#[cfg(test)]
+pub use self::math::verify_schnorr_signature;
 
 #[cfg(test)]
 pub use super::key_storage::tests::DummyKeyStorage;
@@ -46,15 +57,24 @@ pub enum Error {
 	DuplicateSessionId,
 	/// Session with the given id is unknown.
 	InvalidSessionId,
+	/// Session with the given id has been completed recently.
+	SessionAlreadyCompleted,
+	/// Session with the given id has been started concurrently by another node && this node has yielded.
+	SessionAlreadyStarted,
 	/// Invalid number of nodes.
-	/// There must be at least two nodes participating in encryption.
+	/// There must be at least two nodes participating in key generation.
 	/// There must be at least one node participating in decryption.
 	InvalidNodesCount,
 	/// Node which is required to start encryption/decryption session is not a part of cluster.
 	InvalidNodesConfiguration,
 	/// Invalid threshold value has been passed.
-	/// Threshold value must be in [0; n - 1], where n is a number of nodes participating in the encryption.
-	InvalidThreshold,
+	/// Threshold value must be in [0; n - 1], where n is a number of nodes participating in the key generation.
+	InvalidThreshold {
+		/// Requested (or stored) threshold.
+		requested: usize,
+		/// Number of nodes, available to the session.
+		nodes: usize,
+	},
 	/// Current state of encryption/decryption session does not allow to proceed request.
 	/// Reschedule this request for later processing.
 	TooEarlyForRequest,
@@ -64,6 +84,11 @@ pub enum Error {
 	/// Message or some data in the message was recognized as invalid.
 	/// This means that node is misbehaving/cheating.
 	InvalidMessage,
+	/// Key share, received from given node, has failed verification.
+	/// This means that node is misbehaving/cheating.
+	InvalidKeyShare(NodeId),
+	/// Message header has unsupported version.
+	InvalidMessageVersion,
 	/// Connection to node, required for this session is not established.
 	NodeDisconnected,
 	/// Cryptographic error.
@@ -76,6 +101,16 @@ pub enum Error {
 	KeyStorage(String),
 	/// Acl storage error.
 	AccessDenied,
+	/// Too many sessions are started by this node.
+	TooManySessions,
+	/// Server key with given id is not found in the key storage.
+	ServerKeyIsNotFound,
+	/// Document key with given id is not found in the key storage.
+	/// This means that server key has been generated, but document key has not been stored yet.
+	DocumentKeyIsNotFound,
+	/// Requester data is not enough to proceed with request.
+	/// E.g. requester public key is required, but only its address is known.
+	InsufficientRequesterData(String),
 }
 
 impl From<ethkey::Error> for Error {
@@ -103,18 +138,26 @@ impl fmt::Display for Error {
 			Error::InvalidNodeId => write!(f, "invalid node id has been passed"),
 			Error::DuplicateSessionId => write!(f, "session with the same id is already registered"),
 			Error::InvalidSessionId => write!(f, "invalid session id has been passed"),
+			Error::SessionAlreadyCompleted => write!(f, "session with this id is already completed"),
+			Error::SessionAlreadyStarted => write!(f, "session with this id is already started by another node"),
 			Error::InvalidNodesCount => write!(f, "invalid nodes count"),
 			Error::InvalidNodesConfiguration => write!(f, "invalid nodes configuration"),
-			Error::InvalidThreshold => write!(f, "invalid threshold value has been passed"),
+			Error::InvalidThreshold { requested, nodes } => write!(f, "invalid threshold {}: at least {} nodes are required, but only {} are available", requested, requested.saturating_add(1), nodes),
 			Error::TooEarlyForRequest => write!(f, "session is not yet ready to process this request"),
 			Error::InvalidStateForRequest => write!(f, "session is in invalid state for processing this request"),
 			Error::InvalidMessage => write!(f, "invalid message is received"),
+			Error::InvalidKeyShare(ref node) => write!(f, "invalid key share is received from node {}", node),
+			Error::InvalidMessageVersion => write!(f, "unsupported message version"),
 			Error::NodeDisconnected => write!(f, "node required for this operation is currently disconnected"),
 			Error::EthKey(ref e) => write!(f, "cryptographic error {}", e),
 			Error::Io(ref e) => write!(f, "i/o error {}", e),
 			Error::Serde(ref e) => write!(f, "serde error {}", e),
 			Error::KeyStorage(ref e) => write!(f, "key storage error {}", e),
 			Error::AccessDenied => write!(f, "Access denied"),
+			Error::TooManySessions => write!(f, "too many sessions are running on this node"),
+			Error::ServerKeyIsNotFound => write!(f, "server key with this id is not found"),
+			Error::DocumentKeyIsNotFound => write!(f, "document key with this id is not found"),
+			Error::InsufficientRequesterData(ref e) => write!(f, "insufficient requester data: {}", e),
 		}
 	}
 }
@@ -125,10 +168,25 @@ impl Into<String> for Error {
 	}
 }
 
+mod backoff;
 mod cluster;
+mod completed_sessions;
+mod connection_manager;
+mod connection_trigger;
 mod decryption_session;
 mod encryption_session;
+mod generation_session;
 mod io;
+mod key_removal_session;
 mod math;
 mod message;
+mod message_queue;
+mod metrics;
 mod net;
+mod node_reputation;
+mod servers_set_change_session;
+mod session_result;
+mod session_trace;
+mod sessions_queue;
+mod share_add_session;
+mod signing_session;
@@ -0,0 +1,217 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, BTreeSet};
+use parking_lot::Mutex;
+use key_server_cluster::NodeId;
+
+/// Number of protocol violations, after which node is excluded from new sessions.
+const DEFAULT_EXCLUSION_THRESHOLD: u32 = 5;
+/// Single protocol violation is forgotten after this interval (in seconds).
+const DEFAULT_DECAY_INTERVAL: u64 = 60;
+/// Node is excluded from new sessions for this interval (in seconds).
+const DEFAULT_EXCLUSION_INTERVAL: u64 = 600;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Node reputation parameters.
+pub struct NodeReputationParams {
+	/// Number of (not yet forgotten) protocol violations, after which node is excluded from new sessions.
+	pub exclusion_threshold: u32,
+	/// Single protocol violation is forgotten after this interval.
+	pub decay_interval: Duration,
+	/// Node is excluded from new sessions for this interval.
+	pub exclusion_interval: Duration,
+}
+
+/// Tracks protocol violations of every other key server && decides when node must be excluded from new sessions.
+/// Excluded node is still connected && could participate in sessions, started by other nodes.
+pub struct NodeReputation {
+	/// Self node id.
+	self_node_id: NodeId,
+	/// Reputation parameters.
+	params: NodeReputationParams,
+	/// Nodes data.
+	nodes: Mutex<BTreeMap<NodeId, NodeScore>>,
+}
+
+/// Single node data.
+struct NodeScore {
+	/// Number of not yet forgotten protocol violations.
+	violations: u32,
+	/// Time of last violations update.
+	updated: Instant,
+	/// Node is excluded from new sessions until this time.
+	excluded_until: Option<Instant>,
+}
+
+impl Default for NodeReputationParams {
+	fn default() -> Self {
+		NodeReputationParams {
+			exclusion_threshold: DEFAULT_EXCLUSION_THRESHOLD,
+			decay_interval: Duration::from_secs(DEFAULT_DECAY_INTERVAL),
+			exclusion_interval: Duration::from_secs(DEFAULT_EXCLUSION_INTERVAL),
+		}
+	}
+}
+
+impl NodeReputation {
+	pub fn new(self_node_id: NodeId, params: NodeReputationParams) -> Self {
+		NodeReputation {
+			self_node_id: self_node_id,
+			params: params,
+			nodes: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	/// Called when node has violated protocol. Returns true if node is excluded because of this violation.
+	pub fn on_protocol_violation(&self, node: &NodeId, now: Instant) -> bool {
+		let mut nodes = self.nodes.lock();
+		let score = nodes.entry(node.clone()).or_insert_with(|| NodeScore {
+			violations: 0,
+			updated: now,
+			excluded_until: None,
+		});
+
+		score.decay(&self.params, now);
+		score.violations = score.violations.saturating_add(1);
+		if score.violations < self.params.exclusion_threshold || score.is_excluded(now) {
+			return false;
+		}
+
+		warn!(target: "secretstore_net", "{}: node {} has violated protocol {} times. Excluding it from new sessions for {:?}",
+			self.self_node_id, node, score.violations, self.params.exclusion_interval);
+		score.excluded_until = Some(now + self.params.exclusion_interval);
+		true
+	}
+
+	#[cfg(test)]
+	/// Is node excluded from new sessions?
+	pub fn is_excluded(&self, node: &NodeId, now: Instant) -> bool {
+		self.nodes.lock().get(node).map(|score| score.is_excluded(now)).unwrap_or(false)
+	}
+
+	/// Get all nodes, excluded from new sessions.
+	pub fn excluded_nodes(&self, now: Instant) -> BTreeSet<NodeId> {
+		self.nodes.lock().iter()
+			.filter(|&(_, score)| score.is_excluded(now))
+			.map(|(node, _)| node.clone())
+			.collect()
+	}
+
+	/// Forget decayed violations && expired exclusions.
+	pub fn maintain(&self, now: Instant) {
+		let mut nodes = self.nodes.lock();
+		let mut forgotten_nodes = Vec::new();
+		for (node, score) in nodes.iter_mut() {
+			score.decay(&self.params, now);
+			if score.violations == 0 && !score.is_excluded(now) {
+				forgotten_nodes.push(node.clone());
+			}
+		}
+		for node in forgotten_nodes {
+			nodes.remove(&node);
+		}
+	}
+}
+
+impl NodeScore {
+	/// Is node excluded from new sessions?
+	fn is_excluded(&self, now: Instant) -> bool {
+		self.excluded_until.map(|excluded_until| now < excluded_until).unwrap_or(false)
+	}
+
+	/// Forget violations, which have happened more than decay_interval ago.
+	fn decay(&mut self, params: &NodeReputationParams, now: Instant) {
+		while self.violations != 0 && self.updated + params.decay_interval <= now {
+			self.violations -= 1;
+			self.updated = self.updated + params.decay_interval;
+		}
+		if self.violations == 0 {
+			self.updated = now;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+	use ethkey::{Random, Generator};
+	use super::{NodeReputation, NodeReputationParams};
+
+	fn make_reputation() -> NodeReputation {
+		NodeReputation::new(Random.generate().unwrap().public().clone(), NodeReputationParams {
+			exclusion_threshold: 3,
+			decay_interval: Duration::from_secs(10),
+			exclusion_interval: Duration::from_secs(100),
+		})
+	}
+
+	#[test]
+	fn node_is_excluded_after_repeated_violations() {
+		let reputation = make_reputation();
+		let node = Random.generate().unwrap().public().clone();
+		let now = Instant::now();
+
+		assert!(!reputation.on_protocol_violation(&node, now));
+		assert!(!reputation.on_protocol_violation(&node, now));
+		assert!(!reputation.is_excluded(&node, now));
+		assert!(reputation.on_protocol_violation(&node, now));
+		assert!(reputation.is_excluded(&node, now));
+		assert_eq!(reputation.excluded_nodes(now), vec![node.clone()].into_iter().collect());
+
+		// further violations do not prolong exclusion
+		assert!(!reputation.on_protocol_violation(&node, now + Duration::from_secs(50)));
+		assert!(reputation.is_excluded(&node, now + Duration::from_secs(99)));
+		assert!(!reputation.is_excluded(&node, now + Duration::from_secs(100)));
+	}
+
+	#[test]
+	fn violations_are_forgotten_over_time() {
+		let reputation = make_reputation();
+		let node = Random.generate().unwrap().public().clone();
+		let now = Instant::now();
+
+		// two violations are forgotten after 20 seconds
+		assert!(!reputation.on_protocol_violation(&node, now));
+		assert!(!reputation.on_protocol_violation(&node, now));
+		assert!(!reputation.on_protocol_violation(&node, now + Duration::from_secs(20)));
+		assert!(!reputation.on_protocol_violation(&node, now + Duration::from_secs(20)));
+		assert!(!reputation.is_excluded(&node, now + Duration::from_secs(20)));
+
+		// but not after 10 seconds
+		assert!(reputation.on_protocol_violation(&node, now + Duration::from_secs(25)));
+	}
+
+	#[test]
+	fn forgotten_nodes_are_removed_on_maintain() {
+		let reputation = make_reputation();
+		let node = Random.generate().unwrap().public().clone();
+		let now = Instant::now();
+		for _ in 0..3 {
+			reputation.on_protocol_violation(&node, now);
+		}
+
+		// excluded node is kept
+		reputation.maintain(now + Duration::from_secs(50));
+		assert_eq!(reputation.nodes.lock().len(), 1);
+
+		// node is forgotten when exclusion expires && violations are decayed
+		reputation.maintain(now + Duration::from_secs(100));
+		assert!(reputation.nodes.lock().is_empty());
+		assert!(!reputation.is_excluded(&node, now + Duration::from_secs(100)));
+	}
+}
@@ -0,0 +1,1030 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeSet, BTreeMap, VecDeque};
+use std::mem;
+use std::fmt::{Debug, Formatter, Error as FmtError};
+use std::time;
+use std::sync::Arc;
+use parking_lot::{Condvar, Mutex};
+use ethkey::{self, Public, Signature};
+use util::{H256, Hashable};
+use types::all::Error as KeyStorageError;
+use key_server_cluster::{Error, NodeId, SessionId, KeyStorage};
+use key_server_cluster::cluster::Cluster;
+use key_server_cluster::message::{Message, MessageNodeId, ServersSetChangeMessage, UnknownSessionsRequest, UnknownSessions,
+	ServersSetChangeCompleted, ServersSetChangeError};
+
+/// Servers set change session API.
+pub trait Session: Send + Sync + 'static {
+	/// Get servers set change session state.
+	fn state(&self) -> SessionState;
+	/// Get migration state of every key, known to the session.
+	fn keys(&self) -> BTreeMap<SessionId, KeyMigrationState>;
+	/// Wait until session is completed.
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error>;
+}
+
+/// Share add sessions executor. Servers set change session uses it to migrate every single key.
+pub trait ShareAddSessionsExecutor: Send + Sync {
+	/// Start share add session, which extends given key to every node of the new servers set.
+	/// Session completion must be reported back using SessionImpl::on_share_add_session_completed.
+	fn start_share_add_session(&self, key_id: SessionId, old_nodes_set: BTreeSet<NodeId>, servers_set_change: ServersSetChange, admin_signature: Signature) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Servers set change, signed by administrator. Share add sessions, started by servers set change session,
+/// are authorized by the same signature.
+pub struct ServersSetChange {
+	/// Servers set change session id.
+	pub session_id: SessionId,
+	/// Every node of the cluster, which is asked to report its keys.
+	pub old_servers_set: BTreeSet<NodeId>,
+	/// New servers set.
+	pub new_servers_set: BTreeSet<NodeId>,
+}
+
+/// Servers set change session.
+/// Migrates every key, known to the cluster, to the new servers set, signed by administrator.
+/// Brief overview:
+/// 1) unknown sessions: master node asks every other node to report keys it is holding
+/// 2) migration: every key, which is not yet held by every node of the new servers set, is extended to
+///    these nodes using share add session. At most max_active_migrations share add sessions are running at once
+/// 3) completion: when every key is held by enough nodes of the new servers set, master notifies every other node.
+///    Nodes, which are not in the new servers set, could then wipe their shares
+/// Keys, which are already held by every node of the new servers set, are skipped, so session could be
+/// restarted if it has been interrupted.
+pub struct SessionImpl {
+	/// Unique session id.
+	id: SessionId,
+	/// Public identifier of this node.
+	self_node_id: NodeId,
+	/// Administrator public key.
+	admin_public: Option<Public>,
+	/// Key storage.
+	key_storage: Arc<KeyStorage>,
+	/// Cluster which allows this node to send messages to other nodes in the cluster.
+	cluster: Arc<Cluster>,
+	/// Share add sessions executor.
+	executor: Arc<ShareAddSessionsExecutor>,
+	/// Max number of keys, which are migrated at once.
+	max_active_migrations: usize,
+	/// SessionImpl completion condvar.
+	completed: Condvar,
+	/// Mutable session data.
+	data: Mutex<SessionData>,
+}
+
+/// SessionImpl creation parameters
+pub struct SessionParams {
+	/// SessionImpl identifier.
+	pub id: SessionId,
+	/// Id of node, on which this session is running.
+	pub self_node_id: Public,
+	/// Administrator public key.
+	pub admin_public: Option<Public>,
+	/// Key storage.
+	pub key_storage: Arc<KeyStorage>,
+	/// Cluster
+	pub cluster: Arc<Cluster>,
+	/// Share add sessions executor.
+	pub executor: Arc<ShareAddSessionsExecutor>,
+	/// Max number of keys, which are migrated at once.
+	pub max_active_migrations: usize,
+}
+
+#[derive(Debug)]
+/// Mutable data of servers set change session.
+struct SessionData {
+	/// Current state of the session.
+	state: SessionState,
+	/// All nodes of the cluster.
+	old_servers_set: BTreeSet<NodeId>,
+	/// New servers set.
+	new_servers_set: BTreeSet<NodeId>,
+	/// Administrator signature of the servers set change.
+	admin_signature: Option<Signature>,
+	/// Nodes, which have not yet reported their keys.
+	awaiting_unknown_sessions: BTreeSet<NodeId>,
+	/// Keys, reported by nodes.
+	unknown_sessions: BTreeSet<SessionId>,
+	/// Migration state of every key.
+	keys: BTreeMap<SessionId, KeyMigrationState>,
+	/// Keys, which are waiting for migration along with their current holders.
+	pending_migrations: VecDeque<(SessionId, BTreeSet<NodeId>)>,
+	/// Number of keys, which are currently migrated.
+	active_migrations: usize,
+	/// Session result.
+	result: Option<Result<(), Error>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Servers set change session state.
+pub enum SessionState {
+	/// Session is not yet started.
+	WaitingForInitialization,
+	/// Master is waiting for every node to report its keys.
+	WaitingForUnknownSessions,
+	/// Keys are being migrated.
+	MigratingKeys,
+	/// Every key has been migrated.
+	Finished,
+	/// Session has failed.
+	Failed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Migration state of single key.
+pub enum KeyMigrationState {
+	/// Key is waiting for its turn to be migrated.
+	Pending,
+	/// Share add session for the key is running.
+	Migrating,
+	/// Key has been migrated to the new servers set by this session.
+	Migrated,
+	/// Key is already held by every node of the new servers set (i.e. it has been migrated by previous session).
+	AlreadyMigrated,
+	/// Key migration has failed.
+	Failed(Error),
+}
+
+impl SessionImpl {
+	/// Create new servers set change session.
+	pub fn new(params: SessionParams) -> Self {
+		SessionImpl {
+			id: params.id,
+			self_node_id: params.self_node_id,
+			admin_public: params.admin_public,
+			key_storage: params.key_storage,
+			cluster: params.cluster,
+			executor: params.executor,
+			max_active_migrations: params.max_active_migrations,
+			completed: Condvar::new(),
+			data: Mutex::new(SessionData {
+				state: SessionState::WaitingForInitialization,
+				old_servers_set: BTreeSet::new(),
+				new_servers_set: BTreeSet::new(),
+				admin_signature: None,
+				awaiting_unknown_sessions: BTreeSet::new(),
+				unknown_sessions: BTreeSet::new(),
+				keys: BTreeMap::new(),
+				pending_migrations: VecDeque::new(),
+				active_migrations: 0,
+				result: None,
+			}),
+		}
+	}
+
+	/// Get this node Id.
+	pub fn node(&self) -> &NodeId {
+		&self.self_node_id
+	}
+
+	/// Start servers set change. Every node from the old servers set is asked to report its keys. Admin signature
+	/// must be the signature of servers_set_change_hash(session_id, old_servers_set, new_servers_set).
+	pub fn initialize(&self, old_servers_set: BTreeSet<NodeId>, new_servers_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<(), Error> {
+		let migrations = {
+			let mut data = self.data.lock();
+			let result = self.process_initialize(&mut *data, old_servers_set, new_servers_set, admin_signature);
+			self.process_result(&mut *data, result)?
+		};
+
+		self.start_migrations(migrations);
+		Ok(())
+	}
+
+	/// When node has reported its keys.
+	pub fn on_unknown_sessions(&self, sender: NodeId, message: &UnknownSessions) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let migrations = {
+			let mut data = self.data.lock();
+			let result = self.process_unknown_sessions(&mut *data, sender, message);
+			self.process_result(&mut *data, result)?
+		};
+
+		self.start_migrations(migrations);
+		Ok(())
+	}
+
+	/// When share add session, started by this session, is completed.
+	pub fn on_share_add_session_completed(&self, key_id: &SessionId, result: Result<(), Error>) {
+		let migrations = {
+			let mut data = self.data.lock();
+			if data.state != SessionState::MigratingKeys || data.keys.get(key_id) != Some(&KeyMigrationState::Migrating) {
+				return;
+			}
+
+			data.active_migrations -= 1;
+			match result {
+				Ok(()) => {
+					data.keys.insert(key_id.clone(), KeyMigrationState::Migrated);
+				},
+				Err(err) => {
+					warn!("{}: servers set change session has failed to migrate key {}: {}", self.node(), key_id, err);
+					data.keys.insert(key_id.clone(), KeyMigrationState::Failed(err));
+				},
+			}
+
+			self.take_migrations(&mut *data)
+		};
+
+		self.start_migrations(migrations);
+	}
+
+	/// When error has occured on another node.
+	pub fn on_session_error(&self, sender: NodeId, message: &ServersSetChangeError) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		if data.state == SessionState::Finished || data.state == SessionState::Failed {
+			return Ok(());
+		}
+
+		warn!("{}: servers set change session failed with error: {} from {}", self.node(), message.error, sender);
+
+		self.complete(&mut *data, Err(Error::Io(message.error.clone())));
+
+		Ok(())
+	}
+
+	/// When connection to one of cluster nodes has timeouted.
+	pub fn on_node_timeout(&self, node: &NodeId) {
+		let mut data = self.data.lock();
+
+		// when keys are migrated, node timeouts are handled by share add sessions
+		if data.state != SessionState::WaitingForUnknownSessions || !data.awaiting_unknown_sessions.contains(node) {
+			return;
+		}
+
+		warn!("{}: servers set change session failed because {} connection has timeouted", self.node(), node);
+
+		self.complete(&mut *data, Err(Error::NodeDisconnected));
+	}
+
+	/// When session timeout has occured.
+	pub fn on_session_timeout(&self) {
+		let mut data = self.data.lock();
+
+		// when keys are migrated, timeouts are handled by share add sessions
+		if data.state != SessionState::WaitingForInitialization && data.state != SessionState::WaitingForUnknownSessions {
+			return;
+		}
+
+		warn!("{}: servers set change session failed with timeout", self.node());
+
+		self.complete(&mut *data, Err(Error::NodeDisconnected));
+	}
+
+	/// Start session on master node.
+	fn process_initialize(&self, data: &mut SessionData, old_servers_set: BTreeSet<NodeId>, new_servers_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<Vec<(SessionId, BTreeSet<NodeId>)>, Error> {
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// check that administrator has requested this change
+		check_admin_signature(self.admin_public.as_ref(), &self.id, &old_servers_set, &new_servers_set, &admin_signature)?;
+
+		// every node of the new servers set must be known to the cluster && master must stay in the new servers set
+		if !old_servers_set.contains(self.node()) || !new_servers_set.contains(self.node()) || !new_servers_set.is_subset(&old_servers_set) {
+			return Err(Error::InvalidNodesConfiguration);
+		}
+
+		// update state
+		data.state = SessionState::WaitingForUnknownSessions;
+		data.awaiting_unknown_sessions = old_servers_set.iter().filter(|n| *n != self.node()).cloned().collect();
+		data.unknown_sessions = self.key_storage.iter().map(|(key_id, _)| key_id).collect();
+		data.old_servers_set = old_servers_set;
+		data.new_servers_set = new_servers_set;
+		data.admin_signature = Some(admin_signature.clone());
+
+		// ask every other node to report its keys
+		for node in &data.awaiting_unknown_sessions {
+			self.cluster.send(node, Message::ServersSetChange(ServersSetChangeMessage::UnknownSessionsRequest(UnknownSessionsRequest {
+				session: self.id.clone().into(),
+				admin_signature: admin_signature.clone().into(),
+				old_servers_set: data.old_servers_set.iter().cloned().map(Into::into).collect(),
+				new_servers_set: data.new_servers_set.iter().cloned().map(Into::into).collect(),
+			})))?;
+		}
+
+		if !data.awaiting_unknown_sessions.is_empty() {
+			return Ok(Vec::new());
+		}
+
+		self.plan_migrations(data);
+		Ok(self.take_migrations(data))
+	}
+
+	/// Process keys, reported by node.
+	fn process_unknown_sessions(&self, data: &mut SessionData, sender: NodeId, message: &UnknownSessions) -> Result<Vec<(SessionId, BTreeSet<NodeId>)>, Error> {
+		// check state
+		if data.state != SessionState::WaitingForUnknownSessions {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if !data.awaiting_unknown_sessions.remove(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		data.unknown_sessions.extend(message.keys.iter().cloned().map(Into::into));
+		if !data.awaiting_unknown_sessions.is_empty() {
+			return Ok(Vec::new());
+		}
+
+		self.plan_migrations(data);
+		Ok(self.take_migrations(data))
+	}
+
+	/// Decide which keys must be migrated.
+	fn plan_migrations(&self, data: &mut SessionData) {
+		data.state = SessionState::MigratingKeys;
+
+		let unknown_sessions = mem::replace(&mut data.unknown_sessions, BTreeSet::new());
+		for key_id in unknown_sessions {
+			let key_state = match self.key_storage.get(&key_id) {
+				Ok(key_share) => match key_share.last_version() {
+					Ok(version) => {
+						let holders: BTreeSet<_> = version.id_numbers.keys().cloned().collect();
+						if data.new_servers_set.is_subset(&holders) {
+							KeyMigrationState::AlreadyMigrated
+						} else {
+							data.pending_migrations.push_back((key_id.clone(), holders));
+							KeyMigrationState::Pending
+						}
+					},
+					Err(err) => KeyMigrationState::Failed(Error::KeyStorage(err.into())),
+				},
+				// share add session must be started by one of key holders => keys, which are not held by master,
+				// are not migrated by this session. Nodes, which are holding these keys, are not wiping them on completion
+				Err(KeyStorageError::DocumentNotFound) => continue,
+				Err(err) => KeyMigrationState::Failed(Error::KeyStorage(err.into())),
+			};
+			data.keys.insert(key_id, key_state);
+		}
+	}
+
+	/// Select keys for migration. Completes session if every key has been processed.
+	fn take_migrations(&self, data: &mut SessionData) -> Vec<(SessionId, BTreeSet<NodeId>)> {
+		let mut migrations = Vec::new();
+		while data.active_migrations < self.max_active_migrations {
+			match data.pending_migrations.pop_front() {
+				Some((key_id, old_nodes_set)) => {
+					data.keys.insert(key_id.clone(), KeyMigrationState::Migrating);
+					data.active_migrations += 1;
+					migrations.push((key_id, old_nodes_set));
+				},
+				None => break,
+			}
+		}
+
+		if data.active_migrations == 0 && data.pending_migrations.is_empty() {
+			let result = self.check_migration_result(data);
+			if result.is_ok() {
+				self.notify_completed(data);
+			}
+			self.complete(data, result);
+		}
+
+		migrations
+	}
+
+	/// Start share add sessions for selected keys. Must be called without holding the data lock, because
+	/// share add session completion could be reported synchronously.
+	fn start_migrations(&self, migrations: Vec<(SessionId, BTreeSet<NodeId>)>) {
+		if migrations.is_empty() {
+			return;
+		}
+
+		let (servers_set_change, admin_signature) = {
+			let data = self.data.lock();
+			(ServersSetChange {
+				session_id: self.id.clone(),
+				old_servers_set: data.old_servers_set.clone(),
+				new_servers_set: data.new_servers_set.clone(),
+			}, data.admin_signature.clone().expect("migrations are started after initialization; admin_signature is filled in initialization; qed"))
+		};
+		for (key_id, old_nodes_set) in migrations {
+			if let Err(err) = self.executor.start_share_add_session(key_id.clone(), old_nodes_set, servers_set_change.clone(), admin_signature.clone()) {
+				self.on_share_add_session_completed(&key_id, Err(err));
+			}
+		}
+	}
+
+	/// Check that every key has been migrated && is held by enough nodes of the new servers set.
+	fn check_migration_result(&self, data: &mut SessionData) -> Result<(), Error> {
+		let new_servers_set = &data.new_servers_set;
+		let mut first_error = None;
+		for (key_id, key_state) in data.keys.iter_mut() {
+			if let KeyMigrationState::Failed(ref err) = *key_state {
+				first_error = first_error.or_else(|| Some(err.clone()));
+				continue;
+			}
+
+			let is_held_by_new_servers_set = self.key_storage.get(key_id).ok()
+				.and_then(|key_share| key_share.last_version().ok()
+					.map(|version| version.id_numbers.keys().filter(|n| new_servers_set.contains(*n)).count() >= key_share.threshold + 1))
+				.unwrap_or(false);
+			if !is_held_by_new_servers_set {
+				*key_state = KeyMigrationState::Failed(Error::InvalidNodesConfiguration);
+				first_error = first_error.or(Some(Error::InvalidNodesConfiguration));
+			}
+		}
+
+		match first_error {
+			Some(err) => Err(err),
+			None => Ok(()),
+		}
+	}
+
+	/// Notify every other node that the new servers set is now holding every key.
+	fn notify_completed(&self, data: &SessionData) {
+		let admin_signature = data.admin_signature.clone().expect("session is completed after initialization; admin_signature is filled in initialization; qed");
+		for node in data.old_servers_set.iter().filter(|n| *n != self.node()) {
+			// do not bother processing send error: keys are already migrated
+			let _ = self.cluster.send(node, Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeCompleted(ServersSetChangeCompleted {
+				session: self.id.clone().into(),
+				admin_signature: admin_signature.clone().into(),
+				old_servers_set: data.old_servers_set.iter().cloned().map(Into::into).collect(),
+				new_servers_set: data.new_servers_set.iter().cloned().map(Into::into).collect(),
+			})));
+		}
+	}
+
+	/// Fail session if error has occured while processing request.
+	fn process_result<T>(&self, data: &mut SessionData, result: Result<T, Error>) -> Result<T, Error> {
+		if let Err(ref err) = result {
+			if data.state != SessionState::Finished && data.state != SessionState::Failed {
+				self.complete(data, Err(err.clone()));
+			}
+		}
+
+		result
+	}
+
+	/// Complete session with given result.
+	fn complete(&self, data: &mut SessionData, result: Result<(), Error>) {
+		data.state = if result.is_ok() { SessionState::Finished } else { SessionState::Failed };
+		data.result = Some(result);
+		self.completed.notify_all();
+	}
+}
+
+impl Session for SessionImpl {
+	fn state(&self) -> SessionState {
+		self.data.lock().state.clone()
+	}
+
+	fn keys(&self) -> BTreeMap<SessionId, KeyMigrationState> {
+		self.data.lock().keys.clone()
+	}
+
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error> {
+		let mut data = self.data.lock();
+		if !data.result.is_some() {
+			match timeout {
+				None => self.completed.wait(&mut data),
+				Some(timeout) => { self.completed.wait_for(&mut data, timeout); },
+			}
+		}
+
+		match data.result.as_ref() {
+			Some(result) => result.clone(),
+			None => Err(Error::Io("timeout".into())),
+		}
+	}
+}
+
+impl Debug for SessionImpl {
+	fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+		write!(f, "Servers set change session {} on {}", self.id, self.self_node_id)
+	}
+}
+
+/// Process request to report all keys, held by this node. Servers set is the set of nodes, known to this node:
+/// it must be the same set, which master is changing.
+pub fn process_unknown_sessions_request(servers_set: &BTreeSet<NodeId>, admin_public: Option<&Public>, key_storage: &KeyStorage, message: &UnknownSessionsRequest) -> Result<UnknownSessions, Error> {
+	check_servers_set_change(servers_set, admin_public, &message.session, &message.old_servers_set, &message.new_servers_set, &message.admin_signature)?;
+
+	Ok(UnknownSessions {
+		session: message.session.clone(),
+		keys: key_storage.iter().map(|(key_id, _)| key_id.into()).collect(),
+	})
+}
+
+/// Process notification that every key is now held by the new servers set. If this node is not in the new
+/// servers set && wipe_removed_key_shares is true, shares of keys, which are held by enough nodes of the new
+/// servers set, are removed from the key storage. Returns number of removed shares.
+pub fn process_servers_set_change_completed(self_node_id: &NodeId, servers_set: &BTreeSet<NodeId>, admin_public: Option<&Public>, key_storage: &KeyStorage, wipe_removed_key_shares: bool, message: &ServersSetChangeCompleted) -> Result<usize, Error> {
+	let new_servers_set = check_servers_set_change(servers_set, admin_public, &message.session, &message.old_servers_set, &message.new_servers_set, &message.admin_signature)?;
+
+	if !wipe_removed_key_shares || new_servers_set.contains(self_node_id) {
+		return Ok(0);
+	}
+
+	// do not trust master: only remove shares of keys, which could be recovered without this node
+	let keys_to_remove: Vec<_> = key_storage.iter()
+		.filter(|&(_, ref key_share)| key_share.last_version().ok()
+			.map(|version| version.id_numbers.keys().filter(|n| new_servers_set.contains(*n)).count() >= key_share.threshold + 1)
+			.unwrap_or(false))
+		.map(|(key_id, _)| key_id)
+		.collect();
+	for key_id in &keys_to_remove {
+		key_storage.remove(key_id).map_err(|e| Error::KeyStorage(e.into()))?;
+	}
+
+	Ok(keys_to_remove.len())
+}
+
+/// Compute hash of the servers set change, which must be signed by administrator:
+/// keccak("servers_set_change" || session_id || len(old_servers_set) || old_servers_set || new_servers_set).
+/// Signature is bound to the session, so it could not be used to start another change.
+pub fn servers_set_change_hash(session_id: &SessionId, old_servers_set: &BTreeSet<NodeId>, new_servers_set: &BTreeSet<NodeId>) -> H256 {
+	let mut data = b"servers_set_change".to_vec();
+	data.extend_from_slice(&**session_id);
+	let old_servers_set_len = old_servers_set.len() as u64;
+	for i in (0..8).rev() {
+		data.push((old_servers_set_len >> (i * 8)) as u8);
+	}
+	for node in old_servers_set {
+		data.extend_from_slice(&**node);
+	}
+	for node in new_servers_set {
+		data.extend_from_slice(&**node);
+	}
+	data.sha3()
+}
+
+/// Check servers set change, requested by master: master must be changing the servers set, known to this node
+/// && the change must be signed by administrator. Returns the new servers set.
+fn check_servers_set_change(servers_set: &BTreeSet<NodeId>, admin_public: Option<&Public>, session_id: &SessionId, old_servers_set: &BTreeSet<MessageNodeId>, new_servers_set: &BTreeSet<MessageNodeId>, admin_signature: &Signature) -> Result<BTreeSet<NodeId>, Error> {
+	let old_servers_set: BTreeSet<NodeId> = old_servers_set.iter().cloned().map(Into::into).collect();
+	let new_servers_set: BTreeSet<NodeId> = new_servers_set.iter().cloned().map(Into::into).collect();
+	check_admin_signature(admin_public, session_id, &old_servers_set, &new_servers_set, admin_signature)?;
+	if &old_servers_set != servers_set || !new_servers_set.is_subset(&old_servers_set) {
+		return Err(Error::InvalidNodesConfiguration);
+	}
+
+	Ok(new_servers_set)
+}
+
+/// Check that administrator has signed the servers set change.
+fn check_admin_signature(admin_public: Option<&Public>, session_id: &SessionId, old_servers_set: &BTreeSet<NodeId>, new_servers_set: &BTreeSet<NodeId>, admin_signature: &Signature) -> Result<(), Error> {
+	let admin_public = admin_public.ok_or(Error::AccessDenied)?;
+	if new_servers_set.is_empty() || !ethkey::verify_public(admin_public, admin_signature, &servers_set_change_hash(session_id, old_servers_set, new_servers_set))? {
+		return Err(Error::AccessDenied);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time;
+	use std::sync::Arc;
+	use std::cell::Cell;
+	use std::collections::{BTreeSet, BTreeMap, VecDeque};
+	use parking_lot::Mutex;
+	use ethkey::{self, Random, Generator, KeyPair, Signature};
+	use key_server_cluster::{NodeId, SessionId, Error, KeyStorage, DummyKeyStorage, DocumentKeyShare, DocumentKeyShareVersion};
+	use key_server_cluster::math;
+	use key_server_cluster::message::{Message, ShareAddMessage, ServersSetChangeMessage, UnknownSessionsRequest};
+	use key_server_cluster::cluster::tests::DummyCluster;
+	use key_server_cluster::share_add_session::{SessionImpl as ShareAddSessionImpl, SessionParams as ShareAddSessionParams};
+	use key_server_cluster::servers_set_change_session::{Session, SessionImpl, SessionState, SessionParams, KeyMigrationState,
+		ShareAddSessionsExecutor, ServersSetChange, servers_set_change_hash, process_unknown_sessions_request, process_servers_set_change_completed};
+
+	#[derive(Default)]
+	struct DummyExecutor {
+		pub requests: Mutex<VecDeque<(SessionId, BTreeSet<NodeId>, ServersSetChange, Signature)>>,
+	}
+
+	impl ShareAddSessionsExecutor for DummyExecutor {
+		fn start_share_add_session(&self, key_id: SessionId, old_nodes_set: BTreeSet<NodeId>, servers_set_change: ServersSetChange, admin_signature: Signature) -> Result<(), Error> {
+			self.requests.lock().push_back((key_id, old_nodes_set, servers_set_change, admin_signature));
+			Ok(())
+		}
+	}
+
+	struct Node {
+		pub cluster: Arc<DummyCluster>,
+		pub key_storage: Arc<DummyKeyStorage>,
+		pub wipe_removed_key_shares: bool,
+	}
+
+	struct MessageLoop {
+		pub admin: KeyPair,
+		pub master: NodeId,
+		pub new_servers_set: BTreeSet<NodeId>,
+		pub nodes: BTreeMap<NodeId, Node>,
+		pub keys: Vec<SessionId>,
+		pub executor: Arc<DummyExecutor>,
+		pub session: SessionImpl,
+		pub share_add_sessions: BTreeMap<(NodeId, SessionId), ShareAddSessionImpl>,
+		pub reported_share_add_sessions: BTreeSet<SessionId>,
+		pub crashed_nodes: BTreeSet<NodeId>,
+		pub queue: VecDeque<(NodeId, NodeId, Message)>,
+	}
+
+	impl MessageLoop {
+		/// Create cluster, where old_nodes_num nodes are holding keys_num keys. Last removed_nodes_num of old
+		/// nodes are removed && new_nodes_num nodes are added in the new servers set.
+		pub fn new(old_nodes_num: usize, new_nodes_num: usize, removed_nodes_num: usize, keys_num: usize, max_active_migrations: usize) -> Self {
+			let admin = Random.generate().unwrap();
+			let old_nodes: Vec<_> = (0..old_nodes_num).map(|_| Random.generate().unwrap().public().clone()).collect();
+			let new_nodes: Vec<_> = (0..new_nodes_num).map(|_| Random.generate().unwrap().public().clone()).collect();
+			let all_nodes: BTreeSet<_> = old_nodes.iter().cloned().chain(new_nodes.iter().cloned()).collect();
+			let new_servers_set: BTreeSet<_> = old_nodes.iter().take(old_nodes_num - removed_nodes_num).cloned()
+				.chain(new_nodes.iter().cloned()).collect();
+
+			let mut nodes = BTreeMap::new();
+			for node_id in &all_nodes {
+				let cluster = Arc::new(DummyCluster::new(node_id.clone()));
+				for other_node_id in &all_nodes {
+					cluster.add_node(other_node_id.clone());
+				}
+				nodes.insert(node_id.clone(), Node {
+					cluster: cluster,
+					key_storage: Arc::new(DummyKeyStorage::default()),
+					wipe_removed_key_shares: false,
+				});
+			}
+
+			// share keys among old nodes
+			let keys: Vec<_> = (0..keys_num).map(|_| SessionId::random()).collect();
+			for key_id in &keys {
+				let polynom = math::generate_random_polynom(1).unwrap();
+				let id_numbers: BTreeMap<_, _> = old_nodes.iter().map(|n| (n.clone(), math::generate_random_scalar().unwrap())).collect();
+				for (node_id, id_number) in &id_numbers {
+					nodes[node_id].key_storage.insert(key_id.clone(), DocumentKeyShare {
+						author: admin.public().clone(),
+						threshold: 1,
+						common_point: None,
+						encrypted_point: None,
+						versions: vec![DocumentKeyShareVersion::new(id_numbers.clone(), math::compute_polynom(&polynom, id_number).unwrap())],
+					}).unwrap();
+				}
+			}
+
+			let master = old_nodes[0].clone();
+			let executor = Arc::new(DummyExecutor::default());
+			let session = MessageLoop::create_session(&admin, &nodes[&master], executor.clone(), max_active_migrations);
+			MessageLoop {
+				admin: admin,
+				master: master,
+				new_servers_set: new_servers_set,
+				nodes: nodes,
+				keys: keys,
+				executor: executor,
+				session: session,
+				share_add_sessions: BTreeMap::new(),
+				reported_share_add_sessions: BTreeSet::new(),
+				crashed_nodes: BTreeSet::new(),
+				queue: VecDeque::new(),
+			}
+		}
+
+		fn create_session(admin: &KeyPair, master: &Node, executor: Arc<DummyExecutor>, max_active_migrations: usize) -> SessionImpl {
+			SessionImpl::new(SessionParams {
+				id: SessionId::random(),
+				self_node_id: master.cluster.node(),
+				admin_public: Some(admin.public().clone()),
+				key_storage: master.key_storage.clone(),
+				cluster: master.cluster.clone(),
+				executor: executor,
+				max_active_migrations: max_active_migrations,
+			})
+		}
+
+		/// Restart servers set change session after all nodes are back online.
+		pub fn restart(&mut self, max_active_migrations: usize) {
+			while let Some(_) = self.take_message() {}
+			self.crashed_nodes.clear();
+			self.share_add_sessions.clear();
+			self.reported_share_add_sessions.clear();
+			self.session = MessageLoop::create_session(&self.admin, &self.nodes[&self.master], self.executor.clone(), max_active_migrations);
+		}
+
+		pub fn servers_set(&self) -> BTreeSet<NodeId> {
+			self.nodes.keys().cloned().collect()
+		}
+
+		pub fn admin_signature(&self) -> Signature {
+			ethkey::sign(self.admin.secret(), &servers_set_change_hash(&self.session.id, &self.servers_set(), &self.new_servers_set)).unwrap()
+		}
+
+		pub fn initialize(&self) -> Result<(), Error> {
+			self.session.initialize(self.servers_set(), self.new_servers_set.clone(), self.admin_signature())
+		}
+
+		pub fn take_message(&mut self) -> Option<(NodeId, NodeId, Message)> {
+			self.nodes.values()
+				.filter_map(|n| n.cluster.take_message().map(|m| (n.cluster.node(), m.0, m.1)))
+				.nth(0)
+				.or_else(|| self.queue.pop_front())
+		}
+
+		/// Start share add sessions, requested by servers set change session && report completed share add sessions.
+		/// Returns true if anything has been done.
+		fn process_share_add_sessions(&mut self) -> bool {
+			let mut has_progress = false;
+			loop {
+				let request = self.executor.requests.lock().pop_front();
+				let (key_id, old_nodes_set, servers_set_change, admin_signature) = match request {
+					Some(request) => request,
+					None => break,
+				};
+
+				let session = self.create_share_add_session(self.master.clone(), key_id.clone());
+				let _ = session.initialize_with_servers_set(old_nodes_set, servers_set_change, admin_signature);
+				self.share_add_sessions.insert((self.master.clone(), key_id), session);
+				has_progress = true;
+			}
+
+			let completed: Vec<_> = self.share_add_sessions.iter()
+				.filter(|&(&(ref node_id, ref key_id), _)| node_id == &self.master && !self.reported_share_add_sessions.contains(key_id))
+				.filter_map(|(&(_, ref key_id), session)| session.result().map(|result| (key_id.clone(), result)))
+				.collect();
+			for (key_id, result) in completed {
+				self.reported_share_add_sessions.insert(key_id.clone());
+				self.session.on_share_add_session_completed(&key_id, result);
+				has_progress = true;
+			}
+
+			has_progress
+		}
+
+		fn create_share_add_session(&self, node_id: NodeId, key_id: SessionId) -> ShareAddSessionImpl {
+			let node = &self.nodes[&node_id];
+			ShareAddSessionImpl::new(ShareAddSessionParams {
+				id: key_id,
+				self_node_id: node_id,
+				admin_public: Some(self.admin.public().clone()),
+				key_storage: node.key_storage.clone(),
+				cluster: node.cluster.clone(),
+			})
+		}
+
+		pub fn process_message(&mut self, msg: (NodeId, NodeId, Message)) -> Result<(), Error> {
+			if self.crashed_nodes.contains(&msg.0) || self.crashed_nodes.contains(&msg.1) {
+				return Ok(());
+			}
+
+			let result = match msg.2 {
+				Message::ShareAdd(ref message) => {
+					let session_key = (msg.1.clone(), message.session_id().clone());
+					if !self.share_add_sessions.contains_key(&session_key) {
+						let session = self.create_share_add_session(session_key.0.clone(), session_key.1.clone());
+						self.share_add_sessions.insert(session_key.clone(), session);
+					}
+
+					let session = &self.share_add_sessions[&session_key];
+					match *message {
+						ShareAddMessage::InitializeShareAddSession(ref message) => session.on_initialize_session(msg.0.clone(), message),
+						ShareAddMessage::NewKeysDissemination(ref message) => session.on_keys_dissemination(msg.0.clone(), message),
+						ShareAddMessage::NewKeyShareStaged(ref message) => session.on_new_key_share_staged(msg.0.clone(), message),
+						ShareAddMessage::CommitNewKeyShare(ref message) => session.on_commit_new_key_share(msg.0.clone(), message),
+						ShareAddMessage::ShareAddSessionError(ref message) => session.on_session_error(msg.0.clone(), message),
+					}
+				},
+				Message::ServersSetChange(ServersSetChangeMessage::UnknownSessionsRequest(ref message)) => {
+					let node = &self.nodes[&msg.1];
+					let response = process_unknown_sessions_request(&self.servers_set(), Some(self.admin.public()), &*node.key_storage, message)?;
+					node.cluster.send(&msg.0, Message::ServersSetChange(ServersSetChangeMessage::UnknownSessions(response)))
+				},
+				Message::ServersSetChange(ServersSetChangeMessage::UnknownSessions(ref message)) =>
+					self.session.on_unknown_sessions(msg.0.clone(), message),
+				Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeCompleted(ref message)) => {
+					let node = &self.nodes[&msg.1];
+					process_servers_set_change_completed(&msg.1, &self.servers_set(), Some(self.admin.public()), &*node.key_storage, node.wipe_removed_key_shares, message)
+						.map(|_| ())
+				},
+				Message::ServersSetChange(ServersSetChangeMessage::ServersSetChangeError(ref message)) =>
+					self.session.on_session_error(msg.0.clone(), message),
+				_ => panic!("unexpected"),
+			};
+
+			match result {
+				Err(Error::TooEarlyForRequest) => {
+					self.queue.push_back(msg);
+					Ok(())
+				},
+				result => result,
+			}
+		}
+
+		/// Run until there are no more messages. Stops before delivering message, matching given predicate.
+		pub fn run_until<F>(&mut self, predicate: F) -> Option<(NodeId, NodeId, Message)> where F: Fn(&NodeId, &NodeId, &Message) -> bool {
+			loop {
+				let has_progress = self.process_share_add_sessions();
+				match self.take_message() {
+					Some(msg) => {
+						if predicate(&msg.0, &msg.1, &msg.2) {
+							return Some(msg);
+						}
+						// share add sessions errors are reported to servers set change session
+						let _ = self.process_message(msg);
+					},
+					None if has_progress => (),
+					None => return None,
+				}
+			}
+		}
+
+		pub fn run(&mut self) {
+			assert!(self.run_until(|_, _, _| false).is_none());
+		}
+
+		pub fn holders(&self, key_id: &SessionId) -> BTreeSet<NodeId> {
+			self.nodes.iter()
+				.filter(|&(_, node)| node.key_storage.contains(key_id))
+				.map(|(node_id, _)| node_id.clone())
+				.collect()
+		}
+
+		pub fn last_version_nodes(&self, node_id: &NodeId, key_id: &SessionId) -> BTreeSet<NodeId> {
+			self.nodes[node_id].key_storage.get(key_id).unwrap().last_version().unwrap().id_numbers.keys().cloned().collect()
+		}
+	}
+
+	#[test]
+	fn servers_set_change_migrates_every_key_to_new_node() {
+		let mut l = MessageLoop::new(3, 1, 0, 3, 2);
+		l.initialize().unwrap();
+		l.run();
+
+		assert_eq!(l.session.state(), SessionState::Finished);
+		assert_eq!(l.session.wait(None), Ok(()));
+		let keys = l.session.keys();
+		assert_eq!(keys.len(), 3);
+		for key_id in &l.keys {
+			assert_eq!(keys[key_id], KeyMigrationState::Migrated);
+			assert_eq!(l.holders(key_id), l.new_servers_set);
+			for node_id in &l.new_servers_set {
+				assert_eq!(l.last_version_nodes(node_id, key_id), l.new_servers_set);
+			}
+		}
+	}
+
+	#[test]
+	fn servers_set_change_is_resumed_after_node_crash() {
+		let mut l = MessageLoop::new(3, 1, 0, 2, 1);
+		let new_node = l.new_servers_set.iter().find(|n| !l.holders(&l.keys[0]).contains(*n)).unwrap().clone();
+		l.initialize().unwrap();
+
+		// new node crashes when the second key migration is started
+		let initializations = Cell::new(0);
+		let msg = l.run_until(|_, to, msg| match *msg {
+			Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(_)) if to == &new_node => {
+				initializations.set(initializations.get() + 1);
+				initializations.get() == 2
+			},
+			_ => false,
+		}).unwrap();
+		l.crashed_nodes.insert(new_node.clone());
+		let failed_key = match msg.2 {
+			Message::ShareAdd(ref message) => message.session_id().clone(),
+			_ => unreachable!("predicate only matches share add messages; qed"),
+		};
+		l.share_add_sessions[&(l.master.clone(), failed_key.clone())].on_node_timeout(&new_node);
+		l.run();
+
+		// session has failed, but the first key has been migrated
+		assert_eq!(l.session.state(), SessionState::Failed);
+		assert_eq!(l.session.wait(None), Err(Error::NodeDisconnected));
+		let migrated_key = l.keys.iter().find(|k| **k != failed_key).unwrap().clone();
+		assert_eq!(l.session.keys()[&migrated_key], KeyMigrationState::Migrated);
+		assert_eq!(l.session.keys()[&failed_key], KeyMigrationState::Failed(Error::NodeDisconnected));
+		assert!(!l.holders(&failed_key).contains(&new_node));
+
+		// when session is restarted, migrated key is skipped
+		l.restart(1);
+		l.initialize().unwrap();
+		l.run();
+
+		assert_eq!(l.session.state(), SessionState::Finished);
+		assert_eq!(l.session.keys()[&migrated_key], KeyMigrationState::AlreadyMigrated);
+		assert_eq!(l.session.keys()[&failed_key], KeyMigrationState::Migrated);
+		for key_id in &l.keys {
+			assert_eq!(l.holders(key_id), l.new_servers_set);
+			assert_eq!(l.nodes[&new_node].key_storage.get(key_id).unwrap().versions.len(), 1);
+			assert_eq!(l.nodes[&l.master].key_storage.get(key_id).unwrap().versions.len(), 2);
+		}
+	}
+
+	#[test]
+	fn servers_set_change_is_rejected_without_admin_signature() {
+		let l = MessageLoop::new(3, 1, 0, 1, 1);
+		let signature = ethkey::sign(Random.generate().unwrap().secret(), &servers_set_change_hash(&l.session.id, &l.servers_set(), &l.new_servers_set)).unwrap();
+		assert_eq!(l.session.initialize(l.servers_set(), l.new_servers_set.clone(), signature.clone()), Err(Error::AccessDenied));
+		assert_eq!(l.session.state(), SessionState::Failed);
+
+		// other nodes are also refusing to report their keys
+		let node = l.nodes.values().nth(0).unwrap();
+		assert_eq!(process_unknown_sessions_request(&l.servers_set(), Some(l.admin.public()), &*node.key_storage, &UnknownSessionsRequest {
+			session: l.session.id.clone().into(),
+			admin_signature: signature.into(),
+			old_servers_set: l.servers_set().into_iter().map(Into::into).collect(),
+			new_servers_set: l.new_servers_set.iter().cloned().map(Into::into).collect(),
+		}).unwrap_err(), Error::AccessDenied);
+	}
+
+	#[test]
+	fn servers_set_change_signature_could_not_be_replayed() {
+		let l = MessageLoop::new(3, 1, 0, 1, 1);
+		let signature = l.admin_signature();
+		let node = l.nodes.values().nth(0).unwrap();
+		let request = |session_id: SessionId, old_servers_set: BTreeSet<NodeId>| UnknownSessionsRequest {
+			session: session_id.into(),
+			admin_signature: signature.clone().into(),
+			old_servers_set: old_servers_set.into_iter().map(Into::into).collect(),
+			new_servers_set: l.new_servers_set.iter().cloned().map(Into::into).collect(),
+		};
+
+		// signature is accepted for the session it has been computed for
+		assert!(process_unknown_sessions_request(&l.servers_set(), Some(l.admin.public()), &*node.key_storage, &request(l.session.id.clone(), l.servers_set())).is_ok());
+
+		// but could not be used to start another session
+		let other_session = MessageLoop::create_session(&l.admin, &l.nodes[&l.master], l.executor.clone(), 1);
+		assert_eq!(other_session.initialize(l.servers_set(), l.new_servers_set.clone(), signature.clone()), Err(Error::AccessDenied));
+		assert_eq!(process_unknown_sessions_request(&l.servers_set(), Some(l.admin.public()), &*node.key_storage, &request(other_session.id.clone(), l.servers_set())).unwrap_err(), Error::AccessDenied);
+
+		// or to change servers set, which differs from the signed one
+		let mut other_servers_set = l.servers_set();
+		other_servers_set.insert(Random.generate().unwrap().public().clone());
+		assert_eq!(process_unknown_sessions_request(&other_servers_set, Some(l.admin.public()), &*node.key_storage, &request(l.session.id.clone(), l.servers_set())).unwrap_err(), Error::InvalidNodesConfiguration);
+		assert_eq!(process_unknown_sessions_request(&other_servers_set, Some(l.admin.public()), &*node.key_storage, &request(l.session.id.clone(), other_servers_set.clone())).unwrap_err(), Error::AccessDenied);
+	}
+
+	#[test]
+	fn keys_which_are_not_held_by_master_are_skipped() {
+		let mut l = MessageLoop::new(3, 1, 0, 2, 1);
+		let master = l.master.clone();
+		let skipped_key = l.keys[0].clone();
+		l.nodes[&master].key_storage.remove(&skipped_key).unwrap();
+		l.initialize().unwrap();
+		l.run();
+
+		assert_eq!(l.session.state(), SessionState::Finished);
+		let keys = l.session.keys();
+		assert_eq!(keys.len(), 1);
+		assert_eq!(keys[&l.keys[1]], KeyMigrationState::Migrated);
+		// skipped key is still held by other old nodes only
+		assert!(!l.holders(&skipped_key).contains(&master));
+		assert_eq!(l.holders(&skipped_key).len(), 2);
+	}
+
+	#[test]
+	fn servers_set_change_fails_if_master_is_not_in_new_servers_set() {
+		let mut l = MessageLoop::new(3, 1, 0, 1, 1);
+		l.new_servers_set.remove(&l.master.clone());
+		assert_eq!(l.initialize(), Err(Error::InvalidNodesConfiguration));
+	}
+
+	#[test]
+	fn removed_node_wipes_key_shares_when_configured() {
+		let mut l = MessageLoop::new(3, 1, 1, 2, 2);
+		let removed_node = l.holders(&l.keys[0]).into_iter().find(|n| !l.new_servers_set.contains(n)).unwrap();
+		l.nodes.get_mut(&removed_node).unwrap().wipe_removed_key_shares = true;
+		l.initialize().unwrap();
+		l.run();
+
+		assert_eq!(l.session.state(), SessionState::Finished);
+		for key_id in &l.keys {
+			// removed node has wiped its share, but every other node is still holding the key
+			assert_eq!(l.holders(key_id), l.new_servers_set);
+		}
+	}
+
+	#[test]
+	fn removed_node_keeps_key_shares_by_default() {
+		let mut l = MessageLoop::new(3, 1, 1, 2, 2);
+		let removed_node = l.holders(&l.keys[0]).into_iter().find(|n| !l.new_servers_set.contains(n)).unwrap();
+		l.initialize().unwrap();
+		l.run();
+
+		assert_eq!(l.session.state(), SessionState::Finished);
+		for key_id in &l.keys {
+			assert!(l.holders(key_id).contains(&removed_node));
+		}
+	}
+
+	#[test]
+	fn wait_fails_when_timeout_passes() {
+		let l = MessageLoop::new(3, 1, 0, 1, 1);
+		assert_eq!(l.session.wait(Some(time::Duration::from_millis(10))), Err(Error::Io("timeout".into())));
+	}
+}
@@ -0,0 +1,208 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+use futures::{self, Future, Poll, Async};
+use futures::sync::oneshot;
+use parking_lot::Mutex;
+use key_server_cluster::Error;
+use key_server_cluster::cluster::ClusterSessionsListener;
+
+/// Future, which is resolved when the session is completed. If the future is dropped before the session
+/// is completed, the session is cancelled.
+pub struct SessionResultFuture<T> {
+	/// Session result receiver.
+	receiver: oneshot::Receiver<Result<T, Error>>,
+	/// Session result listener. None if the result is already known.
+	listener: Option<Arc<SessionResultSender<T>>>,
+	/// Session cancel action.
+	cancel: Option<Box<Fn() + Send>>,
+}
+
+/// Sessions container listener, which sends result of the single session to the future.
+pub struct SessionResultListener<S, T> {
+	/// The session. Could be replaced with the session, which serves the same request.
+	session: Mutex<Arc<S>>,
+	/// Reads session result. Returns None if the session is not completed yet.
+	result: fn(&S) -> Option<Result<T, Error>>,
+	/// Session result sender. None if the result is already sent.
+	sender: Mutex<Option<oneshot::Sender<Result<T, Error>>>>,
+}
+
+/// Session result sender, referenced by the future.
+trait SessionResultSender<T>: Send + Sync {
+	/// Forget the sender. Returns true if the result has not been sent yet.
+	fn forget(&self) -> bool;
+}
+
+impl<T> SessionResultFuture<T> {
+	/// Create future && listener for given session. Cancel action is executed when the future is dropped before completion.
+	pub fn new<S, F>(session: Arc<S>, result: fn(&S) -> Option<Result<T, Error>>, cancel: F) -> (Self, Arc<SessionResultListener<S, T>>)
+		where S: Send + Sync + 'static, T: Send + 'static, F: Fn() + Send + 'static {
+		let (sender, receiver) = futures::oneshot();
+		let listener = Arc::new(SessionResultListener {
+			session: Mutex::new(session),
+			result: result,
+			sender: Mutex::new(Some(sender)),
+		});
+		let future = SessionResultFuture {
+			receiver: receiver,
+			listener: Some(listener.clone()),
+			cancel: Some(Box::new(cancel)),
+		};
+		(future, listener)
+	}
+
+	/// Create future, which is already resolved with given error.
+	pub fn failed(error: Error) -> Self {
+		let (sender, receiver) = futures::oneshot();
+		// receiver is alive => send never fails
+		let _ = sender.send(Err(error));
+		SessionResultFuture {
+			receiver: receiver,
+			listener: None,
+			cancel: None,
+		}
+	}
+}
+
+impl<T> Future for SessionResultFuture<T> {
+	type Item = T;
+	type Error = Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		match self.receiver.poll() {
+			Ok(Async::NotReady) => Ok(Async::NotReady),
+			Ok(Async::Ready(result)) => result.map(Async::Ready),
+			// sender is dropped only after the result is sent
+			Err(_) => Err(Error::InvalidStateForRequest),
+		}
+	}
+}
+
+impl<T> Drop for SessionResultFuture<T> {
+	fn drop(&mut self) {
+		let is_completed = self.listener.as_ref().map(|listener| !listener.forget()).unwrap_or(true);
+		if !is_completed {
+			if let Some(cancel) = self.cancel.take() {
+				cancel();
+			}
+		}
+	}
+}
+
+impl<S, T> SessionResultListener<S, T> {
+	/// Send session result to the future. Session, which is removed before completion, is treated as failed.
+	pub fn complete(&self) {
+		if let Some(sender) = self.sender.lock().take() {
+			let session = self.session.lock().clone();
+			let result = (self.result)(&*session).unwrap_or(Err(Error::InvalidStateForRequest));
+			// future could have been dropped already
+			let _ = sender.send(result);
+		}
+	}
+}
+
+impl<S, T> SessionResultSender<T> for SessionResultListener<S, T> where S: Send + Sync, T: Send {
+	fn forget(&self) -> bool {
+		self.sender.lock().take().is_some()
+	}
+}
+
+impl<S, T> ClusterSessionsListener<S> for SessionResultListener<S, T> where S: Send + Sync, T: Send {
+	fn on_session_removed(&self, session: Arc<S>) {
+		let is_our_session = Arc::ptr_eq(&*self.session.lock(), &session);
+		if is_our_session {
+			self.complete();
+		}
+	}
+
+	fn on_session_replaced(&self, old_session: Arc<S>, new_session: Arc<S>) {
+		let mut session = self.session.lock();
+		if Arc::ptr_eq(&*session, &old_session) {
+			*session = new_session;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use futures::Future;
+	use parking_lot::Mutex;
+	use key_server_cluster::Error;
+	use key_server_cluster::cluster::ClusterSessionsListener;
+	use super::SessionResultFuture;
+
+	#[derive(Default)]
+	struct DummySession {
+		result: Mutex<Option<Result<u64, Error>>>,
+	}
+
+	fn session_result(session: &DummySession) -> Option<Result<u64, Error>> {
+		session.result.lock().clone()
+	}
+
+	#[test]
+	fn future_is_resolved_when_session_is_removed() {
+		let session = Arc::new(DummySession::default());
+		let (future, listener) = SessionResultFuture::new(session.clone(), session_result, || panic!("completed session is cancelled"));
+		let waiter = thread::spawn(move || future.wait());
+
+		*session.result.lock() = Some(Ok(42));
+		listener.on_session_removed(Arc::new(DummySession::default()));
+		listener.on_session_removed(session);
+		assert_eq!(waiter.join().unwrap(), Ok(42));
+	}
+
+	#[test]
+	fn future_is_resolved_with_result_of_replacing_session() {
+		let session = Arc::new(DummySession::default());
+		let (future, listener) = SessionResultFuture::new(session.clone(), session_result, || panic!("completed session is cancelled"));
+
+		let new_session = Arc::new(DummySession::default());
+		*new_session.result.lock() = Some(Ok(42));
+		listener.on_session_replaced(session.clone(), new_session.clone());
+		listener.on_session_removed(session);
+		listener.on_session_removed(new_session);
+		assert_eq!(future.wait(), Ok(42));
+	}
+
+	#[test]
+	fn session_removed_before_completion_fails_future() {
+		let session = Arc::new(DummySession::default());
+		let (future, listener) = SessionResultFuture::new(session.clone(), session_result, || ());
+		listener.on_session_removed(session);
+		assert_eq!(future.wait(), Err(Error::InvalidStateForRequest));
+	}
+
+	#[test]
+	fn session_is_cancelled_when_future_is_dropped() {
+		let is_cancelled = Arc::new(AtomicBool::new(false));
+		let is_cancelled_clone = is_cancelled.clone();
+		let (future, _listener) = SessionResultFuture::new(Arc::new(DummySession::default()), session_result,
+			move || is_cancelled_clone.store(true, Ordering::Relaxed));
+		drop(future);
+		assert!(is_cancelled.load(Ordering::Relaxed));
+	}
+
+	#[test]
+	fn failed_future_is_resolved_immediately() {
+		assert_eq!(SessionResultFuture::<u64>::failed(Error::TooManySessions).wait(), Err(Error::TooManySessions));
+	}
+}
@@ -0,0 +1,373 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use key_server_cluster::{Error, NodeId, SessionId};
+use key_server_cluster::message::{Message, GenerationMessage, EncryptionMessage, DecryptionMessage, ShareAddMessage,
+	KeyRemovalMessage, SigningMessage};
+use key_server_cluster::metrics::SessionType;
+
+/// Log target of cluster network events.
+pub const NET_LOG_TARGET: &'static str = "secretstore_net";
+/// Log target of session events.
+pub const SESSION_LOG_TARGET: &'static str = "secretstore_session";
+/// Log target of events of jobs, running within sessions.
+pub const JOBS_LOG_TARGET: &'static str = "secretstore_jobs";
+
+/// Job of decryption (signing) session, which is responsible for agreeing on the nodes set && checking access rights.
+pub const CONSENSUS_JOB: &'static str = "consensus";
+/// Job of decryption session, which is responsible for computing partial decryptions.
+pub const PARTIAL_DECRYPTION_JOB: &'static str = "partial_decryption";
+/// Job of signing session, which is responsible for computing partial signatures.
+pub const SIGNING_JOB: &'static str = "signing";
+
+#[derive(Debug, Clone, PartialEq)]
+/// Tracing span of the session. Every event of the session is logged along with span fields, so that
+/// events of the single session could be extracted from interleaved logs of all key servers.
+pub struct SessionSpan {
+	/// Session type.
+	session_type: SessionType,
+	/// Session id.
+	session_id: SessionId,
+	/// Session master.
+	master: NodeId,
+	/// This node id.
+	self_node: NodeId,
+	/// Name of the job, if this is the child span of the job, running within the session.
+	job: Option<&'static str>,
+}
+
+impl SessionSpan {
+	pub fn new(session_type: SessionType, session_id: SessionId, master: NodeId, self_node: NodeId) -> Self {
+		SessionSpan {
+			session_type: session_type,
+			session_id: session_id,
+			master: master,
+			self_node: self_node,
+			job: None,
+		}
+	}
+
+	/// Create child span of the job, running within this session.
+	pub fn job(&self, job: &'static str) -> Self {
+		SessionSpan {
+			job: Some(job),
+			..self.clone()
+		}
+	}
+
+	/// Get span of the session job, which is processing given message. Returns this span if message is not processed by any job.
+	pub fn message_span<M: JobMessage>(&self, message: &M) -> Self {
+		match message.job() {
+			Some(job) if self.job.is_none() => self.job(job),
+			_ => self.clone(),
+		}
+	}
+
+	/// When session is started on this node.
+	pub fn on_started(&self) {
+		debug!(target: self.target(), "{} event=started", self);
+	}
+
+	/// When session state is changed.
+	pub fn on_state_changed<S: fmt::Debug>(&self, from: &S, to: &S) {
+		debug!(target: self.target(), "{} event=state_changed from={:?} to={:?}", self, from, to);
+	}
+
+	/// When session message is sent to other node.
+	pub fn on_message_sent(&self, to: &NodeId, message: &Message) {
+		trace!(target: self.target(), "{} event=send to={} message={}", self, to, message);
+	}
+
+	/// When session message is received from other node.
+	pub fn on_message_received<M: fmt::Display>(&self, from: &NodeId, message: &M) {
+		trace!(target: self.target(), "{} event=receive from={} message={}", self, from, message);
+	}
+
+	/// When session has rejected message, received from other node.
+	pub fn on_message_rejected<M: fmt::Display>(&self, from: &NodeId, message: &M, error: &Error) {
+		debug!(target: self.target(), "{} event=reject from={} message={} error={}", self, from, message, error);
+	}
+
+	/// When connection to the session node is established again.
+	pub fn on_node_connected(&self, node: &NodeId) {
+		debug!(target: self.target(), "{} event=connected node={}", self, node);
+	}
+
+	/// When session (or connection to the given node) has timeouted.
+	pub fn on_timeout(&self, node: Option<&NodeId>) {
+		match node {
+			Some(node) => debug!(target: self.target(), "{} event=timeout node={}", self, node),
+			None => debug!(target: self.target(), "{} event=timeout", self),
+		}
+	}
+
+	/// When session is removed from this node.
+	pub fn on_finished<S: fmt::Debug>(&self, state: &S) {
+		debug!(target: self.target(), "{} event=finished state={:?}", self, state);
+	}
+
+	/// Log target of span events.
+	fn target(&self) -> &'static str {
+		match self.job {
+			Some(_) => JOBS_LOG_TARGET,
+			None => SESSION_LOG_TARGET,
+		}
+	}
+}
+
+impl fmt::Display for SessionSpan {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "session_type={} session_id={} master={} self_node={}", self.session_type.label(), self.session_id, self.master, self.self_node)?;
+		if let Some(job) = self.job {
+			write!(f, " job={}", job)?;
+		}
+		Ok(())
+	}
+}
+
+/// Session message, which could be processed by the session job.
+pub trait JobMessage {
+	/// Get job, which is processing this message.
+	fn job(&self) -> Option<&'static str>;
+}
+
+impl JobMessage for Message {
+	fn job(&self) -> Option<&'static str> {
+		match *self {
+			Message::Decryption(ref message) => message.job(),
+			Message::Signing(ref message) => message.job(),
+			_ => None,
+		}
+	}
+}
+
+impl JobMessage for GenerationMessage {
+	fn job(&self) -> Option<&'static str> {
+		None
+	}
+}
+
+impl JobMessage for EncryptionMessage {
+	fn job(&self) -> Option<&'static str> {
+		None
+	}
+}
+
+impl JobMessage for ShareAddMessage {
+	fn job(&self) -> Option<&'static str> {
+		None
+	}
+}
+
+impl JobMessage for KeyRemovalMessage {
+	fn job(&self) -> Option<&'static str> {
+		None
+	}
+}
+
+impl JobMessage for DecryptionMessage {
+	fn job(&self) -> Option<&'static str> {
+		match *self {
+			DecryptionMessage::InitializeDecryptionSession(_)
+				| DecryptionMessage::ConfirmDecryptionInitialization(_) => Some(CONSENSUS_JOB),
+			DecryptionMessage::RequestPartialDecryption(_)
+				| DecryptionMessage::PartialDecryption(_) => Some(PARTIAL_DECRYPTION_JOB),
+			DecryptionMessage::DecryptionSessionError(_)
+				| DecryptionMessage::DecryptionSessionCompleted(_) => None,
+		}
+	}
+}
+
+impl JobMessage for SigningMessage {
+	fn job(&self) -> Option<&'static str> {
+		match *self {
+			SigningMessage::InitializeSigningSession(_)
+				| SigningMessage::ConfirmSigningInitialization(_) => Some(CONSENSUS_JOB),
+			SigningMessage::RequestSigningNonce(_)
+				| SigningMessage::SigningNonce(_)
+				| SigningMessage::RequestPartialSignature(_)
+				| SigningMessage::PartialSignature(_) => Some(SIGNING_JOB),
+			SigningMessage::SigningSessionError(_)
+				| SigningMessage::SigningSessionCompleted(_) => None,
+		}
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::sync::{Once, ONCE_INIT};
+	use std::collections::{BTreeSet, HashSet, VecDeque};
+	use std::time;
+	use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord};
+	use parking_lot::Mutex;
+	use futures::Future;
+	use tokio_core::reactor::Core;
+	use ethkey::{self, Random, Generator};
+	use key_server_cluster::SessionId;
+	use key_server_cluster::cluster::tests::{make_clusters, run_clusters, loop_until, all_connections_established, wait_for_results};
+	use key_server_cluster::key_removal_session::removal_request_hash;
+	use key_server_cluster::metrics::SessionType;
+	use super::{SessionSpan, SESSION_LOG_TARGET, JOBS_LOG_TARGET, CONSENSUS_JOB, PARTIAL_DECRYPTION_JOB};
+
+	/// Max number of records, kept by the capturing logger. Oldest records are dropped first.
+	const MAX_CAPTURED_RECORDS: usize = 10_000;
+
+	/// Captured log record: target and message.
+	type CapturedRecord = (String, String);
+
+	#[derive(Default)]
+	/// Records of sessions, captured by tests.
+	struct CapturedRecords {
+		/// Tags of sessions, which records are captured.
+		sessions: HashSet<String>,
+		/// Captured records, oldest first.
+		records: VecDeque<CapturedRecord>,
+	}
+
+	lazy_static! {
+		static ref CAPTURED_RECORDS: Mutex<CapturedRecords> = Mutex::new(CapturedRecords::default());
+	}
+
+	/// Logger, which captures secret store records of registered sessions.
+	struct CapturingLogger;
+
+	impl Log for CapturingLogger {
+		fn enabled(&self, metadata: &LogMetadata) -> bool {
+			metadata.target().starts_with("secretstore")
+		}
+
+		fn log(&self, record: &LogRecord) {
+			if !self.enabled(record.metadata()) {
+				return;
+			}
+
+			let message = format!("{}", record.args());
+			let mut captured = CAPTURED_RECORDS.lock();
+			if !captured.sessions.iter().any(|tag| message.contains(tag)) {
+				return;
+			}
+			if captured.records.len() == MAX_CAPTURED_RECORDS {
+				captured.records.pop_front();
+			}
+			captured.records.push_back((record.target().to_owned(), message));
+		}
+	}
+
+	/// Tag, which is a part of every record, logged within span of the session.
+	fn session_tag(session_id: &SessionId) -> String {
+		format!("session_id={} ", session_id)
+	}
+
+	/// Install capturing logger (once per process) && start capturing records of given session.
+	pub fn capture_session_records(session_id: &SessionId) {
+		static INIT: Once = ONCE_INIT;
+		INIT.call_once(|| log::set_logger(|max_log_level| {
+			max_log_level.set(LogLevelFilter::Trace);
+			Box::new(CapturingLogger)
+		}).expect("logger is only installed by tests; qed"));
+		CAPTURED_RECORDS.lock().sessions.insert(session_tag(session_id));
+	}
+
+	/// Get captured records of given session.
+	pub fn session_records(session_id: &SessionId) -> Vec<CapturedRecord> {
+		let tag = session_tag(session_id);
+		CAPTURED_RECORDS.lock().records.iter()
+			.filter(|&&(_, ref record)| record.contains(&tag))
+			.cloned()
+			.collect()
+	}
+
+	/// Check if session has logged given event within given span.
+	fn has_event(target: &str, span: &SessionSpan, event: &str) -> bool {
+		session_records(&span.session_id).iter()
+			.any(|&(ref record_target, ref record)| record_target == target && record.starts_with(&format!("{} event={}", span, event)))
+	}
+
+	#[test]
+	fn span_is_displayed_with_session_fields() {
+		let master = Random.generate().unwrap().public().clone();
+		let self_node = Random.generate().unwrap().public().clone();
+		let span = SessionSpan::new(SessionType::Decryption, SessionId::default(), master.clone(), self_node.clone());
+		assert_eq!(format!("{}", span), format!("session_type=decryption session_id={} master={} self_node={}", SessionId::default(), master, self_node));
+		assert_eq!(format!("{}", span.job(PARTIAL_DECRYPTION_JOB)), format!("{} job=partial_decryption", span));
+	}
+
+	#[test]
+	fn decryption_session_events_are_logged_within_session_span() {
+		let session_id = SessionId::from(42);
+		capture_session_records(&session_id);
+
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6083, 2);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// generate document key && decrypt it
+		let requester = Random.generate().unwrap();
+		let signature = ethkey::sign(requester.secret(), &session_id).unwrap();
+		assert!(wait_for_results(&mut core, vec![clusters[0].client().generate_key(session_id.clone(), requester.public().clone(), 1)])[0].is_ok());
+		assert!(wait_for_results(&mut core, vec![clusters[0].client().retrieve_document_key(session_id.clone(), signature.into(), false)])[0].is_ok());
+
+		// every node has logged decryption session events within the session span && its jobs child spans
+		let master = clusters[0].config().self_key_pair.public().clone();
+		let spans: Vec<_> = clusters.iter()
+			.map(|c| SessionSpan::new(SessionType::Decryption, session_id.clone(), master.clone(), c.config().self_key_pair.public().clone()))
+			.collect();
+		loop_until(&mut core, time::Duration::from_millis(300), || spans.iter().all(|span| has_event(SESSION_LOG_TARGET, span, "finished state=Finished")));
+		for span in &spans {
+			assert!(has_event(SESSION_LOG_TARGET, span, "started"));
+			assert!(has_event(SESSION_LOG_TARGET, span, "state_changed"));
+			assert!(has_event(JOBS_LOG_TARGET, &span.job(CONSENSUS_JOB), "send"));
+			assert!(has_event(JOBS_LOG_TARGET, &span.job(CONSENSUS_JOB), "receive"));
+			assert!(has_event(JOBS_LOG_TARGET, &span.job(PARTIAL_DECRYPTION_JOB), "send"));
+			assert!(has_event(JOBS_LOG_TARGET, &span.job(PARTIAL_DECRYPTION_JOB), "receive"));
+		}
+	}
+	#[test]
+	fn key_removal_session_events_are_logged_within_session_span() {
+		let session_id = SessionId::from(43);
+		capture_session_records(&session_id);
+
+		let mut core = Core::new().unwrap();
+		let clusters = make_clusters(&core, 6088, 2);
+		run_clusters(&clusters);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(all_connections_established));
+
+		// generate server key && remove it
+		let author = Random.generate().unwrap();
+		assert!(wait_for_results(&mut core, vec![clusters[0].client().generate_key(session_id.clone(), author.public().clone(), 1)])[0].is_ok());
+		let signature = ethkey::sign(author.secret(), &removal_request_hash(&session_id, 1)).unwrap();
+		let future = clusters[0].client().remove_key(session_id.clone(), signature, 1);
+		loop_until(&mut core, time::Duration::from_millis(300), || clusters.iter().all(|c| !c.config().key_storage.contains(&session_id)));
+		assert_eq!(future.wait(), Ok(BTreeSet::new()));
+
+		// every node has logged key removal session events within the session span
+		let master = clusters[0].config().self_key_pair.public().clone();
+		let spans: Vec<_> = clusters.iter()
+			.map(|c| SessionSpan::new(SessionType::KeyRemoval, session_id.clone(), master.clone(), c.config().self_key_pair.public().clone()))
+			.collect();
+		loop_until(&mut core, time::Duration::from_millis(300), || spans.iter().all(|span| has_event(SESSION_LOG_TARGET, span, "finished state=Finished")));
+		for span in &spans {
+			assert!(has_event(SESSION_LOG_TARGET, span, "started"));
+			assert!(has_event(SESSION_LOG_TARGET, span, "state_changed"));
+			assert!(has_event(SESSION_LOG_TARGET, span, "receive"));
+		}
+
+		// records of other sessions are not captured
+		assert!(session_records(&SessionId::from(44)).is_empty());
+	}
+}
@@ -0,0 +1,225 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+use std::collections::{BTreeSet, VecDeque};
+use parking_lot::Mutex;
+use key_server_cluster::Error;
+
+/// Limits number of concurrently running sessions, started by this node.
+/// Sessions beyond the limit are queued && started (in FIFO order) as active sessions complete.
+pub struct SessionsQueue<K, S> {
+	/// Max number of active sessions.
+	max_active_sessions: usize,
+	/// Max number of queued sessions. When queue is full, new sessions are rejected.
+	max_queued_sessions: usize,
+	/// Max time session could wait in the queue.
+	queue_timeout: Duration,
+	/// Queue data.
+	data: Mutex<SessionsQueueData<K, S>>,
+}
+
+/// Mutable queue data.
+struct SessionsQueueData<K, S> {
+	/// Active sessions.
+	active: BTreeSet<K>,
+	/// Queued sessions.
+	queued: VecDeque<QueuedSession<K, S>>,
+}
+
+/// Single queued session.
+struct QueuedSession<K, S> {
+	/// Session key.
+	key: K,
+	/// Time session has been queued at.
+	time: Instant,
+	/// Session itself.
+	session: S,
+}
+
+impl<K, S> SessionsQueue<K, S> where K: Ord + Clone {
+	pub fn new(max_active_sessions: usize, max_queued_sessions: usize, queue_timeout: Duration) -> Self {
+		SessionsQueue {
+			max_active_sessions: max_active_sessions,
+			max_queued_sessions: max_queued_sessions,
+			queue_timeout: queue_timeout,
+			data: Mutex::new(SessionsQueueData {
+				active: BTreeSet::new(),
+				queued: VecDeque::new(),
+			}),
+		}
+	}
+
+	/// Number of active sessions.
+	pub fn active_count(&self) -> usize {
+		self.data.lock().active.len()
+	}
+
+	/// Number of queued sessions.
+	pub fn queued_count(&self) -> usize {
+		self.data.lock().queued.len()
+	}
+
+	/// Is session waiting in the queue?
+	pub fn is_queued(&self, key: &K) -> bool {
+		self.data.lock().queued.iter().any(|s| &s.key == key)
+	}
+
+	/// Add new session. Returns the session back if it must be started right now,
+	/// or None if session has been queued.
+	pub fn enqueue(&self, key: K, session: S, now: Instant) -> Result<Option<S>, Error> {
+		let mut data = self.data.lock();
+		if data.active.len() < self.max_active_sessions {
+			data.active.insert(key);
+			return Ok(Some(session));
+		}
+
+		if data.queued.len() >= self.max_queued_sessions {
+			return Err(Error::TooManySessions);
+		}
+
+		data.queued.push_back(QueuedSession {
+			key: key,
+			time: now,
+			session: session,
+		});
+		Ok(None)
+	}
+
+	/// Remove completed (or queued) session. Returns sessions which must be started now.
+	pub fn remove(&self, key: &K) -> Vec<(K, S)> {
+		let mut data = self.data.lock();
+		if !data.active.remove(key) {
+			if let Some(position) = data.queued.iter().position(|s| &s.key == key) {
+				data.queued.remove(position);
+			}
+			return Vec::new();
+		}
+
+		let mut sessions = Vec::new();
+		while data.active.len() < self.max_active_sessions {
+			match data.queued.pop_front() {
+				Some(session) => {
+					data.active.insert(session.key.clone());
+					sessions.push((session.key, session.session));
+				},
+				None => break,
+			}
+		}
+		sessions
+	}
+
+	/// Remove sessions, which are waiting in the queue for too long.
+	pub fn expire(&self, now: Instant) -> Vec<(K, S)> {
+		let mut data = self.data.lock();
+		let mut expired = Vec::new();
+		while data.queued.front().map(|s| s.time + self.queue_timeout <= now).unwrap_or(false) {
+			let session = data.queued.pop_front().expect("checked in loop condition; qed");
+			expired.push((session.key, session.session));
+		}
+		expired
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+	use key_server_cluster::Error;
+	use super::SessionsQueue;
+
+	fn keys<S>(sessions: Vec<(u32, S)>) -> Vec<u32> {
+		sessions.into_iter().map(|(k, _)| k).collect()
+	}
+
+	#[test]
+	fn sessions_are_queued_when_limit_is_reached() {
+		let queue = SessionsQueue::new(2, 10, Duration::from_secs(10));
+		let now = Instant::now();
+		assert_eq!(queue.enqueue(1, "1", now), Ok(Some("1")));
+		assert_eq!(queue.enqueue(2, "2", now), Ok(Some("2")));
+		assert_eq!(queue.enqueue(3, "3", now), Ok(None));
+		assert_eq!(queue.enqueue(4, "4", now), Ok(None));
+		assert_eq!(queue.active_count(), 2);
+		assert_eq!(queue.queued_count(), 2);
+		assert!(!queue.is_queued(&1));
+		assert!(queue.is_queued(&3));
+	}
+
+	#[test]
+	fn queued_sessions_are_started_in_fifo_order() {
+		let queue = SessionsQueue::new(1, 10, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, (), now).unwrap();
+		queue.enqueue(2, (), now).unwrap();
+		queue.enqueue(3, (), now).unwrap();
+
+		assert_eq!(keys(queue.remove(&1)), vec![2]);
+		assert_eq!(keys(queue.remove(&2)), vec![3]);
+		assert!(queue.remove(&3).is_empty());
+		assert_eq!(queue.active_count(), 0);
+	}
+
+	#[test]
+	fn sessions_are_rejected_when_queue_is_full() {
+		let queue = SessionsQueue::new(1, 1, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, (), now).unwrap();
+		queue.enqueue(2, (), now).unwrap();
+		assert_eq!(queue.enqueue(3, (), now), Err(Error::TooManySessions));
+		assert_eq!(queue.queued_count(), 1);
+	}
+
+	#[test]
+	fn failed_active_session_promotes_next_queued_session() {
+		let queue = SessionsQueue::new(2, 10, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, (), now).unwrap();
+		queue.enqueue(2, (), now).unwrap();
+		queue.enqueue(3, (), now).unwrap();
+
+		// session is removed both when it fails && when its owner drops it
+		assert_eq!(keys(queue.remove(&2)), vec![3]);
+		assert!(queue.remove(&2).is_empty());
+		assert_eq!(queue.active_count(), 2);
+		assert_eq!(queue.queued_count(), 0);
+	}
+
+	#[test]
+	fn removed_queued_session_is_never_started() {
+		let queue = SessionsQueue::new(1, 10, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, (), now).unwrap();
+		queue.enqueue(2, (), now).unwrap();
+		queue.enqueue(3, (), now).unwrap();
+
+		assert!(queue.remove(&2).is_empty());
+		assert_eq!(keys(queue.remove(&1)), vec![3]);
+	}
+
+	#[test]
+	fn queued_sessions_expire() {
+		let queue = SessionsQueue::new(1, 10, Duration::from_secs(10));
+		let now = Instant::now();
+		queue.enqueue(1, (), now).unwrap();
+		queue.enqueue(2, (), now).unwrap();
+		queue.enqueue(3, (), now + Duration::from_secs(5)).unwrap();
+
+		assert!(queue.expire(now + Duration::from_secs(9)).is_empty());
+		assert_eq!(keys(queue.expire(now + Duration::from_secs(10))), vec![2]);
+		assert_eq!(queue.active_count(), 1);
+		assert_eq!(queue.queued_count(), 1);
+	}
+}
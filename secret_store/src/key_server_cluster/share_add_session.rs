@@ -0,0 +1,1202 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeSet, BTreeMap};
+use std::fmt::{Debug, Formatter, Error as FmtError};
+use std::time;
+use std::sync::Arc;
+use parking_lot::{Condvar, Mutex};
+use ethkey::{self, Public, Secret, Signature};
+use util::{H256, Hashable};
+use types::all::Error as KeyStorageError;
+use key_server_cluster::{Error, NodeId, SessionId, KeyStorage, TransactionalKeyStorage, DocumentKeyShare, DocumentKeyShareVersion};
+use key_server_cluster::cluster::Cluster;
+use key_server_cluster::math;
+use key_server_cluster::servers_set_change_session::{ServersSetChange, servers_set_change_hash};
+use key_server_cluster::message::{Message, ShareAddMessage, InitializeShareAddSession, ShareAddServersSetChange,
+	NewKeysDissemination, NewKeyShareStaged, CommitNewKeyShare, ShareAddSessionError};
+
+/// Share add session API.
+pub trait Session: Send + Sync + 'static {
+	/// Get share add session state.
+	fn state(&self) -> SessionState;
+	/// Wait until session is completed.
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error>;
+}
+
+/// Share add session.
+/// Extends existing key to the new nodes by adding new version of the key, which is shared among
+/// both old and new nodes. The secret itself is never reconstructed.
+/// Brief overview:
+/// 1) initialization: master node (which must be one of old key holders) checks administrator signature
+///    of (old nodes set, new nodes set) pair && sends initialization message to every other node
+/// 2) every old node splits its Lagrange-weighted secret share into subshares using random polynom
+///    of threshold degree && sends these subshares to every node of the new version
+/// 3) every node verifies received subshares, computes its share of the new version && stages it
+/// 4) when every node has staged the new version, master commits it && asks every other node to commit
+/// If any node fails before commit, staged version is discarded on every node.
+/// The same session is used to recover key, when some of key holders are permanently lost. Then only surviving
+/// holders (at least threshold + 1 of them) are dealing subshares of the new version, which is shared among
+/// surviving && new nodes. Versions, shared with lost nodes, are never used again, but they are kept in the storage,
+/// so that interrupted recovery could be restarted from the same version.
+pub struct SessionImpl {
+	/// Unique session id.
+	id: SessionId,
+	/// Public identifier of this node.
+	self_node_id: NodeId,
+	/// Administrator public key.
+	admin_public: Option<Public>,
+	/// Key storage, where new key version is staged until commit.
+	key_storage: TransactionalKeyStorage,
+	/// Cluster which allows this node to send messages to other nodes in the cluster.
+	cluster: Arc<Cluster>,
+	/// SessionImpl completion condvar.
+	completed: Condvar,
+	/// Mutable session data.
+	data: Mutex<SessionData>,
+}
+
+/// SessionImpl creation parameters
+pub struct SessionParams {
+	/// SessionImpl identifier.
+	pub id: SessionId,
+	/// Id of node, on which this session is running.
+	pub self_node_id: Public,
+	/// Administrator public key.
+	pub admin_public: Option<Public>,
+	/// Key storage.
+	pub key_storage: Arc<KeyStorage>,
+	/// Cluster
+	pub cluster: Arc<Cluster>,
+}
+
+#[derive(Debug)]
+/// Mutable data of share add session.
+struct SessionData {
+	/// Current state of the session.
+	state: SessionState,
+	/// Reference to the node, which has started this session.
+	master: Option<NodeId>,
+	/// Key share of this node. On new nodes it has no versions until new version is staged.
+	key_share: Option<DocumentKeyShare>,
+	/// Nodes, which are dealing subshares of the new version.
+	old_nodes: BTreeSet<NodeId>,
+	/// All nodes of the new key version along with their identification numbers.
+	id_numbers: BTreeMap<NodeId, Secret>,
+	/// Hash of the key version, which is re-shared.
+	version: Option<H256>,
+	/// Is the key recovered from surviving key holders?
+	is_recovery: bool,
+	/// Point, used to verify subshares.
+	derived_point: Option<Public>,
+	/// Subshares of the new version, received from old nodes.
+	subshares: BTreeMap<NodeId, Secret>,
+	/// Nodes, which have not yet staged the new version (on master node).
+	awaiting_staging: BTreeSet<NodeId>,
+	/// Session result.
+	result: Option<Result<(), Error>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Share add session state.
+pub enum SessionState {
+	/// Every node starts in this state.
+	WaitingForInitialization,
+	/// Node is waiting for subshares from every old node.
+	WaitingForKeysDissemination,
+	/// Master node has staged new version && is waiting for every other node to stage it.
+	WaitingForStaging,
+	/// Node has staged new version && is waiting for master to commit it.
+	WaitingForCommit,
+	/// New version is committed.
+	Finished,
+	/// Session has failed. New version is discarded.
+	Failed,
+}
+
+impl SessionImpl {
+	/// Create new share add session.
+	pub fn new(params: SessionParams) -> Self {
+		SessionImpl {
+			id: params.id,
+			self_node_id: params.self_node_id,
+			admin_public: params.admin_public,
+			key_storage: TransactionalKeyStorage::new(params.key_storage),
+			cluster: params.cluster,
+			completed: Condvar::new(),
+			data: Mutex::new(SessionData {
+				state: SessionState::WaitingForInitialization,
+				master: None,
+				key_share: None,
+				old_nodes: BTreeSet::new(),
+				id_numbers: BTreeMap::new(),
+				version: None,
+				is_recovery: false,
+				derived_point: None,
+				subshares: BTreeMap::new(),
+				awaiting_staging: BTreeSet::new(),
+				result: None,
+			}),
+		}
+	}
+
+	/// Get this node Id.
+	pub fn node(&self) -> &NodeId {
+		&self.self_node_id
+	}
+
+	/// Start new session initialization. This must be called on master node.
+	pub fn initialize(&self, old_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<(), Error> {
+		let mut data = self.data.lock();
+		let result = self.process_initialize(&mut *data, old_nodes_set, new_nodes_set, None, false, admin_signature);
+		self.process_result(&mut *data, result)
+	}
+
+	/// Start new key recovery session. Key is re-shared among surviving key holders && new nodes, and the rest of
+	/// key holders are considered lost. Admin signature must be the signature of (surviving nodes set, new nodes set)
+	/// pair. This must be called on master node.
+	pub fn initialize_recovery(&self, surviving_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, admin_signature: Signature) -> Result<(), Error> {
+		let mut data = self.data.lock();
+		let result = self.process_initialize(&mut *data, surviving_nodes_set, new_nodes_set, None, true, admin_signature);
+		self.process_result(&mut *data, result)
+	}
+
+	/// Start new session initialization as a part of servers set change session. Key is extended to every node of
+	/// the new servers set && admin signature must be the signature of the servers set change. This must be called on master node.
+	pub fn initialize_with_servers_set(&self, old_nodes_set: BTreeSet<NodeId>, servers_set_change: ServersSetChange, admin_signature: Signature) -> Result<(), Error> {
+		let new_nodes_set = old_nodes_set.union(&servers_set_change.new_servers_set).cloned().collect();
+		let mut data = self.data.lock();
+		let result = self.process_initialize(&mut *data, old_nodes_set, new_nodes_set, Some(servers_set_change), false, admin_signature);
+		self.process_result(&mut *data, result)
+	}
+
+	/// Get session result. None if session is not yet completed.
+	pub fn result(&self) -> Option<Result<(), Error>> {
+		self.data.lock().result.clone()
+	}
+
+	/// When session initialization message is received.
+	pub fn on_initialize_session(&self, sender: NodeId, message: &InitializeShareAddSession) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_initialize_session(&mut *data, sender, message);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When subshares of the new version are received.
+	pub fn on_keys_dissemination(&self, sender: NodeId, message: &NewKeysDissemination) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_keys_dissemination(&mut *data, sender, message);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When node has staged the new version.
+	pub fn on_new_key_share_staged(&self, sender: NodeId, message: &NewKeyShareStaged) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_new_key_share_staged(&mut *data, sender);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When master asks to commit the new version.
+	pub fn on_commit_new_key_share(&self, sender: NodeId, message: &CommitNewKeyShare) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+		let result = self.process_commit_new_key_share(&mut *data, sender);
+		self.process_result(&mut *data, result)
+	}
+
+	/// When error has occured on another node.
+	pub fn on_session_error(&self, sender: NodeId, message: &ShareAddSessionError) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		// committed version could not be rolled back && failed session is already reported
+		if data.state == SessionState::Finished || data.state == SessionState::Failed {
+			return Ok(());
+		}
+
+		warn!("{}: share add session failed with error: {} from {}", self.node(), message.error, sender);
+
+		self.fail(&mut *data, Error::Io(message.error.clone()), Some(&sender));
+
+		Ok(())
+	}
+
+	/// When connection to one of cluster nodes has timeouted.
+	pub fn on_node_timeout(&self, node: &NodeId) {
+		let mut data = self.data.lock();
+
+		// only nodes of the new version are participating in the session
+		if data.state == SessionState::Finished || data.state == SessionState::Failed || !data.id_numbers.contains_key(node) {
+			return;
+		}
+
+		warn!("{}: share add session failed because {} connection has timeouted", self.node(), node);
+
+		self.fail(&mut *data, Error::NodeDisconnected, Some(node));
+	}
+
+	/// When session timeout has occured.
+	pub fn on_session_timeout(&self) {
+		let mut data = self.data.lock();
+
+		if data.state == SessionState::Finished || data.state == SessionState::Failed {
+			return;
+		}
+
+		warn!("{}: share add session failed with timeout", self.node());
+
+		self.fail(&mut *data, Error::NodeDisconnected, None);
+	}
+
+	/// Start session on master node.
+	fn process_initialize(&self, data: &mut SessionData, old_nodes_set: BTreeSet<NodeId>, new_nodes_set: BTreeSet<NodeId>, servers_set_change: Option<ServersSetChange>, is_recovery: bool, admin_signature: Signature) -> Result<(), Error> {
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// check that administrator has requested this change
+		self.check_admin_signature(&old_nodes_set, &new_nodes_set, servers_set_change.as_ref(), &admin_signature)?;
+
+		// key is extended from the current version && recovered from the last version, which has been shared with lost nodes.
+		// This version is retired on commit, but retired versions are also considered here, so that recovery, interrupted
+		// after master has committed the new version, could be restarted from the same version
+		let key_share = self.read_key_share()?;
+		let (version, version_id_numbers) = {
+			let version = if is_recovery {
+				key_share.versions.iter().rev()
+					.find(|v| old_nodes_set.iter().all(|n| v.id_numbers.contains_key(n)) && v.id_numbers.keys().any(|n| !new_nodes_set.contains(n)))
+					.ok_or(Error::InvalidNodesConfiguration)?
+			} else {
+				key_share.last_version().map_err(|e| Error::KeyStorage(e.into()))?
+			};
+			(version.hash.clone(), version.id_numbers.clone())
+		};
+
+		// master must be one of old nodes && old nodes must be holders of the version (all of them, unless key is recovered)
+		let version_nodes: BTreeSet<_> = version_id_numbers.keys().cloned().collect();
+		if !old_nodes_set.contains(self.node()) || !version_nodes.is_superset(&old_nodes_set) || (!is_recovery && version_nodes != old_nodes_set) {
+			return Err(Error::InvalidNodesConfiguration);
+		}
+		if is_recovery {
+			// at least threshold + 1 surviving nodes are required to reconstruct the key && lost nodes are not coming back
+			if key_share.threshold >= old_nodes_set.len() {
+				return Err(Error::InvalidNodesCount);
+			}
+			if version_nodes.difference(&old_nodes_set).any(|n| new_nodes_set.contains(n)) {
+				return Err(Error::InvalidNodesConfiguration);
+			}
+		}
+		check_nodes_sets(&old_nodes_set, &new_nodes_set)?;
+
+		// old nodes keep their identification numbers && new nodes are given random numbers
+		let mut id_numbers: BTreeMap<_, _> = version_id_numbers.into_iter()
+			.filter(|&(ref n, _)| old_nodes_set.contains(n))
+			.collect();
+		for new_node in new_nodes_set.difference(&old_nodes_set) {
+			id_numbers.insert(new_node.clone(), math::generate_random_scalar()?);
+		}
+
+		// update state
+		let derived_point = math::generate_random_point()?;
+		data.master = Some(self.node().clone());
+		data.old_nodes = old_nodes_set;
+		data.id_numbers = id_numbers;
+		data.version = Some(version.clone());
+		data.is_recovery = is_recovery;
+		data.derived_point = Some(derived_point.clone());
+		data.awaiting_staging = new_nodes_set.iter().filter(|n| *n != self.node()).cloned().collect();
+		data.state = SessionState::WaitingForKeysDissemination;
+
+		// ask every other node to participate in the session
+		for node in &data.awaiting_staging {
+			self.cluster.send(node, Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(InitializeShareAddSession {
+				session: self.id.clone().into(),
+				admin_signature: admin_signature.clone().into(),
+				servers_set_change: servers_set_change.as_ref().map(|change| ShareAddServersSetChange {
+					session: change.session_id.clone().into(),
+					old_servers_set: change.old_servers_set.iter().cloned().map(Into::into).collect(),
+					new_servers_set: change.new_servers_set.iter().cloned().map(Into::into).collect(),
+				}),
+				old_nodes: data.old_nodes.iter().cloned().map(Into::into).collect(),
+				nodes: data.id_numbers.iter().map(|(n, k)| (n.clone().into(), k.clone().into())).collect(),
+				version: version.clone().into(),
+				is_recovery: is_recovery,
+				author: key_share.author.clone().into(),
+				threshold: key_share.threshold,
+				common_point: key_share.common_point.clone().map(Into::into),
+				encrypted_point: key_share.encrypted_point.clone().map(Into::into),
+				derived_point: derived_point.clone().into(),
+			})))?;
+		}
+
+		// and start dealing subshares
+		data.key_share = Some(key_share);
+		self.disseminate_keys(data)
+	}
+
+	/// Process session initialization message.
+	fn process_initialize_session(&self, data: &mut SessionData, sender: NodeId, message: &InitializeShareAddSession) -> Result<(), Error> {
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// check that administrator has requested this change
+		let old_nodes_set: BTreeSet<NodeId> = message.old_nodes.iter().cloned().map(Into::into).collect();
+		let id_numbers: BTreeMap<NodeId, Secret> = message.nodes.iter().map(|(n, k)| (n.clone().into(), k.clone().into())).collect();
+		let new_nodes_set: BTreeSet<NodeId> = id_numbers.keys().cloned().collect();
+		let servers_set_change = message.servers_set_change.as_ref().map(|change| ServersSetChange {
+			session_id: change.session.clone().into(),
+			old_servers_set: change.old_servers_set.iter().cloned().map(Into::into).collect(),
+			new_servers_set: change.new_servers_set.iter().cloned().map(Into::into).collect(),
+		});
+		self.check_admin_signature(&old_nodes_set, &new_nodes_set, servers_set_change.as_ref(), &message.admin_signature)?;
+
+		// check that master is one of old nodes && this node is one of new nodes
+		check_nodes_sets(&old_nodes_set, &new_nodes_set)?;
+		if !old_nodes_set.contains(&sender) || !new_nodes_set.contains(self.node()) {
+			return Err(Error::InvalidNodesConfiguration);
+		}
+		if message.threshold >= old_nodes_set.len() {
+			return Err(Error::InvalidThreshold {
+				requested: message.threshold,
+				nodes: old_nodes_set.len(),
+			});
+		}
+
+		let version: H256 = message.version.clone().into();
+		let author: Public = message.author.clone().into();
+		let key_share = if old_nodes_set.contains(self.node()) {
+			// old node must hold the same version of the key, as master does
+			let key_share = self.read_key_share()?;
+			{
+				let old_id_numbers = &key_share.version(&version).map_err(|e| Error::KeyStorage(e.into()))?.id_numbers;
+				let version_nodes: BTreeSet<_> = old_id_numbers.keys().cloned().collect();
+				if key_share.threshold != message.threshold
+					|| !version_nodes.is_superset(&old_nodes_set)
+					|| (version_nodes == old_nodes_set) == message.is_recovery
+					|| version_nodes.difference(&old_nodes_set).any(|n| new_nodes_set.contains(n))
+					|| old_nodes_set.iter().any(|n| id_numbers.get(n) != old_id_numbers.get(n)) {
+					return Err(Error::InvalidMessage);
+				}
+			}
+			key_share
+		} else if message.is_recovery && self.key_storage.contains(&self.id) {
+			// new node could already hold the version, committed by previous recovery attempt. It is replaced on commit
+			let key_share = self.read_key_share()?;
+			if key_share.author != author || key_share.threshold != message.threshold {
+				return Err(Error::InvalidMessage);
+			}
+			key_share
+		} else {
+			// new node must not hold any version of the key
+			if self.key_storage.contains(&self.id) {
+				return Err(Error::DuplicateSessionId);
+			}
+
+			DocumentKeyShare {
+				author: author,
+				threshold: message.threshold,
+				common_point: message.common_point.clone().map(Into::into),
+				encrypted_point: message.encrypted_point.clone().map(Into::into),
+				versions: Vec::new(),
+			}
+		};
+
+		// update state
+		let is_old_node = old_nodes_set.contains(self.node());
+		data.master = Some(sender);
+		data.key_share = Some(key_share);
+		data.old_nodes = old_nodes_set;
+		data.id_numbers = id_numbers;
+		data.version = Some(version);
+		data.is_recovery = message.is_recovery;
+		data.derived_point = Some(message.derived_point.clone().into());
+		data.state = SessionState::WaitingForKeysDissemination;
+
+		// old nodes are dealing subshares
+		if is_old_node {
+			self.disseminate_keys(data)?;
+		}
+
+		Ok(())
+	}
+
+	/// Process subshares of the new version.
+	fn process_keys_dissemination(&self, data: &mut SessionData, sender: NodeId, message: &NewKeysDissemination) -> Result<(), Error> {
+		// check state
+		if data.state == SessionState::WaitingForInitialization {
+			return Err(Error::TooEarlyForRequest);
+		}
+		if data.state != SessionState::WaitingForKeysDissemination {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// only old nodes are dealing subshares && every old node is dealing subshares once
+		if !data.old_nodes.contains(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+		if data.subshares.contains_key(&sender) {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// verify subshare
+		{
+			let threshold = data.key_share.as_ref().expect("key_share is filled in initialization phase; KD phase follows initialization phase; qed").threshold;
+			let derived_point = data.derived_point.as_ref().expect("derived_point is filled in initialization phase; KD phase follows initialization phase; qed");
+			let self_id_number = data.id_numbers.get(self.node()).expect("this node is one of new nodes; checked in initialization phase; qed");
+			let publics: Vec<Public> = message.publics.iter().cloned().map(Into::into).collect();
+			if publics.len() == 0 || publics.len() - 1 != threshold {
+				return Err(Error::InvalidMessage);
+			}
+			if !math::keys_verification(threshold, derived_point, self_id_number, &message.secret1, &message.secret2, &publics)? {
+				warn!("{}: share add session has received invalid subshare from {}", self.node(), sender);
+				return Err(Error::InvalidKeyShare(sender));
+			}
+		}
+
+		data.subshares.insert(sender, message.secret1.clone().into());
+		self.try_stage_new_version(data)
+	}
+
+	/// Process notification that node has staged the new version.
+	fn process_new_key_share_staged(&self, data: &mut SessionData, sender: NodeId) -> Result<(), Error> {
+		// only master is waiting for these notifications
+		if data.master.as_ref() != Some(self.node()) {
+			return Err(Error::InvalidMessage);
+		}
+		if data.state != SessionState::WaitingForKeysDissemination && data.state != SessionState::WaitingForStaging {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if !data.awaiting_staging.remove(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		self.try_commit_new_version(data)
+	}
+
+	/// Process request to commit the new version.
+	fn process_commit_new_key_share(&self, data: &mut SessionData, sender: NodeId) -> Result<(), Error> {
+		// check state
+		if data.state != SessionState::WaitingForCommit {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if data.master.as_ref() != Some(&sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		let result = self.key_storage.commit().map_err(|e| Error::KeyStorage(e.into()));
+		self.complete(data, result.clone());
+		result
+	}
+
+	/// Send subshares of this node to every node of the new version.
+	fn disseminate_keys(&self, data: &mut SessionData) -> Result<(), Error> {
+		let (threshold, subshare) = {
+			let key_share = data.key_share.as_ref().expect("key_share is filled in initialization phase; KD phase follows initialization phase; qed");
+			let version = data.version.as_ref().expect("version is filled in initialization phase; KD phase follows initialization phase; qed");
+			let old_version = key_share.version(version).map_err(|e| Error::KeyStorage(e.into()))?;
+			let self_id_number = old_version.id_numbers.get(self.node()).expect("this node is one of old nodes; checked by caller; qed");
+			let subshare = math::compute_secret_subshare(self_id_number, &old_version.secret_share,
+				old_version.id_numbers.iter().filter(|&(n, _)| n != self.node() && data.old_nodes.contains(n)).map(|(_, k)| k))?;
+			(key_share.threshold, subshare)
+		};
+
+		// free coefficient of the first polynom is the subshare => sum of all first polynoms is the polynom,
+		// which is sharing the same secret as the old version does
+		let mut polynom1 = math::generate_random_polynom(threshold)?;
+		polynom1[0] = subshare;
+		let polynom2 = math::generate_random_polynom(threshold)?;
+		let derived_point = data.derived_point.clone().expect("derived_point is filled in initialization phase; KD phase follows initialization phase; qed");
+		let publics = math::public_values_generation(threshold, &derived_point, &polynom1, &polynom2)?;
+
+		for (node, id_number) in &data.id_numbers {
+			let secret1 = math::compute_polynom(&polynom1, id_number)?;
+			if node == self.node() {
+				data.subshares.insert(node.clone(), secret1);
+				continue;
+			}
+
+			let secret2 = math::compute_polynom(&polynom2, id_number)?;
+			self.cluster.send(node, Message::ShareAdd(ShareAddMessage::NewKeysDissemination(NewKeysDissemination {
+				session: self.id.clone().into(),
+				secret1: secret1.into(),
+				secret2: secret2.into(),
+				publics: publics.iter().cloned().map(Into::into).collect(),
+			})))?;
+		}
+
+		self.try_stage_new_version(data)
+	}
+
+	/// Stage the new version if subshares from every old node are received.
+	fn try_stage_new_version(&self, data: &mut SessionData) -> Result<(), Error> {
+		if data.subshares.len() != data.old_nodes.len() {
+			return Ok(());
+		}
+
+		let secret_share = math::compute_secret_share(data.subshares.values())?;
+		let mut key_share = data.key_share.clone().expect("key_share is filled in initialization phase; KD phase follows initialization phase; qed");
+		let is_old_node = !key_share.versions.is_empty();
+		if data.is_recovery {
+			// versions, shared with lost nodes, are retired (new nodes are not holding these)
+			let version = data.version.as_ref().expect("version is filled in initialization phase; KD phase follows initialization phase; qed");
+			let lost_nodes: BTreeSet<_> = match key_share.version(version) {
+				Ok(version) => version.id_numbers.keys().filter(|n| !data.old_nodes.contains(n)).cloned().collect(),
+				Err(_) => BTreeSet::new(),
+			};
+			key_share.retire_versions(&lost_nodes);
+
+			// version of the same nodes, committed by previous recovery attempt, is replaced
+			key_share.versions.retain(|v| !v.id_numbers.keys().eq(data.id_numbers.keys()));
+		}
+		key_share.versions.push(DocumentKeyShareVersion::new(data.id_numbers.clone(), secret_share));
+		if is_old_node {
+			self.key_storage.update(self.id.clone(), key_share)
+		} else {
+			self.key_storage.insert(self.id.clone(), key_share)
+		}.map_err(|e| Error::KeyStorage(e.into()))?;
+
+		// master is waiting for other nodes
+		let master = data.master.clone().expect("master is filled in initialization phase; KD phase follows initialization phase; qed");
+		if &master == self.node() {
+			data.state = SessionState::WaitingForStaging;
+			return self.try_commit_new_version(data);
+		}
+
+		// other nodes are waiting for master
+		data.state = SessionState::WaitingForCommit;
+		self.cluster.send(&master, Message::ShareAdd(ShareAddMessage::NewKeyShareStaged(NewKeyShareStaged {
+			session: self.id.clone().into(),
+		})))
+	}
+
+	/// Commit the new version on master if every node has staged it.
+	fn try_commit_new_version(&self, data: &mut SessionData) -> Result<(), Error> {
+		if data.state != SessionState::WaitingForStaging || !data.awaiting_staging.is_empty() {
+			return Ok(());
+		}
+
+		// commit on master first, so that other nodes won't commit if master fails
+		let result = self.key_storage.commit().map_err(|e| Error::KeyStorage(e.into()));
+		self.complete(data, result.clone());
+		result?;
+
+		for node in data.id_numbers.keys().filter(|n| *n != self.node()) {
+			self.cluster.send(node, Message::ShareAdd(ShareAddMessage::CommitNewKeyShare(CommitNewKeyShare {
+				session: self.id.clone().into(),
+			})))?;
+		}
+
+		Ok(())
+	}
+
+	/// Fail session if error has occured while processing request.
+	fn process_result(&self, data: &mut SessionData, result: Result<(), Error>) -> Result<(), Error> {
+		match result {
+			Err(Error::TooEarlyForRequest) => Err(Error::TooEarlyForRequest),
+			Err(err) => {
+				if data.state != SessionState::Finished && data.state != SessionState::Failed {
+					self.fail(data, err.clone(), None);
+				}
+				Err(err)
+			},
+			Ok(()) => Ok(()),
+		}
+	}
+
+	/// Discard staged version && complete session with error.
+	fn fail(&self, data: &mut SessionData, error: Error, failed_node: Option<&NodeId>) {
+		self.key_storage.rollback();
+
+		// master must ask other nodes to discard staged version
+		if data.master.as_ref() == Some(self.node()) {
+			for node in data.id_numbers.keys().filter(|n| *n != self.node() && Some(*n) != failed_node) {
+				// do not bother processing send error, as we already processing error
+				let _ = self.cluster.send(node, Message::ShareAdd(ShareAddMessage::ShareAddSessionError(ShareAddSessionError {
+					session: self.id.clone().into(),
+					error: error.clone().into(),
+				})));
+			}
+		}
+
+		self.complete(data, Err(error));
+	}
+
+	/// Complete session with given result.
+	fn complete(&self, data: &mut SessionData, result: Result<(), Error>) {
+		data.state = if result.is_ok() { SessionState::Finished } else { SessionState::Failed };
+		data.result = Some(result);
+		self.completed.notify_all();
+	}
+
+	/// Read key share from the key storage.
+	fn read_key_share(&self) -> Result<DocumentKeyShare, Error> {
+		match self.key_storage.get(&self.id) {
+			Ok(key_share) => Ok(key_share),
+			Err(KeyStorageError::DocumentNotFound) => Err(Error::ServerKeyIsNotFound),
+			Err(err) => Err(Error::KeyStorage(err.into())),
+		}
+	}
+
+	/// Check that administrator has signed given nodes sets (or the servers set change, if session is a part of it).
+	fn check_admin_signature(&self, old_nodes_set: &BTreeSet<NodeId>, new_nodes_set: &BTreeSet<NodeId>, servers_set_change: Option<&ServersSetChange>, admin_signature: &Signature) -> Result<(), Error> {
+		let admin_public = self.admin_public.as_ref().ok_or(Error::AccessDenied)?;
+		let signed_hash = match servers_set_change {
+			Some(change) => {
+				// key holders must be the nodes of the changed servers set && key is extended to the new servers set only
+				if !old_nodes_set.is_subset(&change.old_servers_set) || !change.new_servers_set.is_subset(&change.old_servers_set)
+					|| old_nodes_set.union(&change.new_servers_set).collect::<BTreeSet<_>>() != new_nodes_set.iter().collect() {
+					return Err(Error::InvalidNodesConfiguration);
+				}
+				servers_set_change_hash(&change.session_id, &change.old_servers_set, &change.new_servers_set)
+			},
+			None => nodes_sets_hash(old_nodes_set, new_nodes_set),
+		};
+		if !ethkey::verify_public(admin_public, admin_signature, &signed_hash)? {
+			return Err(Error::AccessDenied);
+		}
+
+		Ok(())
+	}
+}
+
+impl Session for SessionImpl {
+	fn state(&self) -> SessionState {
+		self.data.lock().state.clone()
+	}
+
+	fn wait(&self, timeout: Option<time::Duration>) -> Result<(), Error> {
+		let mut data = self.data.lock();
+		if !data.result.is_some() {
+			match timeout {
+				None => self.completed.wait(&mut data),
+				Some(timeout) => { self.completed.wait_for(&mut data, timeout); },
+			}
+		}
+
+		match data.result.as_ref() {
+			Some(result) => result.clone(),
+			None => Err(Error::Io("timeout".into())),
+		}
+	}
+}
+
+impl Debug for SessionImpl {
+	fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+		write!(f, "Share add session {} on {}", self.id, self.self_node_id)
+	}
+}
+
+/// Compute hash of (old nodes set, new nodes set) pair, which must be signed by administrator.
+pub fn nodes_sets_hash(old_nodes_set: &BTreeSet<NodeId>, new_nodes_set: &BTreeSet<NodeId>) -> H256 {
+	let mut data = Vec::new();
+	for node in old_nodes_set {
+		data.extend_from_slice(&**node);
+	}
+	for node in new_nodes_set {
+		data.extend_from_slice(&**node);
+	}
+	data.sha3()
+}
+
+/// Check that new nodes set is extending old nodes set.
+fn check_nodes_sets(old_nodes_set: &BTreeSet<NodeId>, new_nodes_set: &BTreeSet<NodeId>) -> Result<(), Error> {
+	if old_nodes_set.is_empty() || !old_nodes_set.is_subset(new_nodes_set) || old_nodes_set.len() == new_nodes_set.len() {
+		return Err(Error::InvalidNodesConfiguration);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time;
+	use std::sync::Arc;
+	use std::collections::{BTreeSet, BTreeMap, VecDeque};
+	use ethkey::{self, Random, Generator, KeyPair, Secret};
+	use key_server_cluster::{NodeId, SessionId, Error, KeyStorage, DummyKeyStorage, DocumentKeyShare, DocumentKeyShareVersion};
+	use key_server_cluster::math;
+	use key_server_cluster::message::{self, Message, ShareAddMessage};
+	use key_server_cluster::cluster::tests::DummyCluster;
+	use key_server_cluster::share_add_session::{Session, SessionImpl, SessionState, SessionParams, nodes_sets_hash};
+
+	#[derive(Debug)]
+	struct Node {
+		pub cluster: Arc<DummyCluster>,
+		pub key_storage: Arc<DummyKeyStorage>,
+		pub session: SessionImpl,
+	}
+
+	#[derive(Debug)]
+	struct MessageLoop {
+		pub session_id: SessionId,
+		pub admin: KeyPair,
+		pub joint_secret: Secret,
+		pub old_nodes_set: BTreeSet<NodeId>,
+		pub new_nodes_set: BTreeSet<NodeId>,
+		pub nodes: BTreeMap<NodeId, Node>,
+		pub queue: VecDeque<(NodeId, NodeId, Message)>,
+	}
+
+	impl MessageLoop {
+		pub fn new(threshold: usize, old_nodes_num: usize, new_nodes_num: usize) -> Self {
+			let session_id = SessionId::default();
+			let admin = Random.generate().unwrap();
+			let author = Random.generate().unwrap().public().clone();
+
+			// share random secret among old nodes
+			let polynom = math::generate_random_polynom(threshold).unwrap();
+			let old_id_numbers: BTreeMap<_, _> = (0..old_nodes_num)
+				.map(|_| (Random.generate().unwrap().public().clone(), math::generate_random_scalar().unwrap()))
+				.collect();
+			let new_nodes: Vec<_> = (0..new_nodes_num).map(|_| Random.generate().unwrap().public().clone()).collect();
+			let old_nodes_set: BTreeSet<_> = old_id_numbers.keys().cloned().collect();
+			let new_nodes_set: BTreeSet<_> = old_nodes_set.iter().cloned().chain(new_nodes.iter().cloned()).collect();
+
+			let mut nodes = BTreeMap::new();
+			for node_id in &new_nodes_set {
+				let cluster = Arc::new(DummyCluster::new(node_id.clone()));
+				for other_node_id in &new_nodes_set {
+					cluster.add_node(other_node_id.clone());
+				}
+
+				let key_storage = Arc::new(DummyKeyStorage::default());
+				if let Some(id_number) = old_id_numbers.get(node_id) {
+					key_storage.insert(session_id.clone(), DocumentKeyShare {
+						author: author.clone(),
+						threshold: threshold,
+						common_point: None,
+						encrypted_point: None,
+						versions: vec![DocumentKeyShareVersion::new(old_id_numbers.clone(), math::compute_polynom(&polynom, id_number).unwrap())],
+					}).unwrap();
+				}
+
+				let session = SessionImpl::new(SessionParams {
+					id: session_id.clone(),
+					self_node_id: node_id.clone(),
+					admin_public: Some(admin.public().clone()),
+					key_storage: key_storage.clone(),
+					cluster: cluster.clone(),
+				});
+				nodes.insert(node_id.clone(), Node { cluster: cluster, key_storage: key_storage, session: session });
+			}
+
+			MessageLoop {
+				session_id: session_id,
+				admin: admin,
+				joint_secret: polynom[0].clone(),
+				old_nodes_set: old_nodes_set,
+				new_nodes_set: new_nodes_set,
+				nodes: nodes,
+				queue: VecDeque::new(),
+			}
+		}
+
+		pub fn master(&self) -> &Node {
+			&self.nodes[self.old_nodes_set.iter().nth(0).unwrap()]
+		}
+
+		pub fn new_nodes(&self) -> Vec<&Node> {
+			self.nodes.values().filter(|n| !self.old_nodes_set.contains(n.session.node())).collect()
+		}
+
+		pub fn admin_signature(&self) -> ethkey::Signature {
+			ethkey::sign(self.admin.secret(), &nodes_sets_hash(&self.old_nodes_set, &self.new_nodes_set)).unwrap()
+		}
+
+		pub fn initialize(&self) -> Result<(), Error> {
+			self.master().session.initialize(self.old_nodes_set.clone(), self.new_nodes_set.clone(), self.admin_signature())
+		}
+
+		pub fn initialize_recovery(&self) -> Result<(), Error> {
+			self.master().session.initialize_recovery(self.old_nodes_set.clone(), self.new_nodes_set.clone(), self.admin_signature())
+		}
+
+		pub fn lose_old_nodes(&mut self, lost_nodes_num: usize) -> Vec<Node> {
+			// master is never lost
+			let lost_nodes: Vec<_> = self.old_nodes_set.iter().rev().take(lost_nodes_num).cloned().collect();
+			lost_nodes.into_iter().map(|node| {
+				self.old_nodes_set.remove(&node);
+				self.new_nodes_set.remove(&node);
+				self.nodes.remove(&node).unwrap()
+			}).collect()
+		}
+
+		pub fn restart_sessions(&mut self) {
+			for node in self.nodes.values_mut() {
+				node.session = SessionImpl::new(SessionParams {
+					id: self.session_id.clone(),
+					self_node_id: node.cluster.node(),
+					admin_public: Some(self.admin.public().clone()),
+					key_storage: node.key_storage.clone(),
+					cluster: node.cluster.clone(),
+				});
+			}
+		}
+
+		pub fn take_message(&mut self) -> Option<(NodeId, NodeId, Message)> {
+			self.nodes.values()
+				.filter_map(|n| n.cluster.take_message().map(|m| (n.session.node().clone(), m.0, m.1)))
+				.nth(0)
+				.or_else(|| self.queue.pop_front())
+		}
+
+		pub fn process_message(&mut self, msg: (NodeId, NodeId, Message)) -> Result<(), Error> {
+			let result = {
+				let session = &self.nodes[&msg.1].session;
+				match msg.2 {
+					Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(ref message)) => session.on_initialize_session(msg.0.clone(), message),
+					Message::ShareAdd(ShareAddMessage::NewKeysDissemination(ref message)) => session.on_keys_dissemination(msg.0.clone(), message),
+					Message::ShareAdd(ShareAddMessage::NewKeyShareStaged(ref message)) => session.on_new_key_share_staged(msg.0.clone(), message),
+					Message::ShareAdd(ShareAddMessage::CommitNewKeyShare(ref message)) => session.on_commit_new_key_share(msg.0.clone(), message),
+					Message::ShareAdd(ShareAddMessage::ShareAddSessionError(ref message)) => session.on_session_error(msg.0.clone(), message),
+					_ => panic!("unexpected"),
+				}
+			};
+
+			match result {
+				Err(Error::TooEarlyForRequest) => {
+					self.queue.push_back(msg);
+					Ok(())
+				},
+				result => result,
+			}
+		}
+
+		pub fn run(&mut self) {
+			while let Some((from, to, message)) = self.take_message() {
+				self.process_message((from, to, message)).unwrap();
+			}
+		}
+
+		pub fn versions_count(&self, node: &NodeId) -> Option<usize> {
+			self.nodes[node].key_storage.get(&self.session_id).ok().map(|key_share| key_share.versions.len())
+		}
+	}
+
+	fn assert_key_is_shared(l: &MessageLoop, threshold: usize, decryptors: &[NodeId]) {
+		// every decryptor holds the same active version && any threshold + 1 of them are sharing the same secret
+		let active_version = l.nodes[&decryptors[0]].key_storage.get(&l.session_id).unwrap().last_version().unwrap().clone();
+		let document_secret_plain = Random.generate().unwrap().public().clone();
+		let joint_public = math::compute_public_share(&l.joint_secret).unwrap();
+		for excluded in 0..decryptors.len() {
+			let nodes: Vec<_> = decryptors.iter().enumerate()
+				.filter(|&(i, _)| i != excluded)
+				.map(|(_, node)| node)
+				.collect();
+			let id_numbers: Vec<_> = nodes.iter().map(|node| active_version.id_numbers[*node].clone()).collect();
+			let secret_shares: Vec<_> = nodes.iter().map(|node| {
+				let version = l.nodes[*node].key_storage.get(&l.session_id).unwrap().last_version().unwrap().clone();
+				assert_eq!(version.hash, active_version.hash);
+				version.secret_share
+			}).collect();
+			let (document_secret_decrypted, document_secret_decrypted_test) = math::tests::do_encryption_and_decryption(threshold,
+				&joint_public, &id_numbers, &secret_shares, Some(&l.joint_secret), document_secret_plain.clone());
+			assert_eq!(document_secret_plain, document_secret_decrypted);
+			assert_eq!(document_secret_plain, document_secret_decrypted_test);
+		}
+	}
+
+	#[test]
+	fn key_is_extended_to_new_nodes() {
+		let mut l = MessageLoop::new(1, 3, 2);
+		l.initialize().unwrap();
+		l.run();
+
+		// every node has committed the new version
+		for node in l.nodes.values() {
+			assert_eq!(node.session.state(), SessionState::Finished);
+			let key_share = node.key_storage.get(&l.session_id).unwrap();
+			let last_version = key_share.last_version().unwrap();
+			assert_eq!(last_version.id_numbers.keys().cloned().collect::<BTreeSet<_>>(), l.new_nodes_set);
+		}
+		for node in &l.old_nodes_set {
+			assert_eq!(l.versions_count(node), Some(2));
+		}
+		assert_eq!(l.master().session.wait(None), Ok(()));
+
+		// two new nodes && one old node are able to decrypt data: any threshold + 1 of them are sharing the same secret
+		let mut decryptors: Vec<_> = l.new_nodes().into_iter().map(|n| n.session.node().clone()).collect();
+		decryptors.push(l.old_nodes_set.iter().nth(1).unwrap().clone());
+		let document_secret_plain = Random.generate().unwrap().public().clone();
+		let joint_public = math::compute_public_share(&l.joint_secret).unwrap();
+		for excluded in 0..decryptors.len() {
+			let shares: Vec<_> = decryptors.iter().enumerate()
+				.filter(|&(i, _)| i != excluded)
+				.map(|(_, node)| l.nodes[node].key_storage.get(&l.session_id).unwrap().last_version().unwrap().clone())
+				.collect();
+			let id_numbers: Vec<_> = decryptors.iter().enumerate()
+				.filter(|&(i, _)| i != excluded)
+				.map(|(_, node)| shares[0].id_numbers[node].clone())
+				.collect();
+			let secret_shares: Vec<_> = shares.iter().map(|v| v.secret_share.clone()).collect();
+			let (document_secret_decrypted, document_secret_decrypted_test) = math::tests::do_encryption_and_decryption(1,
+				&joint_public, &id_numbers, &secret_shares, Some(&l.joint_secret), document_secret_plain.clone());
+			assert_eq!(document_secret_plain, document_secret_decrypted);
+			assert_eq!(document_secret_plain, document_secret_decrypted_test);
+		}
+	}
+
+	#[test]
+	fn fails_to_initialize_without_admin_signature() {
+		let l = MessageLoop::new(1, 3, 2);
+		let signature = ethkey::sign(Random.generate().unwrap().secret(), &nodes_sets_hash(&l.old_nodes_set, &l.new_nodes_set)).unwrap();
+		assert_eq!(l.master().session.initialize(l.old_nodes_set.clone(), l.new_nodes_set.clone(), signature), Err(Error::AccessDenied));
+	}
+
+	#[test]
+	fn fails_to_initialize_if_old_nodes_set_does_not_match_key() {
+		let l = MessageLoop::new(1, 3, 2);
+		let old_nodes_set: BTreeSet<_> = l.old_nodes_set.iter().cloned().skip(1).collect();
+		let signature = ethkey::sign(l.admin.secret(), &nodes_sets_hash(&old_nodes_set, &l.new_nodes_set)).unwrap();
+		assert_eq!(l.master().session.initialize(old_nodes_set, l.new_nodes_set.clone(), signature), Err(Error::InvalidNodesConfiguration));
+	}
+
+	#[test]
+	fn fails_to_initialize_if_there_are_no_new_nodes() {
+		let l = MessageLoop::new(1, 3, 0);
+		assert_eq!(l.initialize(), Err(Error::InvalidNodesConfiguration));
+	}
+
+	#[test]
+	fn new_node_rejects_initialization_if_it_already_has_key() {
+		let l = MessageLoop::new(1, 3, 1);
+		let new_node = l.new_nodes()[0];
+		new_node.key_storage.insert(l.session_id.clone(), l.master().key_storage.get(&l.session_id).unwrap()).unwrap();
+		l.initialize().unwrap();
+
+		while let Some((to, message)) = l.master().cluster.take_message() {
+			match message {
+				Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(ref message)) if &to == new_node.session.node() => {
+					assert_eq!(new_node.session.on_initialize_session(l.master().session.node().clone(), message), Err(Error::DuplicateSessionId));
+					return;
+				},
+				_ => (),
+			}
+		}
+
+		panic!("initialization message is not sent to new node");
+	}
+
+	#[test]
+	fn new_node_rejects_initialization_with_overflowing_threshold() {
+		let l = MessageLoop::new(1, 3, 1);
+		let new_node = l.new_nodes()[0];
+		l.initialize().unwrap();
+
+		while let Some((to, message)) = l.master().cluster.take_message() {
+			match message {
+				Message::ShareAdd(ShareAddMessage::InitializeShareAddSession(ref message)) if &to == new_node.session.node() => {
+					let mut message = message.clone();
+					message.threshold = ::std::usize::MAX;
+					let error = new_node.session.on_initialize_session(l.master().session.node().clone(), &message).unwrap_err();
+					assert_eq!(error, Error::InvalidThreshold { requested: ::std::usize::MAX, nodes: 3 });
+					assert!(!error.to_string().is_empty());
+					return;
+				},
+				_ => (),
+			}
+		}
+
+		panic!("initialization message is not sent to new node");
+	}
+
+	#[test]
+	fn new_version_is_not_committed_anywhere_if_new_node_fails() {
+		let mut l = MessageLoop::new(1, 3, 2);
+		l.initialize().unwrap();
+
+		// run until every node, except master, has staged the new version
+		let failed_node = l.new_nodes()[0].session.node().clone();
+		while let Some((from, to, message)) = l.take_message() {
+			let is_staged_notification = match message {
+				Message::ShareAdd(ShareAddMessage::NewKeyShareStaged(_)) => true,
+				_ => false,
+			};
+			// new node fails instead of notifying master
+			if is_staged_notification && from == failed_node {
+				l.nodes[&failed_node].session.on_session_timeout();
+				l.master().session.on_session_error(failed_node.clone(), &message::ShareAddSessionError {
+					session: l.session_id.clone().into(),
+					error: Error::NodeDisconnected.into(),
+				}).unwrap();
+				continue;
+			}
+			l.process_message((from, to, message)).unwrap();
+		}
+
+		// every node has failed && no one has committed the new version
+		for node in l.nodes.values() {
+			assert_eq!(node.session.state(), SessionState::Failed);
+		}
+		assert!(l.master().session.wait(None).is_err());
+		for node in &l.old_nodes_set {
+			assert_eq!(l.versions_count(node), Some(1));
+		}
+		for node in l.new_nodes() {
+			assert_eq!(l.versions_count(node.session.node()), None);
+		}
+	}
+
+	#[test]
+	fn new_version_is_not_committed_anywhere_if_invalid_subshare_is_received() {
+		let mut l = MessageLoop::new(1, 3, 2);
+		l.initialize().unwrap();
+
+		// corrupt subshare, sent by master to one of new nodes
+		let master = l.master().session.node().clone();
+		let victim = l.new_nodes()[0].session.node().clone();
+		while let Some((from, to, mut message)) = l.take_message() {
+			if from == master && to == victim {
+				if let Message::ShareAdd(ShareAddMessage::NewKeysDissemination(ref mut message)) = message {
+					message.secret1 = math::generate_random_scalar().unwrap().into();
+				}
+			}
+
+			match l.process_message((from.clone(), to.clone(), message)) {
+				Err(Error::InvalidKeyShare(node)) => {
+					assert_eq!(node, master);
+					// error is reported to master
+					l.master().session.on_session_error(victim.clone(), &message::ShareAddSessionError {
+						session: l.session_id.clone().into(),
+						error: Error::InvalidKeyShare(node).into(),
+					}).unwrap();
+				},
+				// the rest of nodes could report errors for messages, which are received after session has failed
+				_ => (),
+			}
+		}
+
+		assert_eq!(l.nodes[&victim].session.state(), SessionState::Failed);
+		assert_eq!(l.master().session.state(), SessionState::Failed);
+		for node in &l.old_nodes_set {
+			assert_eq!(l.versions_count(node), Some(1));
+		}
+		for node in l.new_nodes() {
+			assert_eq!(l.versions_count(node.session.node()), None);
+		}
+	}
+
+	#[test]
+	fn key_is_recovered_to_new_nodes() {
+		let mut l = MessageLoop::new(2, 5, 2);
+		let lost_nodes = l.lose_old_nodes(2);
+		l.initialize_recovery().unwrap();
+		l.run();
+
+		// every node has committed the new version, which is not shared with lost nodes
+		for node in l.nodes.values() {
+			assert_eq!(node.session.state(), SessionState::Finished);
+			let key_share = node.key_storage.get(&l.session_id).unwrap();
+			let last_version = key_share.last_version().unwrap();
+			assert_eq!(last_version.id_numbers.keys().cloned().collect::<BTreeSet<_>>(), l.new_nodes_set);
+		}
+
+		// versions, shared with lost nodes, are retired on surviving nodes
+		for node in &l.old_nodes_set {
+			let key_share = l.nodes[node].key_storage.get(&l.session_id).unwrap();
+			assert_eq!(key_share.versions.len(), 2);
+			assert!(key_share.versions[0].retired);
+			assert!(!key_share.versions[1].retired);
+		}
+
+		// two new nodes && two surviving nodes are able to decrypt data
+		let mut decryptors: Vec<_> = l.new_nodes().into_iter().map(|n| n.session.node().clone()).collect();
+		decryptors.extend(l.old_nodes_set.iter().take(2).cloned());
+		assert_key_is_shared(&l, 2, &decryptors);
+
+		// shares of lost nodes are not matching the active version
+		let active_version = l.master().key_storage.get(&l.session_id).unwrap().last_version().unwrap().clone();
+		for lost_node in lost_nodes {
+			let lost_version = lost_node.key_storage.get(&l.session_id).unwrap().last_version().unwrap().clone();
+			assert!(lost_version.hash != active_version.hash);
+			assert!(!active_version.id_numbers.contains_key(lost_node.session.node()));
+		}
+	}
+
+	#[test]
+	fn fails_to_recover_if_too_few_nodes_survived() {
+		let mut l = MessageLoop::new(2, 5, 2);
+		l.lose_old_nodes(3);
+		assert_eq!(l.initialize_recovery(), Err(Error::InvalidNodesCount));
+	}
+
+	#[test]
+	fn fails_to_recover_if_lost_node_is_one_of_new_nodes() {
+		let mut l = MessageLoop::new(1, 3, 1);
+		let lost_nodes = l.lose_old_nodes(1);
+		l.new_nodes_set.insert(lost_nodes[0].session.node().clone());
+		assert_eq!(l.initialize_recovery(), Err(Error::InvalidNodesConfiguration));
+	}
+
+	#[test]
+	fn recovery_is_restarted_after_partial_commit() {
+		let mut l = MessageLoop::new(2, 5, 2);
+		l.lose_old_nodes(2);
+		l.initialize_recovery().unwrap();
+
+		// commit request is lost on the way to one of new nodes
+		let master = l.master().session.node().clone();
+		let victim = l.new_nodes()[0].session.node().clone();
+		while let Some((from, to, message)) = l.take_message() {
+			let is_commit_request = match message {
+				Message::ShareAdd(ShareAddMessage::CommitNewKeyShare(_)) => true,
+				_ => false,
+			};
+			if is_commit_request && from == master && to == victim {
+				l.nodes[&victim].session.on_session_timeout();
+				continue;
+			}
+			l.process_message((from, to, message)).unwrap();
+		}
+		assert_eq!(l.nodes[&victim].session.state(), SessionState::Failed);
+		assert_eq!(l.versions_count(&victim), None);
+
+		// recovery is restarted && every node ends up with the same version
+		l.restart_sessions();
+		l.initialize_recovery().unwrap();
+		l.run();
+		for node in l.nodes.values() {
+			assert_eq!(node.session.state(), SessionState::Finished);
+		}
+		for node in &l.old_nodes_set {
+			assert_eq!(l.versions_count(node), Some(2));
+		}
+		for node in l.new_nodes() {
+			assert_eq!(l.versions_count(node.session.node()), Some(1));
+		}
+		let decryptors: Vec<_> = l.new_nodes_set.iter().take(4).cloned().collect();
+		assert_key_is_shared(&l, 2, &decryptors);
+	}
+
+	#[test]
+	fn wait_fails_when_timeout_passes() {
+		let l = MessageLoop::new(1, 3, 2);
+		assert_eq!(l.master().session.wait(Some(time::Duration::from_millis(10))), Err(Error::Io("timeout".into())));
+	}
+}
@@ -0,0 +1,1038 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::{Ord, PartialOrd, Ordering};
+use std::collections::{BTreeSet, BTreeMap};
+use std::sync::Arc;
+use parking_lot::{Mutex, Condvar};
+use ethkey::{Secret, Public};
+use util::H256;
+use key_server_cluster::{Error, AclStorage, Requester, DocumentKeyShare, DocumentKeyShareVersion, NodeId, SessionId};
+use key_server_cluster::cluster::Cluster;
+use key_server_cluster::math;
+use key_server_cluster::message::{Message, SigningMessage, InitializeSigningSession, ConfirmSigningInitialization,
+	RequestSigningNonce, SigningNonce, RequestPartialSignature, PartialSignature, SigningSessionError, SigningSessionCompleted};
+
+/// Signing session API.
+pub trait Session: Send + Sync + 'static {
+	/// Wait until session is completed. Returns signed message (signature hash, signature).
+	fn wait(&self) -> Result<(Secret, Secret), Error>;
+}
+
+/// Distributed Schnorr signing session.
+/// Brief overview:
+/// 1) initialization: master node (which has received request for signing the message) requests all other nodes to sign the message
+/// 2) ACL check: all nodes which have received the request are querying ACL-contract to check if requestor has access to the key
+/// 3) nonce generation: every node, selected for signing, generates one-time nonce && sends its public to the master node
+/// 4) partial signing: master node broadcasts all nonces publics && every selected node computes its partial signature
+/// 5) signing: master node receives all partial signatures && combines them into the signature of the message
+/// One-time nonce is erased right after partial signature is computed, so that it is never used twice.
+pub struct SessionImpl {
+	/// Generation session id.
+	id: SessionId,
+	/// Signing session access key.
+	access_key: Secret,
+	/// Public identifier of this node.
+	self_node_id: NodeId,
+	/// Key share.
+	key_share: DocumentKeyShare,
+	/// ACL storate to check access to the resource.
+	acl_storage: Arc<AclStorage>,
+	/// Cluster which allows this node to send messages to other nodes in the cluster.
+	cluster: Arc<Cluster>,
+	/// SessionImpl completion condvar.
+	completed: Condvar,
+	/// Mutable session data.
+	data: Mutex<SessionData>,
+}
+
+/// Signing session Id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningSessionId {
+	/// Generation session id.
+	pub id: SessionId,
+	/// Signing session access key.
+	pub access_key: Secret,
+}
+
+/// SessionImpl creation parameters
+pub struct SessionParams {
+	/// SessionImpl identifier.
+	pub id: SessionId,
+	/// SessionImpl access key.
+	pub access_key: Secret,
+	/// Id of node, on which this session is running.
+	pub self_node_id: Public,
+	/// Key share (result of running generation_session::SessionImpl).
+	pub key_share: DocumentKeyShare,
+	/// ACL storage.
+	pub acl_storage: Arc<AclStorage>,
+	/// Cluster
+	pub cluster: Arc<Cluster>,
+}
+
+#[derive(Debug)]
+/// Mutable data of signing session.
+struct SessionData {
+	/// Current state of the session.
+	state: SessionState,
+
+	// === Values, filled when session initialization just starts ===
+	/// Reference to the node, which has started this session.
+	master: Option<NodeId>,
+	/// Hash of the message to sign.
+	message_hash: Option<H256>,
+
+	// === Values, filled during session initialization ===
+	/// Nodes, which have been requested for signing initialization.
+	requested_nodes: BTreeSet<NodeId>,
+	/// Nodes, which have responded with reject to initialization request.
+	rejected_nodes: BTreeSet<NodeId>,
+	/// Nodes, which have responded with confirm to initialization request.
+	confirmed_nodes: BTreeSet<NodeId>,
+
+	// === Values, filled during signing ===
+	/// Nodes, which are participating in the current signing attempt.
+	signing_nodes: BTreeSet<NodeId>,
+	/// One-time nonce of this node for the current signing attempt. Erased once partial signature is computed.
+	nonce: Option<Secret>,
+	/// Nodes, which have been asked for nonce public.
+	nonce_requests: BTreeSet<NodeId>,
+	/// Nonces publics, received from nodes as a response to nonce request.
+	nonces: BTreeMap<NodeId, Public>,
+	/// Nodes, which have been asked for partial signature.
+	partial_signature_requests: BTreeSet<NodeId>,
+	/// Partial signatures, received from nodes as a response to partial signature request.
+	partial_signatures: BTreeMap<NodeId, Secret>,
+	/// Signature hash of the current signing attempt.
+	signature_hash: Option<Secret>,
+
+	/// === Values, filled during final signing ===
+	/// Signing result: (signature hash, signature).
+	result: Option<Result<(Secret, Secret), Error>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Signing session state.
+pub enum SessionState {
+	/// Every node starts in this state.
+	WaitingForInitialization,
+	/// Master node waits for other nodes to confirm signing.
+	WaitingForInitializationConfirm,
+	/// Slave node waits for nonce or partial signature requests.
+	WaitingForSigningRequest,
+	/// Master node waits for nonces publics.
+	WaitingForNonces,
+	/// Master node waits for partial signatures.
+	WaitingForPartialSignatures,
+	/// Signing session is finished for this node.
+	Finished,
+	/// Signing session is failed for this node.
+	Failed,
+}
+
+impl SessionImpl {
+	/// Create new signing session.
+	pub fn new(params: SessionParams) -> Result<Self, Error> {
+		check_key_share(&params.self_node_id, &params.key_share)?;
+
+		Ok(SessionImpl {
+			id: params.id,
+			access_key: params.access_key,
+			self_node_id: params.self_node_id,
+			key_share: params.key_share,
+			acl_storage: params.acl_storage,
+			cluster: params.cluster,
+			completed: Condvar::new(),
+			data: Mutex::new(SessionData {
+				state: SessionState::WaitingForInitialization,
+				master: None,
+				message_hash: None,
+				requested_nodes: BTreeSet::new(),
+				rejected_nodes: BTreeSet::new(),
+				confirmed_nodes: BTreeSet::new(),
+				signing_nodes: BTreeSet::new(),
+				nonce: None,
+				nonce_requests: BTreeSet::new(),
+				nonces: BTreeMap::new(),
+				partial_signature_requests: BTreeSet::new(),
+				partial_signatures: BTreeMap::new(),
+				signature_hash: None,
+				result: None,
+			})
+		})
+	}
+
+	/// Get this node Id.
+	pub fn node(&self) -> &NodeId {
+		&self.self_node_id
+	}
+
+	/// Get current session state.
+	pub fn state(&self) -> SessionState {
+		self.data.lock().state.clone()
+	}
+
+	/// Get session result. Returns None if session is not completed yet.
+	pub fn result(&self) -> Option<Result<(Secret, Secret), Error>> {
+		self.data.lock().result.clone()
+	}
+
+	/// Initialize signing session.
+	pub fn initialize(&self, requester: Requester, message_hash: H256) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// other key servers do not trust unverified requester => this node must sign message on its own
+		if !requester.is_verifiable() && self.key_share.threshold != 0 {
+			return Err(Error::InsufficientRequesterData("requester signature is required to sign with other key servers".into()));
+		}
+
+		// ACL is checked by requestor address
+		let requestor_address = requester.address(&self.id)?;
+
+		// update state
+		data.master = Some(self.node().clone());
+		data.state = SessionState::WaitingForInitializationConfirm;
+		data.message_hash = Some(message_hash.clone());
+		data.requested_nodes.extend(key_version(&self.key_share).id_numbers.keys().cloned());
+
+		// ..and finally check access on our's own
+		let is_requestor_allowed_to_sign = self.acl_storage.check(&requestor_address, &self.id).unwrap_or(false);
+		process_initialization_response(&self.key_share, &mut *data, self.node(), is_requestor_allowed_to_sign)?;
+
+		// check if we have enough nodes to sign message
+		match data.state {
+			// not enough nodes => pass initialization message to all other nodes
+			SessionState::WaitingForInitializationConfirm => {
+				for node in key_version(&self.key_share).id_numbers.keys().filter(|n| *n != self.node()) {
+					self.cluster.send(node, Message::Signing(SigningMessage::InitializeSigningSession(InitializeSigningSession {
+							session: self.id.clone().into(),
+							sub_session: self.access_key.clone().into(),
+							requester: requester.clone().into(),
+							message_hash: message_hash.clone().into(),
+						})))?;
+				}
+			},
+			// we can sign message on our own
+			SessionState::WaitingForNonces => {
+				self.start_signing(&mut *data)?;
+				if data.state == SessionState::Finished {
+					self.completed.notify_all();
+				}
+			},
+			// we can not sign message
+			SessionState::Failed => self.completed.notify_all(),
+			// cannot reach other states
+			_ => unreachable!("process_initialization_response can change state to WaitingForNonces or Failed; checked that we are in WaitingForInitializationConfirm state above; qed"),
+		}
+
+		Ok(())
+	}
+
+	/// When session initialization message is received.
+	pub fn on_initialize_session(&self, sender: NodeId, message: &InitializeSigningSession) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForInitialization {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// public key or address of the requester could be forged by master => only signature is accepted
+		let requester: Requester = message.requester.clone().into();
+		if !requester.is_verifiable() {
+			return Err(Error::InsufficientRequesterData("requester signature is required".into()));
+		}
+
+		// check access
+		let requestor_address = requester.address(&self.id)?;
+		let is_requestor_allowed_to_sign = self.acl_storage.check(&requestor_address, &self.id).unwrap_or(false);
+		data.state = if is_requestor_allowed_to_sign { SessionState::WaitingForSigningRequest }
+			else { SessionState::Failed };
+		data.message_hash = Some(message.message_hash.clone().into());
+
+		// respond to master node
+		data.master = Some(sender.clone());
+		self.cluster.send(&sender, Message::Signing(SigningMessage::ConfirmSigningInitialization(ConfirmSigningInitialization {
+			session: self.id.clone().into(),
+			sub_session: self.access_key.clone().into(),
+			is_confirmed: is_requestor_allowed_to_sign,
+		})))
+	}
+
+	/// When session initialization confirmation message is received.
+	pub fn on_confirm_initialization(&self, sender: NodeId, message: &ConfirmSigningInitialization) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state == SessionState::WaitingForNonces || data.state == SessionState::WaitingForPartialSignatures {
+			// if there were enough confirmations/rejections before this message
+			// we have already moved to the next state
+			if !data.requested_nodes.remove(&sender) {
+				return Err(Error::InvalidMessage);
+			}
+
+			// late confirmation is only used if signing is restarted
+			if message.is_confirmed {
+				data.confirmed_nodes.insert(sender);
+			} else {
+				data.rejected_nodes.insert(sender);
+			}
+			return Ok(());
+		}
+		if data.state != SessionState::WaitingForInitializationConfirm {
+			return Ok(());
+		}
+
+		// update state
+		process_initialization_response(&self.key_share, &mut *data, &sender, message.is_confirmed)?;
+
+		// check if we have enough nodes to sign message
+		match data.state {
+			// we do not yet have enough nodes for signing
+			SessionState::WaitingForInitializationConfirm => Ok(()),
+			// we have enough nodes for signing
+			SessionState::WaitingForNonces => {
+				self.start_signing(&mut *data)?;
+				if data.state == SessionState::Finished {
+					self.completed.notify_all();
+				}
+				Ok(())
+			},
+			// we can not have enough nodes for signing
+			SessionState::Failed => {
+				self.completed.notify_all();
+				Ok(())
+			},
+			// cannot reach other states
+			_ => unreachable!("process_initialization_response can change state to WaitingForNonces or Failed; checked that we are in WaitingForInitializationConfirm state above; qed"),
+		}
+	}
+
+	/// When nonce is requested.
+	pub fn on_nonce_requested(&self, sender: NodeId, message: &RequestSigningNonce) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.master != Some(sender.clone()) {
+			return Err(Error::InvalidMessage);
+		}
+		if data.state != SessionState::WaitingForSigningRequest {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// check message
+		let signing_nodes: BTreeSet<NodeId> = message.nodes.iter().cloned().map(Into::into).collect();
+		check_signing_nodes(&self.key_share, self.node(), &signing_nodes)?;
+
+		// master could ask us for another nonce in case of restart
+		// => previous nonce (if any) is replaced with the new one
+		let nonce = math::generate_random_scalar()?;
+		let nonce_public = math::compute_public_share(&nonce)?;
+		data.signing_nodes = signing_nodes;
+		data.nonce = Some(nonce);
+
+		self.cluster.send(&sender, Message::Signing(SigningMessage::SigningNonce(SigningNonce {
+			session: self.id.clone().into(),
+			sub_session: self.access_key.clone().into(),
+			nonce_public: nonce_public.into(),
+		})))
+	}
+
+	/// When nonce public is received.
+	pub fn on_nonce(&self, sender: NodeId, message: &SigningNonce) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForNonces {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if !data.nonce_requests.remove(&sender) {
+			return Err(Error::InvalidStateForRequest);
+		}
+		data.nonces.insert(sender, message.nonce_public.clone().into());
+
+		// check if we have received nonces from all signing nodes
+		if !data.nonce_requests.is_empty() {
+			return Ok(());
+		}
+
+		self.request_partial_signatures(&mut *data)
+	}
+
+	/// When partial signature is requested.
+	pub fn on_partial_signature_requested(&self, sender: NodeId, message: &RequestPartialSignature) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.master != Some(sender.clone()) {
+			return Err(Error::InvalidMessage);
+		}
+		if data.state != SessionState::WaitingForSigningRequest {
+			return Err(Error::InvalidStateForRequest);
+		}
+
+		// nonce is erased before anything else is checked, so that it is never used twice
+		let nonce = match data.nonce.take() {
+			Some(nonce) => nonce,
+			None => return Err(Error::InvalidStateForRequest),
+		};
+
+		// check message
+		let nonces: BTreeMap<NodeId, Public> = message.nonces.iter()
+			.map(|(node, nonce_public)| (node.clone().into(), nonce_public.clone().into()))
+			.collect();
+		if nonces.keys().cloned().collect::<BTreeSet<_>>() != data.signing_nodes {
+			return Err(Error::InvalidMessage);
+		}
+		if nonces.get(self.node()) != Some(&math::compute_public_share(&nonce)?) {
+			return Err(Error::InvalidMessage);
+		}
+
+		// compute partial signature
+		let message_hash = data.message_hash.as_ref().expect("message_hash is filled during initialization; WaitingForSigningRequest follows initialization; qed");
+		let nonce_public = math::compute_joint_public(nonces.values())?;
+		let signature_hash = math::compute_schnorr_signature_hash(&nonce_public, message_hash)?;
+		let partial_signature = do_partial_signing(self.node(), &data.signing_nodes, &nonce, &signature_hash, &self.key_share)?;
+
+		self.cluster.send(&sender, Message::Signing(SigningMessage::PartialSignature(PartialSignature {
+			session: self.id.clone().into(),
+			sub_session: self.access_key.clone().into(),
+			partial_signature: partial_signature.into(),
+		})))
+	}
+
+	/// When partial signature is received.
+	pub fn on_partial_signature(&self, sender: NodeId, message: &PartialSignature) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForPartialSignatures {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if !data.partial_signature_requests.remove(&sender) {
+			return Err(Error::InvalidStateForRequest);
+		}
+		data.partial_signatures.insert(sender, message.partial_signature.clone().into());
+
+		// check if we have received partial signatures from all signing nodes
+		if !data.partial_signature_requests.is_empty() {
+			return Ok(());
+		}
+
+		self.complete_signing(&mut *data)?;
+		self.completed.notify_all();
+
+		Ok(())
+	}
+
+	/// When session is completed.
+	pub fn on_session_completed(&self, sender: NodeId, message: &SigningSessionCompleted) -> Result<(), Error> {
+		debug_assert!(self.id == *message.session);
+		debug_assert!(self.access_key == *message.sub_session);
+		debug_assert!(&sender != self.node());
+
+		let mut data = self.data.lock();
+
+		// check state
+		if data.state != SessionState::WaitingForSigningRequest {
+			return Err(Error::InvalidStateForRequest);
+		}
+		if data.master != Some(sender) {
+			return Err(Error::InvalidMessage);
+		}
+
+		// update state
+		data.nonce = None;
+		data.state = SessionState::Finished;
+
+		Ok(())
+	}
+
+	/// When error has occured on another node.
+	pub fn on_session_error(&self, sender: NodeId, message: &SigningSessionError) -> Result<(), Error> {
+		let mut data = self.data.lock();
+
+		warn!("{}: signing session failed with error: {:?} from {}", self.node(), message.error, sender);
+
+		data.nonce = None;
+		data.state = SessionState::Failed;
+		data.result = Some(Err(Error::Io(message.error.clone())));
+		self.completed.notify_all();
+
+		Ok(())
+	}
+
+	/// When connection to one of cluster nodes has timeouted.
+	pub fn on_node_timeout(&self, node: &NodeId) {
+		let mut data = self.data.lock();
+
+		let is_self_master = data.master.as_ref() == Some(self.node());
+		let is_other_master = data.master.as_ref() == Some(node);
+		// if this is master node, we might have to restart
+		if is_self_master {
+			match data.state {
+				SessionState::WaitingForInitializationConfirm => {
+					// we will never receive confirmation from this node => treat as reject
+					if data.requested_nodes.remove(node) || data.confirmed_nodes.remove(node) {
+						data.rejected_nodes.insert(node.clone());
+					}
+					// check if we still have enough nodes for signing
+					if has_enough_nodes(&self.key_share, &*data) {
+						return;
+					}
+				},
+				SessionState::WaitingForNonces | SessionState::WaitingForPartialSignatures => {
+					if data.rejected_nodes.contains(node) {
+						// already rejected => does not affect session
+						return;
+					}
+					if data.requested_nodes.remove(node) {
+						// we have tried to initialize this node, but it has failed
+						// => no restart required, just mark as rejected
+						data.rejected_nodes.insert(node.clone());
+						return;
+					}
+					if !data.signing_nodes.contains(node) {
+						// node has confirmed initialization, but it is not participating in signing
+						// => no restart required, just mark as rejected
+						data.confirmed_nodes.remove(node);
+						data.rejected_nodes.insert(node.clone());
+						return;
+					}
+					if data.partial_signatures.contains_key(node) {
+						// we have already received partial signature from this node
+						// => just ignore this connection drop
+						return;
+					}
+
+					// the worst case: node is participating in signing, but it has not yet sent partial signature
+					// => we have to restart signing with other nodes (and other nonces)
+					data.confirmed_nodes.remove(node);
+					data.rejected_nodes.insert(node.clone());
+					if self.restart_signing(&mut *data) {
+						return;
+					}
+				},
+				_ => (), // all other states lead to failures
+			}
+		} else if !is_other_master {
+			// disconnected from non-master node on non-master node
+			// => this does not affect this session
+			return;
+		}
+		// else: disconnecting from master node means failure
+
+		// no more nodes left for signing => fail
+		warn!("{}: signing session failed because {} connection has timeouted", self.node(), node);
+
+		data.nonce = None;
+		data.state = SessionState::Failed;
+		data.result = Some(Err(Error::NodeDisconnected));
+		self.completed.notify_all();
+	}
+
+	/// When session timeout has occured.
+	pub fn on_session_timeout(&self) {
+		let mut data = self.data.lock();
+
+		let is_self_master = data.master.as_ref() == Some(self.node());
+		// if this is master node, we might have to restart
+		if is_self_master {
+			let timeouted_nodes = match data.state {
+				// we have sent initialization requests to all nodes, but haven't received confirmation
+				// => nodes will never respond => fail
+				SessionState::WaitingForInitializationConfirm => BTreeSet::new(),
+				// we have requested nonces, but some nodes have failed to respond
+				SessionState::WaitingForNonces => data.nonce_requests.clone(),
+				// we have requested partial signatures, but some nodes have failed to respond
+				SessionState::WaitingForPartialSignatures => data.partial_signature_requests.clone(),
+				// no nodes has responded to our requests => session is failed
+				_ => return,
+			};
+
+			// mark timeouted nodes as rejected && restart
+			if !timeouted_nodes.is_empty() {
+				for timeouted_node in timeouted_nodes {
+					data.confirmed_nodes.remove(&timeouted_node);
+					data.rejected_nodes.insert(timeouted_node);
+				}
+				if self.restart_signing(&mut *data) {
+					return;
+				}
+			}
+		}
+
+		// no more nodes left for signing => fail
+		warn!("{}: signing session failed with timeout", self.node());
+
+		data.nonce = None;
+		data.state = SessionState::Failed;
+		data.result = Some(Err(Error::NodeDisconnected));
+		self.completed.notify_all();
+	}
+
+	/// Restart signing with nodes, which are still available. Returns false if there are not enough nodes left.
+	fn restart_signing(&self, data: &mut SessionData) -> bool {
+		// check if we still have enough nodes for signing
+		if !has_enough_nodes(&self.key_share, data) {
+			return false;
+		}
+
+		// if there are not enough confirmed nodes => wait for confirmations from other nodes
+		if data.confirmed_nodes.len() < self.key_share.threshold + 1 {
+			data.state = SessionState::WaitingForInitializationConfirm;
+			return true;
+		}
+
+		data.state = SessionState::WaitingForNonces;
+		if self.start_signing(data).is_err() {
+			return false;
+		}
+		if data.state == SessionState::Finished {
+			self.completed.notify_all();
+		}
+		true
+	}
+
+	/// Select nodes for signing && request nonces from these nodes.
+	fn start_signing(&self, data: &mut SessionData) -> Result<(), Error> {
+		let signing_nodes: BTreeSet<_> = data.confirmed_nodes.difference(&data.rejected_nodes).cloned().collect();
+
+		data.signing_nodes = signing_nodes.clone();
+		data.nonce = None;
+		data.nonce_requests.clear();
+		data.nonces.clear();
+		data.partial_signature_requests.clear();
+		data.partial_signatures.clear();
+		data.signature_hash = None;
+		for node in signing_nodes.iter().filter(|n| *n != self.node()) {
+			data.nonce_requests.insert(node.clone());
+			self.cluster.send(node, Message::Signing(SigningMessage::RequestSigningNonce(RequestSigningNonce {
+				session: self.id.clone().into(),
+				sub_session: self.access_key.clone().into(),
+				nodes: signing_nodes.iter().cloned().map(Into::into).collect(),
+			})))?;
+		}
+
+		if signing_nodes.contains(self.node()) {
+			let nonce = math::generate_random_scalar()?;
+			data.nonces.insert(self.node().clone(), math::compute_public_share(&nonce)?);
+			data.nonce = Some(nonce);
+		}
+
+		// check if we have received nonces from all signing nodes
+		if !data.nonce_requests.is_empty() {
+			return Ok(());
+		}
+
+		self.request_partial_signatures(data)
+	}
+
+	/// Broadcast nonces publics to signing nodes && request partial signatures.
+	fn request_partial_signatures(&self, data: &mut SessionData) -> Result<(), Error> {
+		let nonce_public = math::compute_joint_public(data.nonces.values())?;
+		let signature_hash = {
+			let message_hash = data.message_hash.as_ref().expect("message_hash is filled during initialization; signing follows initialization; qed");
+			math::compute_schnorr_signature_hash(&nonce_public, message_hash)?
+		};
+
+		data.state = SessionState::WaitingForPartialSignatures;
+		data.signature_hash = Some(signature_hash.clone());
+		for node in data.signing_nodes.iter().filter(|n| *n != self.node()) {
+			data.partial_signature_requests.insert(node.clone());
+			self.cluster.send(node, Message::Signing(SigningMessage::RequestPartialSignature(RequestPartialSignature {
+				session: self.id.clone().into(),
+				sub_session: self.access_key.clone().into(),
+				nonces: data.nonces.iter().map(|(node, nonce_public)| (node.clone().into(), nonce_public.clone().into())).collect(),
+			})))?;
+		}
+
+		if let Some(nonce) = data.nonce.take() {
+			let partial_signature = do_partial_signing(self.node(), &data.signing_nodes, &nonce, &signature_hash, &self.key_share)?;
+			data.partial_signatures.insert(self.node().clone(), partial_signature);
+		}
+
+		// check if we have received partial signatures from all signing nodes
+		if !data.partial_signature_requests.is_empty() {
+			return Ok(());
+		}
+
+		self.complete_signing(data)
+	}
+
+	/// Combine partial signatures into the signature && notify other nodes about session completion.
+	fn complete_signing(&self, data: &mut SessionData) -> Result<(), Error> {
+		let signature_hash = data.signature_hash.clone().expect("signature_hash is filled before partial signatures are requested; qed");
+		let signature = math::compute_schnorr_signature(data.partial_signatures.values())?;
+		data.result = Some(Ok((signature_hash, signature)));
+		data.state = SessionState::Finished;
+
+		// signature is already computed => failure to notify other nodes does not affect the result
+		for node in data.confirmed_nodes.iter().filter(|n| *n != self.node()) {
+			let _ = self.cluster.send(node, Message::Signing(SigningMessage::SigningSessionCompleted(SigningSessionCompleted {
+				session: self.id.clone().into(),
+				sub_session: self.access_key.clone().into(),
+			})));
+		}
+
+		Ok(())
+	}
+}
+
+impl Session for SessionImpl {
+	fn wait(&self) -> Result<(Secret, Secret), Error> {
+		let mut data = self.data.lock();
+		if !data.result.is_some() {
+			self.completed.wait(&mut data);
+		}
+
+		data.result.as_ref()
+			.expect("checked above or waited for completed; completed is only signaled when result.is_some(); qed")
+			.clone()
+	}
+}
+
+impl SigningSessionId {
+	/// Create new signing session Id.
+	pub fn new(session_id: SessionId, sub_session_id: Secret) -> Self {
+		SigningSessionId {
+			id: session_id,
+			access_key: sub_session_id,
+		}
+	}
+}
+
+impl PartialOrd for SigningSessionId {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for SigningSessionId {
+	fn cmp(&self, other: &Self) -> Ordering {
+		match self.id.cmp(&other.id) {
+			Ordering::Equal => self.access_key.cmp(&other.access_key),
+			r @ _ => r,
+		}
+	}
+}
+
+fn check_key_share(self_node_id: &Public, key_share: &DocumentKeyShare) -> Result<(), Error> {
+	use key_server_cluster::generation_session::{check_cluster_nodes, check_threshold};
+
+	let key_version = key_share.last_version().map_err(|e| Error::KeyStorage(e.into()))?;
+	let nodes = key_version.id_numbers.keys().cloned().collect();
+	check_cluster_nodes(self_node_id, &nodes)?;
+	check_threshold(key_share.threshold, &nodes)?;
+
+	Ok(())
+}
+
+fn check_signing_nodes(key_share: &DocumentKeyShare, self_node_id: &NodeId, signing_nodes: &BTreeSet<NodeId>) -> Result<(), Error> {
+	let key_version = key_version(key_share);
+	if signing_nodes.len() < key_share.threshold + 1
+		|| !signing_nodes.contains(self_node_id)
+		|| signing_nodes.iter().any(|n| !key_version.id_numbers.contains_key(n)) {
+		return Err(Error::InvalidMessage);
+	}
+
+	Ok(())
+}
+
+fn key_version(key_share: &DocumentKeyShare) -> &DocumentKeyShareVersion {
+	key_share.last_version().expect("key version is checked in check_key_share; session is only created after check; qed")
+}
+
+fn has_enough_nodes(key_share: &DocumentKeyShare, data: &SessionData) -> bool {
+	key_version(key_share).id_numbers.len() - data.rejected_nodes.len() >= key_share.threshold + 1
+}
+
+fn process_initialization_response(key_share: &DocumentKeyShare, data: &mut SessionData, node: &NodeId, check_result: bool) -> Result<(), Error> {
+	if !data.requested_nodes.remove(node) {
+		return Err(Error::InvalidMessage);
+	}
+
+	match check_result {
+		true => {
+			data.confirmed_nodes.insert(node.clone());
+
+			// check if we have enough nodes to do a signing?
+			if data.confirmed_nodes.len() >= key_share.threshold + 1 {
+				data.state = SessionState::WaitingForNonces;
+			}
+		},
+		false => {
+			data.rejected_nodes.insert(node.clone());
+
+			// check if we still can receive enough confirmations to do a signing?
+			if !has_enough_nodes(key_share, data) {
+				data.result = Some(Err(Error::AccessDenied));
+				data.state = SessionState::Failed;
+			}
+		},
+	}
+
+	Ok(())
+}
+
+fn do_partial_signing(node: &NodeId, signing_nodes: &BTreeSet<NodeId>, nonce: &Secret, signature_hash: &Secret, key_share: &DocumentKeyShare) -> Result<Secret, Error> {
+	let key_version = key_version(key_share);
+	let node_id_number = &key_version.id_numbers[node];
+	let node_secret_share = &key_version.secret_share;
+	let other_id_numbers = signing_nodes.iter()
+		.filter(|id| *id != node)
+		.map(|id| &key_version.id_numbers[id]);
+	math::compute_partial_schnorr_signature(nonce, signature_hash, node_id_number, node_secret_share, other_id_numbers)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use super::super::super::acl_storage::tests::DummyAclStorage;
+	use ethkey::{self, Random, Generator, Public};
+	use util::H256;
+	use key_server_cluster::{NodeId, DocumentKeyShare, DocumentKeyShareVersion, SessionId, Error, Requester};
+	use key_server_cluster::cluster::tests::DummyCluster;
+	use key_server_cluster::signing_session::{SessionImpl, SessionParams, SessionState};
+	use key_server_cluster::message::{self, Message, SigningMessage};
+	use key_server_cluster::math;
+
+	fn prepare_signing_sessions(threshold: usize, num_nodes: usize) -> (Public, Vec<Arc<DummyCluster>>, Vec<Arc<DummyAclStorage>>, Vec<SessionImpl>) {
+		// prepare key shares of the joint secret (trusted dealer is fine for tests)
+		let session_id = SessionId::default();
+		let access_key = Random.generate().unwrap().secret().clone();
+		let polynom = math::generate_random_polynom(threshold).unwrap();
+		let joint_public = math::compute_public_share(&polynom[0]).unwrap();
+		let id_numbers: Vec<(NodeId, _)> = (0..num_nodes).map(|_| (Random.generate().unwrap().public().clone(), math::generate_random_scalar().unwrap())).collect();
+		let key_shares: Vec<_> = (0..num_nodes).map(|i| DocumentKeyShare {
+			author: Public::default(),
+			threshold: threshold,
+			common_point: None,
+			encrypted_point: None,
+			versions: vec![DocumentKeyShareVersion::new(id_numbers.clone().into_iter().collect(),
+				math::compute_polynom(&polynom, &id_numbers[i].1).unwrap())],
+		}).collect();
+		let acl_storages: Vec<_> = (0..num_nodes).map(|_| Arc::new(DummyAclStorage::default())).collect();
+		let clusters: Vec<_> = (0..num_nodes).map(|i| {
+			let cluster = Arc::new(DummyCluster::new(id_numbers[i].0.clone()));
+			for id_number in &id_numbers {
+				cluster.add_node(id_number.0.clone());
+			}
+			cluster
+		}).collect();
+		let sessions: Vec<_> = (0..num_nodes).map(|i| SessionImpl::new(SessionParams {
+			id: session_id.clone(),
+			access_key: access_key.clone(),
+			self_node_id: id_numbers[i].0.clone(),
+			key_share: key_shares[i].clone(),
+			acl_storage: acl_storages[i].clone(),
+			cluster: clusters[i].clone(),
+		}).unwrap()).collect();
+
+		(joint_public, clusters, acl_storages, sessions)
+	}
+
+	fn do_messages_exchange_until<F>(clusters: &[Arc<DummyCluster>], sessions: &[SessionImpl], mut cond: F) where F: FnMut(&NodeId, &NodeId, &Message) -> bool {
+		while let Some((from, to, message)) = clusters.iter().filter_map(|c| c.take_message().map(|(to, msg)| (c.node(), to, msg))).next() {
+			let session = &sessions[sessions.iter().position(|s| s.node() == &to).unwrap()];
+			if cond(&from, &to, &message) {
+				break;
+			}
+
+			match message {
+				Message::Signing(SigningMessage::InitializeSigningSession(message)) => session.on_initialize_session(from, &message).unwrap(),
+				Message::Signing(SigningMessage::ConfirmSigningInitialization(message)) => session.on_confirm_initialization(from, &message).unwrap(),
+				Message::Signing(SigningMessage::RequestSigningNonce(message)) => session.on_nonce_requested(from, &message).unwrap(),
+				Message::Signing(SigningMessage::SigningNonce(message)) => session.on_nonce(from, &message).unwrap(),
+				Message::Signing(SigningMessage::RequestPartialSignature(message)) => session.on_partial_signature_requested(from, &message).unwrap(),
+				Message::Signing(SigningMessage::PartialSignature(message)) => session.on_partial_signature(from, &message).unwrap(),
+				Message::Signing(SigningMessage::SigningSessionCompleted(message)) => session.on_session_completed(from, &message).unwrap(),
+				_ => panic!("unexpected"),
+			}
+		}
+	}
+
+	fn requester() -> Requester {
+		Requester::Signature(ethkey::sign(Random.generate().unwrap().secret(), &SessionId::default()).unwrap())
+	}
+
+	#[test]
+	fn complete_signing_session() {
+		let (joint_public, clusters, _, sessions) = prepare_signing_sessions(2, 4);
+		let message_hash = H256::random();
+
+		sessions[0].initialize(requester(), message_hash.clone()).unwrap();
+		do_messages_exchange_until(&clusters, &sessions, |_, _, _| false);
+
+		assert_eq!(sessions[0].state(), SessionState::Finished);
+		let signature = sessions[0].result().unwrap().unwrap();
+		assert!(math::verify_schnorr_signature(&joint_public, &signature, &message_hash).unwrap());
+	}
+
+	#[test]
+	fn complete_signing_session_on_single_node() {
+		let (joint_public, _, _, sessions) = prepare_signing_sessions(0, 1);
+		let message_hash = H256::random();
+
+		sessions[0].initialize(requester(), message_hash.clone()).unwrap();
+
+		assert_eq!(sessions[0].state(), SessionState::Finished);
+		let signature = sessions[0].result().unwrap().unwrap();
+		assert!(math::verify_schnorr_signature(&joint_public, &signature, &message_hash).unwrap());
+	}
+
+	#[test]
+	fn fails_to_initialize_when_already_initialized() {
+		let (_, _, _, sessions) = prepare_signing_sessions(2, 4);
+		assert_eq!(sessions[0].initialize(requester(), H256::random()), Ok(()));
+		assert_eq!(sessions[0].initialize(requester(), H256::random()), Err(Error::InvalidStateForRequest));
+	}
+
+	#[test]
+	fn fails_when_requester_is_not_allowed_to_sign() {
+		let (_, clusters, acl_storages, sessions) = prepare_signing_sessions(2, 4);
+		let requester = requester();
+		let requester_address = requester.address(&SessionId::default()).unwrap();
+		for acl_storage in &acl_storages {
+			acl_storage.prohibit(requester_address.clone(), SessionId::default());
+		}
+
+		sessions[0].initialize(requester, H256::random()).unwrap();
+		assert_eq!(sessions[0].state(), SessionState::WaitingForInitializationConfirm);
+		do_messages_exchange_until(&clusters, &sessions, |_, _, _| false);
+
+		assert_eq!(sessions[0].state(), SessionState::Failed);
+		assert_eq!(sessions[0].result(), Some(Err(Error::AccessDenied)));
+	}
+
+	#[test]
+	fn signing_works_when_some_nodes_are_not_allowed_to_sign() {
+		let (joint_public, clusters, acl_storages, sessions) = prepare_signing_sessions(1, 4);
+		let requester = requester();
+		let requester_address = requester.address(&SessionId::default()).unwrap();
+		acl_storages[1].prohibit(requester_address.clone(), SessionId::default());
+		acl_storages[2].prohibit(requester_address, SessionId::default());
+		let message_hash = H256::random();
+
+		sessions[0].initialize(requester, message_hash.clone()).unwrap();
+		do_messages_exchange_until(&clusters, &sessions, |_, _, _| false);
+
+		assert_eq!(sessions[0].state(), SessionState::Finished);
+		let signature = sessions[0].result().unwrap().unwrap();
+		assert!(math::verify_schnorr_signature(&joint_public, &signature, &message_hash).unwrap());
+	}
+
+	#[test]
+	fn nonce_is_never_used_twice() {
+		let (_, clusters, _, sessions) = prepare_signing_sessions(1, 2);
+		sessions[0].initialize(requester(), H256::random()).unwrap();
+
+		// intercept partial signature request to the slave node
+		let mut request = None;
+		do_messages_exchange_until(&clusters, &sessions, |_, _, message| match *message {
+			Message::Signing(SigningMessage::RequestPartialSignature(ref message)) => {
+				request = Some(message.clone());
+				true
+			},
+			_ => false,
+		});
+		let request: message::RequestPartialSignature = request.unwrap();
+
+		// slave node responds to the first request only
+		assert_eq!(sessions[1].on_partial_signature_requested(sessions[0].node().clone(), &request), Ok(()));
+		assert_eq!(sessions[1].on_partial_signature_requested(sessions[0].node().clone(), &request), Err(Error::InvalidStateForRequest));
+	}
+
+	#[test]
+	fn nonce_is_erased_when_partial_signature_request_is_invalid() {
+		let (_, clusters, _, sessions) = prepare_signing_sessions(1, 2);
+		sessions[0].initialize(requester(), H256::random()).unwrap();
+
+		let mut request = None;
+		do_messages_exchange_until(&clusters, &sessions, |_, _, message| match *message {
+			Message::Signing(SigningMessage::RequestPartialSignature(ref message)) => {
+				request = Some(message.clone());
+				true
+			},
+			_ => false,
+		});
+		let mut request: message::RequestPartialSignature = request.unwrap();
+		let valid_request = request.clone();
+
+		// forged nonce of the slave node is rejected && the nonce is erased
+		request.nonces.insert(sessions[1].node().clone().into(), Random.generate().unwrap().public().clone().into());
+		assert_eq!(sessions[1].on_partial_signature_requested(sessions[0].node().clone(), &request), Err(Error::InvalidMessage));
+		assert_eq!(sessions[1].on_partial_signature_requested(sessions[0].node().clone(), &valid_request), Err(Error::InvalidStateForRequest));
+	}
+
+	#[test]
+	fn signing_is_restarted_with_new_nonces_when_signing_node_is_disconnected() {
+		let (joint_public, clusters, _, sessions) = prepare_signing_sessions(1, 3);
+		let message_hash = H256::random();
+		sessions[0].initialize(requester(), message_hash.clone()).unwrap();
+
+		// master selects the first confirmed node for signing && requests its nonce
+		let mut disconnected_node = None;
+		do_messages_exchange_until(&clusters, &sessions, |_, to, message| match *message {
+			Message::Signing(SigningMessage::RequestSigningNonce(_)) => {
+				disconnected_node = Some(to.clone());
+				true
+			},
+			_ => false,
+		});
+		assert_eq!(sessions[0].state(), SessionState::WaitingForNonces);
+
+		// this node is disconnected before it responds => signing is restarted with the rest of nodes
+		let disconnected_node = disconnected_node.unwrap();
+		sessions[0].on_node_timeout(&disconnected_node);
+		assert_eq!(sessions[0].state(), SessionState::WaitingForInitializationConfirm);
+		do_messages_exchange_until(&clusters, &sessions, |_, to, _| to == &disconnected_node);
+
+		assert_eq!(sessions[0].state(), SessionState::Finished);
+		let signature = sessions[0].result().unwrap().unwrap();
+		assert!(math::verify_schnorr_signature(&joint_public, &signature, &message_hash).unwrap());
+	}
+}
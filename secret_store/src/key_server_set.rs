@@ -0,0 +1,238 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Weak;
+use std::net::SocketAddr;
+use std::collections::BTreeMap;
+use futures::{future, Future};
+use parking_lot::Mutex;
+use ethkey::public_to_address;
+use ethcore::client::ChainNotify;
+use native_contracts::KeyServerSet as KeyServerSetContract;
+use util::{Address, Bytes, H256};
+use acl_storage::CallContract;
+use types::all::{NodeId, Public};
+
+const KEY_SERVER_SET_CONTRACT_REGISTRY_NAME: &'static str = "secretstore_server_set";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// Key servers set snapshot.
+pub struct KeyServerSetSnapshot {
+	/// Key servers, which are currently holding key shares.
+	pub current_set: BTreeMap<NodeId, SocketAddr>,
+	/// Key servers, which are going to hold key shares after next migration.
+	pub new_set: BTreeMap<NodeId, SocketAddr>,
+	/// Active migration, if any.
+	pub migration: Option<KeyServerSetMigration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Key servers set migration, signalled by the key server set.
+pub struct KeyServerSetMigration {
+	/// Migration id.
+	pub id: H256,
+	/// Key servers, which will hold key shares when migration is completed.
+	pub set: BTreeMap<NodeId, SocketAddr>,
+}
+
+/// Key servers set.
+pub trait KeyServerSet: Send + Sync {
+	/// Get snapshot of the key servers set.
+	fn snapshot(&self) -> KeyServerSetSnapshot;
+}
+
+/// On-chain key servers set implementation.
+pub struct OnChainKeyServerSet {
+	/// Contracts caller. Weak, because the client holds this set in its notify list.
+	client: Weak<CallContract>,
+	/// Mutable data.
+	data: Mutex<OnChainKeyServerSetData>,
+}
+
+/// Mutable data of on-chain key servers set.
+struct OnChainKeyServerSetData {
+	/// Last read snapshot.
+	snapshot: KeyServerSetSnapshot,
+}
+
+impl OnChainKeyServerSet {
+	/// Create new on-chain key servers set. Given nodes are used until the contract is read for the first time.
+	pub fn new(client: Weak<CallContract>, nodes: BTreeMap<NodeId, SocketAddr>) -> Self {
+		OnChainKeyServerSet {
+			client: client,
+			data: Mutex::new(OnChainKeyServerSetData {
+				snapshot: KeyServerSetSnapshot {
+					current_set: nodes,
+					new_set: BTreeMap::new(),
+					migration: None,
+				},
+			}),
+		}
+	}
+
+	/// When new block is imported. Key servers set could have been changed => read it again.
+	pub fn on_new_block(&self, block_hash: &H256) {
+		trace!(target: "secretstore", "Reading key servers set on block {}", block_hash);
+
+		let client = match self.client.upgrade() {
+			Some(client) => client,
+			None => return,
+		};
+
+		// contract is read without holding the lock => readers of the snapshot are not blocked by contract calls
+		let contract = match client.registry_address(KEY_SERVER_SET_CONTRACT_REGISTRY_NAME.to_owned()) {
+			Some(contract_addr) => KeyServerSetContract::new(contract_addr),
+			None => return,
+		};
+
+		match read_snapshot(&*client, &contract) {
+			Ok(snapshot) => self.data.lock().snapshot = snapshot,
+			// keep using previous snapshot => we could try to read it again on next block
+			Err(err) => warn!(target: "secretstore", "Failed to read key servers set: {}", err),
+		}
+	}
+}
+
+/// Read key servers set snapshot from the contract.
+fn read_snapshot(client: &CallContract, contract: &KeyServerSetContract) -> Result<KeyServerSetSnapshot, String> {
+	let do_call = |a: Address, d: Bytes| future::done(client.call_contract(a, d));
+
+	let mut current_set = BTreeMap::new();
+	for key_server in contract.get_current_key_servers(&do_call).wait()? {
+		let public = contract.get_current_key_server_public(&do_call, key_server.clone()).wait()?;
+		let address = contract.get_current_key_server_address(&do_call, key_server.clone()).wait()?;
+		let (node_id, node_address) = parse_key_server(&key_server, public, address)?;
+		current_set.insert(node_id, node_address);
+	}
+
+	let mut new_set = BTreeMap::new();
+	for key_server in contract.get_new_key_servers(&do_call).wait()? {
+		let public = contract.get_new_key_server_public(&do_call, key_server.clone()).wait()?;
+		let address = contract.get_new_key_server_address(&do_call, key_server.clone()).wait()?;
+		let (node_id, node_address) = parse_key_server(&key_server, public, address)?;
+		new_set.insert(node_id, node_address);
+	}
+
+	let migration_id = contract.get_migration_id(&do_call).wait()?;
+	let migration = if migration_id.is_zero() {
+		None
+	} else {
+		let mut migration_set = BTreeMap::new();
+		for key_server in contract.get_migration_key_servers(&do_call).wait()? {
+			let public = contract.get_migration_key_server_public(&do_call, key_server.clone()).wait()?;
+			let address = contract.get_migration_key_server_address(&do_call, key_server.clone()).wait()?;
+			let (node_id, node_address) = parse_key_server(&key_server, public, address)?;
+			migration_set.insert(node_id, node_address);
+		}
+
+		Some(KeyServerSetMigration {
+			id: migration_id,
+			set: migration_set,
+		})
+	};
+
+	Ok(KeyServerSetSnapshot {
+		current_set: current_set,
+		new_set: new_set,
+		migration: migration,
+	})
+}
+
+impl KeyServerSet for OnChainKeyServerSet {
+	fn snapshot(&self) -> KeyServerSetSnapshot {
+		self.data.lock().snapshot.clone()
+	}
+}
+
+impl ChainNotify for OnChainKeyServerSet {
+	fn new_blocks(&self, imported: Vec<H256>, _invalid: Vec<H256>, enacted: Vec<H256>, retracted: Vec<H256>, _sealed: Vec<H256>, _proposed: Vec<Bytes>, _duration: u64) {
+		if let Some(block_hash) = enacted.last().or(retracted.last()).or(imported.last()) {
+			self.on_new_block(block_hash);
+		}
+	}
+}
+
+/// Parse key server public && network address, read from the contract.
+fn parse_key_server(key_server: &Address, public: Vec<u8>, address: String) -> Result<(NodeId, SocketAddr), String> {
+	if public.len() != 64 {
+		return Err(format!("invalid public of key server {}", key_server));
+	}
+
+	let public = Public::from_slice(&public);
+	if &public_to_address(&public) != key_server {
+		return Err(format!("public of key server {} does not match its address", key_server));
+	}
+
+	let address = address.parse()
+		.map_err(|err| format!("invalid network address {} of key server {}: {}", address, key_server, err))?;
+	Ok((public, address))
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::net::SocketAddr;
+	use std::collections::BTreeMap;
+	use parking_lot::Mutex;
+	use ethkey::{Random, Generator, public_to_address};
+	use util::Address;
+	use types::all::NodeId;
+	use super::{KeyServerSet, KeyServerSetSnapshot, parse_key_server};
+
+	#[derive(Default)]
+	/// Key servers set, which snapshot is set from tests.
+	pub struct MapKeyServerSet {
+		snapshot: Mutex<KeyServerSetSnapshot>,
+	}
+
+	impl MapKeyServerSet {
+		pub fn new(nodes: BTreeMap<NodeId, SocketAddr>) -> Self {
+			MapKeyServerSet {
+				snapshot: Mutex::new(KeyServerSetSnapshot {
+					current_set: nodes,
+					..Default::default()
+				}),
+			}
+		}
+
+		pub fn set_snapshot(&self, snapshot: KeyServerSetSnapshot) {
+			*self.snapshot.lock() = snapshot;
+		}
+	}
+
+	impl KeyServerSet for MapKeyServerSet {
+		fn snapshot(&self) -> KeyServerSetSnapshot {
+			self.snapshot.lock().clone()
+		}
+	}
+
+	#[test]
+	fn key_server_is_parsed() {
+		let key_pair = Random.generate().unwrap();
+		let key_server = public_to_address(key_pair.public());
+		let (node_id, node_address) = parse_key_server(&key_server, key_pair.public().to_vec(), "127.0.0.1:8083".into()).unwrap();
+		assert_eq!(&node_id, key_pair.public());
+		assert_eq!(node_address, "127.0.0.1:8083".parse().unwrap());
+	}
+
+	#[test]
+	fn key_server_with_invalid_data_is_rejected() {
+		let key_pair = Random.generate().unwrap();
+		let key_server = public_to_address(key_pair.public());
+		assert!(parse_key_server(&key_server, vec![1, 2, 3], "127.0.0.1:8083".into()).is_err());
+		assert!(parse_key_server(&Address::default(), key_pair.public().to_vec(), "127.0.0.1:8083".into()).is_err());
+		assert!(parse_key_server(&key_server, key_pair.public().to_vec(), "localhost".into()).is_err());
+	}
+}
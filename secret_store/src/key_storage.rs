@@ -14,47 +14,134 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::mem;
 use std::path::PathBuf;
-use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
 use serde_json;
-use ethkey::{Secret, Public};
-use util::Database;
+use parking_lot::{Mutex, RwLock};
+use ethkey::{Secret, Public, Signature};
+use util::{Database, DatabaseIterator, Hashable, H256};
 use types::all::{Error, ServiceConfiguration, DocumentAddress, NodeId};
-use serialization::{SerializablePublic, SerializableSecret};
+use serialization::{SerializablePublic, SerializableSecret, SerializableH256, SerializableSignature};
+
+/// Key of version value.
+const DB_META_KEY_VERSION: &'static [u8; 7] = b"version";
+/// Prefix of keys, under which used key removal nonces are stored.
+const DB_KEY_REMOVAL_NONCES_PREFIX: &'static [u8; 14] = b"removal_nonces";
+/// Prefix of keys, under which used document key store nonces are stored.
+const DB_DOCUMENT_KEY_STORE_NONCES_PREFIX: &'static [u8; 12] = b"store_nonces";
+/// Prefix of keys, under which key removal retries are stored.
+const DB_KEY_REMOVAL_RETRIES_PREFIX: &'static [u8; 15] = b"removal_retries";
+/// Current db version.
+const CURRENT_VERSION: u8 = 1;
+/// Current type of serialized key shares.
+type CurrentSerializableDocumentKeyShare = SerializableDocumentKeyShareV1;
+/// Current type of serialized key shares versions.
+type CurrentSerializableDocumentKeyShareVersion = SerializableDocumentKeyShareVersionV1;
 
 #[derive(Debug, Clone, PartialEq)]
 /// Encrypted key share, stored by key storage on the single key server.
 pub struct DocumentKeyShare {
+	/// Author of the entry.
+	pub author: Public,
 	/// Decryption threshold (at least threshold + 1 nodes are required to decrypt data).
 	pub threshold: usize,
+	/// Common (shared) encryption point. None if document key is not yet stored.
+	pub common_point: Option<Public>,
+	/// Encrypted point. None if document key is not yet stored.
+	pub encrypted_point: Option<Public>,
+	/// Key share versions.
+	pub versions: Vec<DocumentKeyShareVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Versioned portion of document key share.
+pub struct DocumentKeyShareVersion {
+	/// Version hash (Keccak(id_numbers)).
+	pub hash: H256,
 	/// Nodes ids numbers.
 	pub id_numbers: BTreeMap<NodeId, Secret>,
 	/// Node secret share.
 	pub secret_share: Secret,
-	/// Common (shared) encryption point.
-	pub common_point: Public,
-	/// Encrypted point.
-	pub encrypted_point: Public,
+	/// True if version is shared with lost nodes && must not be used anymore.
+	pub retired: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Key removal, which must be retried on some key holders.
+pub struct KeyRemovalRetry {
+	/// Signature of the key author or of the administrator.
+	pub requestor_signature: Signature,
+	/// Removal request nonce.
+	pub nonce: u64,
+	/// Key holders, which have not confirmed removal of their key shares.
+	pub nodes: BTreeSet<NodeId>,
 }
 
 /// Document encryption keys storage
 pub trait KeyStorage: Send + Sync {
 	/// Insert document encryption key
 	fn insert(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error>;
+	/// Update document encryption key
+	fn update(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error>;
 	/// Get document encryption key
 	fn get(&self, document: &DocumentAddress) -> Result<DocumentKeyShare, Error>;
+	/// Remove document encryption key
+	fn remove(&self, document: &DocumentAddress) -> Result<(), Error>;
 	/// Check if storage contains document encryption key
 	fn contains(&self, document: &DocumentAddress) -> bool;
+	/// Iterate through storage
+	fn iter<'a>(&'a self) -> Box<Iterator<Item=(DocumentAddress, DocumentKeyShare)> + 'a>;
+	/// Check if key removal request with given nonce has already been processed.
+	fn is_key_removal_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error>;
+	/// Remember that key removal request with given nonce has been processed.
+	fn insert_key_removal_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error>;
+	/// Check if document key store request with given nonce has already been processed.
+	fn is_document_key_store_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error>;
+	/// Remember that document key store request with given nonce has been processed.
+	fn insert_document_key_store_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error>;
+	/// Get all key removals, which must be retried.
+	fn key_removal_retries(&self) -> Result<BTreeMap<DocumentAddress, KeyRemovalRetry>, Error>;
+	/// Remember (or forget, if None is passed) key removal, which must be retried.
+	fn set_key_removal_retry(&self, document: &DocumentAddress, retry: Option<KeyRemovalRetry>) -> Result<(), Error>;
+	/// Atomically check that keys have expected values && apply set of changes. None value means that the key
+	/// is missing (expected) or must be removed (changes). Fails with DatabaseConflict if any of expected values differs.
+	fn apply(&self, expected: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>, changes: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>) -> Result<(), Error>;
 }
 
 /// Persistent document encryption keys storage
 pub struct PersistentKeyStorage {
 	db: Database,
+	/// Held by every writer, so that check-and-apply is atomic with respect to other modifications.
+	write_lock: Mutex<()>,
+}
+
+/// Persistent document encryption keys storage iterator
+pub struct PersistentKeyStorageIterator<'a> {
+	iter: Option<DatabaseIterator<'a>>,
+}
+
+/// Key storage wrapper, which buffers all modifications in memory until these are committed.
+/// Staged modifications are only visible through the wrapper itself.
+pub struct TransactionalKeyStorage {
+	/// Wrapped key storage.
+	inner: Arc<KeyStorage>,
+	/// Staged modifications.
+	staged: RwLock<BTreeMap<DocumentAddress, StagedKeyShare>>,
+}
+
+/// Staged modification of single document key share.
+struct StagedKeyShare {
+	/// Value of the inner storage at the moment when first modification has been staged.
+	original: Option<DocumentKeyShare>,
+	/// Staged value. None if key share is removed.
+	value: Option<DocumentKeyShare>,
 }
 
 #[derive(Serialize, Deserialize)]
-/// Encrypted key share, as it is stored by key storage on the single key server.
-struct SerializableDocumentKeyShare {
+/// V0 of encrypted key share, as it is stored by key storage on the single key server.
+struct SerializableDocumentKeyShareV0 {
 	/// Decryption threshold (at least threshold + 1 nodes are required to decrypt data).
 	pub threshold: usize,
 	/// Nodes ids numbers.
@@ -67,6 +154,46 @@ struct SerializableDocumentKeyShare {
 	pub encrypted_point: SerializablePublic,
 }
 
+#[derive(Serialize, Deserialize)]
+/// V1 of encrypted key share, as it is stored by key storage on the single key server.
+struct SerializableDocumentKeyShareV1 {
+	/// Author of the entry.
+	pub author: SerializablePublic,
+	/// Decryption threshold (at least threshold + 1 nodes are required to decrypt data).
+	pub threshold: usize,
+	/// Common (shared) encryption point.
+	pub common_point: Option<SerializablePublic>,
+	/// Encrypted point.
+	pub encrypted_point: Option<SerializablePublic>,
+	/// Versions.
+	pub versions: Vec<SerializableDocumentKeyShareVersionV1>,
+}
+
+#[derive(Serialize, Deserialize)]
+/// V1 of encrypted key share version, as it is stored by key storage on the single key server.
+struct SerializableDocumentKeyShareVersionV1 {
+	/// Version hash.
+	pub hash: SerializableH256,
+	/// Nodes ids numbers.
+	pub id_numbers: BTreeMap<SerializablePublic, SerializableSecret>,
+	/// Node secret share.
+	pub secret_share: SerializableSecret,
+	/// Is version retired? Missing in versions, stored before retirement has been introduced.
+	#[serde(default)]
+	pub retired: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+/// Key removal retry, as it is stored by key storage on the single key server.
+struct SerializableKeyRemovalRetry {
+	/// Signature of the key author or of the administrator.
+	pub requestor_signature: SerializableSignature,
+	/// Removal request nonce.
+	pub nonce: u64,
+	/// Key holders, which have not confirmed removal of their key shares.
+	pub nodes: BTreeSet<SerializablePublic>,
+}
+
 impl PersistentKeyStorage {
 	/// Create new persistent document encryption keys storage
 	pub fn new(config: &ServiceConfiguration) -> Result<Self, Error> {
@@ -74,18 +201,94 @@ impl PersistentKeyStorage {
 		db_path.push("db");
 		let db_path = db_path.to_str().ok_or(Error::Database("Invalid secretstore path".to_owned()))?;
 
+		let db = Database::open_default(&db_path).map_err(Error::Database)?;
+		let db = upgrade_db(db)?;
+
 		Ok(PersistentKeyStorage {
-			db: Database::open_default(&db_path).map_err(Error::Database)?,
+			db: db,
+			write_lock: Mutex::new(()),
 		})
 	}
+
+	/// Read used nonces, stored under given prefix.
+	fn used_nonces(&self, prefix: &[u8], document: &DocumentAddress) -> Result<BTreeSet<u64>, Error> {
+		match self.db.get(None, &prefixed_db_key(prefix, document)).map_err(Error::Database)? {
+			Some(nonces) => serde_json::from_slice(&nonces).map_err(|e| Error::Database(e.to_string())),
+			None => Ok(BTreeSet::new()),
+		}
+	}
+
+	/// Remember used nonce under given prefix.
+	fn insert_used_nonce(&self, prefix: &[u8], document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		let _write_lock = self.write_lock.lock();
+		let mut nonces = self.used_nonces(prefix, document)?;
+		nonces.insert(nonce);
+
+		let nonces = serde_json::to_vec(&nonces).map_err(|e| Error::Database(e.to_string()))?;
+		let mut batch = self.db.transaction();
+		batch.put(None, &prefixed_db_key(prefix, document), &nonces);
+		self.db.write(batch).map_err(Error::Database)
+	}
+}
+
+/// Make db key, which does not conflict with document keys.
+fn prefixed_db_key(prefix: &[u8], document: &DocumentAddress) -> Vec<u8> {
+	let mut db_key = prefix.to_vec();
+	db_key.extend_from_slice(&**document);
+	db_key
+}
+
+fn upgrade_db(db: Database) -> Result<Database, Error> {
+	let version = db.get(None, DB_META_KEY_VERSION).map_err(Error::Database)?;
+	let version = version.and_then(|v| v.get(0).cloned()).unwrap_or(0);
+	match version {
+		0 => {
+			let mut batch = db.transaction();
+			batch.put(None, DB_META_KEY_VERSION, &[CURRENT_VERSION]);
+			for (db_key, db_value) in db.iter(None).into_iter().flat_map(|inner| inner) {
+				let v0_key = serde_json::from_slice::<SerializableDocumentKeyShareV0>(&db_value).map_err(|e| Error::Database(e.to_string()))?;
+				let id_numbers: BTreeMap<NodeId, Secret> = v0_key.id_numbers.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+				let current_key = CurrentSerializableDocumentKeyShare {
+					// author is used in separate generation + encrypt sessions.
+					// in v0 there have been only simultaneous GenEnc sessions.
+					author: Public::default().into(),
+					threshold: v0_key.threshold,
+					common_point: Some(v0_key.common_point),
+					encrypted_point: Some(v0_key.encrypted_point),
+					versions: vec![DocumentKeyShareVersion::new(id_numbers, v0_key.secret_share.into()).into()],
+				};
+				let db_value = serde_json::to_vec(&current_key).map_err(|e| Error::Database(e.to_string()))?;
+				batch.put(None, &*db_key, &*db_value);
+			}
+			db.write(batch).map_err(Error::Database)?;
+			Ok(db)
+		},
+		1 => Ok(db),
+		_ => Err(Error::Database(format!("unsupported SecretStore database version: {}", version))),
+	}
 }
 
 impl KeyStorage for PersistentKeyStorage {
 	fn insert(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error> {
-		let key: SerializableDocumentKeyShare = key.into();
+		let key: CurrentSerializableDocumentKeyShare = key.into();
+		let key = serde_json::to_vec(&key).map_err(|e| Error::Database(e.to_string()))?;
+		let mut batch = self.db.transaction();
+		batch.put(None, &document, &key);
+		let _write_lock = self.write_lock.lock();
+		self.db.write(batch).map_err(Error::Database)
+	}
+
+	fn update(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error> {
+		let key: CurrentSerializableDocumentKeyShare = key.into();
 		let key = serde_json::to_vec(&key).map_err(|e| Error::Database(e.to_string()))?;
 		let mut batch = self.db.transaction();
 		batch.put(None, &document, &key);
+
+		// key could be removed between check && write => hold the lock across both
+		let _write_lock = self.write_lock.lock();
+		if !self.contains(&document) {
+			return Err(Error::DocumentNotFound);
+		}
 		self.db.write(batch).map_err(Error::Database)
 	}
 
@@ -94,54 +297,390 @@ impl KeyStorage for PersistentKeyStorage {
 			.map_err(Error::Database)?
 			.ok_or(Error::DocumentNotFound)
 			.map(|key| key.to_vec())
-			.and_then(|key| serde_json::from_slice::<SerializableDocumentKeyShare>(&key).map_err(|e| Error::Database(e.to_string())))
+			.and_then(|key| serde_json::from_slice::<CurrentSerializableDocumentKeyShare>(&key).map_err(|e| Error::Database(e.to_string())))
 			.map(Into::into)
 	}
 
+	fn remove(&self, document: &DocumentAddress) -> Result<(), Error> {
+		let mut batch = self.db.transaction();
+		batch.delete(None, &document);
+		let _write_lock = self.write_lock.lock();
+		self.db.write(batch).map_err(Error::Database)
+	}
+
 	fn contains(&self, document: &DocumentAddress) -> bool {
 		self.db.get(None, document)
 			.map(|k| k.is_some())
 			.unwrap_or(false)
 	}
+
+	fn iter<'a>(&'a self) -> Box<Iterator<Item=(DocumentAddress, DocumentKeyShare)> + 'a> {
+		Box::new(PersistentKeyStorageIterator {
+			iter: self.db.iter(None),
+		})
+	}
+
+	fn is_key_removal_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error> {
+		self.used_nonces(DB_KEY_REMOVAL_NONCES_PREFIX, document).map(|nonces| nonces.contains(&nonce))
+	}
+
+	fn insert_key_removal_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		self.insert_used_nonce(DB_KEY_REMOVAL_NONCES_PREFIX, document, nonce)
+	}
+
+	fn is_document_key_store_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error> {
+		self.used_nonces(DB_DOCUMENT_KEY_STORE_NONCES_PREFIX, document).map(|nonces| nonces.contains(&nonce))
+	}
+
+	fn insert_document_key_store_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		self.insert_used_nonce(DB_DOCUMENT_KEY_STORE_NONCES_PREFIX, document, nonce)
+	}
+
+	fn key_removal_retries(&self) -> Result<BTreeMap<DocumentAddress, KeyRemovalRetry>, Error> {
+		let prefix_len = DB_KEY_REMOVAL_RETRIES_PREFIX.len();
+		let mut retries = BTreeMap::new();
+		for (db_key, db_value) in self.db.iter(None).into_iter().flat_map(|inner| inner) {
+			if db_key.len() != prefix_len + DocumentAddress::len() || &db_key[..prefix_len] != &DB_KEY_REMOVAL_RETRIES_PREFIX[..] {
+				continue;
+			}
+
+			let retry: SerializableKeyRemovalRetry = serde_json::from_slice(&db_value).map_err(|e| Error::Database(e.to_string()))?;
+			retries.insert(DocumentAddress::from_slice(&db_key[prefix_len..]), retry.into());
+		}
+		Ok(retries)
+	}
+
+	fn set_key_removal_retry(&self, document: &DocumentAddress, retry: Option<KeyRemovalRetry>) -> Result<(), Error> {
+		let db_key = prefixed_db_key(DB_KEY_REMOVAL_RETRIES_PREFIX, document);
+		let mut batch = self.db.transaction();
+		match retry {
+			Some(retry) => {
+				let retry: SerializableKeyRemovalRetry = retry.into();
+				let retry = serde_json::to_vec(&retry).map_err(|e| Error::Database(e.to_string()))?;
+				batch.put(None, &db_key, &retry);
+			},
+			None => batch.delete(None, &db_key),
+		}
+
+		let _write_lock = self.write_lock.lock();
+		self.db.write(batch).map_err(Error::Database)
+	}
+
+	fn apply(&self, expected: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>, changes: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>) -> Result<(), Error> {
+		let mut batch = self.db.transaction();
+		for (document, key) in changes {
+			match key {
+				Some(key) => {
+					let key: CurrentSerializableDocumentKeyShare = key.into();
+					let key = serde_json::to_vec(&key).map_err(|e| Error::Database(e.to_string()))?;
+					batch.put(None, &document, &key);
+				},
+				None => batch.delete(None, &document),
+			}
+		}
+
+		let _write_lock = self.write_lock.lock();
+		if expected.into_iter().any(|(document, expected)| self.get(&document).ok() != expected) {
+			return Err(Error::DatabaseConflict);
+		}
+		self.db.write(batch).map_err(Error::Database)
+	}
+}
+
+impl TransactionalKeyStorage {
+	/// Create new transactional wrapper over given key storage.
+	pub fn new(inner: Arc<KeyStorage>) -> Self {
+		TransactionalKeyStorage {
+			inner: inner,
+			staged: RwLock::new(BTreeMap::new()),
+		}
+	}
+
+	/// Apply all staged modifications to the inner storage.
+	/// Fails with DatabaseConflict if any of modified keys has been changed in the inner storage since
+	/// modification has been staged (the check is performed by the inner storage, atomically with the write).
+	/// Staged modifications are discarded in both cases.
+	pub fn commit(&self) -> Result<(), Error> {
+		let staged = mem::replace(&mut *self.staged.write(), BTreeMap::new());
+		let mut expected = BTreeMap::new();
+		let mut changes = BTreeMap::new();
+		for (document, staged) in staged {
+			expected.insert(document.clone(), staged.original);
+			changes.insert(document, staged.value);
+		}
+
+		self.inner.apply(expected, changes)
+	}
+
+	/// Discard all staged modifications.
+	pub fn rollback(&self) {
+		self.staged.write().clear();
+	}
+
+	/// Stage modification of given document key share.
+	fn stage(&self, document: DocumentAddress, value: Option<DocumentKeyShare>) {
+		let mut staged = self.staged.write();
+		if let Some(staged) = staged.get_mut(&document) {
+			staged.value = value;
+			return;
+		}
+
+		let original = self.inner.get(&document).ok();
+		staged.insert(document, StagedKeyShare {
+			original: original,
+			value: value,
+		});
+	}
+}
+
+impl KeyStorage for TransactionalKeyStorage {
+	fn insert(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error> {
+		self.stage(document, Some(key));
+		Ok(())
+	}
+
+	fn update(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error> {
+		if !self.contains(&document) {
+			return Err(Error::DocumentNotFound);
+		}
+
+		self.stage(document, Some(key));
+		Ok(())
+	}
+
+	fn get(&self, document: &DocumentAddress) -> Result<DocumentKeyShare, Error> {
+		match self.staged.read().get(document) {
+			Some(staged) => staged.value.clone().ok_or(Error::DocumentNotFound),
+			None => self.inner.get(document),
+		}
+	}
+
+	fn remove(&self, document: &DocumentAddress) -> Result<(), Error> {
+		self.stage(document.clone(), None);
+		Ok(())
+	}
+
+	fn contains(&self, document: &DocumentAddress) -> bool {
+		match self.staged.read().get(document) {
+			Some(staged) => staged.value.is_some(),
+			None => self.inner.contains(document),
+		}
+	}
+
+	fn iter<'a>(&'a self) -> Box<Iterator<Item=(DocumentAddress, DocumentKeyShare)> + 'a> {
+		let mut keys: BTreeMap<_, _> = self.inner.iter().collect();
+		for (document, staged) in self.staged.read().iter() {
+			match staged.value {
+				Some(ref key) => { keys.insert(document.clone(), key.clone()); },
+				None => { keys.remove(document); },
+			}
+		}
+		Box::new(keys.into_iter())
+	}
+
+	fn is_key_removal_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error> {
+		self.inner.is_key_removal_nonce_used(document, nonce)
+	}
+
+	fn insert_key_removal_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		self.inner.insert_key_removal_nonce(document, nonce)
+	}
+
+	fn is_document_key_store_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error> {
+		self.inner.is_document_key_store_nonce_used(document, nonce)
+	}
+
+	fn insert_document_key_store_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		self.inner.insert_document_key_store_nonce(document, nonce)
+	}
+
+	fn key_removal_retries(&self) -> Result<BTreeMap<DocumentAddress, KeyRemovalRetry>, Error> {
+		self.inner.key_removal_retries()
+	}
+
+	fn set_key_removal_retry(&self, document: &DocumentAddress, retry: Option<KeyRemovalRetry>) -> Result<(), Error> {
+		self.inner.set_key_removal_retry(document, retry)
+	}
+
+	fn apply(&self, expected: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>, changes: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>) -> Result<(), Error> {
+		if expected.into_iter().any(|(document, expected)| self.get(&document).ok() != expected) {
+			return Err(Error::DatabaseConflict);
+		}
+
+		for (document, key) in changes {
+			self.stage(document, key);
+		}
+		Ok(())
+	}
+}
+
+impl<'a> Iterator for PersistentKeyStorageIterator<'a> {
+	type Item = (DocumentAddress, DocumentKeyShare);
+
+	fn next(&mut self) -> Option<(DocumentAddress, DocumentKeyShare)> {
+		let iter = match self.iter.as_mut() {
+			Some(iter) => iter,
+			None => return None,
+		};
+
+		// skip db meta entries (they are shorter than document address)
+		while let Some((db_key, db_value)) = iter.next() {
+			if db_key.len() != DocumentAddress::len() {
+				continue;
+			}
+
+			match serde_json::from_slice::<CurrentSerializableDocumentKeyShare>(&db_value) {
+				Ok(key) => return Some((DocumentAddress::from_slice(&db_key), key.into())),
+				Err(err) => warn!(target: "secretstore", "skipping invalid key share {}: {}", DocumentAddress::from_slice(&db_key), err),
+			}
+		}
+
+		None
+	}
+}
+
+impl DocumentKeyShare {
+	/// Get last version reference. Retired versions are skipped.
+	pub fn last_version(&self) -> Result<&DocumentKeyShareVersion, Error> {
+		self.versions.iter().rev()
+			.find(|v| !v.retired)
+			.ok_or_else(|| Error::Database("key version is not found".into()))
+	}
+
+	/// Get last version mutable reference. Retired versions are skipped.
+	pub fn last_version_mut(&mut self) -> Result<&mut DocumentKeyShareVersion, Error> {
+		self.versions.iter_mut().rev()
+			.find(|v| !v.retired)
+			.ok_or_else(|| Error::Database("key version is not found".into()))
+	}
+
+	/// Retire every version, which is shared with any of given nodes. Returns true if any version has been retired.
+	pub fn retire_versions(&mut self, nodes: &BTreeSet<NodeId>) -> bool {
+		let mut is_retired = false;
+		for version in self.versions.iter_mut().filter(|v| !v.retired && v.id_numbers.keys().any(|n| nodes.contains(n))) {
+			version.retired = true;
+			is_retired = true;
+		}
+		is_retired
+	}
+
+	/// Get given version reference.
+	pub fn version(&self, version: &H256) -> Result<&DocumentKeyShareVersion, Error> {
+		self.versions.iter()
+			.find(|v| &v.hash == version)
+			.ok_or_else(|| Error::Database("key version is not found".into()))
+	}
+}
+
+impl DocumentKeyShareVersion {
+	/// Create new version
+	pub fn new(id_numbers: BTreeMap<NodeId, Secret>, secret_share: Secret) -> Self {
+		DocumentKeyShareVersion {
+			hash: Self::data_hash(&id_numbers),
+			id_numbers: id_numbers,
+			secret_share: secret_share,
+			retired: false,
+		}
+	}
+
+	/// Calculate hash of given version data.
+	pub fn data_hash(id_numbers: &BTreeMap<NodeId, Secret>) -> H256 {
+		let mut data = Vec::new();
+		for (node, node_number) in id_numbers {
+			data.extend_from_slice(&**node);
+			data.extend_from_slice(&***node_number);
+		}
+		data.sha3()
+	}
 }
 
-impl From<DocumentKeyShare> for SerializableDocumentKeyShare {
+impl From<DocumentKeyShare> for SerializableDocumentKeyShareV1 {
 	fn from(key: DocumentKeyShare) -> Self {
-		SerializableDocumentKeyShare {
+		SerializableDocumentKeyShareV1 {
+			author: key.author.into(),
 			threshold: key.threshold,
-			id_numbers: key.id_numbers.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
-			secret_share: key.secret_share.into(),
-			common_point: key.common_point.into(),
-			encrypted_point: key.encrypted_point.into(),
+			common_point: key.common_point.map(Into::into),
+			encrypted_point: key.encrypted_point.map(Into::into),
+			versions: key.versions.into_iter().map(Into::into).collect(),
 		}
 	}
 }
 
-impl From<SerializableDocumentKeyShare> for DocumentKeyShare {
-	fn from(key: SerializableDocumentKeyShare) -> Self {
+impl From<DocumentKeyShareVersion> for SerializableDocumentKeyShareVersionV1 {
+	fn from(version: DocumentKeyShareVersion) -> Self {
+		SerializableDocumentKeyShareVersionV1 {
+			hash: version.hash.into(),
+			id_numbers: version.id_numbers.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+			secret_share: version.secret_share.into(),
+			retired: version.retired,
+		}
+	}
+}
+
+impl From<SerializableDocumentKeyShareV1> for DocumentKeyShare {
+	fn from(key: SerializableDocumentKeyShareV1) -> Self {
 		DocumentKeyShare {
+			author: key.author.into(),
 			threshold: key.threshold,
-			id_numbers: key.id_numbers.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
-			secret_share: key.secret_share.into(),
-			common_point: key.common_point.into(),
-			encrypted_point: key.encrypted_point.into(),
+			common_point: key.common_point.map(Into::into),
+			encrypted_point: key.encrypted_point.map(Into::into),
+			versions: key.versions.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
+impl From<KeyRemovalRetry> for SerializableKeyRemovalRetry {
+	fn from(retry: KeyRemovalRetry) -> Self {
+		SerializableKeyRemovalRetry {
+			requestor_signature: retry.requestor_signature.into(),
+			nonce: retry.nonce,
+			nodes: retry.nodes.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
+impl From<SerializableKeyRemovalRetry> for KeyRemovalRetry {
+	fn from(retry: SerializableKeyRemovalRetry) -> Self {
+		KeyRemovalRetry {
+			requestor_signature: retry.requestor_signature.into(),
+			nonce: retry.nonce,
+			nodes: retry.nodes.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
+impl From<SerializableDocumentKeyShareVersionV1> for DocumentKeyShareVersion {
+	fn from(version: SerializableDocumentKeyShareVersionV1) -> Self {
+		DocumentKeyShareVersion {
+			hash: version.hash.into(),
+			id_numbers: version.id_numbers.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+			secret_share: version.secret_share.into(),
+			retired: version.retired,
 		}
 	}
 }
 
 #[cfg(test)]
 pub mod tests {
-	use std::collections::{BTreeMap, HashMap};
+	use std::sync::Arc;
+	use std::collections::{BTreeMap, BTreeSet, HashMap};
 	use parking_lot::RwLock;
+	use serde_json;
 	use devtools::RandomTempPath;
-	use ethkey::{Random, Generator};
+	use ethkey::{Random, Generator, Public, Secret};
+	use util::Database;
 	use super::super::types::all::{Error, NodeAddress, ServiceConfiguration, ClusterConfiguration, DocumentAddress};
-	use super::{KeyStorage, PersistentKeyStorage, DocumentKeyShare};
+	use super::{DB_META_KEY_VERSION, CURRENT_VERSION, KeyStorage, PersistentKeyStorage, TransactionalKeyStorage, DocumentKeyShare,
+		DocumentKeyShareVersion, KeyRemovalRetry, SerializableDocumentKeyShareV0, SerializableDocumentKeyShareV1,
+		SerializableDocumentKeyShareVersionV1, upgrade_db};
 
-	#[derive(Default)]
+	#[derive(Default, Debug)]
 	/// In-memory document encryption keys storage
 	pub struct DummyKeyStorage {
 		keys: RwLock<HashMap<DocumentAddress, DocumentKeyShare>>,
+		removal_nonces: RwLock<HashMap<DocumentAddress, BTreeSet<u64>>>,
+		store_nonces: RwLock<HashMap<DocumentAddress, BTreeSet<u64>>>,
+		removal_retries: RwLock<BTreeMap<DocumentAddress, KeyRemovalRetry>>,
 	}
 
 	impl KeyStorage for DummyKeyStorage {
@@ -150,19 +689,77 @@ pub mod tests {
 			Ok(())
 		}
 
+		fn update(&self, document: DocumentAddress, key: DocumentKeyShare) -> Result<(), Error> {
+			self.keys.write().get_mut(&document)
+				.map(|k| *k = key)
+				.ok_or(Error::DocumentNotFound)
+		}
+
 		fn get(&self, document: &DocumentAddress) -> Result<DocumentKeyShare, Error> {
 			self.keys.read().get(document).cloned().ok_or(Error::DocumentNotFound)
 		}
 
+		fn remove(&self, document: &DocumentAddress) -> Result<(), Error> {
+			self.keys.write().remove(document);
+			Ok(())
+		}
+
 		fn contains(&self, document: &DocumentAddress) -> bool {
 			self.keys.read().contains_key(document)
 		}
+
+		fn iter<'a>(&'a self) -> Box<Iterator<Item=(DocumentAddress, DocumentKeyShare)> + 'a> {
+			Box::new(self.keys.read().clone().into_iter())
+		}
+
+		fn is_key_removal_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error> {
+			Ok(self.removal_nonces.read().get(document).map(|nonces| nonces.contains(&nonce)).unwrap_or(false))
+		}
+
+		fn insert_key_removal_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+			self.removal_nonces.write().entry(document.clone()).or_insert_with(Default::default).insert(nonce);
+			Ok(())
+		}
+
+		fn is_document_key_store_nonce_used(&self, document: &DocumentAddress, nonce: u64) -> Result<bool, Error> {
+			Ok(self.store_nonces.read().get(document).map(|nonces| nonces.contains(&nonce)).unwrap_or(false))
+		}
+
+		fn insert_document_key_store_nonce(&self, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+			self.store_nonces.write().entry(document.clone()).or_insert_with(Default::default).insert(nonce);
+			Ok(())
+		}
+
+		fn key_removal_retries(&self) -> Result<BTreeMap<DocumentAddress, KeyRemovalRetry>, Error> {
+			Ok(self.removal_retries.read().clone())
+		}
+
+		fn set_key_removal_retry(&self, document: &DocumentAddress, retry: Option<KeyRemovalRetry>) -> Result<(), Error> {
+			match retry {
+				Some(retry) => { self.removal_retries.write().insert(document.clone(), retry); },
+				None => { self.removal_retries.write().remove(document); },
+			}
+			Ok(())
+		}
+
+		fn apply(&self, expected: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>, changes: BTreeMap<DocumentAddress, Option<DocumentKeyShare>>) -> Result<(), Error> {
+			let mut keys = self.keys.write();
+			if expected.into_iter().any(|(document, expected)| keys.get(&document) != expected.as_ref()) {
+				return Err(Error::DatabaseConflict);
+			}
+
+			for (document, key) in changes {
+				match key {
+					Some(key) => { keys.insert(document, key); },
+					None => { keys.remove(&document); },
+				}
+			}
+			Ok(())
+		}
 	}
 
-	#[test]
-	fn persistent_key_storage() {
-		let path = RandomTempPath::create_dir();
-		let config = ServiceConfiguration {
+	fn make_config(path: &RandomTempPath) -> ServiceConfiguration {
+		ServiceConfiguration {
 			listener_address: NodeAddress {
 				address: "0.0.0.0".to_owned(),
 				port: 8082,
@@ -177,29 +774,39 @@ pub mod tests {
 				},
 				nodes: BTreeMap::new(),
 				allow_connecting_to_higher_nodes: false,
+				admin_public: None,
+				max_active_key_migrations: 4,
+				wipe_removed_key_shares: false,
 			},
-		};
-		
+			service_contract_address: None,
+		}
+	}
+
+	fn make_version() -> DocumentKeyShareVersion {
+		DocumentKeyShareVersion::new(vec![
+			(Random.generate().unwrap().public().clone(), Random.generate().unwrap().secret().clone())
+		].into_iter().collect(), Random.generate().unwrap().secret().clone())
+	}
+
+	fn make_key_share(threshold: usize) -> DocumentKeyShare {
+		DocumentKeyShare {
+			author: Random.generate().unwrap().public().clone(),
+			threshold: threshold,
+			common_point: Some(Random.generate().unwrap().public().clone()),
+			encrypted_point: Some(Random.generate().unwrap().public().clone()),
+			versions: vec![make_version()],
+		}
+	}
+
+	#[test]
+	fn persistent_key_storage() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
 		let key1 = DocumentAddress::from(1);
-		let value1 = DocumentKeyShare {
-			threshold: 100,
-			id_numbers: vec![
-				(Random.generate().unwrap().public().clone(), Random.generate().unwrap().secret().clone())
-			].into_iter().collect(),
-			secret_share: Random.generate().unwrap().secret().clone(),
-			common_point: Random.generate().unwrap().public().clone(),
-			encrypted_point: Random.generate().unwrap().public().clone(),
-		};
+		let value1 = make_key_share(100);
 		let key2 = DocumentAddress::from(2);
-		let value2 = DocumentKeyShare {
-			threshold: 200,
-			id_numbers: vec![
-				(Random.generate().unwrap().public().clone(), Random.generate().unwrap().secret().clone())
-			].into_iter().collect(),
-			secret_share: Random.generate().unwrap().secret().clone(),
-			common_point: Random.generate().unwrap().public().clone(),
-			encrypted_point: Random.generate().unwrap().public().clone(),
-		};
+		let value2 = make_key_share(200);
 		let key3 = DocumentAddress::from(3);
 
 		let key_storage = PersistentKeyStorage::new(&config).unwrap();
@@ -211,8 +818,265 @@ pub mod tests {
 		drop(key_storage);
 
 		let key_storage = PersistentKeyStorage::new(&config).unwrap();
-		assert_eq!(key_storage.get(&key1), Ok(value1));
-		assert_eq!(key_storage.get(&key2), Ok(value2));
+		assert_eq!(key_storage.get(&key1), Ok(value1.clone()));
+		assert_eq!(key_storage.get(&key2), Ok(value2.clone()));
 		assert_eq!(key_storage.get(&key3), Err(Error::DocumentNotFound));
+		assert_eq!(key_storage.iter().collect::<Vec<_>>(), vec![(key1, value1), (key2, value2)]);
+	}
+
+	#[test]
+	fn persistent_key_storage_update_and_remove() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
+		let key = DocumentAddress::from(1);
+		let mut value = make_key_share(1);
+
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		assert_eq!(key_storage.update(key.clone(), value.clone()), Err(Error::DocumentNotFound));
+		key_storage.insert(key.clone(), value.clone()).unwrap();
+
+		// append new version
+		value.versions.push(make_version());
+		key_storage.update(key.clone(), value.clone()).unwrap();
+		let stored = key_storage.get(&key).unwrap();
+		assert_eq!(stored.versions.len(), 2);
+		assert_eq!(stored.last_version().unwrap(), &value.versions[1]);
+		assert_eq!(stored.version(&value.versions[0].hash).unwrap(), &value.versions[0]);
+
+		// remove
+		key_storage.remove(&key).unwrap();
+		assert!(!key_storage.contains(&key));
+		assert_eq!(key_storage.get(&key), Err(Error::DocumentNotFound));
+	}
+
+	#[test]
+	fn persistent_key_storage_skips_retired_versions() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
+		let key = DocumentAddress::from(1);
+		let mut value = make_key_share(1);
+		value.versions.push(make_version());
+		let lost_nodes = value.versions[1].id_numbers.keys().cloned().collect();
+
+		// last version is retired => previous version is used
+		assert!(value.retire_versions(&lost_nodes));
+		assert!(!value.retire_versions(&lost_nodes));
+		assert!(value.versions[1].retired);
+		assert_eq!(value.last_version().unwrap(), &value.versions[0]);
+
+		// retirement survives restart
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		key_storage.insert(key.clone(), value.clone()).unwrap();
+		drop(key_storage);
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		let stored = key_storage.get(&key).unwrap();
+		assert_eq!(stored, value);
+		assert_eq!(stored.last_version().unwrap(), &value.versions[0]);
+
+		// there's no active version if all versions are retired
+		let lost_nodes = value.versions[0].id_numbers.keys().cloned().collect();
+		assert!(value.retire_versions(&lost_nodes));
+		assert_eq!(value.last_version(), Err(Error::Database("key version is not found".into())));
+	}
+
+	#[test]
+	fn persistent_key_storage_apply_checks_expected_values() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
+		let key1 = DocumentAddress::from(1);
+		let value1 = make_key_share(1);
+		let key2 = DocumentAddress::from(2);
+		let value2 = make_key_share(2);
+
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		key_storage.insert(key1.clone(), value1.clone()).unwrap();
+
+		// expected value differs => nothing is applied
+		let expected = vec![(key1.clone(), Some(make_key_share(1)))].into_iter().collect();
+		let changes = vec![(key1.clone(), None), (key2.clone(), Some(value2.clone()))].into_iter().collect();
+		assert_eq!(key_storage.apply(expected, changes), Err(Error::DatabaseConflict));
+		assert_eq!(key_storage.get(&key1), Ok(value1.clone()));
+		assert!(!key_storage.contains(&key2));
+
+		// expected value matches => all changes are applied
+		let expected = vec![(key1.clone(), Some(value1.clone())), (key2.clone(), None)].into_iter().collect();
+		let changes = vec![(key1.clone(), None), (key2.clone(), Some(value2.clone()))].into_iter().collect();
+		assert_eq!(key_storage.apply(expected, changes), Ok(()));
+		assert!(!key_storage.contains(&key1));
+		assert_eq!(key_storage.get(&key2), Ok(value2));
+	}
+
+	#[test]
+	fn persistent_key_storage_remembers_key_removal_nonces() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
+		let key = DocumentAddress::from(1);
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		key_storage.insert(key.clone(), make_key_share(1)).unwrap();
+		key_storage.insert_key_removal_nonce(&key, 7).unwrap();
+		drop(key_storage);
+
+		// nonces are not confused with key shares && survive restart
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		assert_eq!(key_storage.iter().count(), 1);
+		assert_eq!(key_storage.is_key_removal_nonce_used(&key, 7), Ok(true));
+		assert_eq!(key_storage.is_key_removal_nonce_used(&key, 8), Ok(false));
+		assert_eq!(key_storage.is_key_removal_nonce_used(&DocumentAddress::from(2), 7), Ok(false));
+	}
+
+	#[test]
+	fn persistent_key_storage_separates_document_key_store_nonces() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
+		let key = DocumentAddress::from(1);
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		key_storage.insert(key.clone(), make_key_share(1)).unwrap();
+		key_storage.insert_document_key_store_nonce(&key, 7).unwrap();
+		drop(key_storage);
+
+		// store nonces are not confused with key shares && removal nonces && survive restart
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		assert_eq!(key_storage.iter().count(), 1);
+		assert_eq!(key_storage.is_document_key_store_nonce_used(&key, 7), Ok(true));
+		assert_eq!(key_storage.is_document_key_store_nonce_used(&key, 8), Ok(false));
+		assert_eq!(key_storage.is_key_removal_nonce_used(&key, 7), Ok(false));
+	}
+
+	#[test]
+	fn persistent_key_storage_remembers_key_removal_retries() {
+		let path = RandomTempPath::create_dir();
+		let config = make_config(&path);
+
+		let key = DocumentAddress::from(1);
+		let retry = KeyRemovalRetry {
+			requestor_signature: Default::default(),
+			nonce: 7,
+			nodes: vec![Random.generate().unwrap().public().clone()].into_iter().collect(),
+		};
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		key_storage.insert(key.clone(), make_key_share(1)).unwrap();
+		key_storage.insert_key_removal_nonce(&key, 7).unwrap();
+		key_storage.set_key_removal_retry(&key, Some(retry.clone())).unwrap();
+		drop(key_storage);
+
+		// retries are not confused with key shares && nonces && survive restart
+		let key_storage = PersistentKeyStorage::new(&config).unwrap();
+		assert_eq!(key_storage.iter().count(), 1);
+		assert_eq!(key_storage.key_removal_retries(), Ok(vec![(key.clone(), retry)].into_iter().collect()));
+
+		key_storage.set_key_removal_retry(&key, None).unwrap();
+		assert_eq!(key_storage.key_removal_retries(), Ok(BTreeMap::new()));
+	}
+
+	#[test]
+	fn upgrade_db_from_0() {
+		let db_path = RandomTempPath::create_dir();
+		let id_numbers: BTreeMap<Public, Secret> = vec![
+			(Random.generate().unwrap().public().clone(), Random.generate().unwrap().secret().clone())
+		].into_iter().collect();
+		let secret_share = Random.generate().unwrap().secret().clone();
+		let common_point = Random.generate().unwrap().public().clone();
+		let encrypted_point = Random.generate().unwrap().public().clone();
+		let db = Database::open_default(db_path.as_str()).unwrap();
+
+		// prepare v0 database
+		{
+			let key = serde_json::to_vec(&SerializableDocumentKeyShareV0 {
+				threshold: 777,
+				id_numbers: id_numbers.clone().into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+				secret_share: secret_share.clone().into(),
+				common_point: common_point.clone().into(),
+				encrypted_point: encrypted_point.clone().into(),
+			}).unwrap();
+			let mut batch = db.transaction();
+			batch.put(None, &[7], &key);
+			db.write(batch).unwrap();
+		}
+
+		// upgrade database
+		let db = upgrade_db(db).unwrap();
+
+		// check upgrade
+		assert_eq!(db.get(None, DB_META_KEY_VERSION).unwrap().unwrap()[0], CURRENT_VERSION);
+		let key = serde_json::from_slice::<SerializableDocumentKeyShareV1>(&db.get(None, &[7]).unwrap().map(|key| key.to_vec()).unwrap()).unwrap();
+		let key: DocumentKeyShare = key.into();
+		assert_eq!(key.author, Public::default());
+		assert_eq!(key.threshold, 777);
+		assert_eq!(key.common_point, Some(common_point));
+		assert_eq!(key.encrypted_point, Some(encrypted_point));
+		assert_eq!(key.versions, vec![DocumentKeyShareVersion::new(id_numbers, secret_share)]);
+	}
+
+	#[test]
+	fn versions_stored_before_retirement_are_not_retired() {
+		let version = make_version();
+		let serialized = serde_json::to_value(&SerializableDocumentKeyShareVersionV1::from(version.clone())).unwrap();
+		let mut serialized = match serialized {
+			serde_json::Value::Object(serialized) => serialized,
+			_ => unreachable!("version is serialized as object; qed"),
+		};
+		assert!(serialized.remove("retired").is_some());
+
+		let deserialized: SerializableDocumentKeyShareVersionV1 = serde_json::from_value(serde_json::Value::Object(serialized)).unwrap();
+		assert_eq!(DocumentKeyShareVersion::from(deserialized), version);
+	}
+
+	fn prepare_transactional_key_storage() -> (Arc<DummyKeyStorage>, TransactionalKeyStorage, Vec<DocumentKeyShare>) {
+		let inner = Arc::new(DummyKeyStorage::default());
+		inner.insert(DocumentAddress::from(1), make_key_share(1)).unwrap();
+		inner.insert(DocumentAddress::from(2), make_key_share(2)).unwrap();
+		let storage = TransactionalKeyStorage::new(inner.clone());
+
+		// stage three mutations
+		let updated = make_key_share(10);
+		let inserted = make_key_share(30);
+		storage.update(DocumentAddress::from(1), updated.clone()).unwrap();
+		storage.remove(&DocumentAddress::from(2)).unwrap();
+		storage.insert(DocumentAddress::from(3), inserted.clone()).unwrap();
+
+		// staged values are visible through the wrapper
+		assert_eq!(storage.get(&DocumentAddress::from(1)), Ok(updated.clone()));
+		assert_eq!(storage.get(&DocumentAddress::from(2)), Err(Error::DocumentNotFound));
+		assert_eq!(storage.get(&DocumentAddress::from(3)), Ok(inserted.clone()));
+		assert_eq!(storage.iter().count(), 2);
+
+		(inner, storage, vec![updated, inserted])
+	}
+
+	#[test]
+	fn transactional_key_storage_rollback_leaves_inner_storage_untouched() {
+		let (inner, storage, _) = prepare_transactional_key_storage();
+		let original: BTreeMap<_, _> = inner.iter().collect();
+
+		storage.rollback();
+		assert_eq!(inner.iter().collect::<BTreeMap<_, _>>(), original);
+		assert_eq!(storage.iter().collect::<BTreeMap<_, _>>(), original);
+	}
+
+	#[test]
+	fn transactional_key_storage_commit_applies_all_changes() {
+		let (inner, storage, values) = prepare_transactional_key_storage();
+
+		storage.commit().unwrap();
+		assert_eq!(inner.get(&DocumentAddress::from(1)), Ok(values[0].clone()));
+		assert_eq!(inner.get(&DocumentAddress::from(2)), Err(Error::DocumentNotFound));
+		assert_eq!(inner.get(&DocumentAddress::from(3)), Ok(values[1].clone()));
+	}
+
+	#[test]
+	fn transactional_key_storage_commit_fails_on_concurrent_modification() {
+		let (inner, storage, _) = prepare_transactional_key_storage();
+		let concurrent = make_key_share(100);
+		inner.insert(DocumentAddress::from(1), concurrent.clone()).unwrap();
+
+		assert_eq!(storage.commit(), Err(Error::DatabaseConflict));
+		assert_eq!(inner.get(&DocumentAddress::from(1)), Ok(concurrent));
+		assert!(inner.contains(&DocumentAddress::from(2)));
+		assert!(!inner.contains(&DocumentAddress::from(3)));
 	}
 }
@@ -43,6 +43,10 @@ extern crate ethcrypto;
 extern crate ethkey;
 extern crate native_contracts;
 
+#[cfg(test)]
+#[macro_use]
+extern crate lazy_static;
+
 mod key_server_cluster;
 mod types;
 
@@ -54,23 +58,47 @@ mod traits {
 mod acl_storage;
 mod http_listener;
 mod key_server;
+mod key_server_set;
 mod key_storage;
 mod serialization;
+mod service_contract;
+mod service_contract_listener;
 
 use std::sync::Arc;
 use ethcore::client::Client;
 
 pub use types::all::{DocumentAddress, DocumentKey, DocumentEncryptedKey, RequestSignature, Public,
-	Error, NodeAddress, ServiceConfiguration, ClusterConfiguration};
+	MessageHash, EncryptedMessageSignature, Error, NodeAddress, ServiceConfiguration, ClusterConfiguration};
 pub use traits::{KeyServer};
 
 /// Start new key server instance
 pub fn start(client: Arc<Client>, config: ServiceConfiguration) -> Result<Box<KeyServer>, Error> {
 	use std::sync::Arc;
+	use std::net::{IpAddr, SocketAddr};
+	use std::collections::BTreeMap;
 
-	let acl_storage = Arc::new(acl_storage::OnChainAclStorage::new(client));
+	let acl_storage = Arc::new(acl_storage::OnChainAclStorage::new(Arc::downgrade(&client)));
+	client.add_notify(acl_storage.clone());
+	let mut nodes = BTreeMap::new();
+	for (node_id, node_address) in config.cluster_config.nodes.iter() {
+		let ip_address: IpAddr = node_address.address.parse()
+			.map_err(|err| Error::Internal(format!("invalid address of node {}: {}", node_id, err)))?;
+		nodes.insert(node_id.clone(), SocketAddr::new(ip_address, node_address.port));
+	}
+	let key_server_set = Arc::new(key_server_set::OnChainKeyServerSet::new(Arc::downgrade(&client), nodes));
+	client.add_notify(key_server_set.clone());
 	let key_storage = Arc::new(key_storage::PersistentKeyStorage::new(&config)?);
-	let key_server = key_server::KeyServerImpl::new(&config.cluster_config, acl_storage, key_storage)?;
+	let key_server = key_server::KeyServerImpl::new(&config.cluster_config, Some(key_server_set.clone()), acl_storage, key_storage)?;
 	let listener = http_listener::KeyServerHttpListener::start(&config.listener_address, key_server)?;
-	Ok(Box::new(listener))
+	match config.service_contract_address {
+		Some(service_contract_address) => {
+			let self_key_pair = ethkey::KeyPair::from_secret_slice(&config.cluster_config.self_private)?;
+			let self_node_id = self_key_pair.public().clone();
+			let service_contract = Arc::new(service_contract::OnChainServiceContract::new(client.clone(), client.clone(), self_key_pair, service_contract_address));
+			let listener = service_contract_listener::ServiceContractListener::start(listener, self_node_id, service_contract, key_server_set)?;
+			client.add_notify(listener.chain_notify());
+			Ok(Box::new(listener))
+		},
+		None => Ok(Box::new(listener)),
+	}
 }
@@ -21,7 +21,8 @@ use rustc_serialize::hex::{ToHex, FromHex};
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use serde::de::{Visitor, Error as SerdeError};
 use ethkey::{Public, Secret, Signature};
-use util::{H256, Bytes};
+use util::{H256, Address, Bytes};
+use types::all::Requester;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 /// Serializable shadow decryption result.
@@ -200,6 +201,97 @@ impl Deserialize for SerializableH256 {
 	}
 }
 
+#[derive(Clone, Debug)]
+/// Serializable Address.
+pub struct SerializableAddress(pub Address);
+
+impl<T> From<T> for SerializableAddress where Address: From<T> {
+	fn from(s: T) -> SerializableAddress {
+		SerializableAddress(s.into())
+	}
+}
+
+impl Into<Address> for SerializableAddress {
+	fn into(self) -> Address {
+		self.0
+	}
+}
+
+impl Deref for SerializableAddress {
+	type Target = Address;
+
+	fn deref(&self) -> &Address {
+		&self.0
+	}
+}
+
+impl Serialize for SerializableAddress {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+		let mut serialized = "0x".to_owned();
+		serialized.push_str(self.0.to_hex().as_ref());
+		serializer.serialize_str(serialized.as_ref())
+	}
+}
+
+impl Deserialize for SerializableAddress {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer {
+		struct AddressVisitor;
+
+		impl Visitor for AddressVisitor {
+			type Value = SerializableAddress;
+
+			fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+				write!(formatter, "a hex-encoded Address")
+			}
+
+			fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> where E: SerdeError {
+				if value.len() >= 2 && &value[0..2] == "0x" && value.len() & 1 == 0 {
+					value[2..].parse().map(|s| SerializableAddress(s)).map_err(SerdeError::custom)
+				} else {
+					Err(SerdeError::custom("invalid format"))
+				}
+			}
+
+			fn visit_string<E>(self, value: String) -> Result<Self::Value, E> where E: SerdeError {
+				self.visit_str(value.as_ref())
+			}
+		}
+
+		deserializer.deserialize(AddressVisitor)
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Serializable requester identification data.
+pub enum SerializableRequester {
+	/// Requested with signature of the server key id.
+	Signature(SerializableSignature),
+	/// Requested with public key.
+	Public(SerializablePublic),
+	/// Requested with address.
+	Address(SerializableAddress),
+}
+
+impl From<Requester> for SerializableRequester {
+	fn from(requester: Requester) -> SerializableRequester {
+		match requester {
+			Requester::Signature(signature) => SerializableRequester::Signature(signature.into()),
+			Requester::Public(public) => SerializableRequester::Public(public.into()),
+			Requester::Address(address) => SerializableRequester::Address(address.into()),
+		}
+	}
+}
+
+impl Into<Requester> for SerializableRequester {
+	fn into(self) -> Requester {
+		match self {
+			SerializableRequester::Signature(signature) => Requester::Signature(signature.into()),
+			SerializableRequester::Public(public) => Requester::Public(public.into()),
+			SerializableRequester::Address(address) => Requester::Address(address.into()),
+		}
+	}
+}
+
 #[derive(Clone, Debug)]
 /// Serializable EC scalar/secret key.
 pub struct SerializableSecret(pub Secret);
@@ -343,7 +435,9 @@ impl Deserialize for SerializablePublic {
 #[cfg(test)]
 mod tests {
 	use serde_json;
-	use super::{SerializableBytes, SerializablePublic};
+	use ethkey::{self, Random, Generator};
+	use types::all::Requester;
+	use super::{SerializableBytes, SerializablePublic, SerializableRequester};
 
 	#[test]
 	fn serialize_and_deserialize_bytes() {
@@ -362,4 +456,22 @@ mod tests {
 		let public_deserialized: SerializablePublic = serde_json::from_str(&public_serialized).unwrap();
 		assert_eq!(public_deserialized, public);
 	}
+
+	#[test]
+	fn serialize_and_deserialize_requester() {
+		let key_pair = Random.generate().unwrap();
+		let signature = ethkey::sign(key_pair.secret(), &Default::default()).unwrap();
+		let requesters = vec![
+			Requester::Signature(signature),
+			Requester::Public(key_pair.public().clone()),
+			Requester::Address(ethkey::public_to_address(key_pair.public())),
+		];
+
+		for requester in requesters {
+			let requester_serialized = serde_json::to_string(&SerializableRequester::from(requester.clone())).unwrap();
+			let requester_deserialized: SerializableRequester = serde_json::from_str(&requester_serialized).unwrap();
+			let requester_deserialized: Requester = requester_deserialized.into();
+			assert_eq!(requester_deserialized, requester);
+		}
+	}
 }
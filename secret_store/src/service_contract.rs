@@ -0,0 +1,282 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+use futures::{future, Future};
+use ethkey::{KeyPair, Secret, public_to_address};
+use ethcore::client::{Client, BlockChainClient};
+use ethcore::miner::MinerService;
+use ethcore::transaction::{Transaction, Action};
+use native_contracts::SecretStoreService;
+use util::{Address, Bytes, H256, H520, U256};
+use acl_storage::CallContract;
+use types::all::{Public, RequestSignature};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Request, published to the service contract.
+pub enum ServiceRequest {
+	/// Generate server key.
+	GenerateServerKey {
+		/// Server key id.
+		id: H256,
+		/// Requester signature of the server key id.
+		signature: RequestSignature,
+		/// Key threshold.
+		threshold: usize,
+	},
+	/// Store document key.
+	StoreDocumentKey {
+		/// Server key id.
+		id: H256,
+		/// Requester signature of the store request hash.
+		signature: RequestSignature,
+		/// Common point of the encrypted document key.
+		common_point: Public,
+		/// Encrypted point of the encrypted document key.
+		encrypted_point: Public,
+		/// Nonce of the store request.
+		nonce: u64,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Response, published by the key server.
+pub enum ServiceResponse {
+	/// Server key has been generated.
+	ServerKeyGenerated(H256, Public),
+	/// Server key generation has failed.
+	ServerKeyGenerationFailed(H256),
+	/// Document key has been stored.
+	DocumentKeyStored(H256),
+	/// Document key store has failed.
+	DocumentKeyStoreFailed(H256),
+}
+
+impl ServiceRequest {
+	/// Get id of the server key, this request is for.
+	pub fn id(&self) -> &H256 {
+		match *self {
+			ServiceRequest::GenerateServerKey { ref id, .. } => id,
+			ServiceRequest::StoreDocumentKey { ref id, .. } => id,
+		}
+	}
+}
+
+/// Service contract, used to publish requests && responses on-chain.
+pub trait ServiceContract: Send + Sync {
+	/// Read requests, which are currently pending.
+	fn read_pending_requests(&self) -> Result<Vec<ServiceRequest>, String>;
+	/// Check if the contract is still waiting for the response from this key server.
+	fn is_response_required(&self, request: &ServiceRequest) -> Result<bool, String>;
+	/// Publish response to the contract.
+	fn publish_response(&self, response: &ServiceResponse) -> Result<(), String>;
+}
+
+/// Contracts transactions, required by on-chain service contract.
+pub trait TransactContract: Send + Sync {
+	/// Sign transaction to the contract with given secret && submit it to the transactions queue.
+	fn transact_contract(&self, address: Address, data: Bytes, secret: &Secret) -> Result<(), String>;
+}
+
+/// On-chain service contract implementation.
+pub struct OnChainServiceContract {
+	/// Contracts caller.
+	client: Arc<CallContract>,
+	/// Contracts transactions sender.
+	transactor: Arc<TransactContract>,
+	/// This node key pair. Responses are signed with this key.
+	self_key_pair: KeyPair,
+	/// On-chain contract.
+	contract: SecretStoreService,
+}
+
+impl OnChainServiceContract {
+	pub fn new(client: Arc<CallContract>, transactor: Arc<TransactContract>, self_key_pair: KeyPair, address: Address) -> Self {
+		trace!(target: "secretstore", "Configuring for service contract from {}", address);
+
+		OnChainServiceContract {
+			client: client,
+			transactor: transactor,
+			self_key_pair: self_key_pair,
+			contract: SecretStoreService::new(address),
+		}
+	}
+}
+
+impl ServiceContract for OnChainServiceContract {
+	fn read_pending_requests(&self) -> Result<Vec<ServiceRequest>, String> {
+		let do_call = |a: Address, d: Bytes| future::done(self.client.call_contract(a, d));
+
+		let mut requests = Vec::new();
+		let count = self.contract.server_key_generation_requests_count(&do_call).wait()?.low_u64();
+		for index in 0..count {
+			let (id, signature, threshold) = self.contract.get_server_key_generation_request(&do_call, index.into()).wait()?;
+			requests.push(ServiceRequest::GenerateServerKey {
+				id: id,
+				signature: parse_signature(signature)?,
+				threshold: parse_threshold(threshold)?,
+			});
+		}
+
+		let count = self.contract.document_key_store_requests_count(&do_call).wait()?.low_u64();
+		for index in 0..count {
+			let (id, signature, common_point, encrypted_point, nonce) = self.contract.get_document_key_store_request(&do_call, index.into()).wait()?;
+			requests.push(ServiceRequest::StoreDocumentKey {
+				id: id,
+				signature: parse_signature(signature)?,
+				common_point: parse_public(common_point)?,
+				encrypted_point: parse_public(encrypted_point)?,
+				nonce: parse_nonce(nonce)?,
+			});
+		}
+
+		Ok(requests)
+	}
+
+	fn is_response_required(&self, request: &ServiceRequest) -> Result<bool, String> {
+		let do_call = |a: Address, d: Bytes| future::done(self.client.call_contract(a, d));
+		let self_address = public_to_address(self.self_key_pair.public());
+		match *request {
+			ServiceRequest::GenerateServerKey { ref id, .. } =>
+				self.contract.is_server_key_generation_response_required(&do_call, id.clone(), self_address).wait(),
+			ServiceRequest::StoreDocumentKey { ref id, .. } =>
+				self.contract.is_document_key_store_response_required(&do_call, id.clone(), self_address).wait(),
+		}
+	}
+
+	fn publish_response(&self, response: &ServiceResponse) -> Result<(), String> {
+		let do_transact = |a: Address, d: Bytes| future::done(self.transactor.transact_contract(a, d, self.self_key_pair.secret())
+			.map(|_| Vec::new()));
+		match *response {
+			ServiceResponse::ServerKeyGenerated(ref id, ref public) =>
+				self.contract.server_key_generated(&do_transact, id.clone(), public.to_vec()).wait(),
+			ServiceResponse::ServerKeyGenerationFailed(ref id) =>
+				self.contract.server_key_generation_error(&do_transact, id.clone()).wait(),
+			ServiceResponse::DocumentKeyStored(ref id) =>
+				self.contract.document_key_stored(&do_transact, id.clone()).wait(),
+			ServiceResponse::DocumentKeyStoreFailed(ref id) =>
+				self.contract.document_key_store_error(&do_transact, id.clone()).wait(),
+		}
+	}
+}
+
+impl TransactContract for Client {
+	fn transact_contract(&self, address: Address, data: Bytes, secret: &Secret) -> Result<(), String> {
+		let sender = KeyPair::from_secret(secret.clone())
+			.map_err(|err| format!("invalid secret: {}", err))?
+			.address();
+		let miner = self.miner();
+		let transaction = Transaction {
+			nonce: miner.last_nonce(&sender)
+				.map(|nonce| nonce + U256::one())
+				.unwrap_or_else(|| self.latest_nonce(&sender)),
+			action: Action::Call(address),
+			gas: miner.gas_floor_target(),
+			gas_price: miner.sensible_gas_price(),
+			value: U256::zero(),
+			data: data,
+		};
+		let transaction = transaction.sign(secret, self.signing_network_id());
+		miner.import_own_transaction(self, transaction.into())
+			.map(|_| ())
+			.map_err(|err| format!("{}", err))
+	}
+}
+
+/// Parse requester signature, read from the contract.
+fn parse_signature(signature: Vec<u8>) -> Result<RequestSignature, String> {
+	if signature.len() != 65 {
+		return Err("invalid requester signature".into());
+	}
+
+	Ok(H520::from_slice(&signature).into())
+}
+
+/// Parse public, read from the contract.
+fn parse_public(public: Vec<u8>) -> Result<Public, String> {
+	if public.len() != 64 {
+		return Err("invalid public".into());
+	}
+
+	Ok(Public::from_slice(&public))
+}
+
+/// Parse key threshold, read from the contract.
+fn parse_threshold(threshold: U256) -> Result<usize, String> {
+	if threshold > U256::from(::std::u16::MAX) {
+		return Err(format!("invalid key threshold {}", threshold));
+	}
+
+	Ok(threshold.low_u64() as usize)
+}
+
+fn parse_nonce(nonce: U256) -> Result<u64, String> {
+	if nonce > U256::from(::std::u64::MAX) {
+		return Err(format!("invalid request nonce {}", nonce));
+	}
+
+	Ok(nonce.low_u64())
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::collections::HashSet;
+	use parking_lot::Mutex;
+	use ethkey::{Random, Generator};
+	use util::{H520, U256};
+	use super::{ServiceContract, ServiceRequest, ServiceResponse, parse_signature, parse_public, parse_threshold};
+
+	#[derive(Default)]
+	/// Service contract, which state is set from tests.
+	pub struct DummyServiceContract {
+		/// Requests, which are currently pending.
+		pub pending_requests: Mutex<Vec<ServiceRequest>>,
+		/// Requests, which are already answered by this key server.
+		pub answered_requests: Mutex<HashSet<ServiceRequest>>,
+		/// Published responses.
+		pub published_responses: Mutex<Vec<ServiceResponse>>,
+	}
+
+	impl ServiceContract for DummyServiceContract {
+		fn read_pending_requests(&self) -> Result<Vec<ServiceRequest>, String> {
+			Ok(self.pending_requests.lock().clone())
+		}
+
+		fn is_response_required(&self, request: &ServiceRequest) -> Result<bool, String> {
+			Ok(self.pending_requests.lock().contains(request) && !self.answered_requests.lock().contains(request))
+		}
+
+		fn publish_response(&self, response: &ServiceResponse) -> Result<(), String> {
+			self.published_responses.lock().push(response.clone());
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn contract_values_are_parsed() {
+		let public = Random.generate().unwrap().public().clone();
+		assert_eq!(parse_public(public.to_vec()), Ok(public));
+		assert_eq!(parse_signature(vec![1; 65]), Ok(H520::from_slice(&[1; 65]).into()));
+		assert_eq!(parse_threshold(U256::from(3)), Ok(3));
+	}
+
+	#[test]
+	fn invalid_contract_values_are_rejected() {
+		assert!(parse_public(vec![1; 63]).is_err());
+		assert!(parse_signature(vec![1; 64]).is_err());
+		assert!(parse_threshold(U256::from(::std::u64::MAX)).is_err());
+	}
+}
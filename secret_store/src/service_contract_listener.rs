@@ -0,0 +1,578 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::thread;
+use std::sync::{Arc, Weak};
+use std::collections::{VecDeque, HashSet, HashMap};
+use parking_lot::{Mutex, Condvar};
+use ethcore::client::ChainNotify;
+use util::{Bytes, H256, Hashable};
+use key_server_set::KeyServerSet;
+use service_contract::{ServiceContract, ServiceRequest, ServiceResponse};
+use traits::KeyServer;
+use types::all::{Error, NodeId, Public, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+	MessageHash, EncryptedMessageSignature};
+
+/// Number of blocks, after which every pending request is processed again. This is required to resubmit responses,
+/// which have been dropped from the transactions queue && to retry sessions, which have failed temporarily.
+const RETRY_INTERVAL_BLOCKS: usize = 30;
+
+/// Key server wrapper, which serves requests, published to the service contract.
+pub struct ServiceContractListener<T: KeyServer + 'static> {
+	/// Shared listener data.
+	data: Arc<ServiceContractListenerData<T>>,
+	/// Service thread handle.
+	service_handle: Option<thread::JoinHandle<()>>,
+}
+
+/// Service contract listener data, shared with the service thread.
+struct ServiceContractListenerData<T: KeyServer + 'static> {
+	/// Key server to run sessions with.
+	key_server: T,
+	/// This node id.
+	self_node_id: NodeId,
+	/// Service contract.
+	contract: Arc<ServiceContract>,
+	/// Key servers set.
+	key_server_set: Arc<KeyServerSet>,
+	/// Service tasks queue.
+	tasks_queue: TasksQueue,
+	/// Mutable data.
+	state: Mutex<ServiceContractListenerState>,
+}
+
+/// Chain notifications handler of service contract listener. Holds weak reference to the listener data,
+/// so that the client (which holds the handler) does not keep the listener alive.
+struct ServiceContractListenerNotify<T: KeyServer + 'static> {
+	/// Listener data.
+	data: Weak<ServiceContractListenerData<T>>,
+}
+
+#[derive(Default)]
+/// Mutable data of service contract listener.
+struct ServiceContractListenerState {
+	/// Number of blocks, imported since last retry.
+	blocks_since_retry: usize,
+	/// Requests, which have been seen since last retry.
+	processed_requests: HashSet<ServiceRequest>,
+	/// Responses, which have been published by this key server.
+	published_responses: HashMap<ServiceRequest, ServiceResponse>,
+}
+
+#[derive(Default)]
+/// Service tasks queue.
+struct TasksQueue {
+	/// Service event.
+	service_event: Condvar,
+	/// Service tasks.
+	service_tasks: Mutex<VecDeque<ServiceTask>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Service task.
+enum ServiceTask {
+	/// Process pending requests, which have not been seen since last retry.
+	ProcessPendingRequests,
+	/// Process every pending request again.
+	Retry,
+	/// Stop the service thread.
+	Shutdown,
+}
+
+impl<T> ServiceContractListener<T> where T: KeyServer + 'static {
+	/// Start serving requests, published to the service contract.
+	pub fn start(key_server: T, self_node_id: NodeId, contract: Arc<ServiceContract>, key_server_set: Arc<KeyServerSet>) -> Result<Self, Error> {
+		let data = Arc::new(ServiceContractListenerData {
+			key_server: key_server,
+			self_node_id: self_node_id,
+			contract: contract,
+			key_server_set: key_server_set,
+			tasks_queue: TasksQueue::default(),
+			state: Mutex::new(ServiceContractListenerState::default()),
+		});
+
+		// requests could have been published while this node was offline
+		data.tasks_queue.push(ServiceTask::Retry);
+
+		let thread_data = data.clone();
+		let service_handle = thread::Builder::new()
+			.name("SecretStoreService".into())
+			.spawn(move || ServiceContractListenerData::run_service_thread(thread_data))
+			.map_err(|err| Error::Internal(format!("{}", err)))?;
+
+		Ok(ServiceContractListener {
+			data: data,
+			service_handle: Some(service_handle),
+		})
+	}
+
+	/// Get chain notifications handler. Pending requests are read when new block is imported.
+	pub fn chain_notify(&self) -> Arc<ChainNotify> {
+		Arc::new(ServiceContractListenerNotify {
+			data: Arc::downgrade(&self.data),
+		})
+	}
+}
+
+impl<T> KeyServer for ServiceContractListener<T> where T: KeyServer + 'static {
+	fn generate_server_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<Public, Error> {
+		self.data.key_server.generate_server_key(signature, document, threshold)
+	}
+
+	fn store_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, common_point: &Public, encrypted_document_key: &Public, nonce: u64) -> Result<(), Error> {
+		self.data.key_server.store_document_key(signature, document, common_point, encrypted_document_key, nonce)
+	}
+
+	fn generate_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<DocumentEncryptedKey, Error> {
+		self.data.key_server.generate_document_key(signature, document, threshold)
+	}
+
+	fn document_key(&self, signature: &RequestSignature, document: &DocumentAddress) -> Result<DocumentEncryptedKey, Error> {
+		self.data.key_server.document_key(signature, document)
+	}
+
+	fn document_key_shadow(&self, signature: &RequestSignature, document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error> {
+		self.data.key_server.document_key_shadow(signature, document)
+	}
+
+	fn remove_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, nonce: u64) -> Result<(), Error> {
+		self.data.key_server.remove_document_key(signature, document, nonce)
+	}
+
+	fn sign_message(&self, signature: &RequestSignature, document: &DocumentAddress, message: &MessageHash) -> Result<EncryptedMessageSignature, Error> {
+		self.data.key_server.sign_message(signature, document, message)
+	}
+
+	fn metrics(&self) -> Result<String, Error> {
+		self.data.key_server.metrics()
+	}
+}
+
+impl<T> Drop for ServiceContractListener<T> where T: KeyServer + 'static {
+	fn drop(&mut self) {
+		if let Some(service_handle) = self.service_handle.take() {
+			self.data.tasks_queue.shutdown();
+			// ignore error as we are dropping anyway
+			let _ = service_handle.join();
+		}
+	}
+}
+
+impl<T> ServiceContractListenerData<T> where T: KeyServer + 'static {
+	/// Process service tasks until shutdown is requested.
+	fn run_service_thread(data: Arc<Self>) {
+		loop {
+			match data.tasks_queue.wait() {
+				ServiceTask::ProcessPendingRequests => data.process_pending_requests(false),
+				ServiceTask::Retry => data.process_pending_requests(true),
+				ServiceTask::Shutdown => break,
+			}
+		}
+	}
+
+	/// When new block is imported. New requests could have been published => read them.
+	fn on_new_block(&self) {
+		let is_retry_required = {
+			let mut state = self.state.lock();
+			state.blocks_since_retry += 1;
+			if state.blocks_since_retry >= RETRY_INTERVAL_BLOCKS {
+				state.blocks_since_retry = 0;
+				true
+			} else {
+				false
+			}
+		};
+
+		self.tasks_queue.push(if is_retry_required { ServiceTask::Retry } else { ServiceTask::ProcessPendingRequests });
+	}
+
+	/// Read pending requests && process every request, which has not been seen since last retry.
+	fn process_pending_requests(&self, is_retry: bool) {
+		let requests = match self.contract.read_pending_requests() {
+			Ok(requests) => requests,
+			Err(err) => {
+				warn!(target: "secretstore", "{}: failed to read service contract requests: {}", self.self_node_id, err);
+				return;
+			},
+		};
+
+		if is_retry {
+			let mut state = self.state.lock();
+			state.processed_requests.clear();
+			// responses to requests, which are not pending anymore, will never be resubmitted
+			state.published_responses.retain(|request, _| requests.contains(request));
+		}
+
+		for request in requests {
+			let is_new_request = self.state.lock().processed_requests.insert(request.clone());
+			if is_new_request {
+				self.process_request(request);
+			}
+		}
+	}
+
+	/// Process single pending request.
+	fn process_request(&self, request: ServiceRequest) {
+		// the request could have been answered while we were processing other requests
+		match self.contract.is_response_required(&request) {
+			Ok(true) => (),
+			Ok(false) => {
+				trace!(target: "secretstore", "{}: service request {:?} is already answered", self.self_node_id, request);
+				return;
+			},
+			Err(err) => {
+				warn!(target: "secretstore", "{}: failed to check service request {:?}: {}", self.self_node_id, request, err);
+				return;
+			},
+		}
+
+		// other key servers are participating in the session, started by the responsible key server
+		if !is_processed_by_this_key_server(&*self.key_server_set, &self.self_node_id, request.id()) {
+			return;
+		}
+
+		// if response has already been published, the transaction could have been dropped from the queue => resubmit
+		let published_response = self.state.lock().published_responses.get(&request).cloned();
+		let response = match published_response {
+			Some(response) => response,
+			None => match self.compute_response(&request) {
+				Some(response) => response,
+				None => return,
+			},
+		};
+
+		match self.contract.publish_response(&response) {
+			Ok(()) => {
+				trace!(target: "secretstore", "{}: published service response {:?}", self.self_node_id, response);
+				self.state.lock().published_responses.insert(request, response);
+			},
+			Err(err) => warn!(target: "secretstore", "{}: failed to publish service response {:?}: {}", self.self_node_id, response, err),
+		}
+	}
+
+	/// Run the session, required to answer the request. Returns None if the request must be retried later.
+	fn compute_response(&self, request: &ServiceRequest) -> Option<ServiceResponse> {
+		let (result, error_response) = match *request {
+			ServiceRequest::GenerateServerKey { ref id, ref signature, threshold } => (
+				self.key_server.generate_server_key(signature, id, threshold)
+					.map(|public| ServiceResponse::ServerKeyGenerated(id.clone(), public)),
+				ServiceResponse::ServerKeyGenerationFailed(id.clone()),
+			),
+			ServiceRequest::StoreDocumentKey { ref id, ref signature, ref common_point, ref encrypted_point, nonce } => (
+				self.key_server.store_document_key(signature, id, common_point, encrypted_point, nonce)
+					.map(|_| ServiceResponse::DocumentKeyStored(id.clone())),
+				ServiceResponse::DocumentKeyStoreFailed(id.clone()),
+			),
+		};
+
+		match result {
+			Ok(response) => Some(response),
+			Err(Error::TemporarilyUnavailable(err)) => {
+				warn!(target: "secretstore", "{}: service request {:?} will be retried: {}", self.self_node_id, request, err);
+				None
+			},
+			Err(err) => {
+				warn!(target: "secretstore", "{}: service request {:?} has failed: {}", self.self_node_id, request, err);
+				Some(error_response)
+			},
+		}
+	}
+}
+
+impl<T> ChainNotify for ServiceContractListenerNotify<T> where T: KeyServer + 'static {
+	fn new_blocks(&self, imported: Vec<H256>, _invalid: Vec<H256>, enacted: Vec<H256>, retracted: Vec<H256>, _sealed: Vec<H256>, _proposed: Vec<Bytes>, _duration: u64) {
+		if enacted.is_empty() && retracted.is_empty() && imported.is_empty() {
+			return;
+		}
+
+		// listener is dropped => service thread is stopped && nobody will read the task
+		if let Some(data) = self.data.upgrade() {
+			data.on_new_block();
+		}
+	}
+}
+
+impl TasksQueue {
+	/// Push new task to the queue. Task is ignored if the same task is already waiting in the queue.
+	fn push(&self, task: ServiceTask) {
+		let mut service_tasks = self.service_tasks.lock();
+		if !service_tasks.contains(&task) {
+			service_tasks.push_back(task);
+			self.service_event.notify_all();
+		}
+	}
+
+	/// Request service thread shutdown. Queued tasks are dropped.
+	fn shutdown(&self) {
+		let mut service_tasks = self.service_tasks.lock();
+		service_tasks.clear();
+		service_tasks.push_back(ServiceTask::Shutdown);
+		self.service_event.notify_all();
+	}
+
+	/// Wait for the next task.
+	fn wait(&self) -> ServiceTask {
+		let mut service_tasks = self.service_tasks.lock();
+		loop {
+			if let Some(task) = service_tasks.pop_front() {
+				return task;
+			}
+
+			self.service_event.wait(&mut service_tasks);
+		}
+	}
+}
+
+/// Returns true when this key server is responsible for starting the session for given request, i.e. when
+/// the hash of its node id is the closest to the request id.
+fn is_processed_by_this_key_server(key_server_set: &KeyServerSet, self_node_id: &NodeId, request_id: &H256) -> bool {
+	key_server_set.snapshot().current_set.keys()
+		.min_by_key(|node_id| node_id.sha3() ^ request_id.clone())
+		.map(|node_id| node_id == self_node_id)
+		.unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::collections::BTreeMap;
+	use parking_lot::Mutex;
+	use ethcore::client::ChainNotify;
+	use ethkey::{Random, Generator};
+	use util::H256;
+	use key_server_set::KeyServerSet;
+	use key_server_set::tests::MapKeyServerSet;
+	use service_contract::{ServiceRequest, ServiceResponse};
+	use service_contract::tests::DummyServiceContract;
+	use traits::KeyServer;
+	use types::all::{Error, NodeId, Public, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+		MessageHash, EncryptedMessageSignature};
+	use super::{ServiceContractListener, ServiceContractListenerData, ServiceContractListenerState, ServiceTask, TasksQueue,
+		RETRY_INTERVAL_BLOCKS, is_processed_by_this_key_server};
+
+	#[derive(Default)]
+	/// Key server, which counts generation sessions && fails them with configured error.
+	struct TestKeyServer {
+		generated_keys: Mutex<Vec<DocumentAddress>>,
+		error: Mutex<Option<Error>>,
+	}
+
+	impl KeyServer for TestKeyServer {
+		fn generate_server_key(&self, _signature: &RequestSignature, document: &DocumentAddress, _threshold: usize) -> Result<Public, Error> {
+			if let Some(error) = self.error.lock().clone() {
+				return Err(error);
+			}
+
+			self.generated_keys.lock().push(document.clone());
+			Ok(Public::default())
+		}
+
+		fn store_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _common_point: &Public, _encrypted_document_key: &Public, _nonce: u64) -> Result<(), Error> {
+			unimplemented!()
+		}
+
+		fn generate_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _threshold: usize) -> Result<DocumentEncryptedKey, Error> {
+			unimplemented!()
+		}
+
+		fn document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress) -> Result<DocumentEncryptedKey, Error> {
+			unimplemented!()
+		}
+
+		fn document_key_shadow(&self, _signature: &RequestSignature, _document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error> {
+			unimplemented!()
+		}
+
+		fn remove_document_key(&self, _signature: &RequestSignature, _document: &DocumentAddress, _nonce: u64) -> Result<(), Error> {
+			unimplemented!()
+		}
+
+		fn sign_message(&self, _signature: &RequestSignature, _document: &DocumentAddress, _message: &MessageHash) -> Result<EncryptedMessageSignature, Error> {
+			unimplemented!()
+		}
+
+		fn metrics(&self) -> Result<String, Error> {
+			unimplemented!()
+		}
+	}
+
+	fn make_nodes(num_nodes: usize) -> Vec<NodeId> {
+		(0..num_nodes).map(|_| Random.generate().unwrap().public().clone()).collect()
+	}
+
+	fn make_key_server_set(nodes: &[NodeId]) -> Arc<MapKeyServerSet> {
+		Arc::new(MapKeyServerSet::new(nodes.iter().enumerate()
+			.map(|(i, node_id)| (node_id.clone(), format!("127.0.0.1:{}", 6100 + i).parse().unwrap()))
+			.collect::<BTreeMap<_, _>>()))
+	}
+
+	fn make_listener(self_node_id: NodeId, key_server_set: Arc<KeyServerSet>, contract: Arc<DummyServiceContract>) -> ServiceContractListenerData<TestKeyServer> {
+		ServiceContractListenerData {
+			key_server: TestKeyServer::default(),
+			self_node_id: self_node_id,
+			contract: contract,
+			key_server_set: key_server_set,
+			tasks_queue: TasksQueue::default(),
+			state: Mutex::new(ServiceContractListenerState::default()),
+		}
+	}
+
+	fn make_request() -> ServiceRequest {
+		ServiceRequest::GenerateServerKey {
+			id: H256::random(),
+			signature: Default::default(),
+			threshold: 1,
+		}
+	}
+
+	fn make_single_node_listener(request: &ServiceRequest) -> (Arc<DummyServiceContract>, ServiceContractListenerData<TestKeyServer>) {
+		let nodes = make_nodes(1);
+		let contract = Arc::new(DummyServiceContract::default());
+		contract.pending_requests.lock().push(request.clone());
+		let listener = make_listener(nodes[0].clone(), make_key_server_set(&nodes), contract.clone());
+		(contract, listener)
+	}
+
+	#[test]
+	fn pending_request_is_picked_up() {
+		let request = make_request();
+		let (contract, listener) = make_single_node_listener(&request);
+
+		listener.process_pending_requests(false);
+		assert_eq!(*listener.key_server.generated_keys.lock(), vec![request.id().clone()]);
+		assert_eq!(*contract.published_responses.lock(), vec![ServiceResponse::ServerKeyGenerated(request.id().clone(), Public::default())]);
+
+		// request is not processed again until retry
+		listener.process_pending_requests(false);
+		assert_eq!(listener.key_server.generated_keys.lock().len(), 1);
+		assert_eq!(contract.published_responses.lock().len(), 1);
+	}
+
+	#[test]
+	fn exactly_one_key_server_is_responsible_for_request() {
+		let nodes = make_nodes(5);
+		let key_server_set = make_key_server_set(&nodes);
+		for _ in 0..10 {
+			let request_id = H256::random();
+			assert_eq!(nodes.iter().filter(|node_id| is_processed_by_this_key_server(&*key_server_set, node_id, &request_id)).count(), 1);
+		}
+		assert!(!is_processed_by_this_key_server(&*key_server_set, &make_nodes(1)[0], &H256::random()));
+	}
+
+	#[test]
+	fn only_responsible_key_server_starts_session() {
+		let nodes = make_nodes(5);
+		let key_server_set = make_key_server_set(&nodes);
+		let request = make_request();
+		let contract = Arc::new(DummyServiceContract::default());
+		contract.pending_requests.lock().push(request.clone());
+
+		let listeners: Vec<_> = nodes.iter().map(|node_id| make_listener(node_id.clone(), key_server_set.clone(), contract.clone())).collect();
+		for listener in listeners.iter() {
+			listener.process_pending_requests(false);
+		}
+
+		assert_eq!(listeners.iter().filter(|listener| !listener.key_server.generated_keys.lock().is_empty()).count(), 1);
+		assert_eq!(contract.published_responses.lock().len(), 1);
+	}
+
+	#[test]
+	fn already_answered_request_is_not_processed() {
+		let request = make_request();
+		let (contract, listener) = make_single_node_listener(&request);
+		contract.answered_requests.lock().insert(request.clone());
+
+		listener.process_pending_requests(false);
+		assert!(listener.key_server.generated_keys.lock().is_empty());
+		assert!(contract.published_responses.lock().is_empty());
+	}
+
+	#[test]
+	fn dropped_response_is_resubmitted_on_retry() {
+		let request = make_request();
+		let (contract, listener) = make_single_node_listener(&request);
+		listener.process_pending_requests(false);
+
+		// response transaction has been dropped => response is still required => resubmit without running session again
+		listener.process_pending_requests(true);
+		assert_eq!(listener.key_server.generated_keys.lock().len(), 1);
+		assert_eq!(contract.published_responses.lock().len(), 2);
+		assert_eq!(contract.published_responses.lock()[0], contract.published_responses.lock()[1]);
+
+		// response has been mined => nothing is published
+		contract.answered_requests.lock().insert(request.clone());
+		listener.process_pending_requests(true);
+		assert_eq!(contract.published_responses.lock().len(), 2);
+
+		// request is not pending anymore => published response is forgotten
+		contract.pending_requests.lock().clear();
+		listener.process_pending_requests(true);
+		assert!(listener.state.lock().published_responses.is_empty());
+	}
+
+	#[test]
+	fn temporarily_failed_request_is_retried() {
+		let request = make_request();
+		let (contract, listener) = make_single_node_listener(&request);
+		*listener.key_server.error.lock() = Some(Error::TemporarilyUnavailable("node disconnected".into()));
+
+		listener.process_pending_requests(false);
+		assert!(contract.published_responses.lock().is_empty());
+
+		*listener.key_server.error.lock() = None;
+		listener.process_pending_requests(true);
+		assert_eq!(listener.key_server.generated_keys.lock().len(), 1);
+		assert_eq!(contract.published_responses.lock().len(), 1);
+	}
+
+	#[test]
+	fn failed_request_is_answered_with_error() {
+		let request = make_request();
+		let (contract, listener) = make_single_node_listener(&request);
+		*listener.key_server.error.lock() = Some(Error::AccessDenied);
+
+		listener.process_pending_requests(false);
+		assert_eq!(*contract.published_responses.lock(), vec![ServiceResponse::ServerKeyGenerationFailed(request.id().clone())]);
+	}
+
+	#[test]
+	fn retry_is_scheduled_periodically() {
+		let request = make_request();
+		let (_, listener) = make_single_node_listener(&request);
+
+		listener.on_new_block();
+		listener.on_new_block();
+		assert_eq!(listener.tasks_queue.wait(), ServiceTask::ProcessPendingRequests);
+		for _ in 1..RETRY_INTERVAL_BLOCKS {
+			listener.on_new_block();
+		}
+		assert_eq!(listener.tasks_queue.wait(), ServiceTask::ProcessPendingRequests);
+		assert_eq!(listener.tasks_queue.wait(), ServiceTask::Retry);
+	}
+
+	#[test]
+	fn chain_notify_does_not_keep_listener_alive() {
+		let nodes = make_nodes(1);
+		let contract = Arc::new(DummyServiceContract::default());
+		let listener = ServiceContractListener::start(TestKeyServer::default(), nodes[0].clone(), contract, make_key_server_set(&nodes)).unwrap();
+		let chain_notify = listener.chain_notify();
+		let data = Arc::downgrade(&listener.data);
+
+		drop(listener);
+		assert!(data.upgrade().is_none());
+		chain_notify.new_blocks(vec![H256::random()], vec![], vec![], vec![], vec![], vec![], 0);
+	}
+}
@@ -14,11 +14,18 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use types::all::{Error, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow};
+use types::all::{Error, Public, RequestSignature, DocumentAddress, DocumentEncryptedKey, DocumentEncryptedKeyShadow,
+	MessageHash, EncryptedMessageSignature};
 
 #[ipc(client_ident="RemoteKeyServer")]
 /// Secret store key server
 pub trait KeyServer: Send + Sync {
+	/// Generate server key for given document. Returns public portion of the generated server key.
+	fn generate_server_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<Public, Error>;
+	/// Store document key, encrypted with server key of given document (see `generate_server_key`).
+	/// Only author of the server key is allowed to store document key.
+	/// Signature must be made over the store request hash of the document, document key and nonce. Every nonce is accepted once.
+	fn store_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, common_point: &Public, encrypted_document_key: &Public, nonce: u64) -> Result<(), Error>;
 	/// Generate encryption key for given document.
 	fn generate_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, threshold: usize) -> Result<DocumentEncryptedKey, Error>;
 	/// Request encryption key of given document for given requestor
@@ -31,4 +38,15 @@ pub trait KeyServer: Send + Sync {
 	/// 3) calculate decrypt_shadow_point: decrypt_shadows_sum * result.common_point
 	/// 4) calculate decrypted_secret: result.decrypted_secret + decrypt_shadow_point
 	fn document_key_shadow(&self, signature: &RequestSignature, document: &DocumentAddress) -> Result<DocumentEncryptedKeyShadow, Error>;
+	/// Remove server key (and document key, if any) of given document from all key servers.
+	/// Only author of the server key or administrator is allowed to remove it.
+	/// Key servers, which are currently unreachable, are removing their key shares when connected again.
+	/// Signature must be made over the removal request hash of the document and nonce. Every nonce is accepted once.
+	fn remove_document_key(&self, signature: &RequestSignature, document: &DocumentAddress, nonce: u64) -> Result<(), Error>;
+	/// Sign message hash with server key of given document, using threshold Schnorr signature scheme.
+	/// Signature must be made over the document id. Access to the server key is checked against ACL storage.
+	/// Result is the concatenation of signature hash && signature (c, s), encrypted with requestor public key.
+	fn sign_message(&self, signature: &RequestSignature, document: &DocumentAddress, message: &MessageHash) -> Result<EncryptedMessageSignature, Error>;
+	/// Get key server metrics in Prometheus text format.
+	fn metrics(&self) -> Result<String, Error>;
 }
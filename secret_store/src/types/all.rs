@@ -32,6 +32,10 @@ pub type DocumentKey = util::Bytes;
 pub type DocumentEncryptedKey = util::Bytes;
 /// Request signature type.
 pub type RequestSignature = ethkey::Signature;
+/// Message hash type.
+pub type MessageHash = util::H256;
+/// Encrypted message signature type.
+pub type EncryptedMessageSignature = util::Bytes;
 /// Public key type.
 pub use ethkey::Public;
 
@@ -45,10 +49,16 @@ pub enum Error {
 	AccessDenied,
 	/// Requested document not found
 	DocumentNotFound,
+	/// Request has failed, but it could succeed if retried later
+	TemporarilyUnavailable(String),
 	/// Serialization/deserialization error
 	Serde(String),
 	/// Database-related error
 	Database(String),
+	/// Database has been modified concurrently
+	DatabaseConflict,
+	/// Threshold is not satisfiable by the nodes, available to the request
+	InvalidThreshold(String),
 	/// Internal error
 	Internal(String),
 }
@@ -73,6 +83,8 @@ pub struct ServiceConfiguration {
 	pub data_path: String,
 	/// Cluster configuration.
 	pub cluster_config: ClusterConfiguration,
+	/// Address of the service contract. Requests, published on-chain, are not served if None.
+	pub service_contract_address: Option<util::Address>,
 }
 
 #[derive(Debug)]
@@ -90,6 +102,12 @@ pub struct ClusterConfiguration {
 	/// Allow outbound connections to 'higher' nodes.
 	/// This is useful for tests, but slower a bit for production.
 	pub allow_connecting_to_higher_nodes: bool,
+	/// Administrator public key. Administrative sessions (like share add) are refused if None.
+	pub admin_public: Option<ethkey::Public>,
+	/// Max number of keys, which are migrated at once when servers set is changed.
+	pub max_active_key_migrations: usize,
+	/// Remove key shares from this node, when it is excluded from servers set && every key is migrated.
+	pub wipe_removed_key_shares: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -104,14 +122,28 @@ pub struct DocumentEncryptedKeyShadow {
 	pub decrypt_shadows: Option<Vec<Vec<u8>>>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+/// Requester identification data.
+pub enum Requester {
+	/// Requested with signature of the server key id.
+	Signature(ethkey::Signature),
+	/// Requested with public key (already verified by local caller). Is never accepted from other key servers.
+	Public(ethkey::Public),
+	/// Requested with address (already verified by local caller). Is never accepted from other key servers.
+	Address(util::Address),
+}
+
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
 		match *self {
 			Error::BadSignature => write!(f, "Bad signature"),
 			Error::AccessDenied => write!(f, "Access dened"),
 			Error::DocumentNotFound => write!(f, "Document not found"),
+			Error::TemporarilyUnavailable(ref msg) => write!(f, "Temporarily unavailable: {}", msg),
 			Error::Serde(ref msg) => write!(f, "Serialization error: {}", msg),
 			Error::Database(ref msg) => write!(f, "Database error: {}", msg),
+			Error::DatabaseConflict => write!(f, "Database conflict"),
+			Error::InvalidThreshold(ref msg) => write!(f, "Invalid threshold: {}", msg),
 			Error::Internal(ref msg) => write!(f, "Internal error: {}", msg),
 		}
 	}
@@ -133,11 +165,61 @@ impl From<key_server_cluster::Error> for Error {
 	fn from(err: key_server_cluster::Error) -> Self {
 		match err {
 			key_server_cluster::Error::AccessDenied => Error::AccessDenied,
+			key_server_cluster::Error::TooManySessions | key_server_cluster::Error::NodeDisconnected => Error::TemporarilyUnavailable(err.into()),
+			key_server_cluster::Error::ServerKeyIsNotFound | key_server_cluster::Error::DocumentKeyIsNotFound => Error::DocumentNotFound,
+			key_server_cluster::Error::InsufficientRequesterData(_) => Error::BadSignature,
+			key_server_cluster::Error::InvalidThreshold { .. } => Error::InvalidThreshold(err.into()),
 			_ => Error::Internal(err.into()),
 		}
 	}
 }
 
+impl Requester {
+	/// Get requester public key. Public key could not be computed from address.
+	pub fn public(&self, server_key_id: &DocumentAddress) -> Result<ethkey::Public, key_server_cluster::Error> {
+		match *self {
+			Requester::Signature(ref signature) => ethkey::recover(signature, server_key_id).map_err(Into::into),
+			Requester::Public(ref public) => Ok(public.clone()),
+			Requester::Address(_) => Err(key_server_cluster::Error::InsufficientRequesterData("requester public key is unknown".into())),
+		}
+	}
+
+	/// Get requester address.
+	pub fn address(&self, server_key_id: &DocumentAddress) -> Result<util::Address, key_server_cluster::Error> {
+		match *self {
+			Requester::Address(ref address) => Ok(address.clone()),
+			_ => self.public(server_key_id).map(|public| ethkey::public_to_address(&public)),
+		}
+	}
+
+	/// Check if requester could be verified by every key server. Only signature could be verified, public key
+	/// and address are trusted by the key server only if they are passed by local caller.
+	pub fn is_verifiable(&self) -> bool {
+		match *self {
+			Requester::Signature(_) => true,
+			Requester::Public(_) | Requester::Address(_) => false,
+		}
+	}
+}
+
+impl From<ethkey::Signature> for Requester {
+	fn from(signature: ethkey::Signature) -> Requester {
+		Requester::Signature(signature)
+	}
+}
+
+impl From<ethkey::Public> for Requester {
+	fn from(public: ethkey::Public) -> Requester {
+		Requester::Public(public)
+	}
+}
+
+impl From<util::Address> for Requester {
+	fn from(address: util::Address) -> Requester {
+		Requester::Address(address)
+	}
+}
+
 impl Into<String> for Error {
 	fn into(self) -> String {
 		format!("{}", self)